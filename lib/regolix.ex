@@ -34,8 +34,11 @@ defmodule Regolix do
   alias Regolix.{Error, Native}
 
   @type engine :: reference()
+  @type parsed_input :: reference()
+  @type result_handle :: reference()
   @type json_encodable :: map() | list() | String.t() | number() | boolean() | nil
-  @type eval_result :: json_encodable() | :undefined
+  @type eval_result ::
+          json_encodable() | {:set, list()} | {:decimal, String.t()} | :undefined
   @type coverage_report :: %{String.t() => %{covered: [pos_integer()], not_covered: [pos_integer()]}}
 
   @doc """
@@ -59,338 +62,4364 @@ defmodule Regolix do
   end
 
   @doc """
-  Adds a Rego policy to the engine.
+  Creates an independent copy of the engine.
+
+  The copy has the same policies, data, input and settings. Changes to either
+  engine don't affect the other, which enables fork-per-request patterns and
+  throwaway experiments such as adding test data to a copy.
 
   ## Examples
 
-      {:ok, engine} = Regolix.add_policy(engine, "authz.rego", "package authz")
+      {:ok, scratch} = Regolix.clone(engine)
+      {:ok, scratch} = Regolix.add_data(scratch, %{"feature_flags" => %{"beta" => true}})
   """
-  @spec add_policy(engine(), String.t(), String.t()) :: {:ok, engine()} | {:error, Error.t()}
-  def add_policy(engine, name, source) do
-    case Native.native_add_policy(engine, name, source) do
-      {:ok, {}} -> {:ok, engine}
+  @spec clone(engine()) :: {:ok, engine()} | {:error, Error.t()}
+  def clone(engine) do
+    case Native.native_clone(engine) do
+      {:ok, copy} -> {:ok, copy}
       {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
     end
   end
 
   @doc """
-  Adds a Rego policy to the engine. Raises on error.
+  Creates an independent copy of the engine. Raises on error.
   """
-  @spec add_policy!(engine(), String.t(), String.t()) :: engine()
-  def add_policy!(engine, name, source) do
-    case add_policy(engine, name, source) do
-      {:ok, engine} -> engine
+  @spec clone!(engine()) :: engine()
+  def clone!(engine) do
+    case clone(engine) do
+      {:ok, copy} -> copy
       {:error, error} -> raise error
     end
   end
 
   @doc """
-  Returns the list of package names loaded in the engine.
+  Ties the engine's lifetime to `pid`, by default the calling process.
+
+  When the owner exits, the engine's policies, data and input are dropped
+  right away instead of when the last reference to the engine is garbage
+  collected, which may be much later if references linger in other
+  processes' heaps, ETS tables or messages. The engine then behaves as a new,
+  empty one. Calling it again replaces the owner.
+
+  Clones and restored engines have no owner.
 
   ## Examples
 
-      packages = Regolix.get_packages(engine)
-      # => ["data.authz", "data.rbac"]
+      def init(_) do
+        {:ok, engine} = Regolix.new()
+        {:ok, engine} = Regolix.set_owner(engine)
+        {:ok, %{engine: engine}}
+      end
   """
-  @spec get_packages(engine()) :: [String.t()]
-  def get_packages(engine) do
-    case Native.native_get_packages(engine) do
-      {:ok, packages} -> packages
-      packages when is_list(packages) -> packages
+  @spec set_owner(engine(), pid()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_owner(engine, pid \\ self()) when is_pid(pid) do
+    case Native.native_set_owner(engine, pid) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
     end
   end
 
   @doc """
-  Sets the input document for policy evaluation.
+  Stops releasing the engine when its owner exits.
+  """
+  @spec clear_owner(engine()) :: {:ok, engine()} | {:error, Error.t()}
+  def clear_owner(engine) do
+    case Native.native_clear_owner(engine) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
 
-  Accepts Elixir terms (maps, lists, etc.) which are automatically JSON-encoded.
+  @doc """
+  Serializes the engine's policies, data, input and settings to a binary.
+
+  Pass the binary to `restore/1` to recreate the engine, for example from a
+  cache at startup or on another node. Extensions registered with
+  `add_extension/4` are not included, since they point at local processes, and
+  neither are coverage or profiling results.
 
   ## Examples
 
-      {:ok, engine} = Regolix.set_input(engine, %{"user" => "alice"})
+      {:ok, blob} = Regolix.dump(engine)
+      File.write!("engine.dump", blob)
   """
-  @spec set_input(engine(), json_encodable()) :: {:ok, engine()} | {:error, Error.t()}
-  def set_input(engine, input) do
-    with {:ok, json} <- encode_json(input),
-         {:ok, {}} <- Native.native_set_input(engine, json) do
-      {:ok, engine}
-    else
-      {:error, {type, message}} ->
-        {:error, %Error{type: type, message: message}}
-
-      {:error, %Jason.EncodeError{} = e} ->
-        {:error, %Error{type: :json_error, message: Exception.message(e)}}
-
-      {:error, %Protocol.UndefinedError{} = e} ->
-        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+  @spec dump(engine()) :: {:ok, binary()} | {:error, Error.t()}
+  def dump(engine) do
+    case Native.native_dump(engine) do
+      {:ok, blob} -> {:ok, blob}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
     end
   end
 
   @doc """
-  Sets the input document. Raises on error.
+  Serializes the engine to a binary. Raises on error.
   """
-  @spec set_input!(engine(), json_encodable()) :: engine()
-  def set_input!(engine, input) do
-    case set_input(engine, input) do
-      {:ok, engine} -> engine
+  @spec dump!(engine()) :: binary()
+  def dump!(engine) do
+    case dump(engine) do
+      {:ok, blob} -> blob
       {:error, error} -> raise error
     end
   end
 
   @doc """
-  Adds data to the engine's data document.
-
-  Can be called multiple times to merge data.
+  Recreates an engine from a binary produced by `dump/1`.
 
   ## Examples
 
-      {:ok, engine} = Regolix.add_data(engine, %{"users" => %{"alice" => %{"role" => "admin"}}})
+      {:ok, engine} = Regolix.restore(File.read!("engine.dump"))
   """
-  @spec add_data(engine(), json_encodable()) :: {:ok, engine()} | {:error, Error.t()}
-  def add_data(engine, data) do
-    with {:ok, json} <- encode_json(data),
-         {:ok, {}} <- Native.native_add_data(engine, json) do
-      {:ok, engine}
-    else
-      {:error, {type, message}} ->
-        {:error, %Error{type: type, message: message}}
-
-      {:error, %Jason.EncodeError{} = e} ->
-        {:error, %Error{type: :json_error, message: Exception.message(e)}}
-
-      {:error, %Protocol.UndefinedError{} = e} ->
-        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+  @spec restore(binary()) :: {:ok, engine()} | {:error, Error.t()}
+  def restore(blob) when is_binary(blob) do
+    case Native.native_restore(blob) do
+      {:ok, engine} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
     end
   end
 
   @doc """
-  Adds data to the engine. Raises on error.
+  Recreates an engine from a dump. Raises on error.
   """
-  @spec add_data!(engine(), json_encodable()) :: engine()
-  def add_data!(engine, data) do
-    case add_data(engine, data) do
+  @spec restore!(binary()) :: engine()
+  def restore!(blob) do
+    case restore(blob) do
       {:ok, engine} -> engine
       {:error, error} -> raise error
     end
   end
 
   @doc """
-  Clears all data from the engine, keeping policies intact.
+  Opens an on-disk store and returns an engine with the state saved there, or
+  an empty engine if the store is new.
+
+  From then on the engine is saved to the store whenever anything `dump/1`
+  captures changes (policies, data, input, settings, decode options, limits,
+  label or verification keys), in the background and once per burst of
+  changes, so an application can rebuild its engine on restart without
+  reloading its policies and data.
+
+  Requires regolix to be built with the `store` feature:
+
+      config :regolix, Regolix.Native, features: ["store"]
+
+  Otherwise this returns an `:engine_error`. A store can only be open once at
+  a time, and the engine stays in memory until `close_store/1`.
 
   ## Examples
 
-      {:ok, engine} = Regolix.clear_data(engine)
+      {:ok, engine} = Regolix.open_store("/var/lib/myapp/policies")
   """
-  @spec clear_data(engine()) :: {:ok, engine()} | {:error, Error.t()}
-  def clear_data(engine) do
-    case Native.native_clear_data(engine) do
-      {:ok, {}} -> {:ok, engine}
+  @spec open_store(Path.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def open_store(path) do
+    case Native.native_open_store(to_string(path)) do
+      {:ok, engine} -> {:ok, engine}
       {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
     end
   end
 
   @doc """
-  Clears all data from the engine. Raises on error.
+  Opens an on-disk store. Raises on error.
   """
-  @spec clear_data!(engine()) :: engine()
-  def clear_data!(engine) do
-    case clear_data(engine) do
+  @spec open_store!(Path.t()) :: engine()
+  def open_store!(path) do
+    case open_store(path) do
       {:ok, engine} -> engine
       {:error, error} -> raise error
     end
   end
 
   @doc """
-  Enables coverage tracking on the engine.
-
-  After enabling, all query evaluations will track which policy lines are executed.
-  Use `get_coverage_report/1` to retrieve the accumulated coverage data.
-
-  ## Examples
+  Saves the engine to its store a last time and stops persisting it.
 
-      engine = Regolix.enable_coverage!(engine)
+  Returns an `:io_error` if the last save failed. Does nothing for an engine
+  not opened with `open_store/1`. The engine itself keeps working.
   """
-  @spec enable_coverage!(engine()) :: engine()
-  def enable_coverage!(engine) do
-    case Native.native_enable_coverage(engine, true) do
-      {:ok, {}} -> engine
-      {:error, {type, message}} -> raise %Error{type: type, message: message}
+  @spec close_store(engine()) :: :ok | {:error, Error.t()}
+  def close_store(engine) do
+    case Native.native_close_store(engine) do
+      {:ok, {}} -> :ok
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
     end
   end
 
   @doc """
-  Disables coverage tracking on the engine.
+  Registers an engine under a name so any process can look it up with `whereis/1`.
 
-  Coverage data is retained until explicitly cleared with `clear_coverage!/1`.
+  The registry lives in the NIF, shared by the whole node, so there's no
+  process to call through and no need to pass the engine reference around.
+  Registering a name again replaces the previous engine. A registered engine
+  stays alive until it is unregistered.
 
   ## Examples
 
-      engine = Regolix.disable_coverage!(engine)
+      :ok = Regolix.register(:authz, engine)
+      engine = Regolix.whereis(:authz)
   """
-  @spec disable_coverage!(engine()) :: engine()
-  def disable_coverage!(engine) do
-    case Native.native_enable_coverage(engine, false) do
-      {:ok, {}} -> engine
-      {:error, {type, message}} -> raise %Error{type: type, message: message}
+  @spec register(atom(), engine()) :: :ok | {:error, Error.t()}
+  def register(name, engine) when is_atom(name) do
+    case Native.native_register(name, engine) do
+      {:ok, {}} -> :ok
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
     end
   end
 
   @doc """
-  Returns the accumulated coverage report.
+  Removes a name from the engine registry. Returns whether it was registered.
+  """
+  @spec unregister(atom()) :: boolean()
+  def unregister(name) when is_atom(name) do
+    {:ok, removed} = Native.native_unregister(name)
+    removed
+  end
 
-  Returns coverage data for all policy files, showing which lines were
-  executed (covered) and which were not (not_covered).
+  @doc """
+  Returns the engine registered under `name`, or `nil`.
+  """
+  @spec whereis(atom()) :: engine() | nil
+  def whereis(name) when is_atom(name) do
+    {:ok, engine} = Native.native_whereis(name)
+    engine
+  end
+
+  @doc """
+  Returns the names in the engine registry, sorted.
+  """
+  @spec registered() :: [atom()]
+  def registered do
+    Enum.map(Native.native_registered(), &String.to_existing_atom/1)
+  end
+
+  @doc """
+  Adds a Rego policy to the engine.
 
   ## Examples
 
-      {:ok, coverage} = Regolix.get_coverage_report(engine)
-      # => %{"authz.rego" => %{covered: [1, 2, 5], not_covered: [9, 10]}}
+      {:ok, engine} = Regolix.add_policy(engine, "authz.rego", "package authz")
   """
-  @spec get_coverage_report(engine()) :: {:ok, coverage_report()} | {:error, Error.t()}
-  def get_coverage_report(engine) do
-    case Native.native_get_coverage_report(engine) do
-      {:ok, report} -> {:ok, report}
-      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+  @spec add_policy(engine(), String.t(), String.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def add_policy(engine, name, source) do
+    case Native.native_add_policy(engine, name, source) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
     end
   end
 
   @doc """
-  Returns the accumulated coverage report. Raises on error.
+  Adds a Rego policy to the engine. Raises on error.
   """
-  @spec get_coverage_report!(engine()) :: coverage_report()
-  def get_coverage_report!(engine) do
-    case get_coverage_report(engine) do
-      {:ok, report} -> report
+  @spec add_policy!(engine(), String.t(), String.t()) :: engine()
+  def add_policy!(engine, name, source) do
+    case add_policy(engine, name, source) do
+      {:ok, engine} -> engine
       {:error, error} -> raise error
     end
   end
 
   @doc """
-  Clears accumulated coverage data without disabling coverage.
+  Adds a Rego policy from a file.
 
-  Use this to reset coverage between test runs while keeping coverage enabled.
+  The file is read on the Rust side, on a dirty IO scheduler. The policy is
+  registered under its path.
 
   ## Examples
 
-      engine = Regolix.clear_coverage!(engine)
+      {:ok, engine} = Regolix.add_policy_from_file(engine, "priv/policies/authz.rego")
   """
-  @spec clear_coverage!(engine()) :: engine()
-  def clear_coverage!(engine) do
-    case Native.native_clear_coverage(engine) do
-      {:ok, {}} -> engine
-      {:error, {type, message}} -> raise %Error{type: type, message: message}
+  @spec add_policy_from_file(engine(), Path.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def add_policy_from_file(engine, path) do
+    case Native.native_add_policy_from_file(engine, to_string(path)) do
+      {:ok, _name} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
     end
   end
 
   @doc """
-  Executes a function with coverage tracking enabled, returning both the result and coverage.
+  Adds a Rego policy from a file. Raises on error.
+  """
+  @spec add_policy_from_file!(engine(), Path.t()) :: engine()
+  def add_policy_from_file!(engine, path) do
+    case add_policy_from_file(engine, path) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
 
-  This is the recommended API for single-operation coverage. Coverage is automatically
-  enabled before the function runs and disabled/cleared afterward.
+  @doc """
+  Adds every policy under `dir` matching a glob pattern.
 
-  ## Examples
+  Policies are registered under their paths. Loading is all-or-nothing: if any
+  file fails to read or parse, none of them are added.
 
-      {result, coverage} = Regolix.with_coverage(engine, fn e ->
-        Regolix.eval_query!(e, "data.authz.allow")
-      end)
-      # result => true
-      # coverage => %{"authz.rego" => %{covered: [1, 2, 5], not_covered: [9, 10]}}
+  ## Examples
 
-  For multi-query accumulation, use the raw primitives:
-  - `enable_coverage!/1`
-  - `disable_coverage!/1`
-  - `get_coverage_report!/1`
-  - `clear_coverage!/1`
+      {:ok, engine, paths} = Regolix.add_policies_from_dir(engine, "priv/policies")
+      {:ok, engine, paths} = Regolix.add_policies_from_dir(engine, "priv/policies", "authz/*.rego")
   """
-  @spec with_coverage(engine(), (engine() -> result)) :: {result, coverage_report()}
-        when result: var
-  def with_coverage(engine, fun) when is_function(fun, 1) do
-    engine = enable_coverage!(engine)
-    engine = clear_coverage!(engine)
+  @spec add_policies_from_dir(engine(), Path.t(), String.t()) ::
+          {:ok, engine(), [String.t()]} | {:error, Error.t()}
+  def add_policies_from_dir(engine, dir, pattern \\ "**/*.rego") do
+    case Native.native_add_policies_from_dir(engine, to_string(dir), pattern) do
+      {:ok, paths} -> {:ok, engine, paths}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
 
-    try do
-      result = fun.(engine)
-      coverage = get_coverage_report!(engine)
-      {result, coverage}
-    after
-      # Clean up: disable coverage and clear data
-      disable_coverage!(engine)
-      clear_coverage!(engine)
+  @doc """
+  Adds every policy under `dir` matching a glob pattern. Raises on error.
+  """
+  @spec add_policies_from_dir!(engine(), Path.t(), String.t()) :: engine()
+  def add_policies_from_dir!(engine, dir, pattern \\ "**/*.rego") do
+    case add_policies_from_dir(engine, dir, pattern) do
+      {:ok, engine, _paths} -> engine
+      {:error, error} -> raise error
     end
   end
 
+  @type reload_report :: %{
+          changed: [String.t()],
+          removed: [String.t()],
+          errors: [String.t() | map()]
+        }
+
   @doc """
-  Evaluates a Rego query against the engine.
+  Watches files and directories and reloads them into the engine when they change.
 
-  Returns the result as Elixir terms, or `:undefined` if the query has no result.
+  Every `.rego`, `.json`, `.yaml` and `.yml` file under `paths` is loaded
+  straight away and then reloaded whenever it changes or is deleted. Policies
+  are keyed by file path; data files are merged into the data document and
+  their top-level keys removed again when the file changes or goes away.
+
+  Each reload is applied atomically: if any file fails to read or parse, the
+  engine keeps its previous state and the reload is retried on the next change.
+  After every attempt the subscriber receives
+  `{:regolix_reloaded, %{changed: paths, removed: paths, errors: errors}}`,
+  where `errors` is empty on success and otherwise holds error messages,
+  location maps like those in `Regolix.Error`, or reason atoms such as
+  `:too_deep`.
+
+  Files are polled rather than watched through OS notifications, so this is
+  meant for development loops rather than huge trees.
+
+  ## Options
+
+    * `:interval` - polling interval in milliseconds (default `1000`)
+    * `:subscriber` - process to notify (default `self()`)
+
+  Watching stops when `unwatch/1` is called, the subscriber exits or the
+  watcher reference is garbage collected.
 
   ## Examples
 
-      {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
-      {:ok, :undefined} = Regolix.eval_query(engine, "data.authz.nonexistent")
+      {:ok, watcher} = Regolix.watch(engine, ["priv/policies"])
+      receive do
+        {:regolix_reloaded, %{errors: []}} -> :ok
+      end
   """
-  @spec eval_query(engine(), String.t()) :: {:ok, eval_result()} | {:error, Error.t()}
-  def eval_query(engine, query) do
-    case Native.native_eval_query(engine, query) do
-      {:ok, result} -> {:ok, result}
-      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
-    end
+  @spec watch(engine(), [Path.t()], keyword()) :: {:ok, reference()}
+  def watch(engine, paths, opts \\ []) when is_list(paths) do
+    interval = Keyword.get(opts, :interval, 1000)
+    subscriber = Keyword.get(opts, :subscriber, self())
+
+    {:ok, Native.native_watch(engine, Enum.map(paths, &to_string/1), subscriber, interval)}
   end
 
   @doc """
-  Evaluates a Rego query. Raises on error.
+  Stops a watcher started with `watch/3`.
   """
-  @spec eval_query!(engine(), String.t()) :: eval_result()
-  def eval_query!(engine, query) do
-    case eval_query(engine, query) do
-      {:ok, result} -> result
-      {:error, error} -> raise error
-    end
+  @spec unwatch(reference()) :: :ok
+  def unwatch(watcher) do
+    Native.native_unwatch(watcher)
   end
 
-  @type rule_info :: %{
-          name: String.t(),
-          description: String.t(),
-          start_line: pos_integer(),
-          end_line: pos_integer()
-        }
+  @type bundle_info :: %{revision: String.t(), roots: [String.t()], policies: [String.t()]}
 
   @doc """
-  Returns metadata about rules defined in loaded policies.
+  Loads a standard OPA bundle (`.tar.gz`) from disk.
 
-  Parses the policy sources to extract rule names, descriptions (from comments),
-  and line ranges. Useful for mapping coverage line numbers to human-readable rule names.
+  All `.rego` files are added as policies, named by their path inside the
+  bundle. `data.json` and `data.yaml` files are merged into the data document
+  under the path of their directory, the `patch.json` of a delta bundle is
+  applied to the data document (see `patch_data/2`), and the `.manifest` is
+  read for the revision and roots. Other files, such as `policy.wasm`, are
+  ignored. Installation is atomic: if any file fails to load, the
+  engine is left untouched. The files may add up to at most
+  `:max_bundle_bytes` once decompressed (see `set_limits/2`), or a
+  `:limit_exceeded` error is returned.
+
+  As in OPA, every package and data path must lie under one of the manifest
+  `roots` (a bundle without roots owns all of `data`), and the roots of
+  different bundles may not overlap. Either violation fails with a
+  `:bundle_error`, as does a policy file whose path is already taken by
+  another bundle or by `add_policy/3`, since policies are keyed by path.
+
+  When verification keys are trusted (see `add_verification_key/4`), the
+  bundle must carry a `.signatures.json` as written by `opa sign`: a JWS
+  signed by a trusted key, listing the SHA-256 hash of every other file.
+  Unsigned bundles, unknown keys, bad signatures and files missing from or
+  not matching the signature fail with a `:bundle_error`, as do bundles
+  holding two copies of a file (e.g. `data.json` and `./data.json`).
+
+  Loading a bundle under a name already loaded replaces
+  that bundle: the policies and data under the old revision's roots are
+  removed before the new revision is installed, so policies dropped from a
+  bundle go away. Delta bundles are applied on top of the loaded revision
+  instead. See `bundle_status/1` for the loaded revisions.
+
+  ## Options
+
+    * `:name` - name of the bundle (default: the path)
 
   ## Examples
 
-      rules = Regolix.get_rules(engine)
-      # => %{
-      #   "policy.rego" => [
-      #     %{name: "allow", description: "Allow if not denied", start_line: 10, end_line: 15},
-      #     %{name: "deny", description: "Deny sanctioned countries", start_line: 20, end_line: 25}
-      #   ]
-      # }
+      {:ok, engine, %{revision: "v42"}} = Regolix.load_bundle(engine, "bundle.tar.gz")
   """
-  @spec get_rules(engine()) :: {:ok, %{String.t() => [rule_info()]}} | {:error, Error.t()}
-  def get_rules(engine) do
-    case Native.native_get_rules(engine) do
-      {:ok, rules} -> {:ok, rules}
-      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+  @spec load_bundle(engine(), Path.t(), keyword()) ::
+          {:ok, engine(), bundle_info()} | {:error, Error.t()}
+  def load_bundle(engine, path, opts \\ []) when is_list(opts) do
+    path = to_string(path)
+
+    case Native.native_load_bundle(engine, path, Keyword.get(opts, :name, path)) do
+      {:ok, info} -> {:ok, engine, info}
+      {:error, reason} -> {:error, native_error(reason)}
     end
   end
 
   @doc """
-  Returns metadata about rules defined in loaded policies. Raises on error.
+  Loads a standard OPA bundle from disk. Raises on error.
   """
-  @spec get_rules!(engine()) :: %{String.t() => [rule_info()]}
-  def get_rules!(engine) do
-    case get_rules(engine) do
-      {:ok, rules} -> rules
+  @spec load_bundle!(engine(), Path.t(), keyword()) :: engine()
+  def load_bundle!(engine, path, opts \\ []) do
+    case load_bundle(engine, path, opts) do
+      {:ok, engine, _info} -> engine
       {:error, error} -> raise error
     end
   end
 
-  defp encode_json(term) do
-    Jason.encode(term)
+  @doc """
+  Loads a standard OPA bundle from an in-memory `.tar.gz` binary.
+
+  See `load_bundle/3`. The `:name` option defaults to `"bundle"`, so give
+  each bundle its own name when loading several.
+  """
+  @spec load_bundle_binary(engine(), binary(), keyword()) ::
+          {:ok, engine(), bundle_info()} | {:error, Error.t()}
+  def load_bundle_binary(engine, contents, opts \\ []) when is_binary(contents) and is_list(opts) do
+    case Native.native_load_bundle_binary(engine, contents, Keyword.get(opts, :name, "bundle")) do
+      {:ok, info} -> {:ok, engine, info}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
   end
+
+  @doc """
+  Loads a standard OPA bundle from an in-memory binary. Raises on error.
+  """
+  @spec load_bundle_binary!(engine(), binary(), keyword()) :: engine()
+  def load_bundle_binary!(engine, contents, opts \\ []) do
+    case load_bundle_binary(engine, contents, opts) do
+      {:ok, engine, _info} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Returns the revision and roots of each bundle loaded, by bundle name.
+
+  Useful for status reporting, e.g. confirming which revision of a policy
+  bundle an instance is serving.
+
+  ## Examples
+
+      {:ok, status} = Regolix.bundle_status(engine)
+      # => %{"authz" => %{revision: "v42", roots: ["authz"]}}
+  """
+  @spec bundle_status(engine()) ::
+          {:ok, %{String.t() => %{revision: String.t(), roots: [String.t()]}}} | {:error, Error.t()}
+  def bundle_status(engine) do
+    case Native.native_bundle_status(engine) do
+      {:ok, status} -> {:ok, status}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Packages the engine's policies and data as a standard OPA bundle (`.tar.gz`).
+
+  Policies are stored under their names (with `.rego` appended if missing)
+  and the whole data document goes into a top-level `data.json`, so the
+  bundle can be served to OPA or loaded back with `load_bundle_binary/3`.
+  Engine settings such as `set_time/2` or `disable_builtins/2` are not part
+  of the bundle format; use `dump/1` to copy an engine with its settings.
+
+  ## Options
+
+    * `:revision` - revision string for the `.manifest` (default `""`)
+    * `:roots` - list of roots for the `.manifest`; when omitted the bundle
+      owns the whole data tree
+
+  ## Examples
+
+      {:ok, tarball} = Regolix.build_bundle(engine, revision: "v43", roots: ["authz"])
+      File.write!("bundle.tar.gz", tarball)
+  """
+  @spec build_bundle(engine(), keyword()) :: {:ok, binary()} | {:error, Error.t()}
+  def build_bundle(engine, opts \\ []) when is_list(opts) do
+    opts = %{
+      revision: Keyword.get(opts, :revision, ""),
+      roots: Keyword.get(opts, :roots)
+    }
+
+    case Native.native_build_bundle(engine, opts) do
+      {:ok, tarball} -> {:ok, tarball}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Packages the engine's policies and data as an OPA bundle. Raises on error.
+  """
+  @spec build_bundle!(engine(), keyword()) :: binary()
+  def build_bundle!(engine, opts \\ []) do
+    case build_bundle(engine, opts) do
+      {:ok, tarball} -> tarball
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Removes a previously added policy from the engine.
+
+  The engine is rebuilt from the remaining policies; data and input are preserved.
+  Accumulated coverage data is reset.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.remove_policy(engine, "authz.rego")
+  """
+  @spec remove_policy(engine(), String.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def remove_policy(engine, name) do
+    case Native.native_remove_policy(engine, name) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Removes a previously added policy from the engine. Raises on error.
+  """
+  @spec remove_policy!(engine(), String.t()) :: engine()
+  def remove_policy!(engine, name) do
+    case remove_policy(engine, name) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Controls how Rego sets are returned from evaluations.
+
+    * `:list` (default) - sets are returned as plain lists, like arrays
+    * `:tagged` - sets are returned as `{:set, list}` tuples, so they can be told
+      apart from arrays and converted with `MapSet.new/1`
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_sets_mode(engine, :tagged)
+      {:ok, {:set, roles}} = Regolix.eval_query(engine, "data.authz.roles")
+      MapSet.new(roles)
+  """
+  @spec set_sets_mode(engine(), :list | :tagged) :: {:ok, engine()} | {:error, Error.t()}
+  def set_sets_mode(engine, mode) when mode in [:list, :tagged] do
+    case Native.native_set_sets_mode(engine, mode) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Controls how Rego sets are returned from evaluations. Raises on error.
+  """
+  @spec set_sets_mode!(engine(), :list | :tagged) :: engine()
+  def set_sets_mode!(engine, mode) do
+    case set_sets_mode(engine, mode) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Sets size limits for documents passed to `set_input/2`, `add_data/2` and the
+  other input and data functions.
+
+  Documents that break a limit are rejected with a `:limit_exceeded` error
+  before they reach the engine. Omitted limits are unlimited, and each call
+  replaces all previous limits.
+
+  ## Options
+
+    * `:max_bytes` - maximum size of the encoded document
+    * `:max_depth` - maximum nesting of arrays and objects
+    * `:max_entries` - maximum number of entries in any one array or object
+    * `:max_bundle_bytes` - maximum total size of a bundle's files once
+      decompressed (bundles are capped at 1 GiB whatever this is set to)
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_limits(engine, max_bytes: 1_000_000, max_depth: 32)
+  """
+  @spec set_limits(engine(), keyword()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_limits(engine, opts) when is_list(opts) do
+    limits = %{
+      max_bytes: Keyword.get(opts, :max_bytes),
+      max_depth: Keyword.get(opts, :max_depth),
+      max_entries: Keyword.get(opts, :max_entries),
+      max_bundle_bytes: Keyword.get(opts, :max_bundle_bytes)
+    }
+
+    case Native.native_set_limits(engine, limits) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Sets size limits for input and data documents. Raises on error.
+  """
+  @spec set_limits!(engine(), keyword()) :: engine()
+  def set_limits!(engine, opts) do
+    case set_limits(engine, opts) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Controls how non-integer numbers are returned from evaluations.
+
+  Integers are always returned exactly, as Elixir bignums when they exceed
+  64 bits. Other numbers are returned according to the mode:
+
+    * `:native` (default) - Elixir floats, which may lose precision
+    * `:string` - the exact decimal representation as a string
+    * `:decimal` - `{:decimal, string}` tuples, ready for `Decimal.new/1`
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_numbers_mode(engine, :decimal)
+      {:ok, {:decimal, "0.1"}} = Regolix.eval_query(engine, "data.pricing.rate")
+  """
+  @spec set_numbers_mode(engine(), :native | :string | :decimal) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def set_numbers_mode(engine, mode) when mode in [:native, :string, :decimal] do
+    case Native.native_set_numbers_mode(engine, mode) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Controls how non-integer numbers are returned from evaluations. Raises on error.
+  """
+  @spec set_numbers_mode!(engine(), :native | :string | :decimal) :: engine()
+  def set_numbers_mode!(engine, mode) do
+    case set_numbers_mode(engine, mode) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Controls how object keys are returned from evaluations and `get_data/2`.
+
+    * `:binary` (default) - keys are binaries, as in the JSON document
+    * `:existing_atoms` - keys are atoms when an atom of that name already
+      exists, and binaries otherwise
+
+  `:existing_atoms` never creates atoms, so it is safe with untrusted data, but
+  a key only comes back as an atom once the atom is known to the VM (for
+  example because it appears in a struct or pattern in loaded code). Maps can
+  end up with a mix of atom and binary keys.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_keys_mode(engine, :existing_atoms)
+      {:ok, %{allowed: true}} = Regolix.eval_query(engine, "data.authz.decision")
+  """
+  @spec set_keys_mode(engine(), :binary | :existing_atoms) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def set_keys_mode(engine, mode) when mode in [:binary, :existing_atoms] do
+    case Native.native_set_keys_mode(engine, mode) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Controls how object keys are returned from evaluations. Raises on error.
+  """
+  @spec set_keys_mode!(engine(), :binary | :existing_atoms) :: engine()
+  def set_keys_mode!(engine, mode) do
+    case set_keys_mode(engine, mode) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Controls how undefined query results are returned.
+
+    * `:undefined` (default) - `{:ok, :undefined}`
+    * `nil` - `{:ok, nil}`, indistinguishable from a JSON `null` result
+    * `:error` - `{:error, %Regolix.Error{type: :undefined}}`, so the bang
+      functions raise
+
+  Applies to the single-result evaluation functions (`eval_query/3`,
+  `eval_rule/2`, `eval_query_with_input/3`, `eval_batch/3`, pools and so on).
+  `eval_query_full/2` still returns an empty list.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_undefined_mode(engine, :error)
+      {:error, %Regolix.Error{type: :undefined}} = Regolix.eval_query(engine, "data.authz.nonexistent")
+  """
+  @spec set_undefined_mode(engine(), :undefined | nil | :error) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def set_undefined_mode(engine, mode) when mode in [:undefined, nil, :error] do
+    case Native.native_set_undefined_mode(engine, mode) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Controls how undefined query results are returned. Raises on error.
+  """
+  @spec set_undefined_mode!(engine(), :undefined | nil | :error) :: engine()
+  def set_undefined_mode!(engine, mode) do
+    case set_undefined_mode(engine, mode) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Limits how deeply nested an evaluation result may be.
+
+  Results with more levels of arrays, objects and sets than `max_depth` are
+  returned as `{:error, %Regolix.Error{type: :limit_exceeded}}` instead of
+  being built on the heap. Results are unlimited by default; pass `:infinity`
+  to lift a limit.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_max_result_depth(engine, 64)
+  """
+  @spec set_max_result_depth(engine(), pos_integer() | :infinity) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def set_max_result_depth(engine, max_depth)
+      when max_depth == :infinity or (is_integer(max_depth) and max_depth > 0) do
+    max_depth = if max_depth == :infinity, do: nil, else: max_depth
+
+    case Native.native_set_max_result_depth(engine, max_depth) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Limits how deeply nested an evaluation result may be. Raises on error.
+  """
+  @spec set_max_result_depth!(engine(), pos_integer() | :infinity) :: engine()
+  def set_max_result_depth!(engine, max_depth) do
+    case set_max_result_depth(engine, max_depth) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Sets all of the options controlling how evaluation results are returned
+  in one call.
+
+  Omitted options go back to their defaults, so each call replaces every
+  earlier setting, including those made with the single-option setters.
+
+  ## Options
+
+    * `:sets` - `:list` (default) or `:tagged`, see `set_sets_mode/2`
+    * `:numbers` - `:native` (default), `:string` or `:decimal`, see
+      `set_numbers_mode/2`
+    * `:keys` - `:binary` (default) or `:existing_atoms`, see `set_keys_mode/2`
+    * `:undefined` - `:undefined` (default), `nil` or `:error`, see
+      `set_undefined_mode/2`
+    * `:max_depth` - a positive integer or `:infinity` (default), see
+      `set_max_result_depth/2`
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_decode_opts(engine, sets: :tagged, undefined: nil)
+  """
+  @spec set_decode_opts(engine(), keyword()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_decode_opts(engine, opts) when is_list(opts) do
+    max_depth = Keyword.get(opts, :max_depth, :infinity)
+
+    decode_opts = %{
+      sets: Keyword.get(opts, :sets, :list),
+      numbers: Keyword.get(opts, :numbers, :native),
+      keys: Keyword.get(opts, :keys, :binary),
+      undefined: Keyword.get(opts, :undefined, :undefined),
+      max_depth: if(max_depth == :infinity, do: nil, else: max_depth)
+    }
+
+    case Native.native_set_decode_opts(engine, decode_opts) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Sets all of the options controlling how evaluation results are returned.
+  Raises on error.
+  """
+  @spec set_decode_opts!(engine(), keyword()) :: engine()
+  def set_decode_opts!(engine, opts) do
+    case set_decode_opts(engine, opts) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Caches the results of up to `capacity` recent evaluations, or turns caching
+  off when `capacity` is 0 (the default).
+
+  `eval_query/3` and `eval_query_with_input/3` are cached, keyed by the query,
+  the input and the engine's current policies and data. Any change to the
+  engine, such as `add_policy/3`, `add_data/2` or `set_input/2`, empties the
+  cache, and once it is full the least recently used result is dropped.
+
+  A cached result is returned without evaluating, so it isn't counted by
+  `stats/1` or recorded by coverage and profiling. Policies whose answers
+  change on their own, for example through `time.now_ns()`, keep returning
+  the cached answer until the engine changes or `clear_decision_cache/1` is
+  called.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_decision_cache(engine, 10_000)
+  """
+  @spec set_decision_cache(engine(), non_neg_integer()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_decision_cache(engine, capacity) when is_integer(capacity) and capacity >= 0 do
+    case Native.native_set_decision_cache(engine, capacity) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Caches the results of recent evaluations. Raises on error.
+  """
+  @spec set_decision_cache!(engine(), non_neg_integer()) :: engine()
+  def set_decision_cache!(engine, capacity) do
+    case set_decision_cache(engine, capacity) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Empties the decision cache enabled by `set_decision_cache/2`.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.clear_decision_cache(engine)
+  """
+  @spec clear_decision_cache(engine()) :: {:ok, engine()} | {:error, Error.t()}
+  def clear_decision_cache(engine) do
+    case Native.native_clear_decision_cache(engine) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the list of package names loaded in the engine.
+
+  ## Examples
+
+      packages = Regolix.get_packages(engine)
+      # => ["data.authz", "data.rbac"]
+  """
+  @spec get_packages(engine()) :: [String.t()]
+  def get_packages(engine) do
+    case Native.native_get_packages(engine) do
+      {:ok, packages} -> packages
+      packages when is_list(packages) -> packages
+    end
+  end
+
+  @type policy_info :: %{
+          name: String.t(),
+          package: String.t(),
+          source_length: non_neg_integer()
+        }
+
+  @doc """
+  Returns an inventory of the policies added to the engine, sorted by name.
+
+  Each entry contains the name the policy was added under, its package path
+  and the byte length of its source.
+
+  ## Examples
+
+      {:ok, policies} = Regolix.get_policies(engine)
+      # => [%{name: "authz.rego", package: "data.authz", source_length: 58}]
+  """
+  @spec get_policies(engine()) :: {:ok, [policy_info()]} | {:error, Error.t()}
+  def get_policies(engine) do
+    case Native.native_get_policies(engine) do
+      {:ok, policies} -> {:ok, policies}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns an inventory of the policies added to the engine. Raises on error.
+  """
+  @spec get_policies!(engine()) :: [policy_info()]
+  def get_policies!(engine) do
+    case get_policies(engine) do
+      {:ok, policies} -> policies
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Sets the input document for policy evaluation.
+
+  Accepts Elixir terms (maps, lists, etc.) which are automatically JSON-encoded.
+  Documents nested more than 100 levels deep are rejected with a `:json_error`
+  whose `reason` is `:too_deep`; the same applies to data documents, files and
+  bundles.
+
+  A binary that isn't valid UTF-8 is rejected with a `:json_error` whose
+  `:path` names it, such as `"input.user.name"`.
+
+  ## Options
+
+    * `:invalid_utf8` - `:error` (default) rejects binaries that aren't valid
+      UTF-8, and `:lossy` replaces each invalid sequence with U+FFFD instead.
+      A truncated sequence, such as `<<0xE2, 0x82>>`, is one invalid
+      sequence; any other invalid byte is one on its own
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_input(engine, %{"user" => "alice"})
+  """
+  @spec set_input(engine(), json_encodable(), keyword()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_input(engine, input, opts \\ []) when is_list(opts) do
+    input =
+      case Keyword.get(opts, :invalid_utf8, :error) do
+        :lossy -> replace_invalid_utf8(input)
+        :error -> input
+      end
+
+    with {:ok, json} <- encode_json(input),
+         {:ok, {}} <- Native.native_set_input(engine, json) do
+      {:ok, engine}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        error = invalid_utf8_error(input, "input")
+        {:error, error || %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Sets the input document. Raises on error.
+  """
+  @spec set_input!(engine(), json_encodable(), keyword()) :: engine()
+  def set_input!(engine, input, opts \\ []) do
+    case set_input(engine, input, opts) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Sets the input document from already-encoded JSON.
+
+  Accepts a binary or iodata, such as an HTTP request body, and parses it on
+  the Rust side without decoding it into Elixir terms first. Binaries are read
+  in place rather than copied.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_input_json(engine, ~s({"user": "alice"}))
+  """
+  @spec set_input_json(engine(), iodata()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_input_json(engine, json) do
+    case Native.native_set_input(engine, json) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Sets the input document from already-encoded JSON. Raises on error.
+  """
+  @spec set_input_json!(engine(), iodata()) :: engine()
+  def set_input_json!(engine, json) do
+    case set_input_json(engine, json) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Sets the input document from a YAML string.
+
+  Useful when the input already arrives as YAML, such as Kubernetes manifests
+  in an admission controller or CI pipeline. Size limits from `set_limits/2`
+  apply as for JSON.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_input_yaml(engine, """
+      kind: Deployment
+      metadata:
+        name: web
+      """)
+  """
+  @spec set_input_yaml(engine(), String.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_input_yaml(engine, yaml) when is_binary(yaml) do
+    case Native.native_set_input_yaml(engine, yaml) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Sets the input document from a YAML string. Raises on error.
+  """
+  @spec set_input_yaml!(engine(), String.t()) :: engine()
+  def set_input_yaml!(engine, yaml) do
+    case set_input_yaml(engine, yaml) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Sets the input document from `:erlang.term_to_binary/1` output.
+
+  The term is converted directly to a Rego value without going through JSON,
+  which helps when input arrives already encoded, e.g. from another node.
+  Accepts the same terms as `set_input/2`, plus `MapSet`s and
+  `{:set, list}` for Rego sets. The binary is decoded in safe mode, so it
+  can't create new atoms. Size limits from `set_limits/2` apply to the binary.
+
+  Keyword lists such as `[role: "admin", level: 3]` become objects; the first
+  entry for a key wins, as with `Keyword.get/2`. Lists holding `{:set, list}`
+  or `{:decimal, string}` tuples stay arrays.
+
+  Common structs are converted to their natural Rego counterparts:
+
+    * `DateTime` - an RFC 3339 string, as from `DateTime.to_iso8601/1`
+    * `NaiveDateTime` - an ISO 8601 string without offset, as from
+      `NaiveDateTime.to_iso8601/1`
+    * `Decimal` - an exact number
+    * `MapSet` - a set
+
+  ## Options
+
+    * `:structs` - how other structs are converted: `:map` (default) gives an
+      object of their fields without `__struct__`, and `:strict` returns an
+      error instead
+    * `:tuples` - how other tuples are converted: `:error` (default) rejects
+      them, `:list` gives an array of their elements, and `:pairs` turns a
+      list of 2-tuples into an object keyed by their first elements, like a
+      keyword list, while rejecting any other tuple
+    * `:charlists` - how non-empty lists of printable characters, such as
+      `~c"admin"`, are converted: `:list` (default) gives an array of
+      integers, `:string` a string, and `:error` rejects them. With `:string`,
+      improper lists that are valid iodata, such as `["ab" | "c"]`, also
+      become strings; otherwise they are rejected
+
+    * `:invalid_utf8` - `:error` (default) rejects binaries that aren't valid
+      UTF-8, and `:lossy` replaces each invalid sequence with U+FFFD instead.
+      A truncated sequence, such as `<<0xE2, 0x82>>`, is one invalid
+      sequence; any other invalid byte is one on its own
+
+  Errors name the offending part of the input in their message and `:path`,
+  as in `input.users[3] is an improper list`.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_input_etf(engine, :erlang.term_to_binary(%{"user" => "alice"}))
+  """
+  @spec set_input_etf(engine(), binary(), keyword()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_input_etf(engine, etf, opts \\ []) when is_binary(etf) and is_list(opts) do
+    encode_opts = %{
+      structs: Keyword.get(opts, :structs, :map),
+      tuples: Keyword.get(opts, :tuples, :error),
+      charlists: Keyword.get(opts, :charlists, :list),
+      invalid_utf8: Keyword.get(opts, :invalid_utf8, :error)
+    }
+
+    case Native.native_set_input_etf(engine, etf, encode_opts) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Sets the input document from `:erlang.term_to_binary/1` output. Raises on error.
+  """
+  @spec set_input_etf!(engine(), binary(), keyword()) :: engine()
+  def set_input_etf!(engine, etf, opts \\ []) do
+    case set_input_etf(engine, etf, opts) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Adds data to the engine's data document.
+
+  Can be called multiple times to merge data.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.add_data(engine, %{"users" => %{"alice" => %{"role" => "admin"}}})
+  """
+  @spec add_data(engine(), json_encodable()) :: {:ok, engine()} | {:error, Error.t()}
+  def add_data(engine, data) do
+    with {:ok, json} <- encode_json(data),
+         {:ok, {}} <- Native.native_add_data(engine, json) do
+      {:ok, engine}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Adds data to the engine. Raises on error.
+  """
+  @spec add_data!(engine(), json_encodable()) :: engine()
+  def add_data!(engine, data) do
+    case add_data(engine, data) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Adds a JSON data document streamed in as chunks of binary.
+
+  The chunks are parsed on the Rust side as they arrive, so a document of
+  hundreds of megabytes never has to exist as a single Elixir binary. The data
+  is merged only once the whole document has parsed; on error nothing changes.
+  Size limits from `set_limits/2` apply to the total size of the chunks.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.add_data_stream(engine, File.stream!("data.json", 1_048_576))
+  """
+  @spec add_data_stream(engine(), Enumerable.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def add_data_stream(engine, chunks) do
+    with {:ok, upload} <- Native.native_add_data_begin(engine),
+         :ok <- send_chunks(upload, chunks),
+         {:ok, {}} <- Native.native_add_data_commit(upload) do
+      {:ok, engine}
+    else
+      {:error, {_type, _detail} = reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Adds a JSON data document streamed in as chunks. Raises on error.
+  """
+  @spec add_data_stream!(engine(), Enumerable.t()) :: engine()
+  def add_data_stream!(engine, chunks) do
+    case add_data_stream(engine, chunks) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  defp send_chunks(upload, chunks) do
+    Enum.reduce_while(chunks, :ok, fn chunk, :ok ->
+      case Native.native_add_data_chunk(upload, chunk) do
+        {:ok, {}} -> {:cont, :ok}
+        {:error, _} = error -> {:halt, error}
+      end
+    end)
+  end
+
+  @doc """
+  Adds data at a dotted path in the engine's data document.
+
+  The value is wrapped in the right nesting before being merged, so namespaced
+  data can be maintained without building wrapper objects.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.add_data_at_path(engine, "tenants.acme.roles", ["admin"])
+      # same as Regolix.add_data(engine, %{"tenants" => %{"acme" => %{"roles" => ["admin"]}}})
+  """
+  @spec add_data_at_path(engine(), String.t(), json_encodable()) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def add_data_at_path(engine, path, data) do
+    with {:ok, json} <- encode_json(data),
+         {:ok, {}} <- Native.native_add_data_at_path(engine, path, json) do
+      {:ok, engine}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Adds data at a dotted path in the engine's data document. Raises on error.
+  """
+  @spec add_data_at_path!(engine(), String.t(), json_encodable()) :: engine()
+  def add_data_at_path!(engine, path, data) do
+    case add_data_at_path(engine, path, data) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Adds a YAML document to the engine's data document.
+
+  The YAML is parsed on the Rust side, so there is no need to convert it to
+  JSON first.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.add_data_yaml(engine, \"""
+      users:
+        alice:
+          role: admin
+      \""")
+  """
+  @spec add_data_yaml(engine(), String.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def add_data_yaml(engine, yaml) when is_binary(yaml) do
+    case Native.native_add_data_yaml(engine, yaml) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Adds a YAML document to the engine's data document. Raises on error.
+  """
+  @spec add_data_yaml!(engine(), String.t()) :: engine()
+  def add_data_yaml!(engine, yaml) do
+    case add_data_yaml(engine, yaml) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Adds a data document from a JSON or YAML file.
+
+  Files ending in `.yaml` or `.yml` are parsed as YAML, anything else as JSON.
+  The file is read on a dirty IO scheduler.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.add_data_from_file(engine, "priv/data/users.yaml")
+  """
+  @spec add_data_from_file(engine(), Path.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def add_data_from_file(engine, path) do
+    case Native.native_add_data_from_file(engine, to_string(path)) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Adds a data document from a JSON or YAML file. Raises on error.
+  """
+  @spec add_data_from_file!(engine(), Path.t()) :: engine()
+  def add_data_from_file!(engine, path) do
+    case add_data_from_file(engine, path) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Returns the engine's data document, or the subtree at a dotted path.
+
+  Array elements can be addressed by index. Returns `:undefined` when nothing
+  exists at the path.
+
+  ## Examples
+
+      {:ok, data} = Regolix.get_data(engine)
+      {:ok, %{"roles" => _}} = Regolix.get_data(engine, "tenants.acme")
+      {:ok, "admin"} = Regolix.get_data(engine, "tenants.acme.roles.0")
+  """
+  @spec get_data(engine(), String.t()) :: {:ok, eval_result()} | {:error, Error.t()}
+  def get_data(engine, path \\ "") do
+    case Native.native_get_data(engine, path) do
+      {:ok, data} -> {:ok, data}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the engine's data document, or the subtree at a dotted path. Raises on error.
+  """
+  @spec get_data!(engine(), String.t()) :: eval_result()
+  def get_data!(engine, path \\ "") do
+    case get_data(engine, path) do
+      {:ok, data} -> data
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Clears all data from the engine, keeping policies intact.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.clear_data(engine)
+  """
+  @spec clear_data(engine()) :: {:ok, engine()} | {:error, Error.t()}
+  def clear_data(engine) do
+    case Native.native_clear_data(engine) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Clears all data from the engine. Raises on error.
+  """
+  @spec clear_data!(engine()) :: engine()
+  def clear_data!(engine) do
+    case clear_data(engine) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Removes the subtree at a dotted path from the data document.
+
+  Everything else in the data document is kept, so e.g. one tenant's data can
+  be evicted without reloading the rest. Removing a path that doesn't exist is
+  a no-op.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.remove_data_path(engine, "tenants.acme")
+  """
+  @spec remove_data_path(engine(), String.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def remove_data_path(engine, path) do
+    case Native.native_remove_data_path(engine, path) do
+      {:ok, _removed} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Removes the subtree at a dotted path from the data document. Raises on error.
+  """
+  @spec remove_data_path!(engine(), String.t()) :: engine()
+  def remove_data_path!(engine, path) do
+    case remove_data_path(engine, path) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Applies a JSON Patch (RFC 6902) to the data document.
+
+  Takes the list of operations (`add`, `remove`, `replace`, `move`, `copy`,
+  `test`) with JSON Pointer paths, so frequent small updates don't need the
+  whole document re-sent. The `patch.json` of an OPA delta bundle,
+  `%{"data" => [...]}`, is accepted too and also allows `upsert`, which
+  creates missing parent objects. Either every operation applies or none do.
+  Delta bundle tarballs can also be loaded with `load_bundle/3`.
+
+  ## Examples
+
+      {:ok, engine} =
+        Regolix.patch_data(engine, [
+          %{"op" => "replace", "path" => "/tenants/acme/plan", "value" => "enterprise"},
+          %{"op" => "remove", "path" => "/tenants/globex"}
+        ])
+  """
+  @spec patch_data(engine(), [map()] | map()) :: {:ok, engine()} | {:error, Error.t()}
+  def patch_data(engine, patch) when is_list(patch) or is_map(patch) do
+    with {:ok, json} <- encode_json(patch),
+         {:ok, {}} <- Native.native_patch_data(engine, json) do
+      {:ok, engine}
+    else
+      {:error, {type, message}} ->
+        {:error, %Error{type: type, message: message}}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Applies a JSON Patch to the data document. Raises on error.
+  """
+  @spec patch_data!(engine(), [map()] | map()) :: engine()
+  def patch_data!(engine, patch) do
+    case patch_data(engine, patch) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @type txn :: reference()
+
+  @doc """
+  Applies a group of data changes so evaluations see all of them or none.
+
+  `fun` receives a transaction and queues changes with `txn_add_data/2`,
+  `txn_add_data_at_path/3` and `txn_remove_data_path/2`. When it returns
+  `:ok` or `{:ok, _}` the changes are applied in one step; when it returns an
+  error or raises, they are dropped. Evaluations running meanwhile keep seeing
+  the old data, never a half-applied refresh.
+
+  ## Examples
+
+      {:ok, engine} =
+        Regolix.transaction(engine, fn txn ->
+          with {:ok, txn} <- Regolix.txn_remove_data_path(txn, "users"),
+               {:ok, _txn} <- Regolix.txn_add_data(txn, %{"users" => users}) do
+            :ok
+          end
+        end)
+  """
+  @spec transaction(engine(), (txn() -> :ok | {:ok, term()} | {:error, term()})) ::
+          {:ok, engine()} | {:error, term()}
+  def transaction(engine, fun) when is_function(fun, 1) do
+    with {:ok, txn} <- txn_begin(engine) do
+      try do
+        fun.(txn)
+      rescue
+        e ->
+          txn_abort(txn)
+          reraise e, __STACKTRACE__
+      else
+        :ok ->
+          txn_commit(txn)
+
+        {:ok, _} ->
+          txn_commit(txn)
+
+        error ->
+          txn_abort(txn)
+          error
+      end
+    end
+  end
+
+  @doc """
+  Starts a transaction on the engine. See `transaction/2`.
+  """
+  @spec txn_begin(engine()) :: {:ok, txn()} | {:error, Error.t()}
+  def txn_begin(engine) do
+    case Native.native_txn_begin(engine) do
+      {:ok, txn} -> {:ok, txn}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Queues a data document to merge when the transaction commits.
+  """
+  @spec txn_add_data(txn(), json_encodable()) :: {:ok, txn()} | {:error, Error.t()}
+  def txn_add_data(txn, data) do
+    with {:ok, json} <- encode_json(data),
+         {:ok, {}} <- Native.native_txn_add_data(txn, json) do
+      {:ok, txn}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Queues data to add at a dotted path when the transaction commits.
+  """
+  @spec txn_add_data_at_path(txn(), String.t(), json_encodable()) ::
+          {:ok, txn()} | {:error, Error.t()}
+  def txn_add_data_at_path(txn, path, data) do
+    with {:ok, json} <- encode_json(data),
+         {:ok, {}} <- Native.native_txn_add_data_at_path(txn, path, json) do
+      {:ok, txn}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Queues the removal of the subtree at a dotted path.
+  """
+  @spec txn_remove_data_path(txn(), String.t()) :: {:ok, txn()} | {:error, Error.t()}
+  def txn_remove_data_path(txn, path) do
+    case Native.native_txn_remove_data_path(txn, path) do
+      {:ok, {}} -> {:ok, txn}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Applies the queued changes in one step.
+
+  Changes are replayed onto the engine as it is at commit time, so policies
+  or data changed since `txn_begin/1` are kept. If any change fails, none are
+  applied.
+  """
+  @spec txn_commit(txn()) :: {:ok, engine()} | {:error, Error.t()}
+  def txn_commit(txn) do
+    case Native.native_txn_commit(txn) do
+      {:ok, engine} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Drops the queued changes.
+  """
+  @spec txn_abort(txn()) :: :ok
+  def txn_abort(txn) do
+    {:ok, {}} = Native.native_txn_abort(txn)
+    :ok
+  end
+
+  @doc """
+  Enables or disables strict builtin errors.
+
+  By default, builtin errors (e.g. `to_number("abc")`) make the expression
+  undefined, matching OPA. In strict mode they abort the evaluation with an
+  `:eval_error`, which is useful while developing policies.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_strict_builtin_errors(engine, true)
+  """
+  @spec set_strict_builtin_errors(engine(), boolean()) :: {:ok, engine()} | {:error, Error.t()}
+  def set_strict_builtin_errors(engine, enable) when is_boolean(enable) do
+    case Native.native_set_strict_builtin_errors(engine, enable) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Enables or disables strict builtin errors. Raises on error.
+  """
+  @spec set_strict_builtin_errors!(engine(), boolean()) :: engine()
+  def set_strict_builtin_errors!(engine, enable) do
+    case set_strict_builtin_errors(engine, enable) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Controls what `time.now_ns()` returns in policies, for testing time-based rules.
+
+    * an integer - a fixed time, in nanoseconds since the epoch
+    * `{:offset, ns}` - the system time shifted by `ns` nanoseconds
+    * `:system` - the real time again (the default)
+
+  The other time builtins take their time as an argument, so fixing
+  `time.now_ns()` is enough to make a policy deterministic. The setting
+  applies to policies already loaded and carries over to `clone/1` and
+  `dump/1`. Queries passed to the eval functions keep using the system clock.
+
+  ## Examples
+
+      # Monday 2024-01-15 10:00 UTC
+      {:ok, engine} = Regolix.set_time(engine, 1_705_312_800_000_000_000)
+      {:ok, true} = Regolix.eval_query(engine, "data.hours.business_hours")
+  """
+  @spec set_time(engine(), integer() | {:offset, integer()} | :system) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def set_time(engine, time) do
+    {mode, ns} =
+      case time do
+        :system -> {:system, 0}
+        {:offset, ns} when is_integer(ns) -> {:offset, ns}
+        ns when is_integer(ns) -> {:fixed, ns}
+      end
+
+    case Native.native_set_time(engine, mode, ns) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Controls what `time.now_ns()` returns in policies. Raises on error.
+  """
+  @spec set_time!(engine(), integer() | {:offset, integer()} | :system) :: engine()
+  def set_time!(engine, time) do
+    case set_time(engine, time) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Makes `rand.intn` and `uuid.rfc4122` in policies reproducible.
+
+  With a seed, each result depends only on the seed and the call's
+  arguments, so property tests and replayed evaluations give the same
+  decisions every run. As in OPA, repeating a call with the same arguments
+  gives the same result. Pass `:system` to go back to real randomness.
+
+  Like `set_time/2`, this applies to policies already loaded, carries over to
+  `clone/1` and `dump/1`, and doesn't affect queries passed to the eval
+  functions.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_random_seed(engine, 42)
+  """
+  @spec set_random_seed(engine(), non_neg_integer() | :system) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def set_random_seed(engine, seed) when seed == :system or (is_integer(seed) and seed >= 0) do
+    native_seed = if seed == :system, do: nil, else: seed
+
+    case Native.native_set_random_seed(engine, native_seed) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Makes the random builtins in policies reproducible. Raises on error.
+  """
+  @spec set_random_seed!(engine(), non_neg_integer() | :system) :: engine()
+  def set_random_seed!(engine, seed) do
+    case set_random_seed(engine, seed) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Sets what `opa.runtime()` returns in policies.
+
+  Policies that branch on `opa.runtime().env` or `.config` then behave as they
+  do in their OPA deployment. Pass `nil` to go back to the default.
+
+  Like `set_time/2`, this applies to policies already loaded, carries over to
+  `clone/1` and `dump/1`, and doesn't affect queries passed to the eval
+  functions.
+
+  ## Examples
+
+      {:ok, engine} =
+        Regolix.set_runtime(engine, %{
+          "env" => %{"DEPLOY_ENV" => "production"},
+          "version" => "0.60.0",
+          "config" => %{"labels" => %{"region" => "eu-west-1"}}
+        })
+  """
+  @spec set_runtime(engine(), map() | nil) :: {:ok, engine()} | {:error, Error.t()}
+  def set_runtime(engine, nil) do
+    case Native.native_set_runtime(engine, nil) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  def set_runtime(engine, runtime) when is_map(runtime) do
+    with {:ok, json} <- encode_json(runtime),
+         {:ok, {}} <- Native.native_set_runtime(engine, json) do
+      {:ok, engine}
+    else
+      {:error, {type, message}} ->
+        {:error, %Error{type: type, message: message}}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Sets what `opa.runtime()` returns in policies. Raises on error.
+  """
+  @spec set_runtime!(engine(), map() | nil) :: engine()
+  def set_runtime!(engine, runtime) do
+    case set_runtime(engine, runtime) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Names the engine in the provenance of its decisions, see
+  `eval_with_provenance/3`. Pass `nil` to clear the label.
+
+  The label carries over to `clone/1` and `dump/1`.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_label(engine, "authz-eu-west-1")
+  """
+  @spec set_label(engine(), String.t() | nil) :: {:ok, engine()} | {:error, Error.t()}
+  def set_label(engine, label) when is_binary(label) or is_nil(label) do
+    case Native.native_set_label(engine, label) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Names the engine in the provenance of its decisions. Raises on error.
+  """
+  @spec set_label!(engine(), String.t() | nil) :: engine()
+  def set_label!(engine, label) do
+    case set_label(engine, label) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @type verification_key :: %{
+          keyid: String.t(),
+          algorithm: String.t(),
+          format: :pem | :jwk | :secret
+        }
+
+  @doc """
+  Trusts a key for verifying signatures, under `keyid`.
+
+  `key` is a PEM public key, or the shared secret for the `HS*` algorithms.
+  Private keys and keys that don't parse for `algorithm` are refused. A key
+  already registered under `keyid` is replaced.
+
+  Once any key is trusted, `load_bundle/3` and `load_bundle_binary/3` only
+  accept bundles whose `.signatures.json` is signed by one of the keys, with
+  the `kid` of the signature naming it, and covers every file in the bundle.
+
+  To rotate keys, add the new key under a new keyid, and remove the old one
+  with `remove_verification_key/2` once nothing is signed with it anymore.
+  Keys carry over to `clone/1` and `dump/1`.
+
+  ## Options
+
+    * `:algorithm` - JWS algorithm the key is used with, e.g. `"ES256"` or
+      `"HS256"` (default `"RS256"`)
+
+  ## Examples
+
+      {:ok, engine} = Regolix.add_verification_key(engine, "release-2024", File.read!("release.pem"))
+  """
+  @spec add_verification_key(engine(), String.t(), String.t(), keyword()) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def add_verification_key(engine, keyid, key, opts \\ []) do
+    algorithm = Keyword.get(opts, :algorithm, "RS256")
+
+    case Native.native_add_verification_key(engine, keyid, key, algorithm) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Trusts a key for verifying signatures, under `keyid`. Raises on error.
+  """
+  @spec add_verification_key!(engine(), String.t(), String.t(), keyword()) :: engine()
+  def add_verification_key!(engine, keyid, key, opts \\ []) do
+    case add_verification_key(engine, keyid, key, opts) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Trusts every key of a JWK Set, given as a map or a JSON string, under its
+  `kid`.
+
+  Keys already registered under the same ids are replaced, so loading a fresh
+  copy of a JWKS endpoint picks up rotated keys. The algorithm is the key's
+  `alg`, or else follows from its type. If any key is invalid or private,
+  none are added.
+
+  ## Examples
+
+      {:ok, engine, ["2024-06"]} = Regolix.add_jwks(engine, jwks)
+  """
+  @spec add_jwks(engine(), map() | String.t()) ::
+          {:ok, engine(), [String.t()]} | {:error, Error.t()}
+  def add_jwks(engine, jwks) when is_binary(jwks) do
+    case Native.native_add_jwks(engine, jwks) do
+      {:ok, keyids} -> {:ok, engine, keyids}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  def add_jwks(engine, jwks) when is_map(jwks) do
+    case encode_json(jwks) do
+      {:ok, json} -> add_jwks(engine, json)
+      {:error, e} -> {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Trusts every key of a JWK Set. Raises on error.
+  """
+  @spec add_jwks!(engine(), map() | String.t()) :: engine()
+  def add_jwks!(engine, jwks) do
+    case add_jwks(engine, jwks) do
+      {:ok, engine, _keyids} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Stops trusting the key registered under `keyid`. Does nothing if there's
+  no such key.
+  """
+  @spec remove_verification_key(engine(), String.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def remove_verification_key(engine, keyid) do
+    case Native.native_remove_verification_key(engine, keyid) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Lists the trusted keys, sorted by keyid. Key material is never returned.
+
+  ## Examples
+
+      {:ok, [%{keyid: "release-2024", algorithm: "RS256", format: :pem}]} =
+        Regolix.verification_keys(engine)
+  """
+  @spec verification_keys(engine()) :: {:ok, [verification_key()]} | {:error, Error.t()}
+  def verification_keys(engine) do
+    case Native.native_list_verification_keys(engine) do
+      {:ok, keys} -> {:ok, keys}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Refuses to load any policy that calls one of the given builtins.
+
+  Use this to sandbox untrusted policies, e.g. to guarantee they can't reach
+  the network or the environment. Loading a policy that calls a disabled
+  builtin fails with a `:builtin_disabled` error pointing at the call. If a
+  policy already loaded calls one, nothing is disabled and that error is
+  returned instead.
+
+  The deny-list only grows, and carries over to `clone/1` and `dump/1`.
+  Queries passed to the eval functions are not checked.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.disable_builtins(engine, ["http.send", "opa.runtime", "net.lookup_ip_addr"])
+  """
+  @spec disable_builtins(engine(), [String.t()]) :: {:ok, engine()} | {:error, Error.t()}
+  def disable_builtins(engine, builtins) when is_list(builtins) do
+    case Native.native_disable_builtins(engine, builtins) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Refuses to load any policy that calls one of the given builtins. Raises on error.
+  """
+  @spec disable_builtins!(engine(), [String.t()]) :: engine()
+  def disable_builtins!(engine, builtins) do
+    case disable_builtins(engine, builtins) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Lists the builtins each loaded policy calls, by policy file.
+
+  Meant for reviewing policies before activating them, e.g. flagging any that
+  call `http.send`, `opa.runtime` or `crypto.*`. Calls to functions defined in
+  loaded policies are left out; extensions are included. Names are sorted and
+  listed once per policy, as written in the source.
+
+  ## Examples
+
+      {:ok, usage} = Regolix.builtin_usage(engine)
+      # => %{"authz.rego" => ["count", "http.send", "startswith"]}
+
+      risky = for {file, builtins} <- usage, "http.send" in builtins, do: file
+  """
+  @spec builtin_usage(engine()) :: {:ok, %{String.t() => [String.t()]}} | {:error, Error.t()}
+  def builtin_usage(engine) do
+    case Native.native_builtin_usage(engine) do
+      {:ok, usage} -> {:ok, usage}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Selects the Rego language version used to parse policies.
+
+    * `:v1` (default) - Rego v1 syntax, where rules require `if` and `contains`
+    * `:v0` - legacy OPA syntax, for policies that still use
+      `import future.keywords` and unbraced rule bodies
+
+  Only policies added after the call are affected, so set the version before
+  adding policies.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.set_rego_version(engine, :v0)
+  """
+  @spec set_rego_version(engine(), :v0 | :v1) :: {:ok, engine()} | {:error, Error.t()}
+  def set_rego_version(engine, version) when version in [:v0, :v1] do
+    case Native.native_set_rego_version(engine, version) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Selects the Rego language version used to parse policies. Raises on error.
+  """
+  @spec set_rego_version!(engine(), :v0 | :v1) :: engine()
+  def set_rego_version!(engine, version) do
+    case set_rego_version(engine, version) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Registers a custom builtin implemented in Elixir.
+
+  `handler` is either a function of arity `nargs`, which is run by a handler
+  process started with `Regolix.Extension.start/1`, or the pid of a process that
+  implements the protocol described in `Regolix.Extension`. Arguments and
+  results are exchanged as JSON.
+
+  The evaluation blocks until the handler replies or the timeout elapses, in
+  which case the builtin call fails. The handler must not evaluate against the
+  same engine, as that engine is busy waiting for the reply.
+
+  ## Options
+
+    * `:timeout` - milliseconds to wait for a reply (default: 5000)
+
+  ## Examples
+
+      {:ok, engine} =
+        Regolix.add_extension(engine, "myorg.lookup", 1, fn key ->
+          MyApp.Settings.get(key)
+        end)
+
+      {:ok, engine} = Regolix.add_policy(engine, "app.rego", \"""
+        package app
+        enabled if myorg.lookup("feature_x") == true
+      \""")
+  """
+  @spec add_extension(engine(), String.t(), non_neg_integer(), function() | pid(), keyword()) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def add_extension(engine, path, nargs, handler, opts \\ [])
+
+  def add_extension(engine, path, nargs, fun, opts) when is_function(fun, nargs) do
+    add_extension(engine, path, nargs, Regolix.Extension.start(fun), opts)
+  end
+
+  def add_extension(engine, path, nargs, handler, opts) when is_pid(handler) do
+    timeout = Keyword.get(opts, :timeout, 5000)
+
+    case Native.native_add_extension(engine, path, nargs, handler, timeout) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Registers a custom builtin implemented in Elixir. Raises on error.
+  """
+  @spec add_extension!(engine(), String.t(), non_neg_integer(), function() | pid(), keyword()) ::
+          engine()
+  def add_extension!(engine, path, nargs, handler, opts \\ []) do
+    case add_extension(engine, path, nargs, handler, opts) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Routes the policy builtin `http.send` through an Elixir function.
+
+  Outbound calls then go through the application's own HTTP client, with its
+  connection pools, retries and telemetry. See `Regolix.HTTP` for what
+  `handler` receives and returns. Like any extension, the evaluation waits
+  for the handler, up to the timeout.
+
+  ## Options
+
+    * `:timeout` - milliseconds to wait for a response (default: 30000)
+
+  ## Examples
+
+      {:ok, engine} =
+        Regolix.set_http_handler(engine, fn %{"method" => method, "url" => url} = request ->
+          case Req.request(method: method, url: url, headers: Map.get(request, "headers", %{})) do
+            {:ok, resp} -> {:ok, %{status: resp.status, headers: resp.headers, body: resp.body}}
+            {:error, exception} -> {:error, exception}
+          end
+        end)
+  """
+  @spec set_http_handler(engine(), Regolix.HTTP.handler(), keyword()) ::
+          {:ok, engine()} | {:error, Error.t()}
+  def set_http_handler(engine, handler, opts \\ []) when is_function(handler, 1) do
+    opts = Keyword.put_new(opts, :timeout, 30_000)
+    add_extension(engine, "http.send", 1, Regolix.HTTP.builtin(handler), opts)
+  end
+
+  @doc """
+  Routes `http.send` through an Elixir function. Raises on error.
+  """
+  @spec set_http_handler!(engine(), Regolix.HTTP.handler(), keyword()) :: engine()
+  def set_http_handler!(engine, handler, opts \\ []) do
+    case set_http_handler(engine, handler, opts) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Enables coverage tracking on the engine.
+
+  After enabling, all query evaluations will track which policy lines are executed.
+  Use `get_coverage_report/1` to retrieve the accumulated coverage data.
+
+  ## Examples
+
+      engine = Regolix.enable_coverage!(engine)
+  """
+  @spec enable_coverage!(engine()) :: engine()
+  def enable_coverage!(engine) do
+    case Native.native_enable_coverage(engine, true) do
+      {:ok, {}} -> engine
+      {:error, {type, message}} -> raise %Error{type: type, message: message}
+    end
+  end
+
+  @doc """
+  Disables coverage tracking on the engine.
+
+  Coverage data is retained until explicitly cleared with `clear_coverage!/1`.
+
+  ## Examples
+
+      engine = Regolix.disable_coverage!(engine)
+  """
+  @spec disable_coverage!(engine()) :: engine()
+  def disable_coverage!(engine) do
+    case Native.native_enable_coverage(engine, false) do
+      {:ok, {}} -> engine
+      {:error, {type, message}} -> raise %Error{type: type, message: message}
+    end
+  end
+
+  @doc """
+  Returns the accumulated coverage report.
+
+  Returns coverage data for all policy files, showing which lines were
+  executed (covered) and which were not (not_covered).
+
+  ## Examples
+
+      {:ok, coverage} = Regolix.get_coverage_report(engine)
+      # => %{"authz.rego" => %{covered: [1, 2, 5], not_covered: [9, 10]}}
+  """
+  @spec get_coverage_report(engine()) :: {:ok, coverage_report()} | {:error, Error.t()}
+  def get_coverage_report(engine) do
+    case Native.native_get_coverage_report(engine) do
+      {:ok, report} -> {:ok, report}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the accumulated coverage report. Raises on error.
+  """
+  @spec get_coverage_report!(engine()) :: coverage_report()
+  def get_coverage_report!(engine) do
+    case get_coverage_report(engine) do
+      {:ok, report} -> report
+      {:error, error} -> raise error
+    end
+  end
+
+  @type rule_coverage :: %{
+          file: String.t(),
+          rule: String.t(),
+          start_line: pos_integer(),
+          end_line: pos_integer(),
+          covered: non_neg_integer(),
+          total: non_neg_integer(),
+          percent: float()
+        }
+
+  @doc """
+  Returns coverage per rule definition.
+
+  Combines the line coverage of `get_coverage_report/1` with each rule's span
+  in its policy. `:rules` has an entry per definition, with `:rule` the full
+  path (e.g. `"data.authz.allow"`), so incremental rules and functions with
+  several definitions get one entry each. `:unexercised` lists the
+  definitions none of whose lines ran, which are usually the gaps worth a
+  new test.
+
+  ## Examples
+
+      {:ok, %{unexercised: unexercised}} = Regolix.get_rule_coverage(engine)
+
+      for %{rule: rule, file: file, start_line: line} <- unexercised do
+        IO.puts("\#{file}:\#{line} \#{rule} is never evaluated")
+      end
+  """
+  @spec get_rule_coverage(engine()) ::
+          {:ok, %{rules: [rule_coverage()], unexercised: [rule_coverage()]}} | {:error, Error.t()}
+  def get_rule_coverage(engine) do
+    case Native.native_get_rule_coverage(engine) do
+      {:ok, report} -> {:ok, report}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the coverage report rendered for humans.
+
+  Each policy file is printed with covered lines in green and uncovered lines
+  in red (ANSI escape codes), the same format the regorus CLI uses. Handy for
+  printing from a mix task.
+
+  ## Examples
+
+      {:ok, text} = Regolix.get_coverage_pretty(engine)
+      IO.puts(text)
+  """
+  @spec get_coverage_pretty(engine()) :: {:ok, String.t()} | {:error, Error.t()}
+  def get_coverage_pretty(engine) do
+    case Native.native_get_coverage_pretty(engine) do
+      {:ok, text} -> {:ok, text}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the coverage report rendered for humans. Raises on error.
+  """
+  @spec get_coverage_pretty!(engine()) :: String.t()
+  def get_coverage_pretty!(engine) do
+    case get_coverage_pretty(engine) do
+      {:ok, text} -> text
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Returns the coverage report as an LCOV tracefile.
+
+  Write it to disk to feed Rego coverage into tools that already consume LCOV,
+  such as Codecov or Coveralls.
+
+  ## Examples
+
+      {:ok, lcov} = Regolix.coverage_to_lcov(engine)
+      File.write!("cover/rego.lcov", lcov)
+  """
+  @spec coverage_to_lcov(engine()) :: {:ok, String.t()} | {:error, Error.t()}
+  def coverage_to_lcov(engine) do
+    case Native.native_coverage_to_lcov(engine) do
+      {:ok, lcov} -> {:ok, lcov}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the coverage report as an LCOV tracefile. Raises on error.
+  """
+  @spec coverage_to_lcov!(engine()) :: String.t()
+  def coverage_to_lcov!(engine) do
+    case coverage_to_lcov(engine) do
+      {:ok, lcov} -> lcov
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Returns the coverage report as Cobertura XML.
+
+  Each policy file becomes a class in a single `rego` package. Branch coverage
+  is not tracked and is always reported as zero.
+
+  ## Examples
+
+      {:ok, xml} = Regolix.coverage_to_cobertura(engine)
+      File.write!("cover/rego-cobertura.xml", xml)
+  """
+  @spec coverage_to_cobertura(engine()) :: {:ok, String.t()} | {:error, Error.t()}
+  def coverage_to_cobertura(engine) do
+    case Native.native_coverage_to_cobertura(engine) do
+      {:ok, xml} -> {:ok, xml}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the coverage report as Cobertura XML. Raises on error.
+  """
+  @spec coverage_to_cobertura!(engine()) :: String.t()
+  def coverage_to_cobertura!(engine) do
+    case coverage_to_cobertura(engine) do
+      {:ok, xml} -> xml
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Merges coverage from several engines or coverage reports into one report.
+
+  Useful when a test suite uses a fresh engine per test: collect each engine's
+  report (or pass the engines themselves) and merge them for a suite-wide
+  view. A line is covered if any report covered it.
+
+  ## Examples
+
+      {:ok, coverage} = Regolix.merge_coverage([engine_a, engine_b])
+      {:ok, coverage} = Regolix.merge_coverage([report_a, report_b])
+  """
+  @spec merge_coverage([engine() | coverage_report()]) ::
+          {:ok, coverage_report()} | {:error, Error.t()}
+  def merge_coverage(sources) when is_list(sources) do
+    sources
+    |> Enum.reduce_while({:ok, []}, fn
+      report, {:ok, acc} when is_map(report) ->
+        {:cont, {:ok, [report | acc]}}
+
+      engine, {:ok, acc} ->
+        case get_coverage_report(engine) do
+          {:ok, report} -> {:cont, {:ok, [report | acc]}}
+          {:error, _} = error -> {:halt, error}
+        end
+    end)
+    |> case do
+      {:ok, reports} -> {:ok, Native.native_merge_coverage(reports)}
+      error -> error
+    end
+  end
+
+  @doc """
+  Merges coverage from several engines or coverage reports. Raises on error.
+  """
+  @spec merge_coverage!([engine() | coverage_report()]) :: coverage_report()
+  def merge_coverage!(sources) do
+    case merge_coverage(sources) do
+      {:ok, coverage} -> coverage
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Clears accumulated coverage data without disabling coverage.
+
+  Use this to reset coverage between test runs while keeping coverage enabled.
+
+  ## Examples
+
+      engine = Regolix.clear_coverage!(engine)
+  """
+  @spec clear_coverage!(engine()) :: engine()
+  def clear_coverage!(engine) do
+    case Native.native_clear_coverage(engine) do
+      {:ok, {}} -> engine
+      {:error, {type, message}} -> raise %Error{type: type, message: message}
+    end
+  end
+
+  @doc """
+  Executes a function with coverage tracking enabled, returning both the result and coverage.
+
+  This is the recommended API for single-operation coverage. Coverage is automatically
+  enabled before the function runs and disabled/cleared afterward.
+
+  ## Examples
+
+      {result, coverage} = Regolix.with_coverage(engine, fn e ->
+        Regolix.eval_query!(e, "data.authz.allow")
+      end)
+      # result => true
+      # coverage => %{"authz.rego" => %{covered: [1, 2, 5], not_covered: [9, 10]}}
+
+  For multi-query accumulation, use the raw primitives:
+  - `enable_coverage!/1`
+  - `disable_coverage!/1`
+  - `get_coverage_report!/1`
+  - `clear_coverage!/1`
+  """
+  @spec with_coverage(engine(), (engine() -> result)) :: {result, coverage_report()}
+        when result: var
+  def with_coverage(engine, fun) when is_function(fun, 1) do
+    engine = enable_coverage!(engine)
+    engine = clear_coverage!(engine)
+
+    try do
+      result = fun.(engine)
+      coverage = get_coverage_report!(engine)
+      {result, coverage}
+    after
+      # Clean up: disable coverage and clear data
+      disable_coverage!(engine)
+      clear_coverage!(engine)
+    end
+  end
+
+  @type rule_profile :: %{count: non_neg_integer(), total_ns: non_neg_integer(), max_ns: non_neg_integer()}
+
+  @doc """
+  Enables profiling on the engine.
+
+  While enabled, every `eval_query/2` and `eval_rule/2` call records how long
+  each rule it reads takes to evaluate, keyed by rule path, including the
+  rules reached through other rules. Use `get_profile/1` to read the totals.
+
+  regorus can't time the rules inside an evaluation, so after each call every
+  rule it read is evaluated once more on its own, with the same input. This
+  makes profiled evaluations several times slower. A rule's time includes
+  the rules it depends on, and a query that reads no rules, such as `1 + 1`,
+  records nothing.
+
+  ## Examples
+
+      engine = Regolix.enable_profiling!(engine)
+  """
+  @spec enable_profiling!(engine()) :: engine()
+  def enable_profiling!(engine) do
+    case Native.native_enable_profiling(engine, true) do
+      {:ok, {}} -> engine
+      {:error, {type, message}} -> raise %Error{type: type, message: message}
+    end
+  end
+
+  @doc """
+  Disables profiling and discards the recorded timings.
+  """
+  @spec disable_profiling!(engine()) :: engine()
+  def disable_profiling!(engine) do
+    case Native.native_enable_profiling(engine, false) do
+      {:ok, {}} -> engine
+      {:error, {type, message}} -> raise %Error{type: type, message: message}
+    end
+  end
+
+  @doc """
+  Returns the recorded timings as a map from rule path to
+  `%{count, total_ns, max_ns}`.
+
+  ## Examples
+
+      {:ok, profile} = Regolix.get_profile(engine)
+      # => %{"data.authz.allow" => %{count: 120, total_ns: 5_400_000, max_ns: 210_000}}
+  """
+  @spec get_profile(engine()) :: {:ok, %{String.t() => rule_profile()}} | {:error, Error.t()}
+  def get_profile(engine) do
+    case Native.native_get_profile(engine) do
+      {:ok, profile} -> {:ok, profile}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns the recorded timings. Raises on error.
+  """
+  @spec get_profile!(engine()) :: %{String.t() => rule_profile()}
+  def get_profile!(engine) do
+    case get_profile(engine) do
+      {:ok, profile} -> profile
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Clears the recorded timings without disabling profiling.
+  """
+  @spec clear_profile!(engine()) :: engine()
+  def clear_profile!(engine) do
+    case Native.native_clear_profile(engine) do
+      {:ok, {}} -> engine
+      {:error, {type, message}} -> raise %Error{type: type, message: message}
+    end
+  end
+
+  @type benchmark_report :: %{
+          iterations: pos_integer(),
+          min_ns: non_neg_integer(),
+          p50_ns: non_neg_integer(),
+          p95_ns: non_neg_integer(),
+          p99_ns: non_neg_integer(),
+          max_ns: non_neg_integer(),
+          mean_ns: non_neg_integer()
+        }
+
+  @doc """
+  Evaluates a query repeatedly inside the NIF and reports the latency
+  distribution in nanoseconds.
+
+  The loop runs entirely in Rust, so the numbers leave out the cost of calling
+  into the NIF and converting results, which would otherwise dominate for
+  fast policies. Evaluations run against a private copy of the engine and
+  aren't counted in `stats/1` or the profile.
+
+  ## Options
+
+    * `:input` - input document for the evaluations (default: the engine's input)
+    * `:iterations` - number of timed evaluations, from 1 to 1,000,000
+      (default: 1000). Other counts fail with `:invalid_argument`.
+
+  ## Examples
+
+      {:ok, %{p50_ns: p50, p99_ns: p99}} =
+        Regolix.benchmark(engine, "data.authz.allow", input: %{"user" => "alice"})
+  """
+  @spec benchmark(engine(), String.t(), keyword()) ::
+          {:ok, benchmark_report()} | {:error, Error.t()}
+  def benchmark(engine, query, opts \\ []) when is_binary(query) and is_list(opts) do
+    iterations = Keyword.get(opts, :iterations, 1000)
+
+    with {:ok, json} <- encode_input_option(opts),
+         {:ok, report} <- Native.native_benchmark(engine, query, json, iterations) do
+      {:ok, report}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  # Encodes the `:input` option, or `nil` when it's missing
+  defp encode_input_option(opts) do
+    case Keyword.fetch(opts, :input) do
+      {:ok, input} -> encode_json(input)
+      :error -> {:ok, nil}
+    end
+  end
+
+  @type engine_stats :: %{
+          policy_count: non_neg_integer(),
+          policy_bytes: non_neg_integer(),
+          data_bytes: non_neg_integer(),
+          eval_count: non_neg_integer(),
+          eval_ns: non_neg_integer(),
+          last_error: %{kind: Error.error_type(), message: String.t()} | nil,
+          cache_hits: non_neg_integer(),
+          cache_misses: non_neg_integer()
+        }
+
+  @doc """
+  Returns counters for health checks and metrics endpoints.
+
+    * `:policy_count`, `:policy_bytes` - number and total source size of the loaded policies
+    * `:data_bytes` - approximate size of the data document (its length as JSON)
+    * `:eval_count`, `:eval_ns` - evaluations since the engine was created, and
+      the time spent in them
+    * `:last_error` - type and message of the most recent failed evaluation, or `nil`
+    * `:cache_hits`, `:cache_misses` - lookups in the cache from `set_decision_cache/2`
+
+  Unlike profiling, the counters are always on. Evaluations through a pool
+  from `new_pool/2` aren't counted, and `clone/1` and `restore/1` start from
+  zero.
+
+  ## Examples
+
+      {:ok, %{eval_count: count, eval_ns: ns}} = Regolix.stats(engine)
+  """
+  @spec stats(engine()) :: {:ok, engine_stats()} | {:error, Error.t()}
+  def stats(engine) do
+    case Native.native_stats(engine) do
+      {:ok, stats} -> {:ok, stats}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query against the engine.
+
+  Returns the result as Elixir terms, or `:undefined` if the query has no result.
+
+  ## Options
+
+    * `:timeout` - time budget in milliseconds; returns a `:timeout` error when exceeded
+    * `:cancel` - a token from `cancel_token/0`; returns a `:cancelled` error once
+      `cancel/1` is called on it
+    * `:extract` - return only part of the result, given as a dotted path such
+      as `"violations.0"` or a JSON Pointer such as `"/violations/0"`. The rest
+      of the result is never converted to terms, which saves time and memory
+      when a query returns a large document. A missing path gives `:undefined`.
+    * `:decode` - decode options for this call only, taking precedence over
+      the engine's. Accepts the options of `set_decode_opts/2`; those not given
+      keep the engine's setting.
+
+  With `:timeout` or `:cancel` the query runs against a copy of the engine on
+  the same fixed pool of workers as `eval_async/3`, so coverage is not
+  recorded and time spent waiting for a worker counts against the timeout.
+  regorus can't interrupt an evaluation: the caller gets its error straight
+  away, and an abandoned evaluation that hasn't started is dropped, but one
+  already running keeps its worker until it finishes.
+
+  ## Examples
+
+      {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+      {:ok, :undefined} = Regolix.eval_query(engine, "data.authz.nonexistent")
+      {:error, %Regolix.Error{type: :timeout}} = Regolix.eval_query(engine, slow_query, timeout: 100)
+      {:ok, violations} = Regolix.eval_query(engine, "data.authz", extract: "violations")
+      {:ok, "0.1"} = Regolix.eval_query(engine, "data.pricing.rate", decode: [numbers: :string])
+  """
+  @spec eval_query(engine(), String.t(), keyword()) :: {:ok, eval_result()} | {:error, Error.t()}
+  def eval_query(engine, query, opts \\ [])
+
+  def eval_query(engine, query, []) do
+    case Native.native_eval_query(engine, query, nil, %{}) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  def eval_query(engine, query, opts) do
+    timeout = Keyword.get(opts, :timeout)
+    token = Keyword.get(opts, :cancel)
+    extract = Keyword.get(opts, :extract)
+    overrides = decode_overrides(Keyword.get(opts, :decode, []))
+
+    result =
+      if is_nil(timeout) and is_nil(token) do
+        Native.native_eval_query(engine, query, extract, overrides)
+      else
+        Native.native_eval_query_timeout(engine, query, timeout, token, extract, overrides)
+      end
+
+    case result do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query. Raises on error.
+  """
+  @spec eval_query!(engine(), String.t(), keyword()) :: eval_result()
+  def eval_query!(engine, query, opts \\ []) do
+    case eval_query(engine, query, opts) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and returns the result encoded as a JSON binary.
+
+  Building Elixir terms dominates the cost of very large results. When the
+  result is only going to be sent on, for example as an HTTP response body,
+  this skips that step entirely. Sets are encoded as JSON arrays. Returns
+  `:undefined` if the query has no result.
+
+  ## Examples
+
+      {:ok, "true"} = Regolix.eval_query_json(engine, "data.authz.allow")
+      {:ok, :undefined} = Regolix.eval_query_json(engine, "data.authz.nonexistent")
+  """
+  @spec eval_query_json(engine(), String.t()) :: {:ok, String.t() | :undefined} | {:error, Error.t()}
+  def eval_query_json(engine, query) do
+    case Native.native_eval_query_json(engine, query) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and returns the result as JSON. Raises on error.
+  """
+  @spec eval_query_json!(engine(), String.t()) :: String.t() | :undefined
+  def eval_query_json!(engine, query) do
+    case eval_query_json(engine, query) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and returns a handle to the result, without
+  converting it to Elixir terms.
+
+  Use `result_get/2` to pull out only the parts you need, so a large result
+  such as a full report of violations doesn't have to be converted as a whole.
+  The handle keeps the engine's decode settings from the time of the call.
+
+  ## Examples
+
+      {:ok, result} = Regolix.eval_query_lazy(engine, "data.k8s.report")
+      {:ok, "image must be signed"} = Regolix.result_get(result, "violations.0.msg")
+  """
+  @spec eval_query_lazy(engine(), String.t()) :: {:ok, result_handle()} | {:error, Error.t()}
+  def eval_query_lazy(engine, query) do
+    case Native.native_eval_query_lazy(engine, query) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and returns a handle to the result. Raises on error.
+  """
+  @spec eval_query_lazy!(engine(), String.t()) :: result_handle()
+  def eval_query_lazy!(engine, query) do
+    case eval_query_lazy(engine, query) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Converts the part of a result from `eval_query_lazy/2` at a dotted path.
+
+  Numeric segments index arrays, so `"violations.0.msg"` is the `msg` field of
+  the first violation. An empty path returns the whole result, and a path
+  that doesn't exist returns `:undefined` (subject to `set_undefined_mode/2`).
+
+  ## Examples
+
+      {:ok, 3} = Regolix.result_get(result, "summary.total")
+  """
+  @spec result_get(result_handle(), String.t()) :: {:ok, eval_result()} | {:error, Error.t()}
+  def result_get(result, path) when is_binary(path) do
+    case Native.native_result_get(result, path) do
+      {:ok, value} -> {:ok, value}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Converts the part of a result at a dotted path. Raises on error.
+  """
+  @spec result_get!(result_handle(), String.t()) :: eval_result()
+  def result_get!(result, path) do
+    case result_get(result, path) do
+      {:ok, value} -> value
+      {:error, error} -> raise error
+    end
+  end
+
+  @type eval_metrics :: %{
+          lock_wait_ns: non_neg_integer(),
+          eval_ns: non_neg_integer(),
+          decode_ns: non_neg_integer(),
+          result_count: non_neg_integer(),
+          expression_count: non_neg_integer(),
+          rule_lookup: boolean(),
+          strict_builtin_errors: boolean()
+        }
+
+  @doc """
+  Evaluates a Rego query and returns measurements taken inside the NIF.
+
+  Timing around the NIF call from Elixir can't tell copying the engine
+  snapshot apart from evaluating. The metrics split the call into lock wait,
+  evaluation (including query compilation, which `rule_lookup: true` queries
+  skip) and result decoding, and count the results and expressions produced,
+  ready to attach to a `:telemetry` event.
+
+  ## Examples
+
+      {:ok, true, metrics} = Regolix.eval_query_with_metrics(engine, "data.authz.allow")
+      :telemetry.execute([:regolix, :eval], %{duration: metrics.eval_ns}, %{query: "data.authz.allow"})
+  """
+  @spec eval_query_with_metrics(engine(), String.t()) ::
+          {:ok, eval_result(), eval_metrics()} | {:error, Error.t()}
+  def eval_query_with_metrics(engine, query) do
+    case Native.native_eval_query_with_metrics(engine, query) do
+      {:ok, %{result: result, metrics: metrics}} -> {:ok, result, metrics}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Creates a cancellation token for `eval_query/3`.
+
+  Any process holding the token can abandon the evaluations that use it, e.g.
+  a supervisor shutting down a request.
+
+  ## Examples
+
+      token = Regolix.cancel_token()
+      Task.start(fn -> Regolix.eval_query(engine, query, cancel: token) end)
+      :ok = Regolix.cancel(token)
+  """
+  @spec cancel_token() :: reference()
+  def cancel_token do
+    Native.native_new_cancel_token()
+  end
+
+  @doc """
+  Trips a cancellation token. Evaluations using it return a `:cancelled` error.
+  """
+  @spec cancel(reference()) :: :ok
+  def cancel(token) do
+    Native.native_cancel(token)
+  end
+
+  @doc """
+  Returns whether a cancellation token has been tripped.
+  """
+  @spec cancelled?(reference()) :: boolean()
+  def cancelled?(token) do
+    Native.native_cancelled(token)
+  end
+
+  @doc """
+  Clears the engine's prepared query cache.
+
+  `eval_query/2` remembers how each query string should be evaluated, so a query
+  that names a rule exactly (e.g. `"data.authz.allow"`) skips query compilation
+  on later calls. The cache holds up to 1024 query strings, starting over when
+  it fills up, and is reset automatically whenever policies change; clearing
+  it by hand is rarely needed.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.clear_query_cache(engine)
+  """
+  @spec clear_query_cache(engine()) :: {:ok, engine()} | {:error, Error.t()}
+  def clear_query_cache(engine) do
+    case Native.native_clear_query_cache(engine) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Clears the engine's prepared query cache. Raises on error.
+  """
+  @spec clear_query_cache!(engine()) :: engine()
+  def clear_query_cache!(engine) do
+    case clear_query_cache(engine) do
+      {:ok, engine} -> engine
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Parses an input document once, for reuse across evaluations.
+
+  Pass the returned handle to `eval_query_with_input/3` in place of the input
+  to skip encoding and parsing it again, e.g. when a gateway checks one
+  request against several policies or engines. The handle works with any
+  engine. Documents nested more than 100 levels deep are rejected, but limits
+  from `set_limits/2` don't apply, since no engine is involved.
+
+  ## Examples
+
+      {:ok, request} = Regolix.parse_input(%{"user" => "alice", "path" => "/admin"})
+      {:ok, true} = Regolix.eval_query_with_input(authz, "data.authz.allow", request)
+      {:ok, false} = Regolix.eval_query_with_input(audit, "data.audit.flag", request)
+  """
+  @spec parse_input(json_encodable()) :: {:ok, parsed_input()} | {:error, Error.t()}
+  def parse_input(input) do
+    with {:ok, json} <- encode_json(input),
+         {:ok, parsed} <- Native.native_parse_input(json) do
+      {:ok, parsed}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Parses an input document once, for reuse across evaluations. Raises on error.
+  """
+  @spec parse_input!(json_encodable()) :: parsed_input()
+  def parse_input!(input) do
+    case parse_input(input) do
+      {:ok, parsed} -> parsed
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query against the given input without touching the engine's input.
+
+  The query runs against a private copy of the engine, so many processes can
+  evaluate concurrently against the same engine without coordinating
+  `set_input/2` and `eval_query/2` calls. Coverage is not recorded for these
+  evaluations. The input may be a handle from `parse_input/1`.
+
+  ## Examples
+
+      {:ok, true} = Regolix.eval_query_with_input(engine, "data.authz.allow", %{"user" => "admin"})
+  """
+  @spec eval_query_with_input(engine(), String.t(), json_encodable() | parsed_input()) ::
+          {:ok, eval_result()} | {:error, Error.t()}
+  def eval_query_with_input(engine, query, input) when is_reference(input) do
+    case Native.native_eval_query_with_parsed_input(engine, query, input) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  def eval_query_with_input(engine, query, input) do
+    with {:ok, json} <- encode_json(input),
+         {:ok, result} <- Native.native_eval_query_with_input(engine, query, json) do
+      {:ok, result}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query against the given input. Raises on error.
+  """
+  @spec eval_query_with_input!(engine(), String.t(), json_encodable() | parsed_input()) ::
+          eval_result()
+  def eval_query_with_input!(engine, query, input) do
+    case eval_query_with_input(engine, query, input) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a query with `$name` placeholders bound to the given parameters.
+
+  Parameters are bound as values, never spliced into the query text, so
+  user-supplied strings can be passed safely. Each placeholder is replaced
+  with a reference to its value, which lives in a private copy of the
+  engine's data. Placeholders inside string literals are left alone, and a
+  placeholder without a matching parameter is an `:eval_error`.
+
+  ## Examples
+
+      {:ok, true} =
+        Regolix.eval_query_params(engine, "data.authz.allow with input.user as $user", %{
+          "user" => "alice"
+        })
+  """
+  @spec eval_query_params(engine(), String.t(), %{String.t() => json_encodable()}) ::
+          {:ok, eval_result()} | {:error, Error.t()}
+  def eval_query_params(engine, query, params) when is_map(params) do
+    with {:ok, json} <- encode_json(params),
+         {:ok, result} <- Native.native_eval_query_params(engine, query, json) do
+      {:ok, result}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates a query with bound parameters. Raises on error.
+  """
+  @spec eval_query_params!(engine(), String.t(), %{String.t() => json_encodable()}) ::
+          eval_result()
+  def eval_query_params!(engine, query, params) do
+    case eval_query_params(engine, query, params) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a query with parts of `input` or `data` replaced for this call only.
+
+  Each key is a path such as `"data.ldap.groups"` or `"input.time"`, applied
+  as `with <path> as <value>` to every expression in the query. Overriding
+  the path of a rule replaces the rule's value, which makes this the way to
+  mock external data and helper rules in unit tests. The engine itself is
+  not changed.
+
+  ## Examples
+
+      {:ok, true} =
+        Regolix.eval_query_with_overrides(engine, "data.authz.allow", %{
+          "data.ldap.groups" => ["admins"],
+          "input.user" => "alice"
+        })
+  """
+  @spec eval_query_with_overrides(engine(), String.t(), %{String.t() => json_encodable()}) ::
+          {:ok, eval_result()} | {:error, Error.t()}
+  def eval_query_with_overrides(engine, query, overrides) when is_map(overrides) do
+    with {:ok, json} <- encode_json(overrides),
+         {:ok, result} <- Native.native_eval_query_with_overrides(engine, query, json) do
+      {:ok, result}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates a query with `input` or `data` overrides. Raises on error.
+  """
+  @spec eval_query_with_overrides!(engine(), String.t(), %{String.t() => json_encodable()}) ::
+          eval_result()
+  def eval_query_with_overrides!(engine, query, overrides) do
+    case eval_query_with_overrides(engine, query, overrides) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Starts evaluating a query against the given input without waiting for it.
+
+  The evaluation runs on a native worker pool, one worker per core, rather
+  than a dirty scheduler, so very long evaluations don't tie up either the
+  caller or the scheduler pool. When all workers are busy, calls queue up and
+  run in order. Returns a reference right away; the result arrives as a message
+  `{ref, {:ok, result}}` or `{ref, {:error, reason}}`. Use `await/2` to
+  receive it with errors converted to `Regolix.Error`.
+
+  Like `eval_query_with_input/3`, the engine's own input is left alone and no
+  coverage is recorded.
+
+  ## Examples
+
+      {:ok, ref} = Regolix.eval_async(engine, "data.authz.allow", %{"user" => "admin"})
+      {:ok, true} = Regolix.await(ref)
+  """
+  @spec eval_async(engine(), String.t(), json_encodable()) :: {:ok, reference()} | {:error, Error.t()}
+  def eval_async(engine, query, input) do
+    ref = make_ref()
+
+    with {:ok, json} <- encode_json(input),
+         {:ok, {}} <- Native.native_eval_async(engine, query, json, self(), ref) do
+      {:ok, ref}
+    else
+      {:error, {type, message}} ->
+        {:error, %Error{type: type, message: message}}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Waits for the result of `eval_async/3`.
+
+  Returns a `:timeout` error if no result arrives within `timeout`
+  milliseconds. The evaluation itself is not stopped, so its message may still
+  arrive later.
+  """
+  @spec await(reference(), timeout()) :: {:ok, eval_result()} | {:error, Error.t()}
+  def await(ref, timeout \\ 5000) when is_reference(ref) do
+    receive do
+      {^ref, {:ok, result}} -> {:ok, result}
+      {^ref, {:error, reason}} -> {:error, native_error(reason)}
+    after
+      timeout -> {:error, %Error{type: :timeout, message: "no result after #{timeout}ms"}}
+    end
+  end
+
+  @doc """
+  Evaluates a query once per input in a single native call.
+
+  Useful for bulk authorization checks, where crossing into the NIF for every
+  decision adds up. Like `eval_query_with_input/3`, the inputs are evaluated
+  against a private copy of the engine and coverage is not recorded.
+
+  Returns one `{:ok, result}` or `{:error, %Regolix.Error{}}` per input, in
+  order. The whole call only fails if an input can't be encoded or the engine
+  itself is unusable.
+
+  ## Examples
+
+      {:ok, [{:ok, true}, {:ok, :undefined}]} =
+        Regolix.eval_batch(engine, "data.authz.allow", [%{"user" => "admin"}, %{"user" => "guest"}])
+  """
+  @spec eval_batch(engine(), String.t(), [json_encodable()]) ::
+          {:ok, [{:ok, eval_result()} | {:error, Error.t()}]} | {:error, Error.t()}
+  def eval_batch(engine, query, inputs) when is_list(inputs) do
+    with {:ok, jsons} <- encode_all(inputs),
+         results when is_list(results) <- Native.native_eval_batch(engine, query, jsons) do
+      {:ok, Enum.map(results, &batch_result/1)}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates a query once per input. Raises if the batch as a whole fails;
+  per-input errors are still returned as `{:error, %Regolix.Error{}}`.
+  """
+  @spec eval_batch!(engine(), String.t(), [json_encodable()]) ::
+          [{:ok, eval_result()} | {:error, Error.t()}]
+  def eval_batch!(engine, query, inputs) do
+    case eval_batch(engine, query, inputs) do
+      {:ok, results} -> results
+      {:error, error} -> raise error
+    end
+  end
+
+  defp encode_all(inputs) do
+    Enum.reduce_while(inputs, {:ok, []}, fn input, {:ok, acc} ->
+      case encode_json(input) do
+        {:ok, json} -> {:cont, {:ok, [json | acc]}}
+        {:error, _} = error -> {:halt, error}
+      end
+    end)
+    |> case do
+      {:ok, jsons} -> {:ok, Enum.reverse(jsons)}
+      error -> error
+    end
+  end
+
+  defp batch_result({:ok, result}), do: {:ok, result}
+  defp batch_result({:error, reason}), do: {:error, native_error(reason)}
+
+  @doc """
+  Creates a pool of `size` independent copies of the engine.
+
+  Every evaluation on an engine starts by copying its current snapshot, which
+  costs more the larger the policies and data are. A pool hands each
+  `pool_eval/3` call an idle, already-copied replica instead. The pool is a snapshot: later changes to `engine` (policies,
+  data, options) don't reach it, so build a new pool after reloading.
+
+  ## Examples
+
+      {:ok, pool} = Regolix.new_pool(engine, System.schedulers_online())
+  """
+  @spec new_pool(engine(), pos_integer()) :: {:ok, reference()} | {:error, Error.t()}
+  def new_pool(engine, size) when is_integer(size) and size > 0 do
+    case Native.native_new_pool(engine, size) do
+      {:ok, pool} -> {:ok, pool}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Creates a pool of engine replicas. Raises on error.
+  """
+  @spec new_pool!(engine(), pos_integer()) :: reference()
+  def new_pool!(engine, size) do
+    case new_pool(engine, size) do
+      {:ok, pool} -> pool
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a query against the given input on an idle replica from the pool.
+
+  ## Examples
+
+      {:ok, true} = Regolix.pool_eval(pool, "data.authz.allow", %{"user" => "admin"})
+  """
+  @spec pool_eval(reference(), String.t(), json_encodable()) ::
+          {:ok, eval_result()} | {:error, Error.t()}
+  def pool_eval(pool, query, input) do
+    with {:ok, json} <- encode_json(input),
+         {:ok, result} <- Native.native_pool_eval(pool, query, json) do
+      {:ok, result}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates a query on an idle replica from the pool. Raises on error.
+  """
+  @spec pool_eval!(reference(), String.t(), json_encodable()) :: eval_result()
+  def pool_eval!(pool, query, input) do
+    case pool_eval(pool, query, input) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Returns the number of replicas in a pool.
+  """
+  @spec pool_size(reference()) :: pos_integer()
+  def pool_size(pool) do
+    Native.native_pool_size(pool)
+  end
+
+  @doc """
+  Evaluates a single rule by its full path.
+
+  This is the most direct way to get an authorization decision: the rule is
+  evaluated without going through the generic query parser. Returns `:undefined`
+  if the rule produces no value.
+
+  ## Examples
+
+      {:ok, true} = Regolix.eval_rule(engine, "data.authz.allow")
+  """
+  @spec eval_rule(engine(), String.t()) :: {:ok, eval_result()} | {:error, Error.t()}
+  def eval_rule(engine, path) do
+    case Native.native_eval_rule(engine, path) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a single rule by its full path. Raises on error.
+  """
+  @spec eval_rule!(engine(), String.t()) :: eval_result()
+  def eval_rule!(engine, path) do
+    case eval_rule(engine, path) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a whole package, returning its document of rule values.
+
+  The package can be given with or without the `data.` prefix. Returns
+  `:undefined` if no loaded policy defines anything under it.
+
+  ## Options
+
+    * `:exclude_internal` - leave out rules whose names start with `_`, and
+      test rules (`test_*` and `todo_test_*`). Defaults to `false`.
+
+  ## Examples
+
+      {:ok, %{"allow" => true}} = Regolix.eval_package(engine, "authz")
+  """
+  @spec eval_package(engine(), String.t(), keyword()) ::
+          {:ok, eval_result()} | {:error, Error.t()}
+  def eval_package(engine, package, opts \\ []) do
+    exclude_internal = Keyword.get(opts, :exclude_internal, false)
+
+    case Native.native_eval_package(engine, package, exclude_internal) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a whole package. Raises on error.
+  """
+  @spec eval_package!(engine(), String.t(), keyword()) :: eval_result()
+  def eval_package!(engine, package, opts \\ []) do
+    case eval_package(engine, package, opts) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a query that must produce exactly one boolean.
+
+  Returns an error if the query is undefined, produces multiple results or
+  a non-boolean value.
+
+  ## Examples
+
+      {:ok, true} = Regolix.eval_bool_query(engine, "data.authz.allow")
+  """
+  @spec eval_bool_query(engine(), String.t()) :: {:ok, boolean()} | {:error, Error.t()}
+  def eval_bool_query(engine, query) do
+    case Native.native_eval_bool_query(engine, query) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a query that must produce exactly one boolean. Raises on error.
+  """
+  @spec eval_bool_query!(engine(), String.t()) :: boolean()
+  def eval_bool_query!(engine, query) do
+    case eval_bool_query(engine, query) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates an allow decision.
+
+  An undefined result is `false`. Any non-boolean result is an error rather
+  than being silently treated as a denial.
+
+  ## Examples
+
+      {:ok, true} = Regolix.eval_allow_query(engine, "data.authz.allow")
+  """
+  @spec eval_allow_query(engine(), String.t()) :: {:ok, boolean()} | {:error, Error.t()}
+  def eval_allow_query(engine, query) do
+    case Native.native_eval_allow_query(engine, query) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates an allow decision. Raises on error.
+  """
+  @spec eval_allow_query!(engine(), String.t()) :: boolean()
+  def eval_allow_query!(engine, query) do
+    case eval_allow_query(engine, query) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a deny decision.
+
+  An undefined result is `false`. Set or array results (as produced by
+  `deny contains msg if ...` rules) are `true` when non-empty. Any other
+  non-boolean result is an error.
+
+  ## Examples
+
+      {:ok, false} = Regolix.eval_deny_query(engine, "data.authz.deny")
+  """
+  @spec eval_deny_query(engine(), String.t()) :: {:ok, boolean()} | {:error, Error.t()}
+  def eval_deny_query(engine, query) do
+    case Native.native_eval_deny_query(engine, query) do
+      {:ok, result} -> {:ok, result}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a deny decision. Raises on error.
+  """
+  @spec eval_deny_query!(engine(), String.t()) :: boolean()
+  def eval_deny_query!(engine, query) do
+    case eval_deny_query(engine, query) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @type traced_result :: %{result: eval_result(), prints: [String.t()]}
+
+  @doc """
+  Evaluates a Rego query and collects the output of `print/1` calls made
+  while evaluating it.
+
+  regorus doesn't produce OPA's explain events (enter, eval, fail, redo), so
+  `print` is the way to trace an evaluation: add `print(...)` calls to the
+  rules under investigation and they are returned in order, each prefixed with
+  the file and line it came from, instead of going to stderr.
+
+  The query runs against a copy of the engine, so coverage is not recorded.
+
+  ## Examples
+
+      {:ok, %{result: :undefined, prints: ["authz.rego:5: role viewer"]}} =
+        Regolix.eval_query_traced(engine, "data.authz.allow")
+  """
+  @spec eval_query_traced(engine(), String.t()) :: {:ok, traced_result()} | {:error, Error.t()}
+  def eval_query_traced(engine, query) do
+    case Native.native_eval_query_traced(engine, query) do
+      {:ok, traced} -> {:ok, traced}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and collects its `print/1` output. Raises on error.
+  """
+  @spec eval_query_traced!(engine(), String.t()) :: traced_result()
+  def eval_query_traced!(engine, query) do
+    case eval_query_traced(engine, query) do
+      {:ok, traced} -> traced
+      {:error, error} -> raise error
+    end
+  end
+
+  @type query_result :: %{
+          expressions: [%{value: eval_result(), text: String.t()}],
+          bindings: %{String.t() => json_encodable()} | :undefined
+        }
+
+  @doc """
+  Evaluates a Rego query and returns the full result set.
+
+  Unlike `eval_query/2`, which only returns the first expression of the first
+  result, this returns every result with all of its expressions and variable
+  bindings. Useful for iteration-style queries.
+
+  ## Examples
+
+      {:ok, results} = Regolix.eval_query_full(engine, "x = data.roles[_]")
+      # => [
+      #   %{expressions: [%{value: true, text: "x = data.roles[_]"}], bindings: %{"x" => "admin"}},
+      #   %{expressions: [%{value: true, text: "x = data.roles[_]"}], bindings: %{"x" => "viewer"}}
+      # ]
+  """
+  @spec eval_query_full(engine(), String.t()) :: {:ok, [query_result()]} | {:error, Error.t()}
+  def eval_query_full(engine, query) do
+    case Native.native_eval_query_full(engine, query) do
+      {:ok, results} -> {:ok, results}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and returns the full result set. Raises on error.
+  """
+  @spec eval_query_full!(engine(), String.t()) :: [query_result()]
+  def eval_query_full!(engine, query) do
+    case eval_query_full(engine, query) do
+      {:ok, results} -> results
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and returns the variable bindings of every result.
+
+  A shorthand for iteration-style queries, where the bindings are what you
+  want and the expression values are just `true`. Returns an empty list when
+  the query has no results.
+
+  ## Examples
+
+      {:ok, bindings} = Regolix.eval_bindings(engine, "x := data.roles[_]")
+      # => [%{"x" => "admin"}, %{"x" => "viewer"}]
+  """
+  @spec eval_bindings(engine(), String.t()) ::
+          {:ok, [%{String.t() => json_encodable()}]} | {:error, Error.t()}
+  def eval_bindings(engine, query) do
+    case Native.native_eval_bindings(engine, query) do
+      {:ok, bindings} -> {:ok, bindings}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Evaluates a Rego query and returns the bindings of every result. Raises on error.
+  """
+  @spec eval_bindings!(engine(), String.t()) :: [%{String.t() => json_encodable()}]
+  def eval_bindings!(engine, query) do
+    case eval_bindings(engine, query) do
+      {:ok, bindings} -> bindings
+      {:error, error} -> raise error
+    end
+  end
+
+  @type rule_info :: %{
+          name: String.t(),
+          description: String.t(),
+          start_line: pos_integer(),
+          end_line: pos_integer(),
+          annotations: map() | nil,
+          comments: [String.t()],
+          kind: :complete | :partial_set | :partial_object | :function | :default,
+          default: term(),
+          ref: String.t()
+        }
+
+  @doc """
+  Returns metadata about rules defined in loaded policies.
+
+  Parses the policy sources to extract rule names, descriptions (from comments),
+  and line ranges. Useful for mapping coverage line numbers to human-readable rule names.
+
+  Ref-head rules are named by their full ref (e.g. `"limits.max_users"`), and a
+  rule's line range covers its whole `else` chain.
+
+  The description is the comment block directly above the rule, joined into
+  one line, without empty comments or `# ====` style dividers. `:comments`
+  holds that block's lines as written, `#` included.
+
+  `:kind` says what the definition produces: `:complete` for a single value,
+  `:partial_set` for `contains` rules, `:partial_object` for `p[key] := value`
+  rules, `:function`, or `:default`. Default rules have their value as
+  `:default`; other rules have `default: nil`. `:ref` is the rule's full path,
+  e.g. `"data.authz.allow"`.
+
+  Rules preceded by an OPA `# METADATA` block get its YAML as `:annotations`
+  (string keys, e.g. `"title"`, `"description"`, `"custom"`, `"schemas"`), and
+  take their description from it. Other rules have `annotations: nil`.
+
+  ## Examples
+
+      rules = Regolix.get_rules(engine)
+      # => %{
+      #   "policy.rego" => [
+      #     %{name: "allow", description: "Allow if not denied", start_line: 10, end_line: 15},
+      #     %{name: "deny", description: "Deny sanctioned countries", start_line: 20, end_line: 25}
+      #   ]
+      # }
+  """
+  @spec get_rules(engine()) :: {:ok, %{String.t() => [rule_info()]}} | {:error, Error.t()}
+  def get_rules(engine) do
+    case Native.native_get_rules(engine) do
+      {:ok, rules} -> {:ok, rules}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns metadata about rules defined in loaded policies. Raises on error.
+  """
+  @spec get_rules!(engine()) :: %{String.t() => [rule_info()]}
+  def get_rules!(engine) do
+    case get_rules(engine) do
+      {:ok, rules} -> rules
+      {:error, error} -> raise error
+    end
+  end
+
+  @type entrypoint :: %{
+          name: String.t(),
+          path: String.t(),
+          file: String.t(),
+          line: pos_integer(),
+          description: String.t()
+        }
+
+  @doc """
+  Lists the rules the loaded policies declare as entrypoints, sorted by name.
+
+  A rule is an entrypoint when its `# METADATA` block has `entrypoint: true`.
+  Its name is the rule path without `data.`, separated by slashes as in OPA
+  bundles, e.g. `"authz/allow"`.
+
+  ## Examples
+
+      # METADATA
+      # description: Whether the request is allowed
+      # entrypoint: true
+      allow if input.user == "admin"
+
+      {:ok, [%{name: "authz/allow", path: "data.authz.allow"}]} =
+        Regolix.get_entrypoints(engine)
+  """
+  @spec get_entrypoints(engine()) :: {:ok, [entrypoint()]} | {:error, Error.t()}
+  def get_entrypoints(engine) do
+    case Native.native_get_entrypoints(engine) do
+      {:ok, entrypoints} -> {:ok, entrypoints}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Evaluates an entrypoint against `input`, leaving the engine's input alone.
+
+  `name` is an entrypoint name as listed by `get_entrypoints/1`, or its full
+  rule path. Entrypoints are looked up on each call, so applications can bind
+  to a declared decision point rather than a hard-coded rule path. Returns an
+  `:eval_error` if no entrypoint matches.
+
+  ## Examples
+
+      {:ok, true} = Regolix.eval_entrypoint(engine, "authz/allow", %{"user" => "admin"})
+  """
+  @spec eval_entrypoint(engine(), String.t(), json_encodable()) ::
+          {:ok, eval_result()} | {:error, Error.t()}
+  def eval_entrypoint(engine, name, input) do
+    with {:ok, json} <- encode_json(input),
+         {:ok, result} <- Native.native_eval_entrypoint(engine, name, json) do
+      {:ok, result}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates an entrypoint against `input`. Raises on error.
+  """
+  @spec eval_entrypoint!(engine(), String.t(), json_encodable()) :: eval_result()
+  def eval_entrypoint!(engine, name, input) do
+    case eval_entrypoint(engine, name, input) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Lists the `data.*` paths each rule reads, by policy file and rule name.
+
+  Use it to check which data documents must be provisioned before a policy
+  is enabled. References to loaded rules are left out, but data stored under
+  a package, such as `data.authz.config` next to `package authz`, is kept.
+  `import data.x as y` aliases are resolved, as are local variables assigned
+  a reference, so `u := data.users; u.alice` reads `data.users.alice`. Paths
+  come from the parsed policies, so a lookup built at runtime such as
+  `data.users[input.name]` is reported up to its first dynamic segment,
+  `data.users`.
+
+  ## Examples
+
+      {:ok, deps} = Regolix.data_deps(engine)
+      # => %{"authz.rego" => %{"allow" => ["data.roles", "data.users"]}}
+  """
+  @spec data_deps(engine()) :: {:ok, %{String.t() => %{String.t() => [String.t()]}}} | {:error, Error.t()}
+  def data_deps(engine) do
+    case Native.native_data_deps(engine) do
+      {:ok, deps} -> {:ok, deps}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Lists the `input.*` paths each rule reads, by policy file and rule name.
+
+  Useful for building minimal inputs, e.g. in an API gateway, and for
+  noticing when a policy change starts requiring a new field. Works like
+  `data_deps/1`: `import input.x as y` aliases are resolved and a lookup such
+  as `input.resources[i].owner` is reported as `input.resources`.
+
+  ## Examples
+
+      {:ok, deps} = Regolix.input_deps(engine)
+      # => %{"authz.rego" => %{"allow" => ["input.action", "input.user"]}}
+  """
+  @spec input_deps(engine()) :: {:ok, %{String.t() => %{String.t() => [String.t()]}}} | {:error, Error.t()}
+  def input_deps(engine) do
+    case Native.native_input_deps(engine) do
+      {:ok, deps} -> {:ok, deps}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Returns a template of the input the loaded policies expect, with every
+  `input.*` path they read.
+
+  Leaves are placeholders for the type the policies seem to expect, guessed
+  from how each path is used: `"string"`, `"number"`, `"boolean"` or `"null"`
+  when compared with a literal, `"array"` when iterated or indexed by number,
+  `"object"` when looked up by a variable, and `"any"` when there's no clue
+  or the clues disagree. Paths are found as in `input_deps/1`.
+
+  ## Examples
+
+      {:ok, skeleton} = Regolix.input_skeleton(engine)
+      # => %{"user" => %{"role" => "string", "groups" => "array"}, "amount" => "number"}
+  """
+  @spec input_skeleton(engine()) :: {:ok, map()} | {:error, Error.t()}
+  def input_skeleton(engine) do
+    case Native.native_input_skeleton(engine) do
+      {:ok, skeleton} -> {:ok, skeleton}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @type rule_graph :: %{
+          nodes: [%{id: String.t(), kind: :rule | :data, depth: non_neg_integer()}],
+          edges: [%{from: String.t(), to: String.t()}]
+        }
+
+  @doc """
+  Returns the dependency graph of the loaded rules, with an edge from each
+  rule to every rule and data document it reads.
+
+  Rules are identified by their full path, such as `"data.authz.allow"`, and
+  data documents no rule produces by the path read, as in `data_deps/1`. Each
+  rule's `:depth` is the length of the longest chain of rules below it, which
+  makes unexpectedly deep chains easy to spot.
+
+  With `:dot` the graph is returned as Graphviz source instead, rules drawn
+  as boxes and data documents as cylinders.
+
+  ## Examples
+
+      {:ok, %{nodes: nodes, edges: edges}} = Regolix.rule_graph(engine)
+      {:ok, dot} = Regolix.rule_graph(engine, :dot)
+      File.write!("rules.dot", dot)
+  """
+  @spec rule_graph(engine(), :map | :dot) :: {:ok, rule_graph() | String.t()} | {:error, Error.t()}
+  def rule_graph(engine, format \\ :map) when format in [:map, :dot] do
+    case Native.native_rule_graph(engine, format) do
+      {:ok, graph} -> {:ok, graph}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @type rule_complexity :: %{
+          file: String.t(),
+          name: String.t(),
+          line: pos_integer(),
+          bodies: non_neg_integer(),
+          comprehensions: non_neg_integer(),
+          depth: non_neg_integer(),
+          data_refs: non_neg_integer(),
+          input_refs: non_neg_integer()
+        }
+
+  @doc """
+  Returns complexity metrics for each rule definition of the loaded policies,
+  sorted by file and line.
+
+    * `:bodies` - number of bodies, 0 for `x := 1` and one more per `else`
+    * `:comprehensions` - array, set and object comprehensions
+    * `:depth` - deepest nesting of bodies, comprehensions and collection literals
+    * `:data_refs`, `:input_refs` - references to `data` and `input`, counted
+      as in `data_deps/1` and `input_deps/1`
+
+  Useful for failing CI when a change makes a rule too complex.
+
+  ## Examples
+
+      {:ok, metrics} = Regolix.rule_complexity(engine)
+      Enum.filter(metrics, &(&1.depth > 4))
+  """
+  @spec rule_complexity(engine()) :: {:ok, [rule_complexity()]} | {:error, Error.t()}
+  def rule_complexity(engine) do
+    case Native.native_rule_complexity(engine) do
+      {:ok, metrics} -> {:ok, metrics}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Parses a policy and returns its abstract syntax tree, without an engine.
+
+  The AST is the one regorus builds internally, decoded into nested maps with
+  string keys (or left as a JSON string with `format: :json`). Useful for
+  linters, documentation generators and other tooling.
+
+  ## Options
+
+    * `:name` - file name used in the AST and in error locations (default `"policy.rego"`)
+    * `:rego_version` - `:v1` (default) or `:v0`, see `set_rego_version/2`
+    * `:format` - `:map` (default) or `:json`
+
+  ## Examples
+
+      {:ok, ast} = Regolix.parse_policy("package authz\\nallow := true")
+  """
+  @spec parse_policy(String.t(), keyword()) :: {:ok, map() | String.t()} | {:error, Error.t()}
+  def parse_policy(source, opts \\ []) when is_binary(source) do
+    name = Keyword.get(opts, :name, "policy.rego")
+    version = Keyword.get(opts, :rego_version, :v1)
+
+    with {:ok, json} <- Native.native_parse_policy(name, source, version) do
+      case Keyword.get(opts, :format, :map) do
+        :json -> {:ok, json}
+        :map -> {:ok, Jason.decode!(json)}
+      end
+    else
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Parses a policy and returns its abstract syntax tree. Raises on error.
+  """
+  @spec parse_policy!(String.t(), keyword()) :: map() | String.t()
+  def parse_policy!(source, opts \\ []) do
+    case parse_policy(source, opts) do
+      {:ok, ast} -> ast
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Renders a value as Rego or JSON text, e.g. for REPL output or golden files.
+
+  Takes values as the eval functions return them: maps, lists, strings,
+  numbers, booleans and `nil`, plus sets as `{:set, list}` or `MapSet`,
+  `{:decimal, string}` numbers and `:undefined`.
+
+    * `:rego` (default) - Rego literal syntax: sets as `{1, 2}` (or `set()`
+      when empty), `null`, and `undefined`
+    * `:json` - compact JSON, with sets as arrays
+    * `:pretty_json` - indented JSON
+
+  JSON has no undefined, so rendering `:undefined` as JSON is an error.
+
+  ## Examples
+
+      Regolix.value_to_string(%{"roles" => {:set, ["admin", "dev"]}})
+      # => {:ok, ~s({"roles": {"admin", "dev"}})}
+
+      Regolix.value_to_string(%{"ok" => true}, :json)
+      # => {:ok, ~s({"ok":true})}
+  """
+  @spec value_to_string(term(), :rego | :json | :pretty_json) :: {:ok, String.t()} | {:error, Error.t()}
+  def value_to_string(value, format \\ :rego) when format in [:rego, :json, :pretty_json] do
+    case Native.native_value_to_string(value, format) do
+      {:ok, text} -> {:ok, text}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Renders a value as Rego or JSON text. Raises on error.
+  """
+  @spec value_to_string!(term(), :rego | :json | :pretty_json) :: String.t()
+  def value_to_string!(value, format \\ :rego) do
+    case value_to_string(value, format) do
+      {:ok, text} -> text
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Checks that a query parses, without an engine and without evaluating it.
+
+  Useful for validating stored queries, such as user-configured rules, when
+  they are saved. Returns a `:parse_error` with the line and column of the
+  first problem. Only syntax is checked; a query that parses can still fail
+  at evaluation, e.g. on an unsafe variable.
+
+  ## Options
+
+    * `:rego_version` - `:v1` (default) or `:v0`, see `set_rego_version/2`
+
+  ## Examples
+
+      :ok = Regolix.validate_query("data.authz.allow with input.user as \"alice\"")
+      {:error, %Regolix.Error{type: :parse_error, line: 1}} = Regolix.validate_query("data.authz[")
+  """
+  @spec validate_query(String.t(), keyword()) :: :ok | {:error, Error.t()}
+  def validate_query(query, opts \\ []) when is_binary(query) do
+    version = Keyword.get(opts, :rego_version, :v1)
+
+    case Native.native_validate_query(query, version) do
+      {:ok, {}} -> :ok
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @type lint_finding :: %{
+          check: String.t(),
+          severity: :error | :warning,
+          message: String.t(),
+          line: pos_integer(),
+          column: pos_integer()
+        }
+
+  @doc """
+  Lints a policy without loading it into an engine.
+
+  Returns findings sorted by position. The checks are:
+
+    * `"unused-variable"` - a local assigned with `:=` and never used
+    * `"shadowed-document"` - a rule named `input` or `data`
+    * `"shadowed-builtin"` - a rule named after a builtin such as `count`
+    * `"deprecated-import"` - `import future.keywords`, unnecessary in Rego v1
+    * `"deprecated-builtin"` - builtins like `any`, `all` and `re_match`
+    * `"constant-condition"` - conditions such as `1 == 1` that never vary
+
+  These are lightweight, token-based checks meant for quick feedback; a policy
+  that fails to parse returns a `:parse_error` instead.
+
+  ## Options
+
+    * `:name` - file name used in error locations (default `"policy.rego"`)
+    * `:disable` - list of check names to skip
+
+  ## Examples
+
+      {:ok, [%{check: "unused-variable", line: 4}]} = Regolix.lint_policy(source)
+  """
+  @spec lint_policy(String.t(), keyword()) :: {:ok, [lint_finding()]} | {:error, Error.t()}
+  def lint_policy(source, opts \\ []) when is_binary(source) do
+    name = Keyword.get(opts, :name, "policy.rego")
+    lint_opts = %{disabled: Keyword.get(opts, :disable, [])}
+
+    case Native.native_lint_policy(name, source, lint_opts) do
+      {:ok, findings} -> {:ok, findings}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Lints a policy without loading it into an engine. Raises on error.
+  """
+  @spec lint_policy!(String.t(), keyword()) :: [lint_finding()]
+  def lint_policy!(source, opts \\ []) do
+    case lint_policy(source, opts) do
+      {:ok, findings} -> findings
+      {:error, error} -> raise error
+    end
+  end
+
+  @type migration_issue :: %{
+          check: String.t(),
+          message: String.t(),
+          line: pos_integer(),
+          column: pos_integer(),
+          suggestion: String.t()
+        }
+
+  @doc """
+  Lists the constructs in a Rego v0 policy that must change for Rego v1.
+
+  The checks are:
+
+    * `"missing-if"` - a rule body without the `if` keyword
+    * `"missing-contains"` - a `name[x] { ... }` partial set rule
+    * `"future-keywords-import"` - `import future.keywords`, unnecessary in v1
+    * `"reserved-keyword"` - a rule or variable named `contains`, `every`, `if` or `in`
+    * `"deprecated-builtin"` - builtins removed in v1, such as `any` and `re_match`
+    * `"parse-error"` - the policy fails to parse as v1 for some other reason
+
+  Each issue carries a suggested rewrite. An empty list means the policy is
+  ready for v1. The policy itself must parse as either v0 or v1.
+
+  ## Options
+
+    * `:name` - file name used in error locations (default `"policy.rego"`)
+
+  ## Examples
+
+      {:ok, [%{check: "missing-if", line: 3, suggestion: "insert `if` before `{`"}]} =
+        Regolix.check_rego_v1(source)
+  """
+  @spec check_rego_v1(String.t(), keyword()) :: {:ok, [migration_issue()]} | {:error, Error.t()}
+  def check_rego_v1(source, opts \\ []) when is_binary(source) do
+    name = Keyword.get(opts, :name, "policy.rego")
+
+    case Native.native_check_rego_v1(name, source) do
+      {:ok, issues} -> {:ok, issues}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Lists the constructs in a policy that must change for Rego v1. Raises on error.
+  """
+  @spec check_rego_v1!(String.t(), keyword()) :: [migration_issue()]
+  def check_rego_v1!(source, opts \\ []) do
+    case check_rego_v1(source, opts) do
+      {:ok, issues} -> issues
+      {:error, error} -> raise error
+    end
+  end
+
+  @type policy_diff :: %{added: [String.t()], removed: [String.t()], changed: [String.t()]}
+
+  @doc """
+  Compares two versions of a policy rule by rule.
+
+  Rules are identified by their ref within the package (`allow`, `deny`,
+  `a.b.c`), and all definitions sharing a ref are compared together, so adding
+  a `deny contains ...` clause shows up as `deny` changing. Whitespace and
+  comments are ignored. Lists are sorted by ref.
+
+  ## Examples
+
+      {:ok, %{added: ["audit"], removed: [], changed: ["allow"]}} =
+        Regolix.diff_policies(old_source, new_source)
+  """
+  @spec diff_policies(String.t(), String.t()) :: {:ok, policy_diff()} | {:error, Error.t()}
+  def diff_policies(old_source, new_source) when is_binary(old_source) and is_binary(new_source) do
+    case Native.native_diff_policies(old_source, new_source) do
+      {:ok, diff} -> {:ok, diff}
+      {:error, reason} -> {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Compares two versions of a policy rule by rule. Raises on error.
+  """
+  @spec diff_policies!(String.t(), String.t()) :: policy_diff()
+  def diff_policies!(old_source, new_source) do
+    case diff_policies(old_source, new_source) do
+      {:ok, diff} -> diff
+      {:error, error} -> raise error
+    end
+  end
+
+  @type shadow_report :: %{
+          result: eval_result(),
+          agreed: boolean(),
+          differences: [%{path: String.t(), primary: term(), candidate: term()}],
+          candidate_error: Error.t() | nil
+        }
+
+  @doc """
+  Evaluates a query on two engines, returning the primary's result along with
+  whether the candidate agreed.
+
+  Use this to dark-launch a policy change: serve decisions from the current
+  engine while checking what a candidate engine with the new policies would
+  have decided.
+
+  `:differences` lists the innermost places the results differ, each with a
+  JSON Pointer `:path` (`""` for the whole result) and the value on each side.
+  Objects are compared key by key and arrays index by index; a side missing a
+  key is `:undefined` there.
+
+  An error from the primary is returned as usual. An error from the candidate
+  is reported as `:candidate_error`, with `:agreed` false.
+
+  ## Options
+
+    * `:input` - input for both engines in place of their own
+
+  ## Examples
+
+      {:ok, %{result: true, agreed: false, differences: [%{path: ""}]}} =
+        Regolix.shadow_eval(current, candidate, "data.authz.allow", input: %{"user" => "bob"})
+  """
+  @spec shadow_eval(engine(), engine(), String.t(), keyword()) ::
+          {:ok, shadow_report()} | {:error, Error.t()}
+  def shadow_eval(primary, candidate, query, opts \\ [])
+      when is_binary(query) and is_list(opts) do
+    with {:ok, json} <- encode_input_option(opts),
+         {:ok, report} <- Native.native_shadow_eval(primary, candidate, query, json) do
+      {:ok, Map.update!(report, :candidate_error, &(&1 && native_error(&1)))}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates a query on two engines and compares the results. Raises on error.
+  """
+  @spec shadow_eval!(engine(), engine(), String.t(), keyword()) :: shadow_report()
+  def shadow_eval!(primary, candidate, query, opts \\ []) do
+    case shadow_eval(primary, candidate, query, opts) do
+      {:ok, report} -> report
+      {:error, error} -> raise error
+    end
+  end
+
+  @type provenance :: %{
+          label: String.t() | nil,
+          revision: non_neg_integer(),
+          bundles: %{String.t() => String.t()},
+          policies: [String.t()]
+        }
+
+  @doc """
+  Evaluates a query and returns its result along with where the decision came
+  from, for audit logs.
+
+  The provenance holds:
+
+    * `:label` - the engine's label, see `set_label/2`
+    * `:revision` - how many times the engine's policies, data or settings
+      had changed before the decision
+    * `:policies` - the policy files with lines evaluated for the decision
+    * `:bundles` - the revision of each loaded bundle whose roots hold one of
+      those policies, by bundle name
+
+  Contributing policies are found by evaluating a copy of the engine with
+  coverage enabled, so this is slower than `eval_query/2` and skips the
+  decision cache. The engine's own coverage data is left alone.
+
+  ## Options
+
+    * `:input` - input to evaluate in place of the engine's own
+
+  ## Examples
+
+      {:ok, %{result: true, provenance: %{policies: ["authz.rego"], bundles: %{"authz" => "v7"}}}} =
+        Regolix.eval_with_provenance(engine, "data.authz.allow", input: %{"user" => "alice"})
+  """
+  @spec eval_with_provenance(engine(), String.t(), keyword()) ::
+          {:ok, %{result: eval_result(), provenance: provenance()}} | {:error, Error.t()}
+  def eval_with_provenance(engine, query, opts \\ []) when is_binary(query) and is_list(opts) do
+    with {:ok, json} <- encode_input_option(opts),
+         {:ok, result} <- Native.native_eval_with_provenance(engine, query, json) do
+      {:ok, result}
+    else
+      {:error, {_type, _detail} = reason} ->
+        {:error, native_error(reason)}
+
+      {:error, %Jason.EncodeError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+
+      {:error, %Protocol.UndefinedError{} = e} ->
+        {:error, %Error{type: :json_error, message: Exception.message(e)}}
+    end
+  end
+
+  @doc """
+  Evaluates a query and returns its result with provenance. Raises on error.
+  """
+  @spec eval_with_provenance!(engine(), String.t(), keyword()) ::
+          %{result: eval_result(), provenance: provenance()}
+  def eval_with_provenance!(engine, query, opts \\ []) do
+    case eval_with_provenance(engine, query, opts) do
+      {:ok, result} -> result
+      {:error, error} -> raise error
+    end
+  end
+
+  @doc """
+  Records every successful evaluation of the engine to a file, for `replay/2`.
+
+  Each decision is written as a line of JSON holding the query, the input, the
+  engine's revision (a count of the changes made to it) and the result, and
+  the file is gzipped. Evaluations through `eval_query/3` and
+  `eval_query_with_input/3` are recorded, including cached ones.
+
+  The file at `path` is replaced, as is any recording already running. The
+  file is only complete once `stop_recording/1` is called or the engine is
+  garbage collected. If a write fails, recording stops and evaluations carry
+  on.
+
+  ## Examples
+
+      {:ok, engine} = Regolix.start_recording(engine, "decisions.jsonl.gz")
+  """
+  @spec start_recording(engine(), Path.t()) :: {:ok, engine()} | {:error, Error.t()}
+  def start_recording(engine, path) do
+    case Native.native_start_recording(engine, to_string(path)) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Stops the recording started by `start_recording/2` and finishes the file.
+  """
+  @spec stop_recording(engine()) :: {:ok, engine()} | {:error, Error.t()}
+  def stop_recording(engine) do
+    case Native.native_stop_recording(engine) do
+      {:ok, {}} -> {:ok, engine}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @type replay_report :: %{
+          total: non_neg_integer(),
+          diverged: [
+            %{
+              line: pos_integer(),
+              query: String.t(),
+              revision: non_neg_integer(),
+              recorded: eval_result(),
+              replayed: eval_result() | nil,
+              error: Error.t() | nil
+            }
+          ]
+        }
+
+  @doc """
+  Re-evaluates the decisions in a recording from `start_recording/2` against
+  the engine, and reports those whose result changed.
+
+  Use this to check a policy change against real traffic before deploying it.
+  Each decision is evaluated with its recorded input; one recorded without
+  input uses the engine's own. A decision that now fails has `:replayed` set
+  to `nil` and the failure in `:error`.
+
+  ## Examples
+
+      {:ok, %{total: 1200, diverged: []}} = Regolix.replay(candidate, "decisions.jsonl.gz")
+  """
+  @spec replay(engine(), Path.t()) :: {:ok, replay_report()} | {:error, Error.t()}
+  def replay(engine, path) do
+    case Native.native_replay(engine, to_string(path)) do
+      {:ok, report} ->
+        diverged =
+          Enum.map(report.diverged, fn divergence ->
+            Map.update!(divergence, :error, &(&1 && native_error(&1)))
+          end)
+
+        {:ok, %{report | diverged: diverged}}
+
+      {:error, reason} ->
+        {:error, native_error(reason)}
+    end
+  end
+
+  @doc """
+  Re-evaluates the decisions in a recording against the engine. Raises on error.
+  """
+  @spec replay!(engine(), Path.t()) :: replay_report()
+  def replay!(engine, path) do
+    case replay(engine, path) do
+      {:ok, report} -> report
+      {:error, error} -> raise error
+    end
+  end
+
+  @type test_result :: %{
+          name: String.t(),
+          package: String.t(),
+          file: String.t(),
+          line: pos_integer(),
+          status: :pass | :fail | :error | :skip,
+          duration_ns: non_neg_integer(),
+          message: String.t() | nil
+        }
+
+  @doc """
+  Runs the Rego unit tests loaded in the engine, like `opa test`.
+
+  Every rule whose name starts with `test_` is evaluated. It passes when it is
+  `true`, fails when it is `false` or undefined, and errors when evaluation
+  fails. Rules starting with `todo_test_` are reported as `:skip` without
+  running. Results are ordered by file and position.
+
+  All tests run against one copy of the engine, so when coverage is enabled
+  the report afterwards covers the test run.
+
+  ## Options
+
+    * `:filter` - only run tests whose full name contains this string
+
+  ## Examples
+
+      {:ok, results} = Regolix.run_tests(engine)
+      for %{status: :fail} = result <- results, do: IO.puts("#{result.file}:#{result.line} #{result.name}")
+  """
+  @spec run_tests(engine(), keyword()) :: {:ok, [test_result()]} | {:error, Error.t()}
+  def run_tests(engine, opts \\ []) do
+    case Native.native_run_tests(engine, %{filter: Keyword.get(opts, :filter)}) do
+      {:ok, results} -> {:ok, results}
+      {:error, {type, message}} -> {:error, %Error{type: type, message: message}}
+    end
+  end
+
+  @doc """
+  Runs the Rego unit tests loaded in the engine. Raises on error.
+  """
+  @spec run_tests!(engine(), keyword()) :: [test_result()]
+  def run_tests!(engine, opts \\ []) do
+    case run_tests(engine, opts) do
+      {:ok, results} -> results
+      {:error, error} -> raise error
+    end
+  end
+
+  defp decode_overrides(opts) do
+    opts
+    |> Keyword.take([:sets, :numbers, :keys, :undefined, :max_depth])
+    |> Map.new(fn
+      {:max_depth, :infinity} -> {:max_depth, nil}
+      option -> option
+    end)
+  end
+
+  defp encode_json(term) do
+    Jason.encode(term)
+  end
+
+  # Jason doesn't say where an invalid binary is, so look for it
+  defp invalid_utf8_error(term, root) do
+    case invalid_utf8_path(term, root) do
+      nil -> nil
+      path -> %Error{type: :json_error, message: "#{path} is not valid UTF-8", path: path}
+    end
+  end
+
+  defp invalid_utf8_path(binary, path) when is_binary(binary) do
+    if String.valid?(binary), do: nil, else: path
+  end
+
+  defp invalid_utf8_path(%_{}, _path), do: nil
+
+  defp invalid_utf8_path(map, path) when is_map(map) do
+    Enum.find_value(map, fn {key, value} ->
+      if is_binary(key) and not String.valid?(key) do
+        path
+      else
+        invalid_utf8_path(value, path_key(path, key))
+      end
+    end)
+  end
+
+  defp invalid_utf8_path(list, path) when is_list(list) do
+    list
+    |> Enum.with_index()
+    |> Enum.find_value(fn {item, i} -> invalid_utf8_path(item, "#{path}[#{i}]") end)
+  end
+
+  defp invalid_utf8_path(_term, _path), do: nil
+
+  defp path_key(path, key) when is_atom(key), do: path_key(path, Atom.to_string(key))
+
+  defp path_key(path, key) when is_binary(key) do
+    if key =~ ~r/^[A-Za-z_][A-Za-z0-9_]*$/ do
+      "#{path}.#{key}"
+    else
+      "#{path}[#{inspect(key)}]"
+    end
+  end
+
+  defp path_key(path, key), do: "#{path}[#{inspect(key)}]"
+
+  defp replace_invalid_utf8(binary) when is_binary(binary) do
+    if String.valid?(binary), do: binary, else: replace_invalid_utf8(binary, "")
+  end
+
+  defp replace_invalid_utf8(%_{} = struct), do: struct
+
+  defp replace_invalid_utf8(map) when is_map(map) do
+    Map.new(map, fn {key, value} -> {replace_invalid_utf8(key), replace_invalid_utf8(value)} end)
+  end
+
+  defp replace_invalid_utf8(list) when is_list(list), do: Enum.map(list, &replace_invalid_utf8/1)
+  defp replace_invalid_utf8(term), do: term
+
+  defp replace_invalid_utf8(<<char::utf8, rest::binary>>, acc),
+    do: replace_invalid_utf8(rest, <<acc::binary, char::utf8>>)
+
+  defp replace_invalid_utf8(<<>>, acc), do: acc
+
+  defp replace_invalid_utf8(<<lead, rest::binary>>, acc) do
+    rest = skip_continuations(rest, continuations(lead))
+    replace_invalid_utf8(rest, <<acc::binary, 0xFFFD::utf8>>)
+  end
+
+  # The bytes that may follow `lead` in a well-formed sequence. An invalid
+  # sequence runs as far as they match, the way Rust's
+  # `String::from_utf8_lossy` splits it, so `set_input/3` and
+  # `set_input_etf/3` replace the same bytes.
+  defp continuations(lead) when lead in 0xC2..0xDF, do: [{0x80, 0xBF}]
+  defp continuations(0xE0), do: [{0xA0, 0xBF}, {0x80, 0xBF}]
+  defp continuations(0xED), do: [{0x80, 0x9F}, {0x80, 0xBF}]
+  defp continuations(lead) when lead in 0xE1..0xEF, do: [{0x80, 0xBF}, {0x80, 0xBF}]
+  defp continuations(0xF0), do: [{0x90, 0xBF}, {0x80, 0xBF}, {0x80, 0xBF}]
+  defp continuations(0xF4), do: [{0x80, 0x8F}, {0x80, 0xBF}, {0x80, 0xBF}]
+  defp continuations(lead) when lead in 0xF1..0xF3, do: [{0x80, 0xBF}, {0x80, 0xBF}, {0x80, 0xBF}]
+  defp continuations(_lead), do: []
+
+  defp skip_continuations(<<byte, rest::binary>>, [{min, max} | ranges])
+       when byte >= min and byte <= max,
+       do: skip_continuations(rest, ranges)
+
+  defp skip_continuations(rest, _ranges), do: rest
+
+  # Errors about part of a document come back as %{message, path}
+  defp native_error({type, %{message: message, path: path}}) do
+    %Error{type: type, message: message, path: path}
+  end
+
+  # Located errors come back as %{kind, message, file, line, column, snippet}
+  defp native_error({type, %{message: message} = details}) do
+    %Error{
+      type: type,
+      message: message,
+      file: details.file,
+      line: details.line,
+      column: details.column,
+      snippet: details.snippet
+    }
+  end
+
+  # Errors meant to be matched on come back as a bare atom, e.g. :too_deep
+  defp native_error({type, reason}) when is_atom(reason) do
+    %Error{type: type, reason: reason, message: reason_message(reason)}
+  end
+
+  defp native_error({type, message}) do
+    %Error{type: type, message: message}
+  end
+
+  defp reason_message(:too_deep), do: "document is nested more than 100 levels deep"
 end
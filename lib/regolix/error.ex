@@ -1,14 +1,38 @@
 defmodule Regolix.Error do
-  @type error_type :: :parse_error | :eval_error | :json_error | :engine_error
+  @type error_type ::
+          :parse_error
+          | :eval_error
+          | :json_error
+          | :engine_error
+          | :io_error
+          | :timeout
+          | :cancelled
+          | :limit_exceeded
+          | :undefined
+          | :builtin_disabled
+          | :bundle_error
+          | :key_error
+          | :invalid_argument
 
   @type t :: %__MODULE__{
           type: error_type(),
-          message: String.t()
+          message: String.t(),
+          file: String.t() | nil,
+          line: pos_integer() | nil,
+          column: pos_integer() | nil,
+          snippet: String.t() | nil,
+          path: String.t() | nil,
+          reason: atom() | nil
         }
 
-  defexception [:type, :message]
+  defexception [:type, :message, :file, :line, :column, :snippet, :path, :reason]
 
   @impl true
+  def message(%__MODULE__{type: type, message: msg, file: file, line: line, column: column})
+      when is_binary(file) do
+    "#{type}: #{msg} (#{file}:#{line}:#{column})"
+  end
+
   def message(%__MODULE__{type: type, message: msg}) do
     "#{type}: #{msg}"
   end
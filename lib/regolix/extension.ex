@@ -0,0 +1,55 @@
+defmodule Regolix.Extension do
+  @moduledoc """
+  Runs Elixir implementations of custom Rego builtins.
+
+  When a policy calls a builtin registered with `Regolix.add_extension/5`, the
+  NIF sends `{:regolix_extension, call_id, path, args_json}` to the handler
+  process and waits for `reply/2`. The handler started by `start/1` decodes the
+  arguments, applies the function in a separate process and replies with the
+  JSON-encoded result.
+
+  Custom handlers can be written by receiving the message above and calling
+  `reply/2` with `{:ok, json}` or `{:error, message}`.
+  """
+
+  alias Regolix.Native
+
+  @doc """
+  Starts a handler process that implements a builtin with `fun`.
+
+  `fun` receives the builtin's arguments as JSON-decoded terms and returns the
+  result as a JSON-encodable term. Raised exceptions become evaluation errors.
+  """
+  @spec start(function()) :: pid()
+  def start(fun) when is_function(fun) do
+    spawn(fn -> loop(fun) end)
+  end
+
+  @doc """
+  Replies to a pending builtin call.
+  """
+  @spec reply(pos_integer(), {:ok, String.t()} | {:error, String.t()}) :: :ok
+  def reply(call_id, reply) do
+    {:ok, {}} = Native.native_extension_reply(call_id, reply)
+    :ok
+  end
+
+  defp loop(fun) do
+    receive do
+      {:regolix_extension, call_id, _path, args_json} ->
+        spawn(fn -> reply(call_id, call(fun, args_json)) end)
+        loop(fun)
+    end
+  end
+
+  defp call(fun, args_json) do
+    with {:ok, args} <- Jason.decode(args_json),
+         {:ok, json} <- Jason.encode(apply(fun, args)) do
+      {:ok, json}
+    else
+      {:error, error} -> {:error, Exception.message(error)}
+    end
+  rescue
+    error -> {:error, Exception.message(error)}
+  end
+end
@@ -0,0 +1,87 @@
+defmodule Regolix.HTTP do
+  @moduledoc """
+  Implements Rego's `http.send` with an Elixir function.
+
+  Registered through `Regolix.set_http_handler/3`. The handler receives the
+  request object the policy passed to `http.send`, with string keys such as
+  `"method"`, `"url"`, `"headers"` and `"body"`, and returns one of:
+
+    * `{:ok, %{status: status, headers: headers, body: body}}` - `body` is
+      either a binary, which is decoded as JSON when possible, or an already
+      decoded term. `headers` is optional.
+    * `{:error, reason}` - the request failed to complete.
+
+  The result is converted to the response object OPA policies expect:
+  `status_code`, `status`, `headers`, `body` and `raw_body`. A failed request
+  fails the evaluation, unless the request set `"raise_error": false`, in
+  which case the response carries an `error` object instead, as in OPA.
+  """
+
+  @type response :: %{
+          required(:status) => non_neg_integer(),
+          optional(:headers) => %{String.t() => String.t() | [String.t()]},
+          optional(:body) => term()
+        }
+
+  @type handler :: (map() -> {:ok, response()} | {:error, term()})
+
+  @doc """
+  Wraps `handler` as a one-argument builtin for `Regolix.add_extension/5`.
+  """
+  @spec builtin(handler()) :: (map() -> map())
+  def builtin(handler) when is_function(handler, 1) do
+    fn request ->
+      case handler.(request) do
+        {:ok, %{status: status} = response} ->
+          to_opa_response(status, Map.get(response, :headers, %{}), Map.get(response, :body))
+
+        {:error, reason} ->
+          request_failed(request, reason)
+
+        other ->
+          raise ArgumentError, "http.send handler returned #{inspect(other)}"
+      end
+    end
+  end
+
+  defp to_opa_response(status, headers, body) when is_binary(body) do
+    decoded =
+      case Jason.decode(body) do
+        {:ok, decoded} -> decoded
+        {:error, _} -> nil
+      end
+
+    %{
+      "status_code" => status,
+      "status" => Integer.to_string(status),
+      "headers" => headers,
+      "body" => decoded,
+      "raw_body" => body
+    }
+  end
+
+  defp to_opa_response(status, headers, body) do
+    %{
+      "status_code" => status,
+      "status" => Integer.to_string(status),
+      "headers" => headers,
+      "body" => body,
+      "raw_body" => if(is_nil(body), do: "", else: Jason.encode!(body))
+    }
+  end
+
+  defp request_failed(%{"raise_error" => false}, reason) do
+    %{
+      "status_code" => 0,
+      "error" => %{"code" => "eval_http_send_network_error", "message" => error_message(reason)}
+    }
+  end
+
+  defp request_failed(_request, reason) do
+    raise "http.send failed: #{error_message(reason)}"
+  end
+
+  defp error_message(reason) when is_binary(reason), do: reason
+  defp error_message(%{__exception__: true} = reason), do: Exception.message(reason)
+  defp error_message(reason), do: inspect(reason)
+end
@@ -4,34 +4,480 @@ defmodule Regolix.Native do
   @spec native_new() :: reference()
   def native_new(), do: :erlang.nif_error(:nif_not_loaded)
 
-  @spec native_add_policy(reference(), String.t(), String.t()) ::
+  @spec native_clone(reference()) :: {:ok, reference()} | {:error, {atom(), String.t()}}
+  def native_clone(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_owner(reference(), pid()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_owner(_engine, _pid), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_clear_owner(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_clear_owner(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_dump(reference()) :: {:ok, binary()} | {:error, {atom(), String.t()}}
+  def native_dump(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_restore(binary()) :: {:ok, reference()} | {:error, {atom(), String.t()}}
+  def native_restore(_blob), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_open_store(String.t()) :: {:ok, reference()} | {:error, {atom(), String.t()}}
+  def native_open_store(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_close_store(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_close_store(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_txn_begin(reference()) :: {:ok, reference()} | {:error, {atom(), String.t()}}
+  def native_txn_begin(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_txn_add_data(reference(), iodata()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | atom()}}
+  def native_txn_add_data(_txn, _json_data), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_txn_add_data_at_path(reference(), String.t(), iodata()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | atom()}}
+  def native_txn_add_data_at_path(_txn, _path, _json_data),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_txn_remove_data_path(reference(), String.t()) ::
           {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_txn_remove_data_path(_txn, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_txn_commit(reference()) :: {:ok, reference()} | {:error, {atom(), String.t()}}
+  def native_txn_commit(_txn), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_txn_abort(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_txn_abort(_txn), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_time(reference(), :system | :fixed | :offset, integer()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_time(_engine, _mode, _ns), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_random_seed(reference(), non_neg_integer() | nil) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_random_seed(_engine, _seed), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_runtime(reference(), iodata() | nil) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_runtime(_engine, _json_runtime), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_label(reference(), String.t() | nil) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_label(_engine, _label), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_verification_key(reference(), String.t(), String.t(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_add_verification_key(_engine, _keyid, _key, _algorithm),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_jwks(reference(), String.t()) :: {:ok, [String.t()]} | {:error, {atom(), String.t()}}
+  def native_add_jwks(_engine, _json_jwks), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_remove_verification_key(reference(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_remove_verification_key(_engine, _keyid), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_list_verification_keys(reference()) :: {:ok, [map()]} | {:error, {atom(), String.t()}}
+  def native_list_verification_keys(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_disable_builtins(reference(), [String.t()]) ::
+          {:ok, {}} | {:error, {atom(), String.t() | map()}}
+  def native_disable_builtins(_engine, _builtins), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_builtin_usage(reference()) ::
+          {:ok, %{String.t() => [String.t()]}} | {:error, {atom(), String.t()}}
+  def native_builtin_usage(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_register(atom(), reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_register(_name, _engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_unregister(atom()) :: {:ok, boolean()} | {:error, {atom(), String.t()}}
+  def native_unregister(_name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_whereis(atom()) :: {:ok, reference() | nil} | {:error, {atom(), String.t()}}
+  def native_whereis(_name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_registered() :: [String.t()]
+  def native_registered, do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_policy(reference(), String.t(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | map()}}
   def native_add_policy(_engine, _name, _source), do: :erlang.nif_error(:nif_not_loaded)
 
-  @spec native_set_input(reference(), String.t()) :: :ok | {:error, {atom(), String.t()}}
+  @spec native_add_policy_from_file(reference(), String.t()) ::
+          {:ok, String.t()} | {:error, {atom(), String.t() | map()}}
+  def native_add_policy_from_file(_engine, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_policies_from_dir(reference(), String.t(), String.t()) ::
+          {:ok, [String.t()]} | {:error, {atom(), String.t() | map()}}
+  def native_add_policies_from_dir(_engine, _dir, _pattern),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_watch(reference(), [String.t()], pid(), pos_integer()) :: reference()
+  def native_watch(_engine, _paths, _subscriber, _interval_ms),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_unwatch(reference()) :: :ok
+  def native_unwatch(_watcher), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_load_bundle(reference(), String.t(), String.t()) ::
+          {:ok, map()} | {:error, {atom(), String.t() | map()}}
+  def native_load_bundle(_engine, _path, _name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_load_bundle_binary(reference(), binary(), String.t()) ::
+          {:ok, map()} | {:error, {atom(), String.t() | map()}}
+  def native_load_bundle_binary(_engine, _contents, _name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_bundle_status(reference()) ::
+          {:ok, %{String.t() => %{revision: String.t(), roots: [String.t()]}}}
+          | {:error, {atom(), String.t()}}
+  def native_bundle_status(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_build_bundle(reference(), map()) :: {:ok, binary()} | {:error, {atom(), String.t()}}
+  def native_build_bundle(_engine, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_remove_policy(reference(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_remove_policy(_engine, _name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_input(reference(), iodata()) :: :ok | {:error, {atom(), String.t() | atom()}}
   def native_set_input(_engine, _json_input), do: :erlang.nif_error(:nif_not_loaded)
 
+  @spec native_set_input_yaml(reference(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | atom()}}
+  def native_set_input_yaml(_engine, _yaml_input), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_input_etf(reference(), binary(), map()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | map()}}
+  def native_set_input_etf(_engine, _etf_input, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_limits(reference(), map()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_limits(_engine, _limits), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_sets_mode(reference(), :list | :tagged) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_sets_mode(_engine, _mode), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_numbers_mode(reference(), :native | :string | :decimal) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_numbers_mode(_engine, _mode), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_keys_mode(reference(), :binary | :existing_atoms) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_keys_mode(_engine, _mode), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_undefined_mode(reference(), :undefined | nil | :error) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_undefined_mode(_engine, _mode), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_max_result_depth(reference(), pos_integer() | nil) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_max_result_depth(_engine, _max_depth), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_decode_opts(reference(), map()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_decode_opts(_engine, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_decision_cache(reference(), non_neg_integer()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_decision_cache(_engine, _capacity), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_clear_decision_cache(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_clear_decision_cache(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
   @spec native_get_packages(reference()) :: {:ok, [String.t()]} | {:error, {atom(), String.t()}}
   def native_get_packages(_engine), do: :erlang.nif_error(:nif_not_loaded)
 
-  @spec native_add_data(reference(), String.t()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  @spec native_get_policies(reference()) :: {:ok, [map()]} | {:error, {atom(), String.t()}}
+  def native_get_policies(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_data(reference(), iodata()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | atom()}}
   def native_add_data(_engine, _json), do: :erlang.nif_error(:nif_not_loaded)
 
-  @spec native_eval_query(reference(), String.t()) :: term() | {:error, {atom(), String.t()}}
-  def native_eval_query(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+  @spec native_add_data_begin(reference()) :: {:ok, reference()} | {:error, {atom(), String.t()}}
+  def native_add_data_begin(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_data_chunk(reference(), binary()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_add_data_chunk(_upload, _chunk), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_data_commit(reference()) :: {:ok, {}} | {:error, {atom(), String.t() | atom()}}
+  def native_add_data_commit(_upload), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_data_at_path(reference(), String.t(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | atom()}}
+  def native_add_data_at_path(_engine, _path, _json), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_data_yaml(reference(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | atom()}}
+  def native_add_data_yaml(_engine, _yaml), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_data_from_file(reference(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t() | atom()}}
+  def native_add_data_from_file(_engine, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_get_data(reference(), String.t()) :: {:ok, term()} | {:error, {atom(), String.t()}}
+  def native_get_data(_engine, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query(reference(), String.t(), String.t() | nil, map()) ::
+          term() | {:error, {atom(), String.t() | map()}}
+  def native_eval_query(_engine, _query, _extract, _overrides),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_json(reference(), String.t()) ::
+          {:ok, String.t() | :undefined} | {:error, {atom(), String.t() | map()}}
+  def native_eval_query_json(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_lazy(reference(), String.t()) ::
+          {:ok, reference()} | {:error, {atom(), String.t() | map()}}
+  def native_eval_query_lazy(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_result_get(reference(), String.t()) ::
+          {:ok, term()} | {:error, {atom(), String.t() | map()}}
+  def native_result_get(_result, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_timeout(
+          reference(),
+          String.t(),
+          non_neg_integer() | nil,
+          reference() | nil,
+          String.t() | nil,
+          map()
+        ) :: {:ok, term()} | {:error, {atom(), String.t() | map()}}
+  def native_eval_query_timeout(_engine, _query, _timeout_ms, _token, _extract, _overrides),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_new_cancel_token() :: reference()
+  def native_new_cancel_token, do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_cancel(reference()) :: :ok
+  def native_cancel(_token), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_cancelled(reference()) :: boolean()
+  def native_cancelled(_token), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_with_metrics(reference(), String.t()) ::
+          {:ok, map()} | {:error, {atom(), String.t() | map()}}
+  def native_eval_query_with_metrics(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_clear_query_cache(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_clear_query_cache(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_batch(reference(), String.t(), [String.t()]) ::
+          [{:ok, term()} | {:error, {atom(), String.t() | map()}}]
+          | {:error, {atom(), String.t() | map()}}
+  def native_eval_batch(_engine, _query, _json_inputs), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_with_input(reference(), String.t(), iodata()) ::
+          {:ok, term()} | {:error, {atom(), String.t()}}
+  def native_eval_query_with_input(_engine, _query, _json_input),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_parse_input(iodata()) ::
+          {:ok, reference()} | {:error, {atom(), String.t() | atom()}}
+  def native_parse_input(_json_input), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_with_parsed_input(reference(), String.t(), reference()) ::
+          {:ok, term()} | {:error, {atom(), term()}}
+  def native_eval_query_with_parsed_input(_engine, _query, _input),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_async(reference(), String.t(), String.t(), pid(), reference()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_eval_async(_engine, _query, _json_input, _caller, _ref),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_traced(reference(), String.t()) ::
+          {:ok, map()} | {:error, {atom(), String.t() | map()}}
+  def native_eval_query_traced(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_new_pool(reference(), pos_integer()) ::
+          {:ok, reference()} | {:error, {atom(), String.t()}}
+  def native_new_pool(_engine, _size), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_pool_eval(reference(), String.t(), String.t()) ::
+          {:ok, term()} | {:error, {atom(), String.t() | map()}}
+  def native_pool_eval(_pool, _query, _json_input), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_pool_size(reference()) :: pos_integer()
+  def native_pool_size(_pool), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_params(reference(), String.t(), iodata()) ::
+          {:ok, term()} | {:error, {atom(), String.t()}}
+  def native_eval_query_params(_engine, _query, _json_params),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_with_overrides(reference(), String.t(), iodata()) ::
+          {:ok, term()} | {:error, {atom(), String.t()}}
+  def native_eval_query_with_overrides(_engine, _query, _json_overrides),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_query_full(reference(), String.t()) ::
+          {:ok, [map()]} | {:error, {atom(), String.t()}}
+  def native_eval_query_full(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_bindings(reference(), String.t()) ::
+          {:ok, [map()]} | {:error, {atom(), String.t()}}
+  def native_eval_bindings(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_rule(reference(), String.t()) :: {:ok, term()} | {:error, {atom(), String.t()}}
+  def native_eval_rule(_engine, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_package(reference(), String.t(), boolean()) ::
+          {:ok, term()} | {:error, {atom(), String.t()}}
+  def native_eval_package(_engine, _package, _exclude_internal),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_bool_query(reference(), String.t()) ::
+          {:ok, boolean()} | {:error, {atom(), String.t() | map()}}
+  def native_eval_bool_query(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_allow_query(reference(), String.t()) ::
+          {:ok, boolean()} | {:error, {atom(), String.t() | map()}}
+  def native_eval_allow_query(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_deny_query(reference(), String.t()) ::
+          {:ok, boolean()} | {:error, {atom(), String.t() | map()}}
+  def native_eval_deny_query(_engine, _query), do: :erlang.nif_error(:nif_not_loaded)
 
   @spec native_clear_data(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
   def native_clear_data(_engine), do: :erlang.nif_error(:nif_not_loaded)
 
+  @spec native_set_strict_builtin_errors(reference(), boolean()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_strict_builtin_errors(_engine, _enable), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_set_rego_version(reference(), :v0 | :v1) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_set_rego_version(_engine, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_add_extension(reference(), String.t(), non_neg_integer(), pid(), non_neg_integer()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_add_extension(_engine, _path, _nargs, _handler, _timeout_ms),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_extension_reply(pos_integer(), {:ok | :error, String.t()}) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_extension_reply(_call_id, _reply), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_remove_data_path(reference(), String.t()) ::
+          {:ok, boolean()} | {:error, {atom(), String.t()}}
+  def native_remove_data_path(_engine, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_patch_data(reference(), iodata()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_patch_data(_engine, _patch_json), do: :erlang.nif_error(:nif_not_loaded)
+
   @spec native_enable_coverage(reference(), boolean()) :: {:ok, {}} | {:error, {atom(), String.t()}}
   def native_enable_coverage(_engine, _enable), do: :erlang.nif_error(:nif_not_loaded)
 
   @spec native_get_coverage_report(reference()) :: {:ok, map()} | {:error, {atom(), String.t()}}
   def native_get_coverage_report(_engine), do: :erlang.nif_error(:nif_not_loaded)
 
+  @spec native_get_rule_coverage(reference()) :: {:ok, map()} | {:error, {atom(), String.t()}}
+  def native_get_rule_coverage(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_get_coverage_pretty(reference()) :: {:ok, String.t()} | {:error, {atom(), String.t()}}
+  def native_get_coverage_pretty(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_coverage_to_lcov(reference()) :: {:ok, String.t()} | {:error, {atom(), String.t()}}
+  def native_coverage_to_lcov(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_coverage_to_cobertura(reference()) ::
+          {:ok, String.t()} | {:error, {atom(), String.t()}}
+  def native_coverage_to_cobertura(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_merge_coverage([map()]) :: map()
+  def native_merge_coverage(_reports), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_enable_profiling(reference(), boolean()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_enable_profiling(_engine, _enabled), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_get_profile(reference()) :: {:ok, map()} | {:error, {atom(), String.t()}}
+  def native_get_profile(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_clear_profile(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_clear_profile(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_benchmark(reference(), String.t(), iodata() | nil, pos_integer()) ::
+          {:ok, map()} | {:error, {atom(), term()}}
+  def native_benchmark(_engine, _query, _json_input, _iterations),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_stats(reference()) :: {:ok, map()} | {:error, {atom(), String.t()}}
+  def native_stats(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
   @spec native_clear_coverage(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
   def native_clear_coverage(_engine), do: :erlang.nif_error(:nif_not_loaded)
 
+  @spec native_parse_policy(String.t(), String.t(), :v0 | :v1) ::
+          {:ok, String.t()} | {:error, {atom(), String.t() | map()}}
+  def native_parse_policy(_name, _source, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_value_to_string(term(), :rego | :json | :pretty_json) ::
+          {:ok, String.t()} | {:error, {atom(), String.t()}}
+  def native_value_to_string(_value, _format), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_data_deps(reference()) ::
+          {:ok, %{String.t() => %{String.t() => [String.t()]}}} | {:error, {atom(), String.t()}}
+  def native_data_deps(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_input_deps(reference()) ::
+          {:ok, %{String.t() => %{String.t() => [String.t()]}}} | {:error, {atom(), String.t()}}
+  def native_input_deps(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_input_skeleton(reference()) :: {:ok, map()} | {:error, {atom(), String.t()}}
+  def native_input_skeleton(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_rule_graph(reference(), :map | :dot) ::
+          {:ok, map() | String.t()} | {:error, {atom(), String.t()}}
+  def native_rule_graph(_engine, _format), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_rule_complexity(reference()) :: {:ok, [map()]} | {:error, {atom(), String.t()}}
+  def native_rule_complexity(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_validate_query(String.t(), :v0 | :v1) ::
+          {:ok, {}} | {:error, {atom(), String.t() | map()}}
+  def native_validate_query(_query, _version), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_lint_policy(String.t(), String.t(), map()) ::
+          {:ok, [map()]} | {:error, {atom(), String.t() | map()}}
+  def native_lint_policy(_name, _source, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_check_rego_v1(String.t(), String.t()) ::
+          {:ok, [map()]} | {:error, {atom(), String.t() | map()}}
+  def native_check_rego_v1(_name, _source), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_diff_policies(String.t(), String.t()) ::
+          {:ok, map()} | {:error, {atom(), String.t() | map()}}
+  def native_diff_policies(_old_source, _new_source), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_shadow_eval(reference(), reference(), String.t(), String.t() | nil) ::
+          {:ok, map()} | {:error, {atom(), String.t() | map()}}
+  def native_shadow_eval(_primary, _candidate, _query, _json_input),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_with_provenance(reference(), String.t(), String.t() | nil) ::
+          {:ok, map()} | {:error, {atom(), String.t()}}
+  def native_eval_with_provenance(_engine, _query, _json_input),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_start_recording(reference(), String.t()) ::
+          {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_start_recording(_engine, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_stop_recording(reference()) :: {:ok, {}} | {:error, {atom(), String.t()}}
+  def native_stop_recording(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_replay(reference(), String.t()) ::
+          {:ok, map()} | {:error, {atom(), String.t() | map()}}
+  def native_replay(_engine, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_run_tests(reference(), map()) :: {:ok, [map()]} | {:error, {atom(), String.t()}}
+  def native_run_tests(_engine, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
   @spec native_get_rules(reference()) :: {:ok, map()} | {:error, {atom(), String.t()}}
   def native_get_rules(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_get_entrypoints(reference()) :: {:ok, [map()]} | {:error, {atom(), String.t()}}
+  def native_get_entrypoints(_engine), do: :erlang.nif_error(:nif_not_loaded)
+
+  @spec native_eval_entrypoint(reference(), String.t(), String.t()) ::
+          {:ok, term()} | {:error, {atom(), String.t()}}
+  def native_eval_entrypoint(_engine, _name, _json_input), do: :erlang.nif_error(:nif_not_loaded)
 end
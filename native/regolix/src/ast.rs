@@ -0,0 +1,82 @@
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::lint::tokenize;
+use crate::{atoms, RegoVersion};
+use regorus::Engine;
+use rustler::Atom;
+
+/// Parse a single policy and return its module AST as JSON.
+///
+/// Runs on a scratch engine, so nothing is loaded into any existing engine.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_parse_policy(
+    name: String,
+    source: String,
+    version: RegoVersion,
+) -> Result<String, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = Engine::new();
+        engine.set_rego_v0(version == RegoVersion::V0);
+
+        engine
+            .add_policy(name, source)
+            .map_err(|e| located_error(atoms::parse_error(), e))?;
+
+        let json = engine
+            .get_ast_as_json()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        // regorus returns every loaded module; there is exactly one here
+        match regorus::Value::from_json_str(&json) {
+            Ok(regorus::Value::Array(modules)) if modules.len() == 1 => modules[0]
+                .to_json_str()
+                .map_err(|e| (atoms::json_error(), e.to_string().into())),
+            _ => Ok(json),
+        }
+    })
+}
+
+/// Lines placed before the query when it's wrapped in a policy for parsing
+const QUERY_PREAMBLE_LINES: u32 = 2;
+
+/// Parse a query without evaluating it, reporting the first syntax error
+/// with its position in the query.
+///
+/// regorus has no query-only parser, so the query is parsed as the body of a
+/// rule in a scratch policy. Braces are checked first so a query can't close
+/// that body and pass off a rule of its own as valid.
+#[rustler::nif]
+fn native_validate_query(query: String, version: RegoVersion) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut depth = 0usize;
+        for token in tokenize(&query) {
+            match token.text {
+                "{" => depth += 1,
+                "}" if depth == 0 => {
+                    return Err(located_error(
+                        atoms::parse_error(),
+                        format!("query:{}:{}: unexpected `}}`", token.line, token.column),
+                    ));
+                }
+                "}" => depth -= 1,
+                _ => {}
+            }
+        }
+
+        let mut engine = Engine::new();
+        engine.set_rego_v0(version == RegoVersion::V0);
+
+        let head = if version == RegoVersion::V0 { "__query {" } else { "__query if {" };
+        let policy = format!("package __regolix_query\n{}\n{}\n}}\n", head, query);
+
+        match engine.add_policy("query".to_string(), policy) {
+            Ok(_) => Ok(()),
+            Err(e) => match located_error(atoms::parse_error(), e) {
+                (kind, ErrorDetail::Located(mut location)) => {
+                    location.line = location.line.saturating_sub(QUERY_PREAMBLE_LINES).max(1);
+                    Err((kind, ErrorDetail::Located(location)))
+                }
+                error => Err(error),
+            },
+        }
+    })
+}
@@ -0,0 +1,112 @@
+use crate::decode::result_to_term;
+use crate::error::catch_panic;
+use crate::{atoms, eval_prepared, prepare_query, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, Encoder, Env, LocalPid, OwnedEnv, ResourceArc, Term};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::Instant;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Queue feeding the async evaluation workers.
+///
+/// A fixed number of workers, one per core, take jobs in order, so a burst of
+/// `eval_async` calls queues up instead of starting a thread each.
+fn workers() -> &'static Mutex<Sender<Job>> {
+    static WORKERS: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+    WORKERS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let count = thread::available_parallelism().map_or(4, |n| n.get());
+
+        for i in 0..count {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("regolix-eval-{}", i))
+                .spawn(move || loop {
+                    let job = receiver
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to start evaluation worker");
+        }
+
+        Mutex::new(sender)
+    })
+}
+
+/// Queue `job` for the evaluation workers
+pub(crate) fn spawn(job: impl FnOnce() + Send + 'static) -> Result<(), (Atom, String)> {
+    workers()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .send(Box::new(job))
+        .map_err(|_| {
+            (
+                atoms::engine_error(),
+                "evaluation workers stopped".to_string(),
+            )
+        })
+}
+
+/// Evaluate a query on a worker thread and send `{ref, result}` to `caller`.
+///
+/// Returns as soon as the job is queued, so a long evaluation occupies
+/// neither the calling process nor a dirty scheduler. The worker evaluates
+/// a copy of the engine with its own input, leaving the engine's input alone.
+/// The caller always gets a message, including when the evaluation panics.
+#[rustler::nif]
+fn native_eval_async<'a>(
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    json_input: String,
+    caller: LocalPid,
+    reference: Term<'a>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let mut msg_env = OwnedEnv::new();
+        let reference = msg_env.save(reference);
+
+        let job: Job = Box::new(move || {
+            let result = catch_panic(|| {
+                let input = limits.parse_json(&json_input)?;
+                let prepared = prepare_query(&resource, &query)?;
+                let mut engine = Engine::clone(&resource.snapshot());
+                engine.set_input(input);
+                let started = Instant::now();
+                let value = eval_prepared(&mut engine, prepared, &query);
+                resource.record_eval(started, &value);
+                value
+            });
+
+            // The caller may have exited in the meantime; nobody is left to tell
+            let _ = msg_env.send_and_clear(&caller, |env: Env| {
+                let result =
+                    result.and_then(|value| catch_panic(|| result_to_term(env, value, &decode)));
+                let message = match result {
+                    Ok(term) => (atoms::ok(), term).encode(env),
+                    Err(error) => (atoms::error(), error).encode(env),
+                };
+                (reference.load(env), message).encode(env)
+            });
+        });
+
+        spawn(job)
+    })
+}
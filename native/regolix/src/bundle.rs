@@ -0,0 +1,616 @@
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::keys::{file_hash, verify_signatures, VerificationKey, SIGNATURES_FILE};
+use crate::limits::Limits;
+use crate::patch::{self, pointer_segments, Operation, PatchDocument};
+use crate::sandbox::check_disabled_builtins;
+use crate::{
+    atoms, nest_value, rebuild_engine, remove_at_path, EngineResource, PolicySource, RegoVersion,
+};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rustler::{Atom, Binary, Env, NifMap, OwnedBinary, ResourceArc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+use std::path::{Component, Path};
+
+/// Contents of an OPA bundle (a gzipped tarball)
+struct Bundle {
+    manifest: Manifest,
+    /// Rego files as (path inside the bundle, source)
+    policies: Vec<(String, String)>,
+    /// Data documents, already nested under their directory path
+    data: Vec<regorus::Value>,
+    /// Operations from the `patch.json` of a delta bundle
+    patch: Vec<Operation>,
+}
+
+#[derive(Clone, NifMap, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    revision: String,
+    roots: Vec<String>,
+}
+
+impl Default for Manifest {
+    /// A bundle without a `.manifest` owns the whole data tree, as in OPA
+    fn default() -> Self {
+        Manifest {
+            revision: String::new(),
+            roots: vec![String::new()],
+        }
+    }
+}
+
+impl Manifest {
+    pub(crate) fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    /// Whether `package`, e.g. `data.authz`, lies under one of the roots
+    pub(crate) fn owns(&self, package: &str) -> bool {
+        let path: Vec<String> = package.split('.').skip(1).map(String::from).collect();
+        self.roots
+            .iter()
+            .any(|root| is_prefix(&root_segments(root), &path))
+    }
+}
+
+/// Summary of a loaded bundle returned to Elixir
+#[derive(NifMap)]
+struct BundleInfo {
+    revision: String,
+    roots: Vec<String>,
+    policies: Vec<String>,
+}
+
+/// What to put in the `.manifest` of a built bundle
+#[derive(NifMap)]
+struct BuildOptions {
+    revision: String,
+    /// `None` leaves roots out, which OPA reads as owning all of `data`
+    roots: Option<Vec<String>>,
+}
+
+/// Read a bundle, checking its signature when any keys are trusted: a bundle
+/// must then be signed with one of `keys`, over all of its files.
+fn read_bundle<R: Read>(
+    reader: R,
+    limits: &Limits,
+    keys: &BTreeMap<String, VerificationKey>,
+) -> Result<Bundle, (Atom, ErrorDetail)> {
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    let mut bundle = Bundle {
+        manifest: Manifest::default(),
+        policies: Vec::new(),
+        data: Vec::new(),
+        patch: Vec::new(),
+    };
+    let mut signatures = None;
+    let mut hashes = HashMap::new();
+    let mut names = HashSet::new();
+    let mut budget = limits.bundle_bytes();
+
+    let entries = archive
+        .entries()
+        .map_err(|e| (atoms::io_error(), e.to_string().into()))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| (atoms::io_error(), e.to_string().into()))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = entry
+            .path()
+            .map_err(|e| (atoms::io_error(), e.to_string().into()))?;
+        let segments = normalize_path(&path);
+        let Some(file_name) = segments.last().cloned() else {
+            continue;
+        };
+
+        let name = segments.join("/");
+        // `./data.json` and `data.json` are the same file to OPA, so a second
+        // copy would install contents the signature never covered
+        if !names.insert(name.clone()) {
+            return Err((
+                atoms::bundle_error(),
+                format!("{} appears more than once in the bundle", name).into(),
+            ));
+        }
+        let known = name == SIGNATURES_FILE
+            || file_name.ends_with(".rego")
+            || matches!(
+                file_name.as_str(),
+                ".manifest" | "data.json" | "data.yaml" | "data.yml" | "patch.json"
+            );
+        // Other files, such as `policy.wasm`, are skipped unread unless the
+        // signature covers them
+        if !known && keys.is_empty() {
+            continue;
+        }
+
+        let bytes = limits.read_bundle_file(&name, &mut entry, &mut budget)?;
+        if !keys.is_empty() && name != SIGNATURES_FILE {
+            let hash =
+                file_hash(&name, &bytes).map_err(|(kind, message)| (kind, message.into()))?;
+            hashes.insert(name.clone(), hash);
+        }
+        if !known {
+            continue;
+        }
+
+        let contents = String::from_utf8(bytes)
+            .map_err(|e| (atoms::io_error(), format!("{}: {}", name, e).into()))?;
+        if name == SIGNATURES_FILE {
+            signatures = Some(contents);
+            continue;
+        }
+
+        let dir: Vec<&str> = segments[..segments.len() - 1]
+            .iter()
+            .map(String::as_str)
+            .collect();
+
+        match file_name.as_str() {
+            ".manifest" => bundle.manifest = parse_manifest(&contents)?,
+            "data.json" => {
+                let value = limits
+                    .parse_json(&contents)
+                    .map_err(|(kind, detail)| (kind, detail.in_file(&name)))?;
+                bundle.data.push(nest_value(&dir, value));
+            }
+            "data.yaml" | "data.yml" => {
+                let value = limits
+                    .parse_yaml(&contents)
+                    .map_err(|(kind, detail)| (kind, detail.in_file(&name)))?;
+                bundle.data.push(nest_value(&dir, value));
+            }
+            "patch.json" => {
+                bundle.patch = serde_json::from_str::<PatchDocument>(&contents)
+                    .map_err(|e| e.to_string())
+                    .and_then(PatchDocument::into_operations)
+                    .map_err(|e| (atoms::json_error(), format!("{}: {}", name, e).into()))?;
+            }
+            file if file.ends_with(".rego") => {
+                bundle.policies.push((name, contents));
+            }
+            _ => {}
+        }
+    }
+
+    if !keys.is_empty() {
+        let signatures = signatures.ok_or_else(|| {
+            let message = format!(
+                "bundle has no {} but verification keys are set",
+                SIGNATURES_FILE
+            );
+            (atoms::bundle_error(), message.into())
+        })?;
+        verify_signatures(keys, &signatures, &hashes)
+            .map_err(|(kind, message)| (kind, message.into()))?;
+    }
+
+    Ok(bundle)
+}
+
+/// Split an archive path into its normal components, dropping `./` and `/`
+fn normalize_path(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(segment) => Some(segment.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_manifest(contents: &str) -> Result<Manifest, (Atom, ErrorDetail)> {
+    let value = Limits::default()
+        .parse_json(contents)
+        .map_err(|(kind, detail)| (kind, detail.in_file(".manifest")))?;
+
+    let revision = match &value["revision"] {
+        regorus::Value::String(s) => s.to_string(),
+        _ => String::new(),
+    };
+
+    let roots = match &value["roots"] {
+        regorus::Value::Array(roots) => roots
+            .iter()
+            .filter_map(|root| match root {
+                regorus::Value::String(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect(),
+        // OPA defaults to owning the whole data tree
+        _ => vec![String::new()],
+    };
+
+    Ok(Manifest { revision, roots })
+}
+
+/// Split a manifest root such as `"authz/users"` into path segments; the
+/// empty root owns the whole data tree
+fn root_segments(root: &str) -> Vec<String> {
+    root.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn is_prefix(prefix: &[String], path: &[String]) -> bool {
+    prefix.len() <= path.len() && prefix == &path[..prefix.len()]
+}
+
+/// Whether one root contains the other
+fn overlaps(a: &[String], b: &[String]) -> bool {
+    is_prefix(a, b) || is_prefix(b, a)
+}
+
+/// Reject data that isn't under one of `roots`.
+///
+/// As in OPA, objects above a root are walked rather than rejected, so a
+/// top-level `data.json` may hold several roots side by side.
+fn check_data_roots(
+    roots: &[Vec<String>],
+    path: &mut Vec<String>,
+    value: &regorus::Value,
+) -> Result<(), (Atom, ErrorDetail)> {
+    if roots.iter().any(|root| is_prefix(root, path)) {
+        return Ok(());
+    }
+
+    match value {
+        regorus::Value::Object(fields) if roots.iter().any(|root| is_prefix(path, root)) => {
+            for (key, field) in fields.iter() {
+                path.push(match key {
+                    regorus::Value::String(key) => key.to_string(),
+                    key => key.to_json_str().unwrap_or_default(),
+                });
+                check_data_roots(roots, path, field)?;
+                path.pop();
+            }
+            Ok(())
+        }
+        _ => Err((
+            atoms::bundle_error(),
+            format!("data at `{}` is outside the manifest roots", path.join("/")).into(),
+        )),
+    }
+}
+
+/// Check a bundle's roots against each other and against other bundles
+/// already loaded
+fn check_roots(
+    name: &str,
+    manifest: &Manifest,
+    loaded: &HashMap<String, Manifest>,
+) -> Result<(), (Atom, ErrorDetail)> {
+    for (i, root) in manifest.roots.iter().enumerate() {
+        let segments = root_segments(root);
+
+        if let Some(other) = manifest.roots[i + 1..]
+            .iter()
+            .find(|other| overlaps(&segments, &root_segments(other)))
+        {
+            return Err((
+                atoms::bundle_error(),
+                format!("manifest roots `{}` and `{}` overlap", root, other).into(),
+            ));
+        }
+
+        for (bundle, other) in loaded.iter().filter(|(bundle, _)| bundle.as_str() != name) {
+            if let Some(other) = other
+                .roots
+                .iter()
+                .find(|other| overlaps(&segments, &root_segments(other)))
+            {
+                return Err((
+                    atoms::bundle_error(),
+                    format!(
+                        "root `{}` overlaps root `{}` of bundle `{}`",
+                        root, other, bundle
+                    )
+                    .into(),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Install a bundle into the engine; nothing is applied unless every file
+/// loads and lies within the manifest roots.
+///
+/// Loading a bundle under a name already loaded is treated as a new revision
+/// of it: its roots may overlap the old ones, and the policies and data under
+/// the old roots are removed before the new revision is installed.
+fn install_bundle(
+    resource: &EngineResource,
+    name: String,
+    bundle: Bundle,
+) -> Result<BundleInfo, (Atom, ErrorDetail)> {
+    let mut engine = resource.begin_write();
+    let settings = resource
+        .settings
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+        .clone();
+
+    let mut policies = resource
+        .policies
+        .write()
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+    let mut bundles = resource
+        .bundles
+        .write()
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+    check_roots(&name, &bundle.manifest, &bundles)?;
+
+    // A new revision replaces the old one, so drop the policies and data the
+    // previous revision owned. Delta bundles patch the old revision instead.
+    let mut kept = policies.clone();
+    if let Some(previous) = bundles.get(&name).filter(|_| bundle.patch.is_empty()) {
+        let mut data = engine.get_data();
+        for root in &previous.roots {
+            let segments = root_segments(root);
+            if segments.is_empty() {
+                data = regorus::Value::new_object();
+            } else {
+                let path: Vec<&str> = segments.iter().map(String::as_str).collect();
+                remove_at_path(&mut data, &path);
+            }
+        }
+
+        kept.retain(|_, policy| !previous.owns(&policy.package));
+        let input = resource
+            .input
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .clone();
+        *engine = rebuild_engine(&kept, data, input, &settings)
+            .map_err(|(kind, message)| (kind, message.into()))?;
+    }
+
+    let roots: Vec<Vec<String>> = bundle
+        .manifest
+        .roots
+        .iter()
+        .map(String::as_str)
+        .map(root_segments)
+        .collect();
+
+    let mut added = Vec::with_capacity(bundle.policies.len());
+
+    for (file, source) in bundle.policies {
+        // Policies are keyed by their path alone, so a file of the same name
+        // from another bundle or `add_policy` would be silently replaced
+        let previous = bundles.get(&name);
+        if let Some(existing) = kept.get(&file) {
+            if !previous.is_some_and(|previous| previous.owns(&existing.package)) {
+                return Err((
+                    atoms::bundle_error(),
+                    format!("{}: a policy of the same name is already loaded", file).into(),
+                ));
+            }
+        }
+
+        check_disabled_builtins(&settings.disabled_builtins, &file, &source)?;
+        let package = settings
+            .add_policy(&mut engine, &file, &source)
+            .map_err(|e| located_error(atoms::parse_error(), e))?;
+
+        let path: Vec<String> = package.split('.').skip(1).map(String::from).collect();
+        if !roots.iter().any(|root| is_prefix(root, &path)) {
+            return Err((
+                atoms::bundle_error(),
+                format!("{}: package `{}` is outside the manifest roots", file, package).into(),
+            ));
+        }
+        added.push((file, PolicySource { source, package }));
+    }
+
+    for data in bundle.data {
+        check_data_roots(&roots, &mut Vec::new(), &data)?;
+        engine
+            .add_data(data)
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+    }
+
+    if !bundle.patch.is_empty() {
+        for operation in &bundle.patch {
+            let mut pointers = vec![operation.path()];
+            if let Operation::Move { from, .. } = operation {
+                pointers.push(from);
+            }
+
+            for pointer in pointers {
+                let path = pointer_segments(pointer).map_err(|e| (atoms::json_error(), e.into()))?;
+                if !roots.iter().any(|root| is_prefix(root, &path)) {
+                    return Err((
+                        atoms::bundle_error(),
+                        format!("patch of `{}` is outside the manifest roots", pointer).into(),
+                    ));
+                }
+            }
+        }
+
+        let mut data = serde_json::to_value(engine.get_data())
+            .map_err(|e| (atoms::json_error(), e.to_string().into()))?;
+        patch::apply(&mut data, bundle.patch).map_err(|e| (atoms::engine_error(), e.into()))?;
+        let data: regorus::Value = serde_json::from_value(data)
+            .map_err(|e| (atoms::json_error(), e.to_string().into()))?;
+
+        engine.clear_data();
+        engine
+            .add_data(data)
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+    }
+
+    engine.commit();
+    let names = added.iter().map(|(file, _)| file.clone()).collect();
+    kept.extend(added);
+    *policies = kept;
+    bundles.insert(name, bundle.manifest.clone());
+    resource.invalidate_queries();
+
+    Ok(BundleInfo {
+        revision: bundle.manifest.revision,
+        roots: bundle.manifest.roots,
+        policies: names,
+    })
+}
+
+/// Append a regular file to a bundle being built.
+///
+/// Entries get a fixed mode and mtime so the same engine always builds the
+/// same bundle.
+fn append_file<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    contents: &[u8],
+) -> Result<(), (Atom, String)> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+
+    builder
+        .append_data(&mut header, path, contents)
+        .map_err(|e| (atoms::io_error(), format!("{}: {}", path, e)))
+}
+
+/// Path for a policy inside a built bundle: the policy name made relative,
+/// with a `.rego` extension so OPA picks it up
+fn bundle_path(name: &str) -> String {
+    let path = normalize_path(Path::new(name)).join("/");
+    if path.ends_with(".rego") {
+        path
+    } else {
+        format!("{}.rego", path)
+    }
+}
+
+/// Package the engine's policies and data as an OPA bundle (gzipped tarball).
+///
+/// Policies are written under their names and all data goes into a single
+/// top-level `data.json`, so loading the bundle recreates the same policies
+/// and data. Settings such as the clock or disabled builtins are not part of
+/// the bundle format; see `native_dump` for a full copy of an engine.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_build_bundle<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    opts: BuildOptions,
+) -> Result<Binary<'a>, (Atom, String)> {
+    catch_panic(|| {
+        // Hold off writers so the data matches the policies
+        let _writer = resource.lock_writer();
+
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let rego_version = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .rego_version;
+
+        let mut manifest = serde_json::json!({
+            "revision": opts.revision,
+            "rego_version": if rego_version == RegoVersion::V0 { 0 } else { 1 },
+        });
+        if let Some(roots) = opts.roots {
+            manifest["roots"] = serde_json::json!(roots);
+        }
+
+        let data = resource
+            .snapshot()
+            .get_data()
+            .to_json_str()
+            .map_err(|e| (atoms::json_error(), e.to_string()))?;
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        append_file(&mut builder, ".manifest", manifest.to_string().as_bytes())?;
+        append_file(&mut builder, "data.json", data.as_bytes())?;
+
+        let mut names: Vec<&String> = policies.keys().collect();
+        names.sort();
+        for name in names {
+            append_file(&mut builder, &bundle_path(name), policies[name].source.as_bytes())?;
+        }
+
+        let bytes = builder
+            .into_inner()
+            .and_then(GzEncoder::finish)
+            .map_err(|e| (atoms::io_error(), e.to_string()))?;
+
+        let mut binary = OwnedBinary::new(bytes.len())
+            .ok_or_else(|| (atoms::engine_error(), "failed to allocate binary".to_string()))?;
+        binary.as_mut_slice().copy_from_slice(&bytes);
+
+        Ok(binary.release(env))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_load_bundle(
+    resource: ResourceArc<EngineResource>,
+    path: String,
+    name: String,
+) -> Result<BundleInfo, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let file = std::fs::File::open(&path)
+            .map_err(|e| (atoms::io_error(), format!("{}: {}", path, e).into()))?;
+
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let keys = resource
+            .keys
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .clone();
+        let bundle = read_bundle(file, &limits, &keys)?;
+        install_bundle(&resource, name, bundle)
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_load_bundle_binary(
+    resource: ResourceArc<EngineResource>,
+    contents: Binary,
+    name: String,
+) -> Result<BundleInfo, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let keys = resource
+            .keys
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .clone();
+        let bundle = read_bundle(contents.as_slice(), &limits, &keys)?;
+        install_bundle(&resource, name, bundle)
+    })
+}
+
+/// Revision and roots of each bundle loaded, by bundle name
+#[rustler::nif]
+fn native_bundle_status(
+    resource: ResourceArc<EngineResource>,
+) -> Result<HashMap<String, Manifest>, (Atom, String)> {
+    catch_panic(|| {
+        Ok(resource
+            .bundles
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clone())
+    })
+}
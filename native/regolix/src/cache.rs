@@ -0,0 +1,228 @@
+use crate::error::catch_panic;
+use crate::{atoms, EngineResource};
+use rustler::{Atom, ResourceArc};
+use std::collections::HashMap;
+
+/// What a cached decision depends on
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct DecisionKey {
+    query: String,
+    /// A per-call input as written by `write_key`, or `None` for the engine's
+    /// own input, which `revision` already covers. The whole document is kept
+    /// so inputs whose hashes collide never share a result.
+    input: Option<String>,
+    revision: u64,
+}
+
+/// Results of recent evaluations, evicting the least recently used entry
+/// once `capacity` is reached. A capacity of zero turns caching off.
+#[derive(Default)]
+pub(crate) struct DecisionCache {
+    capacity: usize,
+    /// Number of changes made to the engine, so results computed against
+    /// older policies, data or input never match again
+    revision: u64,
+    /// Use counter standing in for a clock, for finding the oldest entry
+    tick: u64,
+    entries: HashMap<DecisionKey, (u64, regorus::Value)>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Append `value` to `out` with a distinct mark for each kind of collection,
+/// so documents that serialize to the same JSON, such as a set and an array
+/// of the same items, get different keys. Sets and objects are sorted, so
+/// equal documents always get the same key.
+fn write_key(value: &regorus::Value, out: &mut String) -> Option<()> {
+    match value {
+        regorus::Value::Array(items) => {
+            out.push('[');
+            for item in items.iter() {
+                write_key(item, out)?;
+                out.push(',');
+            }
+            out.push(']');
+        }
+        regorus::Value::Set(items) => {
+            out.push('<');
+            for item in items.iter() {
+                write_key(item, out)?;
+                out.push(',');
+            }
+            out.push('>');
+        }
+        regorus::Value::Object(fields) => {
+            out.push('{');
+            for (key, field) in fields.iter() {
+                write_key(key, out)?;
+                out.push(':');
+                write_key(field, out)?;
+                out.push(',');
+            }
+            out.push('}');
+        }
+        regorus::Value::Undefined => out.push('?'),
+        // Scalars are told apart by their JSON: strings are quoted
+        scalar => out.push_str(&scalar.to_json_str().ok()?),
+    }
+    Some(())
+}
+
+impl DecisionCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        DecisionCache {
+            capacity,
+            ..DecisionCache::default()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Lookups that found a result, and lookups that didn't
+    pub fn counts(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Forget every entry; called whenever the engine changes
+    pub fn invalidate(&mut self) {
+        self.revision += 1;
+        self.entries.clear();
+    }
+
+    fn get(&mut self, key: &DecisionKey) -> Option<regorus::Value> {
+        self.tick += 1;
+        match self.entries.get_mut(key) {
+            Some((used, value)) => {
+                *used = self.tick;
+                self.hits += 1;
+                Some(value.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: DecisionKey, value: regorus::Value) {
+        // The engine changed while this result was being computed
+        if self.capacity == 0 || key.revision != self.revision {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            // A linear scan keeps the cache dependency-free; capacities are
+            // small next to the cost of an evaluation
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(key, (self.tick, value));
+    }
+}
+
+impl EngineResource {
+    /// Key for evaluating `query` against the current engine, with `input` in
+    /// place of the engine's own if given. `None` while caching is off.
+    ///
+    /// Take the key before copying the engine to evaluate, so a result from a
+    /// snapshot replaced mid-evaluation is keyed to a stale revision.
+    pub(crate) fn decision_key(
+        &self,
+        query: &str,
+        input: Option<&regorus::Value>,
+    ) -> Option<DecisionKey> {
+        let revision = {
+            let cache = self.decisions.lock().ok()?;
+            if cache.capacity == 0 {
+                return None;
+            }
+            cache.revision
+        };
+
+        let input = match input {
+            Some(input) => {
+                let mut key = String::new();
+                write_key(input, &mut key)?;
+                Some(key)
+            }
+            None => None,
+        };
+
+        Some(DecisionKey {
+            query: query.to_string(),
+            input,
+            revision,
+        })
+    }
+
+    pub(crate) fn cached_decision(&self, key: &DecisionKey) -> Option<regorus::Value> {
+        self.decisions.lock().ok()?.get(key)
+    }
+
+    pub(crate) fn cache_decision(&self, key: DecisionKey, value: &regorus::Value) {
+        if let Ok(mut cache) = self.decisions.lock() {
+            cache.insert(key, value.clone());
+        }
+    }
+
+    pub(crate) fn invalidate_decisions(&self) {
+        if let Ok(mut cache) = self.decisions.lock() {
+            cache.invalidate();
+        }
+    }
+}
+
+/// Cache up to `capacity` evaluation results, or turn caching off with zero.
+/// Clears the cache either way.
+#[rustler::nif]
+fn native_set_decision_cache(
+    resource: ResourceArc<EngineResource>,
+    capacity: usize,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut cache = resource
+            .decisions
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        cache.capacity = capacity;
+        cache.clear();
+
+        Ok(())
+    })
+}
+
+/// Drop cached results, for policies whose answers change without the engine
+/// changing, such as those calling `time.now_ns()`
+#[rustler::nif]
+fn native_clear_decision_cache(
+    resource: ResourceArc<EngineResource>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        resource
+            .decisions
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clear();
+
+        Ok(())
+    })
+}
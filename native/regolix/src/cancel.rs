@@ -0,0 +1,149 @@
+use crate::decode::{result_to_term, DecodeOverrides};
+use crate::error::{catch_panic, ErrorDetail};
+use crate::{async_eval, atoms, eval_prepared, extract_path, prepare_query, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, Env, ResourceArc, Term};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a waiting evaluation checks its cancel token
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Flag an Elixir process can trip to abandon evaluations that use it
+pub struct CancelToken {
+    cancelled: AtomicBool,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for CancelToken {}
+
+#[rustler::nif]
+fn native_new_cancel_token() -> ResourceArc<CancelToken> {
+    ResourceArc::new(CancelToken {
+        cancelled: AtomicBool::new(false),
+    })
+}
+
+#[rustler::nif]
+fn native_cancel(token: ResourceArc<CancelToken>) -> Atom {
+    token.cancelled.store(true, Ordering::SeqCst);
+    atoms::ok()
+}
+
+#[rustler::nif]
+fn native_cancelled(token: ResourceArc<CancelToken>) -> bool {
+    token.cancelled.load(Ordering::SeqCst)
+}
+
+/// Evaluate a query with an optional time budget and cancel token.
+///
+/// regorus has no way to interrupt an evaluation, so the query runs against a
+/// copy of the engine on the `eval_async` workers. When the budget runs out or
+/// the token is tripped the caller gets an error straight away; an abandoned
+/// evaluation that hasn't started yet is dropped, but one already running
+/// keeps its worker until it finishes on its own. Time spent queued for a
+/// worker counts against the budget.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_timeout<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    timeout_ms: Option<u64>,
+    token: Option<ResourceArc<CancelToken>>,
+    extract: Option<String>,
+    overrides: DecodeOverrides,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let decode = overrides.apply(decode);
+
+        let key = resource.decision_key(&query, None);
+        if let Some(value) = key.as_ref().and_then(|key| resource.cached_decision(key)) {
+            resource.record_decision(&query, None, &value);
+            let value = match &extract {
+                Some(path) => extract_path(&value, path),
+                None => value,
+            };
+            return result_to_term(env, value, &decode);
+        }
+
+        let prepared = prepare_query(&resource, &query)?;
+
+        let mut engine = Engine::clone(&resource.snapshot());
+
+        let (tx, rx) = mpsc::channel();
+        let owner = resource.clone();
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let gave_up = Arc::clone(&abandoned);
+        async_eval::spawn(move || {
+            if gave_up.load(Ordering::SeqCst) {
+                return;
+            }
+            let started = Instant::now();
+            // A panic would otherwise take the shared worker down with it
+            let result = catch_panic(|| eval_prepared(&mut engine, prepared, &query));
+            owner.record_eval(started, &result);
+            // Keep the result even if the caller has given up on it
+            if let Ok(value) = &result {
+                if let Some(key) = key {
+                    owner.cache_decision(key, value);
+                }
+                owner.record_decision(&query, None, value);
+            }
+            let _ = tx.send(result);
+        })
+        .map_err(|(kind, message)| (kind, message.into()))?;
+
+        let deadline = timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+
+        loop {
+            if let Some(token) = &token {
+                if token.cancelled.load(Ordering::SeqCst) {
+                    abandoned.store(true, Ordering::SeqCst);
+                    return Err((atoms::cancelled(), "evaluation cancelled".to_string().into()));
+                }
+            }
+
+            let wait = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        abandoned.store(true, Ordering::SeqCst);
+                        return Err((
+                            atoms::timeout(),
+                            format!(
+                                "evaluation exceeded {}ms",
+                                timeout_ms.unwrap_or_default()
+                            )
+                            .into(),
+                        ));
+                    }
+                    remaining.min(POLL_INTERVAL)
+                }
+                None => POLL_INTERVAL,
+            };
+
+            match rx.recv_timeout(wait) {
+                Ok(result) => {
+                    let value = match &extract {
+                        Some(path) => extract_path(&result?, path),
+                        None => result?,
+                    };
+                    return result_to_term(env, value, &decode);
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err((
+                        atoms::engine_error(),
+                        "evaluation thread exited without a result".to_string().into(),
+                    ))
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,109 @@
+use crate::error::catch_panic;
+use crate::{atoms, rebuild_engine, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, NifUnitEnum, ResourceArc};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Builtin that stands in for `time.now_ns` while the clock is overridden
+const NOW_NS: &str = "regolix.time.now_ns";
+
+/// What `time.now_ns()` returns in policies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Clock {
+    #[default]
+    System,
+    /// Always this many nanoseconds since the epoch
+    Fixed(i64),
+    /// The system time shifted by this many nanoseconds
+    Offset(i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+enum ClockMode {
+    System,
+    Fixed,
+    Offset,
+}
+
+impl Clock {
+    fn now_ns(self) -> i64 {
+        let system = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_nanos() as i64)
+                .unwrap_or_default()
+        };
+
+        match self {
+            Clock::System => system(),
+            Clock::Fixed(ns) => ns,
+            Clock::Offset(ns) => system().saturating_add(ns),
+        }
+    }
+
+    /// Register the stand-in builtin; a no-op for the system clock
+    pub(crate) fn register(self, engine: &mut Engine) -> anyhow::Result<()> {
+        if self == Clock::System {
+            return Ok(());
+        }
+
+        let now_ns = move |_args: Vec<regorus::Value>| -> anyhow::Result<regorus::Value> {
+            Ok(regorus::Value::from(self.now_ns()))
+        };
+        engine.add_extension(NOW_NS.to_string(), 0, Box::new(now_ns))
+    }
+
+    /// Builtins to point at stand-ins while this clock is in effect. regorus
+    /// has no hook for its clock, so policy calls are renamed instead.
+    pub(crate) fn renames(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Clock::System => &[],
+            _ => &[("time.now_ns", NOW_NS)],
+        }
+    }
+}
+
+/// Make `time.now_ns()` in policies return a fixed time, the system time
+/// shifted by `ns`, or the system time again.
+///
+/// The engine is rebuilt from its policy sources, since the change applies to
+/// policies already loaded. Queries passed to the eval functions keep using
+/// the system clock.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_set_time(
+    resource: ResourceArc<EngineResource>,
+    mode: ClockMode,
+    ns: i64,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let input = resource
+            .input
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clone();
+
+        let mut next = settings.clone();
+        next.clock = match mode {
+            ClockMode::System => Clock::System,
+            ClockMode::Fixed => Clock::Fixed(ns),
+            ClockMode::Offset => Clock::Offset(ns),
+        };
+
+        *engine = rebuild_engine(&policies, engine.get_data(), input, &next)?;
+        engine.commit();
+        *settings = next;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,74 @@
+use crate::deps::{bases, scan_rule};
+use crate::error::catch_panic;
+use crate::rules::{parse_module, rule_infos};
+use crate::{atoms, EngineResource};
+use rustler::{Atom, NifMap, ResourceArc};
+
+#[derive(NifMap)]
+struct RuleComplexity {
+    file: String,
+    /// The rule's ref as written, e.g. `allow`
+    name: String,
+    line: usize,
+    bodies: usize,
+    comprehensions: usize,
+    /// Deepest nesting of bodies, comprehensions and collection literals in
+    /// the definition
+    depth: usize,
+    data_refs: usize,
+    input_refs: usize,
+}
+
+/// Size and shape metrics for each rule definition of each loaded policy,
+/// for gating changes on policy complexity.
+///
+/// Works from the parsed policies, counting references like
+/// `native_data_deps`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_rule_complexity(
+    resource: ResourceArc<EngineResource>,
+) -> Result<Vec<RuleComplexity>, (Atom, String)> {
+    catch_panic(|| {
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let mut names: Vec<&String> = policies.keys().collect();
+        names.sort();
+
+        let mut metrics = Vec::new();
+        for name in names {
+            let source = &policies[name].source;
+            let module =
+                parse_module(name, source).map_err(|e| (atoms::parse_error(), e.to_string()))?;
+            let rules = rule_infos(&module, source);
+            let bases = bases(&module);
+
+            for (rule, info) in module.policy.iter().zip(rules) {
+                let scan = scan_rule(rule.as_ref(), &bases);
+                let count = |root: &str| {
+                    scan.refs
+                        .iter()
+                        .filter(|found| {
+                            found.path == root || found.path.starts_with(&format!("{}.", root))
+                        })
+                        .count()
+                };
+
+                metrics.push(RuleComplexity {
+                    file: name.clone(),
+                    name: info.name,
+                    line: info.start_line,
+                    bodies: info.bodies,
+                    comprehensions: scan.comprehensions,
+                    depth: scan.depth,
+                    data_refs: count("data"),
+                    input_refs: count("input"),
+                });
+            }
+        }
+
+        Ok(metrics)
+    })
+}
@@ -0,0 +1,273 @@
+use crate::error::catch_panic;
+use crate::rules::parse_rules;
+use crate::{atoms, EngineResource};
+use rustler::{Atom, NifMap, ResourceArc};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Line hits for one policy file, sorted by line number
+struct FileLines {
+    path: String,
+    /// (line, hit)
+    lines: Vec<(u32, bool)>,
+}
+
+impl FileLines {
+    fn hit(&self) -> usize {
+        self.lines.iter().filter(|(_, hit)| *hit).count()
+    }
+
+    fn rate(&self) -> f64 {
+        line_rate(self.hit(), self.lines.len())
+    }
+}
+
+fn line_rate(hit: usize, total: usize) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        hit as f64 / total as f64
+    }
+}
+
+fn collect_lines(resource: &EngineResource) -> Result<Vec<FileLines>, (Atom, String)> {
+    let engine = resource.snapshot();
+
+    let report = engine
+        .get_coverage_report()
+        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+    let mut files: Vec<FileLines> = report
+        .files
+        .iter()
+        .map(|file| {
+            let mut lines: Vec<(u32, bool)> = file
+                .covered
+                .iter()
+                .map(|&line| (line, true))
+                .chain(file.not_covered.iter().map(|&line| (line, false)))
+                .collect();
+            lines.sort_unstable();
+
+            FileLines {
+                path: file.path.clone(),
+                lines,
+            }
+        })
+        .collect();
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Coverage as an LCOV tracefile, one `SF:` record per policy file
+#[rustler::nif]
+fn native_coverage_to_lcov(resource: ResourceArc<EngineResource>) -> Result<String, (Atom, String)> {
+    catch_panic(|| {
+        let files = collect_lines(&resource)?;
+        let mut out = String::new();
+
+        for file in &files {
+            let _ = writeln!(out, "TN:");
+            let _ = writeln!(out, "SF:{}", file.path);
+            for (line, hit) in &file.lines {
+                let _ = writeln!(out, "DA:{},{}", line, u8::from(*hit));
+            }
+            let _ = writeln!(out, "LF:{}", file.lines.len());
+            let _ = writeln!(out, "LH:{}", file.hit());
+            let _ = writeln!(out, "end_of_record");
+        }
+
+        Ok(out)
+    })
+}
+
+/// Coverage as a Cobertura XML report with one class per policy file
+#[rustler::nif]
+fn native_coverage_to_cobertura(
+    resource: ResourceArc<EngineResource>,
+) -> Result<String, (Atom, String)> {
+    catch_panic(|| {
+        let files = collect_lines(&resource)?;
+
+        let total: usize = files.iter().map(|file| file.lines.len()).sum();
+        let hit: usize = files.iter().map(FileLines::hit).sum();
+        let rate = line_rate(hit, total);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+
+        let mut out = String::new();
+        let _ = writeln!(out, r#"<?xml version="1.0" ?>"#);
+        let _ = writeln!(
+            out,
+            r#"<!DOCTYPE coverage SYSTEM "http://cobertura.sourceforge.net/xml/coverage-04.dtd">"#
+        );
+        let _ = writeln!(
+            out,
+            r#"<coverage line-rate="{:.4}" branch-rate="0" lines-covered="{}" lines-valid="{}" branches-covered="0" branches-valid="0" complexity="0" version="regolix" timestamp="{}">"#,
+            rate, hit, total, timestamp
+        );
+        let _ = writeln!(out, "  <sources>\n    <source>.</source>\n  </sources>");
+        let _ = writeln!(out, "  <packages>");
+        let _ = writeln!(
+            out,
+            r#"    <package name="rego" line-rate="{:.4}" branch-rate="0" complexity="0">"#,
+            rate
+        );
+        let _ = writeln!(out, "      <classes>");
+
+        for file in &files {
+            let path = escape_xml(&file.path);
+            let _ = writeln!(
+                out,
+                r#"        <class name="{}" filename="{}" line-rate="{:.4}" branch-rate="0" complexity="0">"#,
+                path,
+                path,
+                file.rate()
+            );
+            let _ = writeln!(out, "          <methods/>");
+            let _ = writeln!(out, "          <lines>");
+            for (line, hit) in &file.lines {
+                let _ = writeln!(
+                    out,
+                    r#"            <line number="{}" hits="{}" branch="false"/>"#,
+                    line,
+                    u8::from(*hit)
+                );
+            }
+            let _ = writeln!(out, "          </lines>");
+            let _ = writeln!(out, "        </class>");
+        }
+
+        let _ = writeln!(out, "      </classes>");
+        let _ = writeln!(out, "    </package>");
+        let _ = writeln!(out, "  </packages>");
+        let _ = writeln!(out, "</coverage>");
+
+        Ok(out)
+    })
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// One file's entry in a coverage report map, as returned by
+/// `native_get_coverage_report`
+#[derive(NifMap)]
+struct FileCoverage {
+    covered: Vec<u32>,
+    not_covered: Vec<u32>,
+}
+
+/// Combine coverage reports from several engines into one.
+///
+/// A line counts as covered if any report covered it; it's only uncovered if
+/// no report did.
+#[rustler::nif]
+fn native_merge_coverage(
+    reports: Vec<HashMap<String, FileCoverage>>,
+) -> HashMap<String, FileCoverage> {
+    let mut merged: HashMap<String, (BTreeSet<u32>, BTreeSet<u32>)> = HashMap::new();
+
+    for report in reports {
+        for (path, file) in report {
+            let (covered, not_covered) = merged.entry(path).or_default();
+            covered.extend(file.covered);
+            not_covered.extend(file.not_covered);
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(path, (covered, not_covered))| {
+            let not_covered = not_covered.difference(&covered).copied().collect();
+            let file = FileCoverage {
+                covered: covered.into_iter().collect(),
+                not_covered,
+            };
+            (path, file)
+        })
+        .collect()
+}
+
+/// Coverage of one rule definition
+#[derive(Clone, NifMap)]
+struct RuleCoverage {
+    file: String,
+    /// Full path of the rule, e.g. `data.authz.allow`
+    rule: String,
+    start_line: usize,
+    end_line: usize,
+    covered: usize,
+    total: usize,
+    /// `covered / total` as a percentage; 100 for rules without executable lines
+    percent: f64,
+}
+
+#[derive(NifMap)]
+struct RuleCoverageReport {
+    rules: Vec<RuleCoverage>,
+    /// Definitions none of whose lines ran
+    unexercised: Vec<RuleCoverage>,
+}
+
+/// Coverage per rule definition, from the line coverage and each rule's span
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_get_rule_coverage(
+    resource: ResourceArc<EngineResource>,
+) -> Result<RuleCoverageReport, (Atom, String)> {
+    catch_panic(|| {
+        let files = collect_lines(&resource)?;
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let mut rules = Vec::new();
+        for file in &files {
+            let Some(policy) = policies.get(&file.path) else {
+                continue;
+            };
+            let definitions = parse_rules(&file.path, &policy.source)
+                .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+
+            for definition in definitions {
+                let lines: Vec<bool> = file
+                    .lines
+                    .iter()
+                    .filter(|(line, _)| {
+                        (definition.start_line..=definition.end_line).contains(&(*line as usize))
+                    })
+                    .map(|(_, hit)| *hit)
+                    .collect();
+                let covered = lines.iter().filter(|hit| **hit).count();
+
+                rules.push(RuleCoverage {
+                    file: file.path.clone(),
+                    rule: format!("{}.{}", policy.package, definition.name),
+                    start_line: definition.start_line,
+                    end_line: definition.end_line,
+                    covered,
+                    total: lines.len(),
+                    percent: line_rate(covered, lines.len()) * 100.0,
+                });
+            }
+        }
+
+        let unexercised = rules
+            .iter()
+            .filter(|rule| rule.total > 0 && rule.covered == 0)
+            .cloned()
+            .collect();
+
+        Ok(RuleCoverageReport { rules, unexercised })
+    })
+}
@@ -0,0 +1,330 @@
+use crate::atoms;
+use crate::error::ErrorDetail;
+use rustler::{Atom, BigInt, Decoder, Encoder, Env, NifMap, NifResult, NifUnitEnum, Term};
+use serde::{Deserialize, Serialize};
+
+/// How Rego sets are represented in Elixir
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum, Serialize, Deserialize)]
+pub(crate) enum SetEncoding {
+    /// Plain list, indistinguishable from an array
+    #[default]
+    List,
+    /// `{:set, list}`, which the Elixir side can turn into a MapSet
+    Tagged,
+}
+
+/// How non-integer numbers that may not fit an f64 exactly are represented
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum, Serialize, Deserialize)]
+pub(crate) enum NumberEncoding {
+    /// Elixir floats (may lose precision)
+    #[default]
+    Native,
+    /// Exact decimal string, e.g. `"0.1000000000000000000001"`
+    String,
+    /// `{:decimal, string}`, ready for `Decimal.new/1`
+    Decimal,
+}
+
+/// How object keys are represented in Elixir
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum, Serialize, Deserialize)]
+pub(crate) enum KeyEncoding {
+    /// Binaries, matching the JSON document
+    #[default]
+    Binary,
+    /// Atoms when an atom with that name already exists, binaries otherwise.
+    /// Never creates atoms, so untrusted documents can't exhaust the atom table.
+    ExistingAtoms,
+}
+
+/// How an undefined query result is represented in Elixir
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum, Serialize, Deserialize)]
+pub(crate) enum UndefinedEncoding {
+    /// The `:undefined` atom
+    #[default]
+    Undefined,
+    /// `nil`
+    Nil,
+    /// An `:undefined` error instead of a result
+    Error,
+}
+
+/// Per-engine options controlling how regorus values become Elixir terms
+#[derive(Clone, Copy, Debug, Default, NifMap, Serialize, Deserialize)]
+pub(crate) struct DecodeOptions {
+    pub sets: SetEncoding,
+    pub numbers: NumberEncoding,
+    pub keys: KeyEncoding,
+    pub undefined: UndefinedEncoding,
+    /// Deepest nesting of arrays, objects and sets a result may have;
+    /// `None` means unlimited
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+}
+
+/// Replacements for some of the engine's decode options, for a single call.
+///
+/// Decoded from a map holding only the options to replace, since `nil` is a
+/// valid undefined mode and can't mean "keep the engine's setting".
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct DecodeOverrides {
+    sets: Option<SetEncoding>,
+    numbers: Option<NumberEncoding>,
+    keys: Option<KeyEncoding>,
+    undefined: Option<UndefinedEncoding>,
+    max_depth: Option<Option<usize>>,
+}
+
+impl DecodeOverrides {
+    /// The engine's options `opts` with these replacements made
+    pub fn apply(&self, opts: DecodeOptions) -> DecodeOptions {
+        DecodeOptions {
+            sets: self.sets.unwrap_or(opts.sets),
+            numbers: self.numbers.unwrap_or(opts.numbers),
+            keys: self.keys.unwrap_or(opts.keys),
+            undefined: self.undefined.unwrap_or(opts.undefined),
+            max_depth: self.max_depth.unwrap_or(opts.max_depth),
+        }
+    }
+}
+
+impl<'a> Decoder<'a> for DecodeOverrides {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if !term.is_map() {
+            return Err(rustler::Error::BadArg);
+        }
+        let field = |name: &str| -> NifResult<Option<Term<'a>>> {
+            Ok(term.map_get(Atom::from_str(term.get_env(), name)?).ok())
+        };
+
+        Ok(DecodeOverrides {
+            sets: field("sets")?.map(Term::decode).transpose()?,
+            numbers: field("numbers")?.map(Term::decode).transpose()?,
+            keys: field("keys")?.map(Term::decode).transpose()?,
+            undefined: field("undefined")?.map(Term::decode).transpose()?,
+            max_depth: field("max_depth")?.map(Term::decode).transpose()?,
+        })
+    }
+}
+
+/// Convert the result of an evaluation, applying the engine's undefined mode
+pub(crate) fn result_to_term<'a>(
+    env: Env<'a>,
+    value: regorus::Value,
+    opts: &DecodeOptions,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    if value == regorus::Value::Undefined && opts.undefined == UndefinedEncoding::Error {
+        return Err((atoms::undefined(), "query result is undefined".to_string().into()));
+    }
+
+    value_to_term(env, value, opts).map_err(|(kind, message)| (kind, message.into()))
+}
+
+/// One step of `value_to_term`'s walk
+enum Step<'v> {
+    /// Convert a value found under `depth` collections
+    Value(&'v regorus::Value, usize),
+    /// Convert an object key
+    Key(&'v regorus::Value, usize),
+    /// Gather the last `len` terms into a list
+    Array(usize),
+    /// Gather the last `len` terms into a set
+    Set(usize),
+    /// Gather the last `2 * len` terms, alternating keys and values, into a map
+    Object(usize),
+}
+
+/// Convert a regorus value to an Elixir term.
+///
+/// Walks the value with an explicit stack rather than recursing, so deeply
+/// nested results can't overflow the scheduler's stack. Results nested deeper
+/// than `opts.max_depth` are a `limit_exceeded` error.
+pub(crate) fn value_to_term<'a>(
+    env: Env<'a>,
+    value: regorus::Value,
+    opts: &DecodeOptions,
+) -> Result<Term<'a>, (Atom, String)> {
+    let mut steps = vec![Step::Value(&value, 0)];
+    // Converted terms waiting to be gathered into their collection
+    let mut terms: Vec<Term<'a>> = Vec::new();
+
+    while let Some(step) = steps.pop() {
+        let (value, depth) = match step {
+            Step::Value(value, depth) => (value, depth),
+            Step::Key(key, depth) => match existing_atom(env, key, opts) {
+                Some(atom) => {
+                    terms.push(atom);
+                    continue;
+                }
+                None => (key, depth),
+            },
+            Step::Array(len) => {
+                let items = terms.split_off(terms.len() - len);
+                terms.push(items.encode(env));
+                continue;
+            }
+            Step::Set(len) => {
+                let items = terms.split_off(terms.len() - len);
+                terms.push(match opts.sets {
+                    SetEncoding::List => items.encode(env),
+                    SetEncoding::Tagged => (atoms::set(), items).encode(env),
+                });
+                continue;
+            }
+            Step::Object(len) => {
+                let entries = terms.split_off(terms.len() - 2 * len);
+                let pairs: Vec<(Term<'a>, Term<'a>)> = entries
+                    .chunks(2)
+                    .map(|entry| (entry[0], entry[1]))
+                    .collect();
+                terms.push(map_from_pairs::<String>(env, &pairs)?);
+                continue;
+            }
+        };
+
+        // Children are pushed in reverse so they're converted in order
+        let depth = depth + 1;
+        match value {
+            regorus::Value::Array(items) => {
+                check_depth(depth, opts)?;
+                steps.push(Step::Array(items.len()));
+                steps.extend(items.iter().rev().map(|item| Step::Value(item, depth)));
+            }
+            regorus::Value::Set(items) => {
+                check_depth(depth, opts)?;
+                steps.push(Step::Set(items.len()));
+                steps.extend(items.iter().rev().map(|item| Step::Value(item, depth)));
+            }
+            regorus::Value::Object(fields) => {
+                check_depth(depth, opts)?;
+                steps.push(Step::Object(fields.len()));
+                for (key, field) in fields.iter().rev() {
+                    steps.push(Step::Value(field, depth));
+                    steps.push(Step::Key(key, depth));
+                }
+            }
+            scalar => terms.push(scalar_to_term(env, scalar, opts)),
+        }
+    }
+
+    Ok(terms.pop().unwrap_or_else(|| atoms::undefined().encode(env)))
+}
+
+/// `Term::map_from_pairs`, with duplicate keys as an `engine_error`
+pub(crate) fn map_from_pairs<'a, E: From<String>>(
+    env: Env<'a>,
+    pairs: &[(Term<'a>, Term<'a>)],
+) -> Result<Term<'a>, (Atom, E)> {
+    Term::map_from_pairs(env, pairs)
+        .map_err(|_| (atoms::engine_error(), "duplicate map keys".to_string().into()))
+}
+
+fn check_depth(depth: usize, opts: &DecodeOptions) -> Result<(), (Atom, String)> {
+    match opts.max_depth {
+        Some(max) if depth > max => Err((
+            atoms::limit_exceeded(),
+            format!("result nesting exceeds {} levels", max),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn scalar_to_term<'a>(env: Env<'a>, value: &regorus::Value, opts: &DecodeOptions) -> Term<'a> {
+    match value {
+        regorus::Value::Undefined => match opts.undefined {
+            UndefinedEncoding::Nil => rustler::types::atom::nil().encode(env),
+            _ => atoms::undefined().encode(env),
+        },
+        regorus::Value::Bool(b) => b.encode(env),
+        regorus::Value::String(s) => s.encode(env),
+        regorus::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                return i.encode(env);
+            }
+
+            // Exact textual form of the number, as regorus serializes it
+            let text = value.to_json_str().ok();
+
+            if let Some(big) = text.as_deref().and_then(parse_big_integer) {
+                return big.encode(env);
+            }
+
+            match (opts.numbers, text) {
+                (NumberEncoding::String, Some(text)) => text.encode(env),
+                (NumberEncoding::Decimal, Some(text)) => (atoms::decimal(), text).encode(env),
+                _ => match n.as_f64() {
+                    Some(f) => f.encode(env),
+                    None => atoms::undefined().encode(env),
+                },
+            }
+        }
+        // Null; collections never get here
+        _ => rustler::types::atom::nil().encode(env),
+    }
+}
+
+/// The existing atom for a string key in `:existing_atoms` mode
+fn existing_atom<'a>(
+    env: Env<'a>,
+    key: &regorus::Value,
+    opts: &DecodeOptions,
+) -> Option<Term<'a>> {
+    match (opts.keys, key) {
+        (KeyEncoding::ExistingAtoms, regorus::Value::String(s)) => {
+            match Atom::try_from_bytes(env, s.as_bytes()) {
+                Ok(Some(atom)) => Some(atom.encode(env)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Longest integer `parse_big_integer` will expand an exponent into, so that
+/// `1e1000000000` can't allocate a gigabyte of zeros
+const MAX_INTEGER_DIGITS: usize = 4096;
+
+/// Parse an integer-valued number that doesn't fit in an i64, whether written
+/// plainly (`1208925819614629174706176`) or with a fraction or exponent
+/// (`1.208925819614629174706176e24`, `12e30`, `5.0e20`). Numbers with a
+/// fractional part, and integers that fit in an i64, give `None`.
+fn parse_big_integer(text: &str) -> Option<BigInt> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => {
+            let exponent = exponent.strip_prefix('+').unwrap_or(exponent);
+            (mantissa, exponent.parse::<i64>().ok()?)
+        }
+        None => (unsigned, 0),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if whole.is_empty() || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Shift the decimal point to the end of the digits
+    let mut digits = format!("{}{}", whole, fraction);
+    let shift = exponent.checked_sub(i64::try_from(fraction.len()).ok()?)?;
+    if shift < 0 {
+        let end = digits.len().checked_sub(usize::try_from(shift.unsigned_abs()).ok()?)?;
+        if !digits[end..].bytes().all(|b| b == b'0') {
+            return None;
+        }
+        digits.truncate(end);
+    } else {
+        let zeros = usize::try_from(shift).ok()?;
+        if digits.len().saturating_add(zeros) > MAX_INTEGER_DIGITS {
+            return None;
+        }
+        digits.push_str(&"0".repeat(zeros));
+    }
+
+    let mut big = digits.parse::<BigInt>().ok()?;
+    if negative {
+        big = -big;
+    }
+    // Small numbers that aren't plain integers, such as `3.0`, stay floats
+    i64::try_from(&big).is_err().then_some(big)
+}
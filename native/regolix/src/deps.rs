@@ -0,0 +1,451 @@
+use crate::error::catch_panic;
+use crate::graph::{resolve, NodeKind};
+use crate::rules::{parse_module, rule_infos};
+use crate::{atoms, EngineResource};
+use regorus::unstable::{BoolOp, Expr, Literal, Module, Query, Rule, RuleHead};
+use rustler::{Atom, ResourceArc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Referenced paths per rule, per policy file
+pub(crate) type Deps = HashMap<String, HashMap<String, Vec<String>>>;
+
+/// Names a policy reads documents through, each with the path it stands for:
+/// `data`, `input` and the aliases its `import data.a.b [as c]` lines define
+pub(crate) fn bases(module: &Module) -> HashMap<String, String> {
+    let roots = HashMap::from([
+        ("data".to_string(), "data".to_string()),
+        ("input".to_string(), "input".to_string()),
+    ]);
+    let mut bases = roots.clone();
+
+    for import in &module.imports {
+        // `import rego.v1` and `import future.keywords` name no document
+        let Some(found) = ref_path(import.refr.as_ref(), &roots) else {
+            continue;
+        };
+        let alias = match &import.r#as {
+            Some(alias) => alias.text().to_string(),
+            None => found
+                .path
+                .rsplit('.')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        };
+        bases.insert(alias, found.path);
+    }
+
+    bases
+}
+
+/// The static part of a reference, as found by `ref_path`
+struct RefPath<'e> {
+    path: String,
+    /// The first index that isn't a constant string, where the path stops
+    stop: Option<&'e Expr>,
+}
+
+/// The path `expr` reads if it's a reference starting at one of `bases`,
+/// following `.name` and `["name"]` segments up to the first variable or
+/// expression index
+fn ref_path<'e>(expr: &'e Expr, bases: &HashMap<String, String>) -> Option<RefPath<'e>> {
+    match expr {
+        Expr::Var { .. } => bases.get(expr.span().text()).map(|base| RefPath {
+            path: base.clone(),
+            stop: None,
+        }),
+        Expr::RefDot { refr, .. } => {
+            let mut found = ref_path(refr.as_ref(), bases)?;
+            if found.stop.is_none() {
+                // The field is the last segment of the reference as written
+                let field = expr.span().text().rsplit('.').next().unwrap_or_default();
+                found.path.push('.');
+                found.path.push_str(field.trim());
+            }
+            Some(found)
+        }
+        Expr::RefBrack { refr, index, .. } => {
+            let mut found = ref_path(refr.as_ref(), bases)?;
+            if found.stop.is_none() {
+                match string_key(index.as_ref()) {
+                    Some(key) => {
+                        found.path.push('.');
+                        found.path.push_str(&key);
+                    }
+                    None => found.stop = Some(index.as_ref()),
+                }
+            }
+            Some(found)
+        }
+        _ => None,
+    }
+}
+
+/// The key an index expression names, if it's a constant string
+fn string_key(index: &Expr) -> Option<String> {
+    let text = index.span().text();
+    match index {
+        Expr::String { .. } if text.starts_with('"') => serde_json::from_str(text).ok(),
+        Expr::String { .. } => serde_json::from_str(&format!("\"{}\"", text)).ok(),
+        Expr::RawString { .. } => Some(text.trim_matches('`').to_string()),
+        _ => None,
+    }
+}
+
+/// Type of a constant expression
+pub(crate) fn literal_type(expr: &Expr) -> Option<&'static str> {
+    match expr {
+        Expr::String { .. } | Expr::RawString { .. } => Some("string"),
+        Expr::Number { .. } => Some("number"),
+        Expr::True { .. } | Expr::False { .. } => Some("boolean"),
+        Expr::Null { .. } => Some("null"),
+        _ => None,
+    }
+}
+
+/// A reference to a path under one of the bases, as found by `scan_rule`
+pub(crate) struct Found {
+    pub path: String,
+    /// The type the reference's surroundings suggest it has: what it's
+    /// compared with, and whether it's indexed or iterated
+    pub hint: Option<&'static str>,
+}
+
+/// What `scan_rule` finds in a rule definition
+#[derive(Default)]
+pub(crate) struct RuleScan {
+    /// References to a path under one of the bases, in source order
+    pub refs: Vec<Found>,
+    pub comprehensions: usize,
+    /// Deepest nesting of bodies, comprehensions and collection literals
+    pub depth: usize,
+}
+
+/// Every reference in `rule` to a path under one of `bases`, along with its
+/// comprehensions and nesting.
+///
+/// A path built at runtime, e.g. `data.users[input.name]`, is reported up to
+/// its first dynamic segment (`data.users`). Local variables assigned a
+/// reference stand for its path, so `u := data.users; u.alice` reads
+/// `data.users.alice`, while `with` targets are left out since they are
+/// replaced rather than read.
+pub(crate) fn scan_rule(rule: &Rule, bases: &HashMap<String, String>) -> RuleScan {
+    let mut scanner = Scanner::default();
+
+    match rule {
+        Rule::Spec { head, bodies, .. } => {
+            let (refr, value): (_, Option<&Expr>) = match head {
+                RuleHead::Compr { refr, assign, .. } | RuleHead::Func { refr, assign, .. } => {
+                    (refr, assign.as_ref().map(|assign| assign.value.as_ref()))
+                }
+                RuleHead::Set { refr, key, .. } => (refr, key.as_ref().map(|key| key.as_ref())),
+            };
+
+            // The head is read with the variables of the first body; each
+            // `else` value with those of its own
+            let mut head_bases = None;
+            for body in bodies {
+                let locals = scanner.query(body.query.as_ref(), bases);
+                if let Some(assign) = &body.assign {
+                    scanner.expr(assign.value.as_ref(), &locals, None);
+                }
+                head_bases.get_or_insert(locals);
+            }
+
+            let bases = head_bases.as_ref().unwrap_or(bases);
+            scanner.head(refr.as_ref(), bases);
+            if let Some(value) = value {
+                scanner.expr(value, bases, None);
+            }
+        }
+        Rule::Default { value, .. } => scanner.expr(value.as_ref(), bases, None),
+    }
+
+    scanner.scan
+}
+
+#[derive(Default)]
+struct Scanner {
+    scan: RuleScan,
+    /// Current nesting, see `RuleScan::depth`
+    level: usize,
+}
+
+impl Scanner {
+    fn enter(&mut self) {
+        self.level += 1;
+        self.scan.depth = self.scan.depth.max(self.level);
+    }
+
+    /// Scan `query`, returning `bases` along with the local variables it
+    /// assigns references to
+    fn query(&mut self, query: &Query, bases: &HashMap<String, String>) -> HashMap<String, String> {
+        let mut locals = bases.clone();
+        self.enter();
+
+        for stmt in &query.stmts {
+            match &stmt.literal {
+                Literal::Expr { expr, .. } | Literal::NotExpr { expr, .. } => {
+                    self.expr(expr.as_ref(), &locals, None);
+                    assign(expr.as_ref(), &mut locals);
+                }
+                Literal::SomeIn {
+                    key,
+                    value,
+                    collection,
+                    ..
+                } => {
+                    self.expr(collection.as_ref(), &locals, Some("array"));
+                    // The loop variables hide any base of the same name
+                    for var in key.iter().chain([value]) {
+                        if let Expr::Var { .. } = var.as_ref() {
+                            locals.remove(var.span().text());
+                        }
+                    }
+                }
+                Literal::Every { domain, query, .. } => {
+                    self.expr(domain.as_ref(), &locals, Some("array"));
+                    self.query(query.as_ref(), &locals);
+                }
+                Literal::SomeVars { .. } => {}
+            }
+
+            for modifier in &stmt.with_mods {
+                self.expr(modifier.r#as.as_ref(), &locals, None);
+            }
+        }
+
+        self.level -= 1;
+        locals
+    }
+
+    /// Scan the indexes of a rule's head, as in `p[input.key] := ...`; the
+    /// rest of it names the rule being defined
+    fn head(&mut self, refr: &Expr, bases: &HashMap<String, String>) {
+        match refr {
+            Expr::RefDot { refr, .. } => self.head(refr.as_ref(), bases),
+            Expr::RefBrack { refr, index, .. } => {
+                self.head(refr.as_ref(), bases);
+                self.expr(index.as_ref(), bases, None);
+            }
+            _ => {}
+        }
+    }
+
+    /// Scan `expr`, whose surroundings suggest it has type `hint`
+    fn expr(&mut self, expr: &Expr, bases: &HashMap<String, String>, hint: Option<&'static str>) {
+        if let Some(found) = ref_path(expr, bases) {
+            let hint = match found.stop {
+                // Indexed by position or iterated with `_`
+                Some(index) if literal_type(index).is_some() || index.span().text() == "_" => {
+                    Some("array")
+                }
+                Some(_) => Some("object"),
+                None => hint,
+            };
+            self.scan.refs.push(Found {
+                path: found.path,
+                hint,
+            });
+            self.indexes(expr, bases);
+            return;
+        }
+
+        match expr {
+            Expr::Array { items, .. } | Expr::Set { items, .. } => {
+                self.enter();
+                for item in items {
+                    self.expr(item.as_ref(), bases, None);
+                }
+                self.level -= 1;
+            }
+            Expr::Object { fields, .. } => {
+                self.enter();
+                for (_, key, value) in fields {
+                    self.expr(key.as_ref(), bases, None);
+                    self.expr(value.as_ref(), bases, None);
+                }
+                self.level -= 1;
+            }
+            Expr::ArrayCompr { term, query, .. } | Expr::SetCompr { term, query, .. } => {
+                self.scan.comprehensions += 1;
+                let locals = self.query(query.as_ref(), bases);
+                self.expr(term.as_ref(), &locals, None);
+            }
+            Expr::ObjectCompr {
+                key, value, query, ..
+            } => {
+                self.scan.comprehensions += 1;
+                let locals = self.query(query.as_ref(), bases);
+                self.expr(key.as_ref(), &locals, None);
+                self.expr(value.as_ref(), &locals, None);
+            }
+            Expr::Call { fcn, params, .. } => {
+                self.expr(fcn.as_ref(), bases, None);
+                for param in params {
+                    self.expr(param.as_ref(), bases, None);
+                }
+            }
+            Expr::UnaryExpr { expr, .. } => self.expr(expr.as_ref(), bases, None),
+            Expr::RefDot { refr, .. } => self.expr(refr.as_ref(), bases, None),
+            Expr::RefBrack { refr, index, .. } => {
+                self.expr(refr.as_ref(), bases, None);
+                self.expr(index.as_ref(), bases, None);
+            }
+            Expr::BoolExpr { op, lhs, rhs, .. } => {
+                let lhs_hint = comparison_hint(op, rhs.as_ref());
+                let rhs_hint = comparison_hint(op, lhs.as_ref());
+                self.expr(lhs.as_ref(), bases, lhs_hint);
+                self.expr(rhs.as_ref(), bases, rhs_hint);
+            }
+            Expr::BinExpr { lhs, rhs, .. } | Expr::ArithExpr { lhs, rhs, .. } => {
+                self.expr(lhs.as_ref(), bases, None);
+                self.expr(rhs.as_ref(), bases, None);
+            }
+            Expr::AssignExpr { lhs, rhs, .. } => {
+                // A variable on the left is being assigned, not read
+                if !matches!(lhs.as_ref(), Expr::Var { .. }) {
+                    self.expr(lhs.as_ref(), bases, None);
+                }
+                self.expr(rhs.as_ref(), bases, None);
+            }
+            Expr::Membership {
+                key,
+                value,
+                collection,
+                ..
+            } => {
+                for item in key.iter().chain([value]) {
+                    self.expr(item.as_ref(), bases, None);
+                }
+                self.expr(collection.as_ref(), bases, Some("array"));
+            }
+            _ => {}
+        }
+    }
+
+    /// Scan the variable and expression indexes of a reference
+    fn indexes(&mut self, refr: &Expr, bases: &HashMap<String, String>) {
+        match refr {
+            Expr::RefDot { refr, .. } => self.indexes(refr.as_ref(), bases),
+            Expr::RefBrack { refr, index, .. } => {
+                self.indexes(refr.as_ref(), bases);
+                self.expr(index.as_ref(), bases, None);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What comparing a reference with `other` says about its type
+fn comparison_hint(op: &BoolOp, other: &Expr) -> Option<&'static str> {
+    match literal_type(other) {
+        Some(kind) => Some(kind),
+        // Ordering comparisons are almost always on numbers
+        None if !matches!(op, BoolOp::Eq | BoolOp::Ne) => Some("number"),
+        None => None,
+    }
+}
+
+/// Record a local variable assigned a reference, as in `x := data.a.b`, or
+/// forget one assigned anything else
+fn assign(expr: &Expr, locals: &mut HashMap<String, String>) {
+    let Expr::AssignExpr { lhs, rhs, .. } = expr else {
+        return;
+    };
+    if !matches!(lhs.as_ref(), Expr::Var { .. }) {
+        return;
+    }
+
+    let name = lhs.span().text().to_string();
+    match ref_path(rhs.as_ref(), locals) {
+        Some(found) if found.stop.is_none() => locals.insert(name, found.path),
+        _ => locals.remove(&name),
+    };
+}
+
+/// Every path under `root` (`data` or `input`) read by each rule of each
+/// loaded policy.
+///
+/// Works from the parsed policies rather than the evaluator, see `scan_rule`
+/// for what counts as a read. When `skip_packages` is set, references to
+/// loaded rules, or to packages or prefixes holding them, are left out,
+/// since they aren't documents that have to be provided.
+pub(crate) fn ref_deps(
+    resource: &EngineResource,
+    root: &str,
+    skip_packages: bool,
+) -> Result<Deps, (Atom, String)> {
+    let policies = resource
+        .policies
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+    let mut parsed = Vec::new();
+    for (name, policy) in policies.iter() {
+        let module = parse_module(name, &policy.source)
+            .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+        let rules = rule_infos(&module, &policy.source);
+        parsed.push((name, policy, module, rules));
+    }
+
+    let rule_ids: BTreeSet<String> = parsed
+        .iter()
+        .flat_map(|(_, policy, _, rules)| {
+            rules
+                .iter()
+                .map(|rule| format!("{}.{}", policy.package, rule.name))
+        })
+        .collect();
+    // Data stored under a package, such as `data.authz.config` next to the
+    // rules of `package authz`, is still a document to provide
+    let is_rule = |path: &str| {
+        skip_packages
+            && resolve(path, &rule_ids)
+                .iter()
+                .any(|(_, kind)| *kind == NodeKind::Rule)
+    };
+    let prefix = format!("{}.", root);
+    let under_root = |path: &str| path == root || path.starts_with(&prefix);
+
+    let mut deps = Deps::new();
+
+    for (name, _, module, rules) in &parsed {
+        let bases = bases(module);
+
+        let mut by_rule: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for (rule, info) in module.policy.iter().zip(rules) {
+            for found in scan_rule(rule.as_ref(), &bases).refs {
+                if under_root(&found.path) && !is_rule(&found.path) {
+                    by_rule
+                        .entry(info.name.clone())
+                        .or_default()
+                        .insert(found.path);
+                }
+            }
+        }
+
+        deps.insert(
+            name.to_string(),
+            by_rule
+                .into_iter()
+                .map(|(rule, paths)| (rule, paths.into_iter().collect()))
+                .collect(),
+        );
+    }
+
+    Ok(deps)
+}
+
+/// Every `data.*` path each rule of each loaded policy reads, leaving out
+/// references to other rules
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_data_deps(resource: ResourceArc<EngineResource>) -> Result<Deps, (Atom, String)> {
+    catch_panic(|| ref_deps(&resource, "data", true))
+}
+
+/// Every `input.*` path each rule of each loaded policy reads
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_input_deps(resource: ResourceArc<EngineResource>) -> Result<Deps, (Atom, String)> {
+    catch_panic(|| ref_deps(&resource, "input", false))
+}
@@ -0,0 +1,72 @@
+use crate::atoms;
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::lint::tokenize;
+use crate::rules::parse_rules;
+use rustler::{Atom, NifMap};
+use std::collections::BTreeMap;
+
+#[derive(NifMap)]
+struct PolicyDiff {
+    /// Rules only in the new source
+    added: Vec<String>,
+    /// Rules only in the old source
+    removed: Vec<String>,
+    /// Rules in both whose definitions differ
+    changed: Vec<String>,
+}
+
+/// Every definition of each rule in a policy, as token text.
+///
+/// Comparing tokens ignores whitespace and comments, so reformatting a rule
+/// doesn't count as changing it. Definitions sharing a ref (including
+/// `default` rules and incremental definitions) are grouped together.
+fn rule_bodies(
+    name: &str,
+    source: &str,
+) -> Result<BTreeMap<String, Vec<String>>, (Atom, ErrorDetail)> {
+    let rules = parse_rules(name, source).map_err(|e| located_error(atoms::parse_error(), e))?;
+    let tokens = tokenize(source);
+
+    let mut bodies: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for rule in rules {
+        let text: Vec<&str> = tokens
+            .iter()
+            .filter(|t| t.line >= rule.start_line && t.line <= rule.end_line)
+            .map(|t| t.text)
+            .collect();
+
+        bodies.entry(rule.name).or_default().push(text.join(" "));
+    }
+
+    for definitions in bodies.values_mut() {
+        definitions.sort();
+    }
+
+    Ok(bodies)
+}
+
+/// Compare two versions of a policy rule by rule
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_diff_policies(
+    old_source: String,
+    new_source: String,
+) -> Result<PolicyDiff, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let old = rule_bodies("old.rego", &old_source)?;
+        let new = rule_bodies("new.rego", &new_source)?;
+
+        let added = new.keys().filter(|name| !old.contains_key(*name)).cloned().collect();
+        let removed = old.keys().filter(|name| !new.contains_key(*name)).cloned().collect();
+        let changed = old
+            .iter()
+            .filter(|(name, body)| new.get(*name).is_some_and(|other| other != *body))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        Ok(PolicyDiff {
+            added,
+            removed,
+            changed,
+        })
+    })
+}
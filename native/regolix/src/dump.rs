@@ -0,0 +1,191 @@
+use crate::bundle::Manifest;
+use crate::cache::DecisionCache;
+use crate::clock::Clock;
+use crate::decode::DecodeOptions;
+use crate::error::catch_panic;
+use crate::keys::VerificationKey;
+use crate::limits::Limits;
+use crate::stats::EvalStats;
+use crate::{
+    atoms, rebuild_engine, EngineResource, EngineSettings, PolicySource, QueryCache, RegoVersion,
+};
+use arc_swap::ArcSwap;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rustler::{Atom, Binary, Env, OwnedBinary, ResourceArc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Mutex, RwLock};
+
+/// Bumped whenever `EngineDump` changes shape
+const FORMAT_VERSION: u32 = 1;
+
+/// Everything needed to recreate an engine, minus extensions (which point at
+/// processes) and coverage or profiling data
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EngineDump {
+    version: u32,
+    policies: HashMap<String, PolicySource>,
+    data: regorus::Value,
+    input: Option<regorus::Value>,
+    coverage_enabled: bool,
+    strict_builtin_errors: bool,
+    rego_version: RegoVersion,
+    decode: DecodeOptions,
+    limits: Limits,
+    #[serde(default)]
+    disabled_builtins: Vec<String>,
+    #[serde(default)]
+    clock: Clock,
+    #[serde(default)]
+    random_seed: Option<u64>,
+    #[serde(default)]
+    runtime: Option<regorus::Value>,
+    #[serde(default)]
+    bundles: HashMap<String, Manifest>,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    keys: BTreeMap<String, VerificationKey>,
+}
+
+impl EngineDump {
+    /// Everything needed to recreate `resource`. The caller holds the writer
+    /// lock, so the data matches the policies.
+    pub(crate) fn capture(resource: &EngineResource) -> Result<EngineDump, (Atom, String)> {
+        let settings = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        Ok(EngineDump {
+            version: FORMAT_VERSION,
+            policies: resource
+                .policies
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?
+                .clone(),
+            data: resource.snapshot().get_data(),
+            input: resource
+                .input
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?
+                .clone(),
+            coverage_enabled: settings.coverage_enabled,
+            strict_builtin_errors: settings.strict_builtin_errors,
+            rego_version: settings.rego_version,
+            decode: *resource
+                .decode
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?,
+            limits: *resource
+                .limits
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?,
+            disabled_builtins: settings.disabled_builtins.clone(),
+            clock: settings.clock,
+            random_seed: settings.random_seed,
+            runtime: settings.runtime.clone(),
+            bundles: resource
+                .bundles
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?
+                .clone(),
+            label: settings.label.clone(),
+            keys: resource
+                .keys
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?
+                .clone(),
+        })
+    }
+
+    /// A new engine with the state captured
+    pub(crate) fn restore(self) -> Result<EngineResource, (Atom, String)> {
+        if self.version != FORMAT_VERSION {
+            return Err((
+                atoms::engine_error(),
+                format!("unsupported dump version {}, expected {}", self.version, FORMAT_VERSION),
+            ));
+        }
+
+        let settings = EngineSettings {
+            coverage_enabled: self.coverage_enabled,
+            strict_builtin_errors: self.strict_builtin_errors,
+            rego_version: self.rego_version,
+            extensions: Vec::new(),
+            disabled_builtins: self.disabled_builtins,
+            clock: self.clock,
+            random_seed: self.random_seed,
+            runtime: self.runtime,
+            label: self.label,
+        };
+
+        let engine = rebuild_engine(&self.policies, self.data, self.input.clone(), &settings)?;
+
+        Ok(EngineResource {
+            engine: ArcSwap::from_pointee(engine),
+            writer: Mutex::new(()),
+            release_pending: AtomicBool::new(false),
+            policies: RwLock::new(self.policies),
+            input: RwLock::new(self.input),
+            settings: RwLock::new(settings),
+            decode: RwLock::new(self.decode),
+            limits: RwLock::new(self.limits),
+            queries: RwLock::new(QueryCache::default()),
+            profile: Mutex::new(None),
+            bundles: RwLock::new(self.bundles),
+            stats: Mutex::new(EvalStats::default()),
+            owner: Mutex::new(None),
+            decisions: Mutex::new(DecisionCache::default()),
+            recorder: Mutex::new(None),
+            keys: RwLock::new(self.keys),
+            store: Mutex::new(None),
+        })
+    }
+}
+
+/// Serialize an engine to gzipped JSON
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_dump<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+) -> Result<Binary<'a>, (Atom, String)> {
+    catch_panic(|| {
+        // Hold off writers so the data matches the policies
+        let _writer = resource.lock_writer();
+        let dump = EngineDump::capture(&resource)?;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, &dump)
+            .map_err(|e| (atoms::json_error(), e.to_string()))?;
+        let bytes = encoder
+            .finish()
+            .map_err(|e| (atoms::io_error(), e.to_string()))?;
+
+        let mut binary = OwnedBinary::new(bytes.len())
+            .ok_or_else(|| (atoms::engine_error(), "failed to allocate binary".to_string()))?;
+        binary.as_mut_slice().copy_from_slice(&bytes);
+
+        Ok(binary.release(env))
+    })
+}
+
+/// Recreate an engine from a `native_dump` blob
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_restore(blob: Binary) -> Result<ResourceArc<EngineResource>, (Atom, String)> {
+    catch_panic(|| {
+        let mut json = Vec::new();
+        GzDecoder::new(blob.as_slice())
+            .read_to_end(&mut json)
+            .map_err(|e| (atoms::io_error(), format!("not an engine dump: {}", e)))?;
+
+        let dump: EngineDump = serde_json::from_slice(&json)
+            .map_err(|e| (atoms::json_error(), format!("not an engine dump: {}", e)))?;
+
+        Ok(ResourceArc::new(dump.restore()?))
+    })
+}
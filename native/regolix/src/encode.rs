@@ -0,0 +1,529 @@
+use crate::atoms;
+use crate::error::{ErrorDetail, ErrorPath};
+use crate::limits::MAX_JSON_DEPTH;
+use rustler::types::map::MapIterator;
+use rustler::types::tuple::get_tuple;
+use rustler::{Atom, BigInt, Binary, Decoder, NifMap, NifUnitEnum, Term, TermType};
+
+/// How structs without a Rego counterpart are converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum)]
+pub(crate) enum StructEncoding {
+    /// An object of the struct's fields, without `__struct__`
+    #[default]
+    Map,
+    /// An error, so structs aren't converted by accident
+    Strict,
+}
+
+/// How tuples other than `{:set, list}` and `{:decimal, string}` are
+/// converted, since JSON and Rego have nothing like them
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum)]
+pub(crate) enum TupleEncoding {
+    /// An error
+    #[default]
+    Error,
+    /// An array of the elements
+    List,
+    /// A list of 2-tuples becomes an object with the first elements as keys,
+    /// like a keyword list; other tuples are an error
+    Pairs,
+}
+
+/// How charlists, which are indistinguishable from lists of integers, and
+/// iodata in improper lists are converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum)]
+pub(crate) enum CharlistEncoding {
+    /// An array of integers; improper lists are an error
+    #[default]
+    List,
+    /// A string, for non-empty lists of printable characters and for
+    /// improper lists that are valid iodata
+    String,
+    /// An error for non-empty lists of printable characters, so they aren't
+    /// taken for numbers by accident
+    Error,
+}
+
+/// How binaries that aren't valid UTF-8 are converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum)]
+pub(crate) enum Utf8Encoding {
+    /// An error naming where the binary is
+    #[default]
+    Error,
+    /// A string with each invalid sequence replaced by U+FFFD
+    Lossy,
+}
+
+/// Options controlling how Elixir terms become regorus values
+#[derive(Clone, Copy, Debug, Default, NifMap)]
+pub(crate) struct EncodeOptions {
+    pub structs: StructEncoding,
+    pub tuples: TupleEncoding,
+    pub charlists: CharlistEncoding,
+    pub invalid_utf8: Utf8Encoding,
+}
+
+/// Where a term sits inside the one being converted
+#[derive(Debug)]
+enum Segment {
+    Index(usize),
+    Key(regorus::Value),
+}
+
+/// A term that couldn't be converted, and where it is
+#[derive(Debug)]
+pub(crate) struct EncodeError {
+    /// Innermost first, as the error is passed up
+    path: Vec<Segment>,
+    /// What's wrong with the term, worded to follow its path, e.g.
+    /// `is an improper list`
+    problem: String,
+}
+
+impl EncodeError {
+    fn new(problem: impl Into<String>) -> Self {
+        EncodeError {
+            path: Vec::new(),
+            problem: problem.into(),
+        }
+    }
+
+    fn at(mut self, segment: Segment) -> Self {
+        self.path.push(segment);
+        self
+    }
+
+    /// Describe the error with the path starting from `root`, e.g.
+    /// `input.users[3] is an improper list`
+    pub fn describe(&self, root: &str) -> String {
+        format!("{} {}", self.path(root), self.problem)
+    }
+
+    /// The path to the term, starting from `root`, e.g. `input.users[3]`
+    pub fn path(&self, root: &str) -> String {
+        let mut text = root.to_string();
+        for segment in self.path.iter().rev() {
+            match segment {
+                Segment::Index(i) => text.push_str(&format!("[{}]", i)),
+                Segment::Key(regorus::Value::String(key)) if is_identifier(key) => {
+                    text.push('.');
+                    text.push_str(key);
+                }
+                Segment::Key(key) => {
+                    let key = serde_json::to_string(key).unwrap_or_else(|_| "?".to_string());
+                    text.push_str(&format!("[{}]", key));
+                }
+            }
+        }
+        text
+    }
+
+    /// The error as a NIF error payload, with the path starting from `root`
+    pub fn detail(&self, root: &str) -> ErrorDetail {
+        ErrorDetail::AtPath(ErrorPath {
+            message: self.describe(root),
+            path: self.path(root),
+        })
+    }
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Convert an Elixir term to a regorus value without going through JSON.
+///
+/// Accepts what `value_to_term` produces: maps, lists, binaries, numbers,
+/// booleans and `nil`, plus `{:set, list}`, `MapSet`s, `{:decimal, string}`
+/// and `:undefined`. Other atoms become strings, as with Jason. Keyword
+/// lists become objects, and other tuples are handled according to
+/// `opts.tuples`, charlists according to `opts.charlists`. `DateTime` and
+/// `NaiveDateTime` become ISO 8601 strings, `Decimal`s exact numbers, and
+/// other structs are handled according to `opts.structs`.
+///
+/// Terms nested more than `MAX_JSON_DEPTH` levels deep are rejected, as with
+/// JSON, so a hostile term can't overflow the scheduler's stack.
+pub(crate) fn term_to_value(
+    term: Term,
+    opts: &EncodeOptions,
+) -> Result<regorus::Value, EncodeError> {
+    convert(term, opts, 0)
+}
+
+/// `term_to_value` for a term nested `depth` levels down
+fn convert(term: Term, opts: &EncodeOptions, depth: usize) -> Result<regorus::Value, EncodeError> {
+    if depth > MAX_JSON_DEPTH {
+        return Err(EncodeError::new(format!(
+            "is nested more than {} levels deep",
+            MAX_JSON_DEPTH
+        )));
+    }
+
+    match term.get_type() {
+        TermType::Atom => {
+            let name = term
+                .atom_to_string()
+                .map_err(|_| EncodeError::new("is an invalid atom"))?;
+            Ok(match name.as_str() {
+                "nil" => regorus::Value::Null,
+                "true" => regorus::Value::Bool(true),
+                "false" => regorus::Value::Bool(false),
+                "undefined" => regorus::Value::Undefined,
+                _ => regorus::Value::from(name),
+            })
+        }
+        TermType::Binary => {
+            let binary = term
+                .decode::<Binary>()
+                .map_err(|_| EncodeError::new("is a bitstring, which can't be converted"))?;
+            utf8_to_value(binary.as_slice(), opts)
+        }
+        TermType::Integer => {
+            if let Ok(i) = term.decode::<i64>() {
+                return Ok(regorus::Value::from(i));
+            }
+            let big = term
+                .decode::<BigInt>()
+                .map_err(|_| EncodeError::new("is an invalid integer"))?;
+            regorus::Value::from_json_str(&big.to_string())
+                .map_err(|e| EncodeError::new(format!("is an invalid integer: {}", e)))
+        }
+        TermType::Float => term
+            .decode::<f64>()
+            .map(regorus::Value::from)
+            .map_err(|_| EncodeError::new("is an invalid float")),
+        TermType::List => list_to_value(term, opts, depth),
+        TermType::Map => map_to_value(term, opts, depth),
+        TermType::Tuple => tuple_to_value(term, opts, depth),
+        TermType::Pid => Err(EncodeError::new("is a pid, which can't be converted")),
+        TermType::Port => Err(EncodeError::new("is a port, which can't be converted")),
+        TermType::Ref => Err(EncodeError::new("is a reference, which can't be converted")),
+        TermType::Fun => Err(EncodeError::new("is a function, which can't be converted")),
+        _ => Err(EncodeError::new("can't be converted")),
+    }
+}
+
+fn list_to_value(
+    term: Term,
+    opts: &EncodeOptions,
+    depth: usize,
+) -> Result<regorus::Value, EncodeError> {
+    let Ok(items) = term.decode::<Vec<Term>>() else {
+        // Improper lists are only valid as iodata, e.g. `["ab" | "c"]`
+        if opts.charlists == CharlistEncoding::String {
+            if let Ok(binary) = Binary::from_iolist(term) {
+                return utf8_to_value(binary.as_slice(), opts);
+            }
+        }
+        return Err(EncodeError::new("is an improper list"));
+    };
+
+    if let Some(text) = charlist_text(&items) {
+        match opts.charlists {
+            CharlistEncoding::List => {}
+            CharlistEncoding::String => return Ok(regorus::Value::from(text)),
+            CharlistEncoding::Error => {
+                return Err(EncodeError::new(format!("is a charlist ('{}')", text)))
+            }
+        }
+    }
+
+    let any_keys = opts.tuples == TupleEncoding::Pairs;
+    if is_entries(&items, any_keys) {
+        return entries_to_value(items, opts, depth);
+    }
+
+    let items = items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| convert(item, opts, depth + 1).map_err(|e| e.at(Segment::Index(i))))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(regorus::Value::from(items))
+}
+
+/// Convert the contents of a binary to a string, according to
+/// `opts.invalid_utf8` if they aren't valid UTF-8
+fn utf8_to_value(bytes: &[u8], opts: &EncodeOptions) -> Result<regorus::Value, EncodeError> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(regorus::Value::from(text)),
+        Err(_) if opts.invalid_utf8 == Utf8Encoding::Lossy => {
+            let text = String::from_utf8_lossy(bytes).into_owned();
+            Ok(regorus::Value::from(text))
+        }
+        Err(e) => Err(EncodeError::new(format!(
+            "is not valid UTF-8: invalid byte at offset {}",
+            e.valid_up_to()
+        ))),
+    }
+}
+
+/// The text of `items` if it's a non-empty list of printable characters, as
+/// with `List.printable?/1`
+fn charlist_text(items: &[Term]) -> Option<String> {
+    if items.is_empty() {
+        return None;
+    }
+    items
+        .iter()
+        .map(|item| {
+            let c = char::from_u32(item.decode::<u32>().ok()?)?;
+            let printable = !c.is_control() || "\n\r\t\x0b\x08\x0c\x1b\x7f\x07".contains(c);
+            printable.then_some(c)
+        })
+        .collect()
+}
+
+/// Whether `items` is a non-empty list of object entries: a keyword list, or
+/// with `any_keys` any list of 2-tuples.
+///
+/// `{:set, list}` and `{:decimal, string}` keep their meaning as values, so a
+/// list holding any of them is an array.
+fn is_entries(items: &[Term], any_keys: bool) -> bool {
+    !items.is_empty()
+        && items.iter().all(|item| match get_tuple(*item).as_deref() {
+            Ok([key, value]) => match key.decode::<Atom>() {
+                Ok(key) if key == atoms::set() => !value.is_list(),
+                Ok(key) if key == atoms::decimal() => !value.is_binary(),
+                Ok(_) => true,
+                Err(_) => any_keys,
+            },
+            _ => false,
+        })
+}
+
+/// Convert a list of 2-tuples to an object. As with `Keyword.get/2`, the
+/// first entry for a key wins.
+fn entries_to_value(
+    items: Vec<Term>,
+    opts: &EncodeOptions,
+    depth: usize,
+) -> Result<regorus::Value, EncodeError> {
+    let mut object = regorus::Value::new_object();
+    let fields = object
+        .as_object_mut()
+        .map_err(|e| EncodeError::new(e.to_string()))?;
+
+    for (i, item) in items.into_iter().enumerate() {
+        let entry = get_tuple(item).map_err(|_| EncodeError::new("is an invalid entry"))?;
+        let [key, value] = entry[..] else {
+            return Err(EncodeError::new("is an invalid entry").at(Segment::Index(i)));
+        };
+        let key = convert(key, opts, depth + 1).map_err(|e| e.at(Segment::Index(i)))?;
+        if !fields.contains_key(&key) {
+            let value =
+                convert(value, opts, depth + 1).map_err(|e| e.at(Segment::Key(key.clone())))?;
+            fields.insert(key, value);
+        }
+    }
+
+    Ok(object)
+}
+
+fn map_to_value(
+    term: Term,
+    opts: &EncodeOptions,
+    depth: usize,
+) -> Result<regorus::Value, EncodeError> {
+    let struct_key = atom(term, "__struct__").map_err(EncodeError::new)?;
+
+    if let Ok(module) = term.map_get(struct_key) {
+        let module = module
+            .atom_to_string()
+            .map_err(|_| EncodeError::new("is an invalid struct"))?;
+        let name = module.strip_prefix("Elixir.").unwrap_or(&module);
+
+        match name {
+            "MapSet" => {
+                // A MapSet keeps its members as the keys of its `map` field
+                let iter = field(term, "map")
+                    .ok()
+                    .and_then(MapIterator::new)
+                    .ok_or_else(|| EncodeError::new("is an invalid MapSet"))?;
+                return set_from(iter.map(|(member, _)| member), opts, depth);
+            }
+            "DateTime" | "NaiveDateTime" => {
+                return date_time_to_value(term, name).map_err(EncodeError::new)
+            }
+            "Decimal" => return decimal_to_value(term).map_err(EncodeError::new),
+            _ if opts.structs == StructEncoding::Strict => {
+                return Err(EncodeError::new(format!(
+                    "is a {} struct, which strict mode rejects",
+                    name
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    let iter = MapIterator::new(term).ok_or_else(|| EncodeError::new("is an invalid map"))?;
+    let mut object = regorus::Value::new_object();
+    let fields = object
+        .as_object_mut()
+        .map_err(|e| EncodeError::new(e.to_string()))?;
+
+    for (key, value) in iter {
+        if key.decode::<Atom>().ok() == Some(struct_key) {
+            continue;
+        }
+        let key = convert(key, opts, depth + 1)?;
+        let value = convert(value, opts, depth + 1).map_err(|e| e.at(Segment::Key(key.clone())))?;
+        fields.insert(key, value);
+    }
+
+    Ok(object)
+}
+
+fn atom(term: Term, name: &str) -> Result<Atom, String> {
+    Atom::from_str(term.get_env(), name).map_err(|_| "is an invalid atom".to_string())
+}
+
+/// The value of a struct's field
+fn field<'a>(term: Term<'a>, name: &str) -> Result<Term<'a>, String> {
+    term.map_get(atom(term, name)?)
+        .map_err(|_| format!("missing field {}", name))
+}
+
+fn decode_field<'a, T: Decoder<'a>>(term: Term<'a>, name: &str) -> Result<T, String> {
+    field(term, name)?
+        .decode()
+        .map_err(|_| format!("invalid field {}", name))
+}
+
+/// Write a `DateTime` or `NaiveDateTime` as `DateTime.to_iso8601/1` and
+/// `NaiveDateTime.to_iso8601/1` do. A `DateTime` is a valid RFC 3339
+/// timestamp; a `NaiveDateTime` has no offset, so it can't be one.
+fn date_time_to_value(term: Term, name: &str) -> Result<regorus::Value, String> {
+    let invalid = |e: String| format!("is an invalid {}: {}", name, e);
+
+    let calendar = field(term, "calendar")
+        .and_then(|calendar| {
+            calendar
+                .atom_to_string()
+                .map_err(|_| "bad calendar".to_string())
+        })
+        .map_err(invalid)?;
+    if calendar != "Elixir.Calendar.ISO" {
+        return Err(format!(
+            "is a {} in calendar {}, which is not supported",
+            name, calendar
+        ));
+    }
+
+    let part = |field: &str| decode_field::<i64>(term, field).map_err(invalid);
+    let year = part("year")?;
+    let mut text = format!(
+        "{}{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        if year < 0 { "-" } else { "" },
+        year.abs(),
+        part("month")?,
+        part("day")?,
+        part("hour")?,
+        part("minute")?,
+        part("second")?,
+    );
+
+    let (micros, precision): (u32, usize) = decode_field(term, "microsecond").map_err(invalid)?;
+    if precision > 0 {
+        let digits = format!("{:06}", micros);
+        text.push('.');
+        text.push_str(&digits[..precision.min(6)]);
+    }
+
+    if name == "DateTime" {
+        let offset = part("utc_offset")? + part("std_offset")?;
+        if offset == 0 {
+            text.push('Z');
+        } else {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let minutes = offset.abs() / 60;
+            text.push_str(&format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60));
+        }
+    }
+
+    Ok(regorus::Value::from(text))
+}
+
+/// Convert a `Decimal` to an exact number
+fn decimal_to_value(term: Term) -> Result<regorus::Value, String> {
+    let invalid = |e: String| format!("is an invalid Decimal: {}", e);
+
+    let sign: i64 = decode_field(term, "sign").map_err(invalid)?;
+    let exp: i64 = decode_field(term, "exp").map_err(invalid)?;
+    let coef = field(term, "coef").map_err(invalid)?;
+    let coef = match coef.decode::<BigInt>() {
+        Ok(coef) => coef,
+        Err(_) => {
+            let special = coef.atom_to_string().unwrap_or_default();
+            return Err(format!(
+                "is a Decimal {}, which has no Rego representation",
+                special
+            ));
+        }
+    };
+
+    let text = format!("{}{}e{}", if sign < 0 { "-" } else { "" }, coef, exp);
+    regorus::Value::from_json_str(&text).map_err(|e| invalid(e.to_string()))
+}
+
+fn tuple_to_value(
+    term: Term,
+    opts: &EncodeOptions,
+    depth: usize,
+) -> Result<regorus::Value, EncodeError> {
+    let elements = get_tuple(term).map_err(|_| EncodeError::new("is an invalid tuple"))?;
+
+    match elements.as_slice() {
+        [tag, items] if tag.decode::<Atom>().ok() == Some(atoms::set()) => {
+            let items = items
+                .decode::<Vec<Term>>()
+                .map_err(|_| EncodeError::new("is not a valid {:set, list}"))?;
+            set_from(items.into_iter(), opts, depth)
+        }
+        [tag, text] if tag.decode::<Atom>().ok() == Some(atoms::decimal()) => {
+            let text = text
+                .decode::<String>()
+                .map_err(|_| EncodeError::new("is not a valid {:decimal, string}"))?;
+            match regorus::Value::from_json_str(&text) {
+                Ok(number @ regorus::Value::Number(_)) => Ok(number),
+                _ => Err(EncodeError::new(format!(
+                    "is not a decimal number: `{}`",
+                    text
+                ))),
+            }
+        }
+        elements if opts.tuples == TupleEncoding::List => {
+            let items = elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| {
+                    convert(*element, opts, depth + 1).map_err(|e| e.at(Segment::Index(i)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(regorus::Value::from(items))
+        }
+        _ => Err(EncodeError::new(
+            "is a tuple, and only {:set, list} and {:decimal, string} are supported",
+        )),
+    }
+}
+
+fn set_from<'a>(
+    members: impl Iterator<Item = Term<'a>>,
+    opts: &EncodeOptions,
+    depth: usize,
+) -> Result<regorus::Value, EncodeError> {
+    let mut set = regorus::Value::new_set();
+    let items = set
+        .as_set_mut()
+        .map_err(|e| EncodeError::new(e.to_string()))?;
+
+    for member in members {
+        items.insert(convert(member, opts, depth + 1)?);
+    }
+
+    Ok(set)
+}
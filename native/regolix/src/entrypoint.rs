@@ -0,0 +1,134 @@
+use crate::error::{catch_panic, ErrorDetail};
+use crate::rules::parse_rules;
+use crate::{atoms, eval_with_input, EngineResource, PolicySource};
+use rustler::{Atom, Env, NifMap, ResourceArc, Term};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+#[derive(Clone, NifMap)]
+pub(crate) struct Entrypoint {
+    /// The rule path without `data.`, slash separated as in OPA bundles,
+    /// e.g. `authz/allow`
+    name: String,
+    /// Full rule path, e.g. `data.authz.allow`
+    path: String,
+    file: String,
+    line: usize,
+    description: String,
+}
+
+/// Rules annotated with `entrypoint: true` in their `# METADATA` block, by
+/// name. A rule defined in several places is listed once, from the first
+/// file by name.
+///
+/// Parsing every policy is costly, so the result is kept with the prepared
+/// queries until the policies change.
+fn entrypoints(
+    resource: &EngineResource,
+) -> Result<Arc<BTreeMap<String, Entrypoint>>, (Atom, String)> {
+    if let Some(found) = &resource
+        .queries
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string()))?
+        .entrypoints
+    {
+        return Ok(Arc::clone(found));
+    }
+
+    // Policies before queries, the order writers take them in
+    let policies = resource
+        .policies
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+    let found = Arc::new(find_entrypoints(&policies)?);
+
+    resource
+        .queries
+        .write()
+        .map_err(|e| (atoms::engine_error(), e.to_string()))?
+        .entrypoints = Some(Arc::clone(&found));
+    Ok(found)
+}
+
+fn find_entrypoints(
+    policies: &HashMap<String, PolicySource>,
+) -> Result<BTreeMap<String, Entrypoint>, (Atom, String)> {
+    let mut files: Vec<&String> = policies.keys().collect();
+    files.sort();
+
+    let mut found = BTreeMap::new();
+    for file in files {
+        let policy = &policies[file];
+        let rules =
+            parse_rules(file, &policy.source).map_err(|e| (atoms::parse_error(), e.to_string()))?;
+
+        for rule in rules {
+            let declared = rule
+                .annotations
+                .as_ref()
+                .is_some_and(|annotations| annotations["entrypoint"] == regorus::Value::Bool(true));
+            if !declared {
+                continue;
+            }
+
+            let path = format!("{}.{}", policy.package, rule.name);
+            let name = path
+                .strip_prefix("data.")
+                .unwrap_or(&path)
+                .replace('.', "/");
+            found.entry(name.clone()).or_insert(Entrypoint {
+                name,
+                path,
+                file: file.clone(),
+                line: rule.start_line,
+                description: rule.description,
+            });
+        }
+    }
+
+    Ok(found)
+}
+
+/// The decision points the loaded policies declare with `entrypoint: true`
+/// annotations, sorted by name
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_get_entrypoints(
+    resource: ResourceArc<EngineResource>,
+) -> Result<Vec<Entrypoint>, (Atom, String)> {
+    catch_panic(|| Ok(entrypoints(&resource)?.values().cloned().collect()))
+}
+
+/// Evaluate the entrypoint called `name`, or with the full path `name`,
+/// against `json_input`.
+///
+/// Entrypoints are looked up by name on each call, so applications can bind
+/// to the name while the rule behind it moves between packages.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_entrypoint<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    name: String,
+    json_input: Term<'a>,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let found = entrypoints(&resource).map_err(|(kind, message)| (kind, message.into()))?;
+        let entrypoint = found
+            .get(&name)
+            .or_else(|| found.values().find(|entrypoint| entrypoint.path == name))
+            .ok_or_else(|| {
+                (
+                    atoms::eval_error(),
+                    format!("no entrypoint named {}", name).into(),
+                )
+            })?;
+        let path = entrypoint.path.clone();
+
+        let input = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .parse_json_term(json_input)?;
+
+        eval_with_input(env, &resource, path, input)
+    })
+}
@@ -0,0 +1,205 @@
+use crate::atoms;
+use crate::limits::MAX_JSON_DEPTH;
+use rustler::{Atom, NifMap, NifUnitEnum, NifUntaggedEnum};
+use std::fmt::Display;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Error payload returned alongside the error type atom.
+///
+/// Encodes as a plain string, as a map when the regorus error carries a
+/// source location: `%{kind, message, file, line, column, snippet}`, as
+/// `%{message, path}` when the error is about one part of a document, or as a
+/// bare atom for errors callers are expected to match on.
+#[derive(NifUntaggedEnum)]
+pub(crate) enum ErrorDetail {
+    Located(ErrorLocation),
+    AtPath(ErrorPath),
+    Reason(ErrorReason),
+    Message(String),
+}
+
+/// Errors reported as an atom, e.g. `{:json_error, :too_deep}`
+#[derive(Clone, Copy, Debug, NifUnitEnum)]
+pub(crate) enum ErrorReason {
+    /// A document nested deeper than `MAX_JSON_DEPTH`
+    TooDeep,
+}
+
+impl Display for ErrorReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ErrorReason::TooDeep => write!(
+                f,
+                "document is nested more than {} levels deep",
+                MAX_JSON_DEPTH
+            ),
+        }
+    }
+}
+
+#[derive(NifMap)]
+pub(crate) struct ErrorLocation {
+    kind: Atom,
+    pub message: String,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    snippet: String,
+}
+
+/// Where in a document an error is, e.g. `input.users[3]`
+#[derive(NifMap)]
+pub(crate) struct ErrorPath {
+    pub message: String,
+    pub path: String,
+}
+
+impl From<String> for ErrorDetail {
+    fn from(message: String) -> Self {
+        ErrorDetail::Message(message)
+    }
+}
+
+impl From<ErrorReason> for ErrorDetail {
+    fn from(reason: ErrorReason) -> Self {
+        ErrorDetail::Reason(reason)
+    }
+}
+
+impl ErrorDetail {
+    /// Prefix a plain message with the file it's about
+    pub fn in_file(self, file: &str) -> Self {
+        match self {
+            ErrorDetail::Message(message) => ErrorDetail::Message(format!("{}: {}", file, message)),
+            detail => detail,
+        }
+    }
+}
+
+/// The message alone, for NIFs that report errors as plain strings
+impl From<ErrorDetail> for String {
+    fn from(detail: ErrorDetail) -> Self {
+        match detail {
+            ErrorDetail::Located(location) => location.message,
+            ErrorDetail::AtPath(at_path) => at_path.message,
+            ErrorDetail::Reason(reason) => reason.to_string(),
+            ErrorDetail::Message(message) => message,
+        }
+    }
+}
+
+/// Run a NIF body, turning a panic into `{:engine_error, "panic: ..."}`.
+///
+/// Rustler would otherwise raise `:nif_panicked` in the caller, dropping the
+/// panic message. Locks held by the body are poisoned, so later calls on the
+/// same engine fail with an `engine_error` too.
+pub(crate) fn catch_panic<T, E: From<String>>(
+    body: impl FnOnce() -> Result<T, (Atom, E)>,
+) -> Result<T, (Atom, E)> {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string());
+        Err((atoms::engine_error(), format!("panic: {}", message).into()))
+    })
+}
+
+/// Build an error tuple, extracting file/line/column when regorus reports them
+pub(crate) fn located_error(kind: Atom, err: impl Display) -> (Atom, ErrorDetail) {
+    let text = err.to_string();
+
+    match parse_location(kind, &text) {
+        Some(location) => (kind, ErrorDetail::Located(location)),
+        None => (kind, ErrorDetail::Message(text)),
+    }
+}
+
+/// Parse a regorus diagnostic such as:
+///
+/// ```text
+/// --> policy.rego:3:9
+///   |
+/// 3 | allow if {
+///   |         ^
+/// error: unexpected token
+/// ```
+///
+/// Also accepts the single-line `policy.rego:3:9: error: unexpected token` form.
+pub(crate) fn parse_location(kind: Atom, text: &str) -> Option<ErrorLocation> {
+    let mut position: Option<(String, u32, u32)> = None;
+    let mut message: Option<String> = None;
+    let mut snippet = String::new();
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if position.is_none() {
+            let candidate = trimmed.trim_start_matches("-->").trim();
+            if let Some((file, line_num, column, rest)) = split_file_line_col(candidate) {
+                position = Some((file, line_num, column));
+                if !rest.is_empty() {
+                    message = Some(strip_severity(rest).to_string());
+                }
+                continue;
+            }
+        }
+
+        // Source excerpt: `3 | allow if {`
+        if let Some((gutter, code)) = trimmed.split_once('|') {
+            let gutter = gutter.trim();
+            if let Some((_, line_num, _)) = &position {
+                if gutter.parse::<u32>().ok() == Some(*line_num) {
+                    snippet = code.strip_prefix(' ').unwrap_or(code).to_string();
+                }
+            }
+            continue;
+        }
+
+        if message.is_none() {
+            message = Some(strip_severity(trimmed).to_string());
+        }
+    }
+
+    let (file, line, column) = position?;
+
+    Some(ErrorLocation {
+        kind,
+        message: message.unwrap_or_else(|| text.trim().to_string()),
+        file,
+        line,
+        column,
+        snippet,
+    })
+}
+
+/// Split `file:line:col[: rest]`, allowing colons inside the file name
+fn split_file_line_col(text: &str) -> Option<(String, u32, u32, &str)> {
+    let parts: Vec<&str> = text.split(':').collect();
+
+    for i in 1..parts.len().saturating_sub(1) {
+        let line = parts[i].trim().parse::<u32>();
+        let column = parts[i + 1].trim().parse::<u32>();
+
+        if let (Ok(line), Ok(column)) = (line, column) {
+            let file = parts[..i].join(":");
+            if file.trim().is_empty() || file.contains(' ') {
+                return None;
+            }
+
+            let consumed: usize = parts[..i + 2].iter().map(|p| p.len() + 1).sum();
+            let rest = text.get(consumed..).unwrap_or("").trim();
+            return Some((file, line, column, rest));
+        }
+    }
+
+    None
+}
+
+fn strip_severity(text: &str) -> &str {
+    text.strip_prefix("error:").map(str::trim).unwrap_or(text)
+}
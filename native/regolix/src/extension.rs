@@ -0,0 +1,113 @@
+use crate::atoms;
+use crate::error::catch_panic;
+use rustler::{Atom, Encoder, LocalPid, OwnedEnv, Term};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// A custom builtin whose implementation lives in an Elixir process
+#[derive(Clone)]
+pub(crate) struct ElixirExtension {
+    pub path: String,
+    pub nargs: u8,
+    pub handler: LocalPid,
+    pub timeout: Duration,
+}
+
+type Reply = Result<String, String>;
+
+static NEXT_CALL_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Calls waiting for a reply from their Elixir handler, keyed by call id
+fn pending() -> &'static Mutex<HashMap<u64, Sender<Reply>>> {
+    static PENDING: OnceLock<Mutex<HashMap<u64, Sender<Reply>>>> = OnceLock::new();
+    PENDING.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+impl ElixirExtension {
+    /// Register this extension with a regorus engine
+    pub fn register(&self, engine: &mut regorus::Engine) -> anyhow::Result<()> {
+        let extension = self.clone();
+        engine.add_extension(
+            self.path.clone(),
+            self.nargs,
+            Box::new(move |args: Vec<regorus::Value>| extension.call(args)),
+        )
+    }
+
+    /// Send `{:regolix_extension, call_id, path, args_json}` to the handler and
+    /// block until it answers through `native_extension_reply`.
+    ///
+    /// Evaluations run on dirty schedulers, so blocking here doesn't stall the BEAM.
+    fn call(&self, args: Vec<regorus::Value>) -> anyhow::Result<regorus::Value> {
+        let args_json = args
+            .iter()
+            .map(|arg| arg.to_json_str())
+            .collect::<anyhow::Result<Vec<String>>>()?
+            .join(",");
+        let args_json = format!("[{}]", args_json);
+
+        let call_id = NEXT_CALL_ID.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        pending()
+            .lock()
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?
+            .insert(call_id, tx);
+
+        // OwnedEnv can't send from a scheduler thread
+        let handler = self.handler;
+        let path = self.path.clone();
+        thread::spawn(move || {
+            let mut msg_env = OwnedEnv::new();
+            let _ = msg_env.send_and_clear(&handler, |env| {
+                (atoms::regolix_extension(), call_id, path, args_json).encode(env)
+            });
+        });
+
+        let reply = rx.recv_timeout(self.timeout);
+
+        if let Ok(mut calls) = pending().lock() {
+            calls.remove(&call_id);
+        }
+
+        match reply {
+            Ok(Ok(json)) => regorus::Value::from_json_str(&json),
+            Ok(Err(message)) => Err(anyhow::anyhow!("{}: {}", self.path, message)),
+            Err(_) => Err(anyhow::anyhow!(
+                "{}: extension did not reply within {}ms",
+                self.path,
+                self.timeout.as_millis()
+            )),
+        }
+    }
+}
+
+#[rustler::nif]
+fn native_extension_reply(call_id: u64, reply: Term) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let (status, payload): (Atom, String) = reply
+            .decode()
+            .map_err(|_| (atoms::engine_error(), "invalid extension reply".to_string()))?;
+
+        let reply = if status == atoms::ok() {
+            Ok(payload)
+        } else {
+            Err(payload)
+        };
+
+        let sender = pending()
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .remove(&call_id);
+
+        // The call may already have timed out; a late reply is dropped
+        if let Some(sender) = sender {
+            let _ = sender.send(reply);
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,256 @@
+use crate::deps::{bases, scan_rule};
+use crate::error::catch_panic;
+use crate::rules::{parse_module, rule_infos};
+use crate::{atoms, EngineResource, PolicySource};
+use rustler::{Atom, NifMap, NifUnitEnum, NifUntaggedEnum, ResourceArc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+enum GraphFormat {
+    Dot,
+    Map,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub(crate) enum NodeKind {
+    Rule,
+    /// A document under `data` that no loaded rule produces
+    Data,
+}
+
+#[derive(NifMap)]
+struct GraphNode {
+    id: String,
+    kind: NodeKind,
+    /// Rules in the longest chain of rules below this one
+    depth: usize,
+}
+
+#[derive(NifMap)]
+struct GraphEdge {
+    from: String,
+    to: String,
+}
+
+#[derive(NifMap)]
+struct RuleGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+#[derive(NifUntaggedEnum)]
+enum GraphOutput {
+    Map(RuleGraph),
+    Dot(String),
+}
+
+/// The nodes a reference to `path` depends on: the rule producing it, every
+/// rule under it when it names a package or a prefix of rule paths, or else
+/// the data document itself
+pub(crate) fn resolve(path: &str, rule_ids: &BTreeSet<String>) -> Vec<(String, NodeKind)> {
+    let producer = rule_ids
+        .iter()
+        .filter(|id| {
+            path == id.as_str()
+                || path.starts_with(&format!("{}.", id))
+                || path.starts_with(&format!("{}[", id))
+        })
+        .max_by_key(|id| id.len());
+    if let Some(id) = producer {
+        return vec![(id.clone(), NodeKind::Rule)];
+    }
+
+    let prefix = format!("{}.", path);
+    let below: Vec<_> = rule_ids
+        .iter()
+        .filter(|id| id.starts_with(&prefix))
+        .map(|id| (id.clone(), NodeKind::Rule))
+        .collect();
+    if below.is_empty() {
+        vec![(path.to_string(), NodeKind::Data)]
+    } else {
+        below
+    }
+}
+
+/// Rules in the longest chain of rules starting below `id`. A cycle, which
+/// Rego rejects anyway, is cut where it closes.
+fn chain_depth(
+    id: &str,
+    edges: &BTreeMap<String, BTreeSet<String>>,
+    rule_ids: &BTreeSet<String>,
+    memo: &mut BTreeMap<String, usize>,
+    visiting: &mut BTreeSet<String>,
+) -> usize {
+    if let Some(depth) = memo.get(id) {
+        return *depth;
+    }
+    if !visiting.insert(id.to_string()) {
+        return 0;
+    }
+
+    let depth = edges
+        .get(id)
+        .into_iter()
+        .flatten()
+        .filter(|to| rule_ids.contains(*to))
+        .map(|to| 1 + chain_depth(to, edges, rule_ids, memo, visiting))
+        .max()
+        .unwrap_or(0);
+
+    visiting.remove(id);
+    memo.insert(id.to_string(), depth);
+    depth
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn to_dot(graph: &RuleGraph) -> String {
+    let mut out = String::from("digraph rules {\n");
+    for node in &graph.nodes {
+        let shape = match node.kind {
+            NodeKind::Rule => "box",
+            NodeKind::Data => "cylinder",
+        };
+        out.push_str(&format!("  {} [shape={}];\n", quote(&node.id), shape));
+    }
+    for edge in &graph.edges {
+        out.push_str(&format!("  {} -> {};\n", quote(&edge.from), quote(&edge.to)));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// The loaded rules and the rules and data documents each of them reads
+pub(crate) struct Dependencies {
+    /// Full path of every rule, e.g. `data.authz.allow`
+    pub rule_ids: BTreeSet<String>,
+    pub edges: BTreeMap<String, BTreeSet<String>>,
+    /// Documents under `data` that rules read but no loaded rule produces
+    pub data_ids: BTreeSet<String>,
+}
+
+/// Find the dependencies of every rule in `policies`.
+///
+/// References are found in the parsed policies as in `native_data_deps`,
+/// plus bare references to rules of the same package.
+pub(crate) fn dependencies(
+    policies: &HashMap<String, PolicySource>,
+) -> Result<Dependencies, (Atom, String)> {
+    let mut parsed = Vec::new();
+    for (name, policy) in policies.iter() {
+        let module = parse_module(name, &policy.source)
+            .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+        let rules = rule_infos(&module, &policy.source);
+        parsed.push((policy, module, rules));
+    }
+
+    let rule_ids: BTreeSet<String> = parsed
+        .iter()
+        .flat_map(|(policy, _, rules)| {
+            rules
+                .iter()
+                .map(|rule| format!("{}.{}", policy.package, rule.name))
+        })
+        .collect();
+    // First segment of each rule name, per package, for bare references
+    let mut local_names: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (policy, _, rules) in &parsed {
+        let names = local_names.entry(policy.package.as_str()).or_default();
+        for rule in rules {
+            names.extend(rule.name.split(['.', '[']).next());
+        }
+    }
+
+    let mut edges: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut data_ids = BTreeSet::new();
+
+    for (policy, module, rules) in &parsed {
+        let mut bases = bases(module);
+        bases.remove("input");
+        for name in &local_names[policy.package.as_str()] {
+            bases.insert(name.to_string(), format!("{}.{}", policy.package, name));
+        }
+
+        for (rule, info) in module.policy.iter().zip(rules) {
+            let from = format!("{}.{}", policy.package, info.name);
+
+            for found in scan_rule(rule.as_ref(), &bases).refs {
+                for (to, kind) in resolve(&found.path, &rule_ids) {
+                    if to == from {
+                        continue;
+                    }
+                    if kind == NodeKind::Data {
+                        data_ids.insert(to.clone());
+                    }
+                    edges.entry(from.clone()).or_default().insert(to);
+                }
+            }
+        }
+    }
+
+    Ok(Dependencies {
+        rule_ids,
+        edges,
+        data_ids,
+    })
+}
+
+/// The dependency graph of the loaded rules: an edge from each rule to every
+/// rule and data document it reads, as a map or as Graphviz DOT.
+///
+/// Rules are identified by their full path, e.g. `data.authz.allow`, with all
+/// definitions of a rule merged into one node. See `dependencies` for how
+/// references are found.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_rule_graph(
+    resource: ResourceArc<EngineResource>,
+    format: GraphFormat,
+) -> Result<GraphOutput, (Atom, String)> {
+    catch_panic(|| {
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let Dependencies {
+            rule_ids,
+            edges,
+            data_ids,
+        } = dependencies(&policies)?;
+
+        let mut memo = BTreeMap::new();
+        let mut nodes: Vec<GraphNode> = rule_ids
+            .iter()
+            .map(|id| GraphNode {
+                id: id.clone(),
+                kind: NodeKind::Rule,
+                depth: chain_depth(id, &edges, &rule_ids, &mut memo, &mut BTreeSet::new()),
+            })
+            .collect();
+        nodes.extend(data_ids.into_iter().map(|id| GraphNode {
+            id,
+            kind: NodeKind::Data,
+            depth: 0,
+        }));
+
+        let graph = RuleGraph {
+            nodes,
+            edges: edges
+                .into_iter()
+                .flat_map(|(from, to)| {
+                    to.into_iter().map(move |to| GraphEdge {
+                        from: from.clone(),
+                        to,
+                    })
+                })
+                .collect(),
+        };
+
+        Ok(match format {
+            GraphFormat::Map => GraphOutput::Map(graph),
+            GraphFormat::Dot => GraphOutput::Dot(to_dot(&graph)),
+        })
+    })
+}
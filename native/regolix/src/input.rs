@@ -0,0 +1,36 @@
+use crate::error::{catch_panic, ErrorDetail};
+use crate::limits::Limits;
+use crate::{eval_with_input, EngineResource};
+use rustler::{Atom, Env, ResourceArc, Term};
+
+/// An input document parsed once and reused across evaluations and engines
+pub struct InputResource {
+    value: regorus::Value,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for InputResource {}
+
+/// Parse a JSON input document into a handle that can be passed to any
+/// number of evaluations, on any engine, without parsing it again.
+///
+/// No engine is involved yet, so only the built-in nesting limit applies,
+/// not the limits set with `set_limits/2`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_parse_input(json_input: Term) -> Result<ResourceArc<InputResource>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = Limits::default().parse_json_term(json_input)?;
+        Ok(ResourceArc::new(InputResource { value }))
+    })
+}
+
+/// Evaluate a query with a parsed input, leaving the engine's own input alone
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_with_parsed_input<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    input: ResourceArc<InputResource>,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| eval_with_input(env, &resource, query, input.value.clone()))
+}
@@ -0,0 +1,414 @@
+use crate::error::catch_panic;
+use crate::{atoms, EngineResource};
+use jsonwebtoken::jwk::Jwk;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rustler::{Atom, NifMap, NifUnitEnum, ResourceArc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Algorithms accepted for signature verification, as named in JWS
+const ALGORITHMS: [&str; 12] = [
+    "RS256", "RS384", "RS512", "PS256", "PS384", "PS512", "ES256", "ES384", "HS256", "HS384",
+    "HS512", "EdDSA",
+];
+
+/// PEM labels of public key material; private keys are refused
+const PEM_LABELS: [&str; 2] = ["PUBLIC KEY", "RSA PUBLIC KEY"];
+
+/// Name of the file holding a bundle's signature
+pub(crate) const SIGNATURES_FILE: &str = ".signatures.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum, Serialize, Deserialize)]
+enum KeyFormat {
+    Pem,
+    Jwk,
+    /// Shared secret for the `HS*` algorithms
+    Secret,
+}
+
+/// A trusted key, see `native_add_verification_key`
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct VerificationKey {
+    algorithm: String,
+    format: KeyFormat,
+    /// PEM text, JWK JSON or secret
+    material: String,
+}
+
+impl VerificationKey {
+    /// Parse the key material into a key `jsonwebtoken` can verify with
+    fn decoding_key(&self) -> Result<(Algorithm, DecodingKey), (Atom, String)> {
+        let algorithm = Algorithm::from_str(&self.algorithm)
+            .map_err(|_| key_error(format!("unsupported algorithm `{}`", self.algorithm)))?;
+
+        let key = match self.format {
+            KeyFormat::Secret => Ok(DecodingKey::from_secret(self.material.as_bytes())),
+            KeyFormat::Jwk => serde_json::from_str::<Jwk>(&self.material)
+                .map_err(|e| e.to_string())
+                .and_then(|jwk| DecodingKey::from_jwk(&jwk).map_err(|e| e.to_string())),
+            KeyFormat::Pem => {
+                let pem = self.material.as_bytes();
+                match self.algorithm.get(..2) {
+                    Some("ES") => DecodingKey::from_ec_pem(pem),
+                    Some("Ed") => DecodingKey::from_ed_pem(pem),
+                    _ => DecodingKey::from_rsa_pem(pem),
+                }
+                .map_err(|e| e.to_string())
+            }
+        }
+        .map_err(|e| key_error(format!("key is not a valid {} key: {}", self.algorithm, e)))?;
+
+        Ok((algorithm, key))
+    }
+}
+
+#[derive(NifMap)]
+struct KeyInfo {
+    keyid: String,
+    algorithm: String,
+    format: KeyFormat,
+}
+
+fn key_error(message: impl Into<String>) -> (Atom, String) {
+    (atoms::key_error(), message.into())
+}
+
+fn check_algorithm(algorithm: &str) -> Result<(), (Atom, String)> {
+    if ALGORITHMS.contains(&algorithm) {
+        Ok(())
+    } else {
+        Err(key_error(format!("unsupported algorithm `{}`", algorithm)))
+    }
+}
+
+/// Check that `pem` is a single PEM block of public key material; the key
+/// itself is parsed by `VerificationKey::decoding_key`
+fn check_pem(pem: &str) -> Result<(), (Atom, String)> {
+    let lines: Vec<&str> = pem.trim().lines().map(str::trim).collect();
+    let label = |line: Option<&&str>, marker: &str| {
+        line.and_then(|line| line.strip_prefix(marker))
+            .and_then(|rest| rest.strip_suffix("-----"))
+            .map(str::to_string)
+    };
+
+    let begin = label(lines.first(), "-----BEGIN ");
+    let end = label(lines.last(), "-----END ");
+    let (Some(begin), Some(end)) = (begin, end) else {
+        return Err(key_error("key is not PEM encoded"));
+    };
+    if begin != end {
+        return Err(key_error(format!(
+            "PEM block `{}` ends with `{}`",
+            begin, end
+        )));
+    }
+    if !PEM_LABELS.contains(&begin.as_str()) {
+        return Err(key_error(format!(
+            "PEM block `{}` is not a public key",
+            begin
+        )));
+    }
+
+    Ok(())
+}
+
+/// The keyid and key for one JWK, checking it holds the members its key type
+/// needs and no private ones
+fn parse_jwk(jwk: &serde_json::Value) -> Result<(String, VerificationKey), (Atom, String)> {
+    let member = |name: &str| jwk.get(name).and_then(serde_json::Value::as_str);
+
+    let keyid = member("kid").ok_or_else(|| key_error("JWK has no `kid`"))?;
+    let kty = member("kty").ok_or_else(|| key_error(format!("JWK `{}` has no `kty`", keyid)))?;
+
+    let (required, default_algorithm): (&[&str], _) = match (kty, member("crv")) {
+        ("RSA", _) => (&["n", "e"], "RS256"),
+        ("EC", Some("P-256")) => (&["crv", "x", "y"], "ES256"),
+        ("EC", Some("P-384")) => (&["crv", "x", "y"], "ES384"),
+        ("EC", crv) => {
+            return Err(key_error(format!(
+                "JWK `{}` has unsupported `crv` {}",
+                keyid,
+                crv.unwrap_or("(none)")
+            )))
+        }
+        ("OKP", _) => (&["crv", "x"], "EdDSA"),
+        ("oct", _) => (&["k"], "HS256"),
+        _ => {
+            return Err(key_error(format!(
+                "JWK `{}` has unsupported `kty` {}",
+                keyid, kty
+            )))
+        }
+    };
+    if let Some(missing) = required.iter().find(|name| member(name).is_none()) {
+        return Err(key_error(format!("JWK `{}` has no `{}`", keyid, missing)));
+    }
+    if kty != "oct" && jwk.get("d").is_some() {
+        return Err(key_error(format!("JWK `{}` is a private key", keyid)));
+    }
+
+    let algorithm = member("alg").unwrap_or(default_algorithm);
+    check_algorithm(algorithm)?;
+
+    let key = VerificationKey {
+        algorithm: algorithm.to_string(),
+        format: KeyFormat::Jwk,
+        material: jwk.to_string(),
+    };
+    key.decoding_key()
+        .map_err(|(kind, message)| (kind, format!("JWK `{}`: {}", keyid, message)))?;
+
+    Ok((keyid.to_string(), key))
+}
+
+/// Trust `key` under `keyid`, replacing any key with that id.
+///
+/// `key` is PEM public key material, or the shared secret for the `HS*`
+/// algorithms.
+#[rustler::nif]
+fn native_add_verification_key(
+    resource: ResourceArc<EngineResource>,
+    keyid: String,
+    key: String,
+    algorithm: String,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        check_algorithm(&algorithm)?;
+
+        let format = if algorithm.starts_with("HS") {
+            if key.is_empty() {
+                return Err(key_error("secret is empty"));
+            }
+            KeyFormat::Secret
+        } else {
+            check_pem(&key)?;
+            KeyFormat::Pem
+        };
+
+        let key = VerificationKey {
+            algorithm,
+            format,
+            material: key,
+        };
+        key.decoding_key()?;
+
+        resource
+            .keys
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .insert(keyid, key);
+        resource.store_changed();
+        Ok(())
+    })
+}
+
+/// Trust every key of a JWK Set, replacing keys with the same ids, and return
+/// their ids. Nothing is added if any key is invalid.
+#[rustler::nif]
+fn native_add_jwks(
+    resource: ResourceArc<EngineResource>,
+    json_jwks: String,
+) -> Result<Vec<String>, (Atom, String)> {
+    catch_panic(|| {
+        let jwks: serde_json::Value =
+            serde_json::from_str(&json_jwks).map_err(|e| (atoms::json_error(), e.to_string()))?;
+        let jwks = jwks
+            .get("keys")
+            .and_then(serde_json::Value::as_array)
+            .ok_or_else(|| key_error("JWK Set has no `keys` array"))?;
+
+        let parsed = jwks.iter().map(parse_jwk).collect::<Result<Vec<_>, _>>()?;
+        let keyids = parsed.iter().map(|(keyid, _)| keyid.clone()).collect();
+
+        resource
+            .keys
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .extend(parsed);
+        resource.store_changed();
+        Ok(keyids)
+    })
+}
+
+/// Stop trusting the key with `keyid`, if there is one
+#[rustler::nif]
+fn native_remove_verification_key(
+    resource: ResourceArc<EngineResource>,
+    keyid: String,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        resource
+            .keys
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .remove(&keyid);
+        resource.store_changed();
+        Ok(())
+    })
+}
+
+/// Ids, algorithms and formats of the trusted keys, sorted by id. Key
+/// material is never returned.
+#[rustler::nif]
+fn native_list_verification_keys(
+    resource: ResourceArc<EngineResource>,
+) -> Result<Vec<KeyInfo>, (Atom, String)> {
+    catch_panic(|| {
+        Ok(resource
+            .keys
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .iter()
+            .map(|(keyid, key)| KeyInfo {
+                keyid: keyid.clone(),
+                algorithm: key.algorithm.clone(),
+                format: key.format,
+            })
+            .collect())
+    })
+}
+
+/// Contents of a bundle's `.signatures.json`
+#[derive(Deserialize)]
+struct Signatures {
+    signatures: Vec<String>,
+}
+
+/// Payload of a bundle signature: the files it covers
+#[derive(Deserialize)]
+struct SignedFiles {
+    files: Vec<SignedFile>,
+}
+
+#[derive(Deserialize)]
+struct SignedFile {
+    name: String,
+    hash: String,
+    algorithm: String,
+}
+
+fn signature_error(message: impl Into<String>) -> (Atom, String) {
+    (atoms::bundle_error(), message.into())
+}
+
+/// SHA-256 of a bundle file, in hex, as listed in `.signatures.json`.
+///
+/// As in OPA, JSON files are hashed in canonical form, with object keys
+/// sorted and no whitespace, so reformatting them doesn't break a signature.
+pub(crate) fn file_hash(name: &str, contents: &[u8]) -> Result<String, (Atom, String)> {
+    let digest = if name.ends_with(".json") {
+        let value: serde_json::Value = serde_json::from_slice(contents)
+            .map_err(|e| (atoms::json_error(), format!("{}: {}", name, e)))?;
+        let mut canonical = String::new();
+        write_canonical(&value, &mut canonical);
+        Sha256::digest(canonical.as_bytes())
+    } else {
+        Sha256::digest(contents)
+    };
+
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+            out.push('{');
+            for (i, (key, field)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(field, out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Check a bundle's `.signatures.json` against the trusted keys.
+///
+/// The signature is a JWS whose `kid` names a trusted key, and its payload
+/// must list every file of the bundle, `hashes` by name, with matching
+/// hashes and nothing else.
+pub(crate) fn verify_signatures(
+    keys: &BTreeMap<String, VerificationKey>,
+    signatures: &str,
+    hashes: &HashMap<String, String>,
+) -> Result<(), (Atom, String)> {
+    let signatures: Signatures = serde_json::from_str(signatures)
+        .map_err(|e| (atoms::json_error(), format!("{}: {}", SIGNATURES_FILE, e)))?;
+    let [token] = signatures.signatures.as_slice() else {
+        return Err(signature_error(format!(
+            "{} must hold exactly one signature",
+            SIGNATURES_FILE
+        )));
+    };
+
+    let header = jsonwebtoken::decode_header(token)
+        .map_err(|e| signature_error(format!("signature is malformed: {}", e)))?;
+    let keyid = header
+        .kid
+        .ok_or_else(|| signature_error("signature has no `kid`"))?;
+    let key = keys
+        .get(&keyid)
+        .ok_or_else(|| signature_error(format!("signing key `{}` is not trusted", keyid)))?;
+
+    let (algorithm, decoding_key) = key.decoding_key()?;
+    let mut validation = Validation::new(algorithm);
+    validation.required_spec_claims.clear();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+
+    let signed = jsonwebtoken::decode::<SignedFiles>(token, &decoding_key, &validation)
+        .map_err(|e| signature_error(format!("signature by `{}` is invalid: {}", keyid, e)))?
+        .claims;
+
+    let mut expected = HashMap::new();
+    for file in signed.files {
+        if !file.algorithm.eq_ignore_ascii_case("SHA-256") {
+            return Err(signature_error(format!(
+                "{}: unsupported hash algorithm `{}`",
+                file.name, file.algorithm
+            )));
+        }
+        let name = file.name.trim_start_matches("./").trim_start_matches('/');
+        expected.insert(name.to_string(), file.hash);
+    }
+
+    for (name, hash) in hashes {
+        match expected.remove(name) {
+            None => return Err(signature_error(format!("{} is not signed", name))),
+            Some(signed) if !signed.eq_ignore_ascii_case(hash) => {
+                return Err(signature_error(format!(
+                    "{} does not match its signature",
+                    name
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+
+    match expected.into_keys().min() {
+        Some(name) => Err(signature_error(format!(
+            "signed file {} is missing from the bundle",
+            name
+        ))),
+        None => Ok(()),
+    }
+}
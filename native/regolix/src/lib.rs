@@ -1,7 +1,81 @@
+use arc_swap::ArcSwap;
+use flate2::write::GzEncoder;
 use regorus::Engine;
-use rustler::{Atom, Encoder, Env, ResourceArc, Term};
-use std::collections::HashMap;
-use std::sync::RwLock;
+use rustler::{
+    Atom, Binary, Encoder, Env, LocalPid, Monitor, NifMap, NifUnitEnum, ResourceArc, Term,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+mod ast;
+mod async_eval;
+mod bundle;
+mod cache;
+mod cancel;
+mod clock;
+mod complexity;
+mod coverage;
+mod decode;
+mod deps;
+mod diff;
+mod dump;
+mod encode;
+mod entrypoint;
+mod error;
+mod extension;
+mod graph;
+mod input;
+mod keys;
+mod limits;
+mod lint;
+mod migrate;
+mod owner;
+mod params;
+mod patch;
+mod pool;
+mod profile;
+mod provenance;
+mod random;
+mod record;
+mod registry;
+mod render;
+mod result;
+mod rules;
+mod runtime;
+mod sandbox;
+mod shadow;
+mod skeleton;
+mod snapshot;
+mod stats;
+mod store;
+mod test_runner;
+mod txn;
+mod upload;
+mod watch;
+
+use bundle::Manifest;
+use cache::DecisionCache;
+use clock::Clock;
+use decode::{
+    map_from_pairs, result_to_term, value_to_term, DecodeOptions, DecodeOverrides, KeyEncoding,
+    NumberEncoding, SetEncoding, UndefinedEncoding,
+};
+use encode::EncodeOptions;
+use error::{catch_panic, located_error, ErrorDetail};
+use extension::ElixirExtension;
+use keys::VerificationKey;
+use limits::Limits;
+use lint::rename_calls;
+use profile::Profile;
+use rules::{parse_rules, RuleKind};
+use sandbox::check_disabled_builtins;
+use stats::EvalStats;
+use store::StoreHandle;
 
 mod atoms {
     rustler::atoms! {
@@ -12,427 +86,1787 @@ mod atoms {
         eval_error,
         json_error,
         engine_error,
+        io_error,
+        set,
+        decimal,
+        regolix_extension,
+        regolix_reloaded,
+        timeout,
+        cancelled,
+        limit_exceeded,
+        builtin_disabled,
+        bundle_error,
+        expressions,
+        bindings,
+        value,
+        text,
+        name,
+        package,
+        source_length,
+        covered,
+        not_covered,
+        description,
+        start_line,
+        end_line,
+        annotations,
+        comments,
+        key_error,
+        invalid_argument,
+        kind,
+        default,
+        rule_ref = "ref",
     }
 }
 
+/// Rego language version used when parsing policies
+#[derive(Clone, Copy, Debug, Default, PartialEq, NifUnitEnum, Serialize, Deserialize)]
+enum RegoVersion {
+    /// Legacy syntax: `import future.keywords`, rule bodies without `if`
+    V0,
+    #[default]
+    V1,
+}
+
+/// Engine configuration that has to survive rebuilding the engine
+#[derive(Clone, Default)]
+struct EngineSettings {
+    coverage_enabled: bool,
+    strict_builtin_errors: bool,
+    rego_version: RegoVersion,
+    extensions: Vec<ElixirExtension>,
+    /// Builtins that policies may not call, see `native_disable_builtins`
+    disabled_builtins: Vec<String>,
+    clock: Clock,
+    /// Seed for the random builtins, see `native_set_random_seed`
+    random_seed: Option<u64>,
+    /// What `opa.runtime()` returns, see `native_set_runtime`
+    runtime: Option<regorus::Value>,
+    /// Name reported in decision provenance, see `native_set_label`
+    label: Option<String>,
+}
+
+impl EngineSettings {
+    fn apply(&self, engine: &mut Engine) {
+        engine.set_enable_coverage(self.coverage_enabled);
+        engine.set_strict_builtin_errors(self.strict_builtin_errors);
+        engine.set_rego_v0(self.rego_version == RegoVersion::V0);
+
+        // These registered fine before, so they can't conflict on a fresh engine
+        for extension in &self.extensions {
+            let _ = extension.register(engine);
+        }
+        let _ = self.clock.register(engine);
+        if let Some(seed) = self.random_seed {
+            let _ = random::register(seed, engine);
+        }
+        if let Some(value) = &self.runtime {
+            let _ = runtime::register(value, engine);
+        }
+    }
+
+    /// Add a policy the way these settings require, returning its package
+    fn add_policy(&self, engine: &mut Engine, name: &str, source: &str) -> anyhow::Result<String> {
+        let mut renames = self.clock.renames().to_vec();
+        if self.random_seed.is_some() {
+            renames.extend_from_slice(random::RENAMES);
+        }
+        if self.runtime.is_some() {
+            renames.extend_from_slice(runtime::RENAMES);
+        }
+
+        engine.add_policy(name.to_string(), rename_calls(source, &renames).into_owned())
+    }
+}
+
+/// A policy as added to the engine
+#[derive(Clone, Serialize, Deserialize)]
+struct PolicySource {
+    source: String,
+    package: String,
+}
+
 pub struct EngineResource {
-    engine: RwLock<Engine>,
-    policies: RwLock<HashMap<String, String>>,
+    /// Published engine state; evaluations work on copies of it
+    engine: ArcSwap<Engine>,
+    /// Serializes writers building the next engine state, see `lock_writer`
+    writer: Mutex<()>,
+    /// Set when the owner exited while a writer held the lock, see `release`
+    release_pending: AtomicBool,
+    policies: RwLock<HashMap<String, PolicySource>>,
+    input: RwLock<Option<regorus::Value>>,
+    settings: RwLock<EngineSettings>,
+    decode: RwLock<DecodeOptions>,
+    limits: RwLock<Limits>,
+    /// Prepared form of query strings seen by `native_eval_query`
+    queries: RwLock<QueryCache>,
+    profile: Mutex<Profile>,
+    /// Manifest of each bundle loaded, by bundle name
+    bundles: RwLock<HashMap<String, Manifest>>,
+    stats: Mutex<EvalStats>,
+    /// Monitor on the process whose exit releases the engine
+    owner: Mutex<Option<Monitor>>,
+    /// Recent results, see `native_set_decision_cache`
+    decisions: Mutex<DecisionCache>,
+    /// File decisions are recorded to, see `native_start_recording`
+    recorder: Mutex<Option<GzEncoder<File>>>,
+    /// Trusted keys by keyid, see `native_add_verification_key`
+    keys: RwLock<BTreeMap<String, VerificationKey>>,
+    /// Thread saving changes to disk, see `native_open_store`
+    store: Mutex<Option<StoreHandle>>,
+}
+
+/// How a query string is evaluated, decided once per engine and policy set
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PreparedQuery {
+    /// The query names a loaded rule exactly, so it skips query compilation
+    Rule,
+    /// Anything else goes through the full query evaluator
+    Query,
+}
+
+/// Most query strings `QueryCache` remembers. Past this it starts over, so
+/// callers building query strings on the fly can't grow it without bound.
+const MAX_PREPARED_QUERIES: usize = 1024;
+
+#[derive(Default)]
+struct QueryCache {
+    /// `data.<package>.<rule>` of every loaded rule other than functions,
+    /// collected on first use after the policies change
+    rules: Option<HashSet<String>>,
+    prepared: HashMap<String, PreparedQuery>,
+    /// Annotated entrypoints by name, found on first use after the policies
+    /// change
+    entrypoints: Option<Arc<BTreeMap<String, entrypoint::Entrypoint>>>,
+}
+
+impl EngineResource {
+    /// An engine without policies, data or settings
+    fn new() -> Self {
+        EngineResource {
+            engine: ArcSwap::from_pointee(Engine::new()),
+            writer: Mutex::new(()),
+            release_pending: AtomicBool::new(false),
+            policies: RwLock::new(HashMap::new()),
+            input: RwLock::new(None),
+            settings: RwLock::new(EngineSettings::default()),
+            decode: RwLock::new(DecodeOptions::default()),
+            limits: RwLock::new(Limits::default()),
+            queries: RwLock::new(QueryCache::default()),
+            profile: Mutex::new(None),
+            bundles: RwLock::new(HashMap::new()),
+            stats: Mutex::new(EvalStats::default()),
+            owner: Mutex::new(None),
+            decisions: Mutex::new(DecisionCache::default()),
+            recorder: Mutex::new(None),
+            keys: RwLock::new(BTreeMap::new()),
+            store: Mutex::new(None),
+        }
+    }
+
+    /// Drop prepared queries; they depend on which rules are loaded
+    fn invalidate_queries(&self) {
+        if let Ok(mut queries) = self.queries.write() {
+            *queries = QueryCache::default();
+        }
+    }
 }
 
 #[rustler::resource_impl]
-impl rustler::Resource for EngineResource {}
+impl rustler::Resource for EngineResource {
+    fn down<'a>(&'a self, _env: Env<'a>, _pid: LocalPid, monitor: Monitor) {
+        owner::owner_down(self, monitor);
+    }
+}
 
 #[rustler::nif]
 fn native_new() -> ResourceArc<EngineResource> {
-    ResourceArc::new(EngineResource {
-        engine: RwLock::new(Engine::new()),
-        policies: RwLock::new(HashMap::new()),
-    })
+    ResourceArc::new(EngineResource::new())
 }
 
 #[rustler::nif]
+fn native_clone(
+    resource: ResourceArc<EngineResource>,
+) -> Result<ResourceArc<EngineResource>, (Atom, String)> {
+    catch_panic(|| {
+        // Hold off writers so the snapshot matches the bookkeeping copied below
+        let _writer = resource.lock_writer();
+        let engine = resource.snapshot();
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let input = resource
+            .input
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let settings = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let decode = resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let limits = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let profile = resource
+            .profile
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let bundles = resource
+            .bundles
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let capacity = resource
+            .decisions
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .capacity();
+        let keys = resource
+            .keys
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        Ok(ResourceArc::new(EngineResource {
+            engine: ArcSwap::from_pointee(Engine::clone(&engine)),
+            writer: Mutex::new(()),
+            release_pending: AtomicBool::new(false),
+            policies: RwLock::new(policies.clone()),
+            input: RwLock::new(input.clone()),
+            settings: RwLock::new(settings.clone()),
+            decode: RwLock::new(*decode),
+            limits: RwLock::new(*limits),
+            queries: RwLock::new(QueryCache::default()),
+            profile: Mutex::new(profile.clone()),
+            bundles: RwLock::new(bundles.clone()),
+            stats: Mutex::new(EvalStats::default()),
+            owner: Mutex::new(None),
+            decisions: Mutex::new(DecisionCache::with_capacity(capacity)),
+            recorder: Mutex::new(None),
+            keys: RwLock::new(keys.clone()),
+            store: Mutex::new(None),
+        }))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
 fn native_add_policy(
     resource: ResourceArc<EngineResource>,
     name: String,
     source: String,
-) -> Result<(), (Atom, String)> {
-    let mut engine = resource
-        .engine
-        .write()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let mut policies = resource
+            .policies
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let settings = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        // Checked under the writer lock so the deny-list can't change meanwhile
+        check_disabled_builtins(&settings.disabled_builtins, &name, &source)?;
+        let package = settings
+            .add_policy(&mut engine, &name, &source)
+            .map_err(|e| located_error(atoms::parse_error(), e))?;
+
+        engine.commit();
+        // Store the source for later rule extraction and engine rebuilds
+        policies.insert(name, PolicySource { source, package });
+        resource.invalidate_queries();
+        Ok(())
+    })
+}
 
-    // Store the source for later rule extraction
-    let mut policies = resource
-        .policies
-        .write()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
-    policies.insert(name.clone(), source.clone());
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_add_policy_from_file(
+    resource: ResourceArc<EngineResource>,
+    path: String,
+) -> Result<String, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| (atoms::io_error(), format!("{}: {}", path, e).into()))?;
+
+        let mut engine = resource.begin_write();
+
+        let mut policies = resource
+            .policies
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let settings = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        check_disabled_builtins(&settings.disabled_builtins, &path, &source)?;
+        let package = settings
+            .add_policy(&mut engine, &path, &source)
+            .map_err(|e| located_error(atoms::parse_error(), e))?;
+
+        engine.commit();
+        policies.insert(path.clone(), PolicySource { source, package });
+        resource.invalidate_queries();
+        Ok(path)
+    })
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_add_policies_from_dir(
+    resource: ResourceArc<EngineResource>,
+    dir: String,
+    pattern: String,
+) -> Result<Vec<String>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let full_pattern = Path::new(&dir).join(&pattern);
+        let paths = glob::glob(&full_pattern.to_string_lossy())
+            .map_err(|e| (atoms::io_error(), e.to_string().into()))?;
+
+        let mut sources: Vec<(String, String)> = Vec::new();
+        for entry in paths {
+            let path = entry.map_err(|e| (atoms::io_error(), e.to_string().into()))?;
+            if !path.is_file() {
+                continue;
+            }
+
+            let name = path.to_string_lossy().into_owned();
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| (atoms::io_error(), format!("{}: {}", name, e).into()))?;
+            sources.push((name, source));
+        }
+
+        let mut engine = resource.begin_write();
+
+        let mut policies = resource
+            .policies
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let settings = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        // Nothing is published unless every file loads
+        let mut added = Vec::with_capacity(sources.len());
+        for (name, source) in sources {
+            check_disabled_builtins(&settings.disabled_builtins, &name, &source)?;
+            let package = settings
+                .add_policy(&mut engine, &name, &source)
+                .map_err(|e| located_error(atoms::parse_error(), e))?;
+            added.push((name, PolicySource { source, package }));
+        }
+
+        engine.commit();
+        let names = added.iter().map(|(name, _)| name.clone()).collect();
+        policies.extend(added);
+        resource.invalidate_queries();
+
+        Ok(names)
+    })
+}
+
+/// Build a fresh engine from stored policy sources, carrying over data and input
+fn rebuild_engine(
+    policies: &HashMap<String, PolicySource>,
+    data: regorus::Value,
+    input: Option<regorus::Value>,
+    settings: &EngineSettings,
+) -> Result<Engine, (Atom, String)> {
+    let mut engine = Engine::new();
+    settings.apply(&mut engine);
+
+    for (name, policy) in policies.iter() {
+        settings
+            .add_policy(&mut engine, name, &policy.source)
+            .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+    }
 
     engine
-        .add_policy(name, source)
-        .map(|_| ())
-        .map_err(|e| (atoms::parse_error(), e.to_string()))
+        .add_data(data)
+        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+    if let Some(input) = input {
+        engine.set_input(input);
+    }
+
+    Ok(engine)
 }
 
-#[rustler::nif]
-fn native_set_input(
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_remove_policy(
     resource: ResourceArc<EngineResource>,
-    json_input: String,
+    name: String,
 ) -> Result<(), (Atom, String)> {
-    let mut engine = resource
-        .engine
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let mut policies = resource
+            .policies
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        if !policies.contains_key(&name) {
+            return Err((atoms::engine_error(), format!("policy not found: {}", name)));
+        }
+
+        // regorus can't unload a module, so rebuild the engine without it
+        let mut remaining = policies.clone();
+        remaining.remove(&name);
+
+        let input = resource
+            .input
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clone();
+        let settings = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clone();
+
+        *engine = rebuild_engine(&remaining, engine.get_data(), input, &settings)?;
+        engine.commit();
+        *policies = remaining;
+        resource.invalidate_queries();
+
+        Ok(())
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_set_input(
+    resource: ResourceArc<EngineResource>,
+    json_input: Term,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .parse_json_term(json_input)?;
+
+        replace_input(&resource, value).map_err(|(kind, message)| (kind, message.into()))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_set_input_yaml(
+    resource: ResourceArc<EngineResource>,
+    yaml_input: String,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .parse_yaml(&yaml_input)?;
+
+        replace_input(&resource, value).map_err(|(kind, message)| (kind, message.into()))
+    })
+}
+
+/// Set the input from `:erlang.term_to_binary/1` output, converting the
+/// decoded term straight to a regorus value instead of going through JSON.
+///
+/// Decoding is done in safe mode, so the binary can't create new atoms.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_set_input_etf<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    etf_input: Binary<'a>,
+    opts: EncodeOptions,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        limits.check_bytes(etf_input.as_slice())?;
+
+        let (term, _) = env.binary_to_term(etf_input.as_slice()).ok_or_else(|| {
+            (
+                atoms::json_error(),
+                "not a valid external term".to_string().into(),
+            )
+        })?;
+        let value = encode::term_to_value(term, &opts)
+            .map_err(|e| (atoms::json_error(), e.detail("input")))?;
+        limits.check_value(&value)?;
+
+        replace_input(&resource, value).map_err(|(kind, message)| (kind, message.into()))
+    })
+}
+
+/// Make `value` the input document
+fn replace_input(resource: &EngineResource, value: regorus::Value) -> Result<(), (Atom, String)> {
+    let mut engine = resource.begin_write();
+
+    // Keep a copy so the input survives engine rebuilds
+    let mut input = resource
+        .input
         .write()
         .map_err(|e| (atoms::engine_error(), e.to_string()))?;
-
-    let value: regorus::Value = regorus::Value::from_json_str(&json_input)
-        .map_err(|e| (atoms::json_error(), e.to_string()))?;
+    *input = Some(value.clone());
 
     engine.set_input(value);
+    engine.commit();
 
     Ok(())
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn native_add_data(
     resource: ResourceArc<EngineResource>,
+    json_data: Term,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .parse_json_term(json_data)?;
+
+        let mut engine = resource.begin_write();
+        engine
+            .add_data(value)
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        engine.commit();
+        Ok(())
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_add_data_at_path(
+    resource: ResourceArc<EngineResource>,
+    path: String,
     json_data: String,
-) -> Result<(), (Atom, String)> {
-    let mut engine = resource
-        .engine
-        .write()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .parse_json(&json_data)?;
+
+        let segments = path_segments(&path);
+        if segments.is_empty() && !matches!(value, regorus::Value::Object(_)) {
+            return Err((
+                atoms::engine_error(),
+                "data at the root path must be an object".to_string().into(),
+            ));
+        }
 
-    let value: regorus::Value = regorus::Value::from_json_str(&json_data)
-        .map_err(|e| (atoms::json_error(), e.to_string()))?;
+        let mut engine = resource.begin_write();
+        engine
+            .add_data(nest_value(&segments, value))
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        engine.commit();
+        Ok(())
+    })
+}
 
-    engine
-        .add_data(value)
-        .map_err(|e| (atoms::engine_error(), e.to_string()))
-}
-
-fn value_to_term<'a>(env: Env<'a>, value: regorus::Value) -> Term<'a> {
-    match value {
-        regorus::Value::Undefined => atoms::undefined().encode(env),
-        regorus::Value::Null => rustler::types::atom::nil().encode(env),
-        regorus::Value::Bool(b) => b.encode(env),
-        regorus::Value::String(s) => s.encode(env),
-        regorus::Value::Number(n) => {
-            if let Some(i) = n.as_i64() {
-                i.encode(env)
-            } else if let Some(f) = n.as_f64() {
-                f.encode(env)
-            } else {
-                atoms::undefined().encode(env)
-            }
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_add_data_yaml(
+    resource: ResourceArc<EngineResource>,
+    yaml_data: String,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .parse_yaml(&yaml_data)?;
+
+        let mut engine = resource.begin_write();
+        engine
+            .add_data(value)
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        engine.commit();
+        Ok(())
+    })
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_add_data_from_file(
+    resource: ResourceArc<EngineResource>,
+    path: String,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| (atoms::io_error(), format!("{}: {}", path, e).into()))?;
+
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let is_yaml = matches!(
+            Path::new(&path).extension().and_then(|ext| ext.to_str()),
+            Some("yaml" | "yml")
+        );
+
+        let value = if is_yaml {
+            limits.parse_yaml(&contents)
+        } else {
+            limits.parse_json(&contents)
         }
-        regorus::Value::Array(arr) => {
-            let terms: Vec<Term<'a>> = arr.iter().map(|v| value_to_term(env, v.clone())).collect();
-            terms.encode(env)
+        .map_err(|(kind, detail)| (kind, detail.in_file(&path)))?;
+
+        let mut engine = resource.begin_write();
+        engine
+            .add_data(value)
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        engine.commit();
+        Ok(())
+    })
+}
+
+/// Wrap a value in nested objects so it sits at `path` in the data document
+fn nest_value(path: &[&str], value: regorus::Value) -> regorus::Value {
+    path.iter().rev().fold(value, |inner, key| {
+        let mut object = regorus::Value::new_object();
+        if let Ok(map) = object.as_object_mut() {
+            map.insert(regorus::Value::from(*key), inner);
         }
-        regorus::Value::Object(obj) => {
-            let pairs: Vec<(Term<'a>, Term<'a>)> = obj
-                .iter()
-                .map(|(k, v)| {
-                    let key: Term<'a> = value_to_term(env, k.clone());
-                    let val: Term<'a> = value_to_term(env, v.clone());
-                    (key, val)
-                })
-                .collect();
-            Term::map_from_pairs(env, &pairs).unwrap()
+        object
+    })
+}
+
+/// Split a dotted path like `"tenants.acme"` into segments; empty means the root
+fn path_segments(path: &str) -> Vec<&str> {
+    path.split('.').filter(|segment| !segment.is_empty()).collect()
+}
+
+/// Look up the value at `path`, indexing arrays by number; undefined if missing
+fn value_at_path(value: &regorus::Value, path: &[&str]) -> regorus::Value {
+    let mut current = value;
+    for segment in path {
+        let next = match current {
+            regorus::Value::Object(obj) => obj.get(&regorus::Value::from(*segment)),
+            regorus::Value::Array(arr) => segment.parse::<usize>().ok().and_then(|i| arr.get(i)),
+            _ => None,
+        };
+
+        match next {
+            Some(value) => current = value,
+            None => return regorus::Value::Undefined,
         }
-        regorus::Value::Set(set) => {
-            let terms: Vec<Term<'a>> = set.iter().map(|v| value_to_term(env, v.clone())).collect();
-            terms.encode(env)
+    }
+    current.clone()
+}
+
+/// Narrow a result to the part at `path`, given either as a dotted path such
+/// as `"violations.0.msg"` or as a JSON Pointer such as `"/violations/0/msg"`
+fn extract_path(value: &regorus::Value, path: &str) -> regorus::Value {
+    if path.starts_with('/') {
+        let segments = patch::pointer_segments(path).unwrap_or_default();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        value_at_path(value, &segments)
+    } else {
+        value_at_path(value, &path_segments(path))
+    }
+}
+
+/// Remove the subtree at `path`, returning whether anything was removed
+fn remove_at_path(value: &mut regorus::Value, path: &[&str]) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+
+    let mut current = value;
+    for segment in parents {
+        let Ok(map) = current.as_object_mut() else {
+            return false;
+        };
+        match map.get_mut(&regorus::Value::from(*segment)) {
+            Some(next) => current = next,
+            None => return false,
         }
     }
+
+    match current.as_object_mut() {
+        Ok(map) => map.remove(&regorus::Value::from(*last)).is_some(),
+        Err(_) => false,
+    }
 }
 
 #[rustler::nif]
-fn native_eval_query<'a>(
+fn native_get_data<'a>(
     env: Env<'a>,
     resource: ResourceArc<EngineResource>,
-    query: String,
+    path: String,
 ) -> Result<Term<'a>, (Atom, String)> {
-    let mut engine = resource
-        .engine
-        .write()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+    catch_panic(|| {
+        let engine = resource.snapshot();
 
-    let results = engine
-        .eval_query(query, false)
-        .map_err(|e| (atoms::eval_error(), e.to_string()))?;
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let data = engine.get_data();
+        let value = value_at_path(&data, &path_segments(&path));
+
+        value_to_term(env, value, &decode)
+    })
+}
+
+/// Evaluate a query. With `extract`, only the part of the result at that path
+/// is converted to terms; see `extract_path`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    extract: Option<String>,
+    overrides: DecodeOverrides,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let decode = overrides.apply(decode);
+
+        let key = resource.decision_key(&query, None);
+        let cached = key.as_ref().and_then(|key| resource.cached_decision(key));
+
+        let mut value = match cached {
+            Some(value) => value,
+            None => {
+                let mut engine = resource.eval_engine();
+                let prepared = prepare_query(&resource, &query)?;
+                let started = Instant::now();
+                let value = eval_prepared(&mut engine, prepared, &query);
+                resource.record_eval(started, &value);
+                let value = value?;
+                profile::record(&resource, &engine, &query);
+
+                if let Some(key) = key {
+                    resource.cache_decision(key, &value);
+                }
+                value
+            }
+        };
+        resource.record_decision(&query, None, &value);
 
-    // Return the first result's first expression value, or undefined
-    if let Some(result) = results.result.into_iter().next() {
-        if let Some(expr) = result.expressions.into_iter().next() {
-            return Ok(value_to_term(env, expr.value));
+        if let Some(path) = extract {
+            value = extract_path(&value, &path);
         }
-    }
 
-    Ok(atoms::undefined().encode(env))
+        result_to_term(env, value, &decode)
+    })
 }
 
-#[rustler::nif]
-fn native_get_packages(
+/// Evaluate a query and serialize the result straight to JSON, skipping term
+/// construction for callers that only forward the result
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_json<'a>(
+    env: Env<'a>,
     resource: ResourceArc<EngineResource>,
-) -> Result<Vec<String>, (Atom, String)> {
-    let engine = resource
-        .engine
-        .read()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+    query: String,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let prepared = prepare_query(&resource, &query)?;
+        let started = Instant::now();
+        let value = eval_prepared(&mut engine, prepared, &query);
+        resource.record_eval(started, &value);
+        let value = value?;
+        profile::record(&resource, &engine, &query);
+
+        if value == regorus::Value::Undefined {
+            return result_to_term(env, value, &decode);
+        }
 
-    engine
-        .get_packages()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))
+        let json = value
+            .to_json_str()
+            .map_err(|e| (atoms::json_error(), e.to_string().into()))?;
+
+        Ok(json.encode(env))
+    })
 }
 
-#[rustler::nif]
-fn native_clear_data(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
-    let mut engine = resource
-        .engine
-        .write()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+/// Timings and counts for a single evaluation, for telemetry
+#[derive(NifMap)]
+struct EvalMetrics {
+    /// Time spent taking a copy of the engine snapshot, including waiting
+    /// for other evaluations when coverage is enabled
+    lock_wait_ns: u64,
+    /// Query compilation and evaluation
+    eval_ns: u64,
+    /// Converting the result into Elixir terms
+    decode_ns: u64,
+    result_count: usize,
+    expression_count: usize,
+    /// Whether the query was a direct rule lookup that skipped compilation
+    rule_lookup: bool,
+    strict_builtin_errors: bool,
+}
 
-    engine.clear_data();
-    Ok(())
+#[derive(NifMap)]
+struct MeasuredResult<'a> {
+    result: Term<'a>,
+    metrics: EvalMetrics,
 }
 
-#[rustler::nif]
-fn native_enable_coverage(
+fn elapsed_ns(since: Instant) -> u64 {
+    u64::try_from(since.elapsed().as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_with_metrics<'a>(
+    env: Env<'a>,
     resource: ResourceArc<EngineResource>,
-    enable: bool,
-) -> Result<(), (Atom, String)> {
-    let mut engine = resource
-        .engine
+    query: String,
+) -> Result<MeasuredResult<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let waiting = Instant::now();
+        let mut engine = resource.eval_engine();
+        let lock_wait_ns = elapsed_ns(waiting);
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let strict_builtin_errors = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .strict_builtin_errors;
+
+        let prepared = prepare_query(&resource, &query)?;
+        let evaluating = Instant::now();
+
+        let evaluated = match prepared {
+            PreparedQuery::Rule => eval_prepared(&mut engine, prepared, &query).map(|value| {
+                let count = usize::from(value != regorus::Value::Undefined);
+                (value, count, count)
+            }),
+            PreparedQuery::Query => engine
+                .eval_query(query.clone(), false)
+                .map_err(|e| located_error(atoms::eval_error(), e))
+                .map(|results| {
+                    let result_count = results.result.len();
+                    let expression_count = results.result.iter().map(|r| r.expressions.len()).sum();
+                    (first_value(results), result_count, expression_count)
+                }),
+        };
+        resource.record_eval(evaluating, &evaluated);
+        let (value, result_count, expression_count) = evaluated?;
+
+        let eval_ns = elapsed_ns(evaluating);
+        profile::record(&resource, &engine, &query);
+
+        let decoding = Instant::now();
+        let result = result_to_term(env, value, &decode)?;
+        let decode_ns = elapsed_ns(decoding);
+
+        Ok(MeasuredResult {
+            result,
+            metrics: EvalMetrics {
+                lock_wait_ns,
+                eval_ns,
+                decode_ns,
+                result_count,
+                expression_count,
+                rule_lookup: prepared == PreparedQuery::Rule,
+                strict_builtin_errors,
+            },
+        })
+    })
+}
+
+fn eval_prepared(
+    engine: &mut Engine,
+    prepared: PreparedQuery,
+    query: &str,
+) -> Result<regorus::Value, (Atom, ErrorDetail)> {
+    match prepared {
+        PreparedQuery::Rule => engine
+            .eval_rule(query.to_string())
+            .map_err(|e| located_error(atoms::eval_error(), e)),
+        PreparedQuery::Query => engine
+            .eval_query(query.to_string(), false)
+            .map(first_value)
+            .map_err(|e| located_error(atoms::eval_error(), e)),
+    }
+}
+
+/// Look up or decide how to evaluate a query string.
+///
+/// A query that is exactly `data.<package>.<rule>` for a loaded rule can go
+/// through `eval_rule`, skipping query parsing and compilation on every call.
+fn prepare_query(
+    resource: &EngineResource,
+    query: &str,
+) -> Result<PreparedQuery, (Atom, ErrorDetail)> {
+    if let Some(prepared) = resource
+        .queries
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+        .prepared
+        .get(query)
+    {
+        return Ok(*prepared);
+    }
+
+    // Policies before queries, the order writers take them in
+    let policies = resource
+        .policies
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+    let mut queries = resource
+        .queries
         .write()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
 
-    engine.set_enable_coverage(enable);
-    Ok(())
+    let rules = queries.rules.get_or_insert_with(|| rule_paths(&policies));
+    let prepared = if rules.contains(query) {
+        PreparedQuery::Rule
+    } else {
+        PreparedQuery::Query
+    };
+
+    if queries.prepared.len() >= MAX_PREPARED_QUERIES {
+        queries.prepared.clear();
+    }
+    queries.prepared.insert(query.to_string(), prepared);
+
+    Ok(prepared)
+}
+
+/// `data.<package>.<rule>` of every loaded rule other than functions
+fn rule_paths(policies: &HashMap<String, PolicySource>) -> HashSet<String> {
+    policies
+        .iter()
+        .flat_map(|(name, policy)| {
+            parse_rules(name, &policy.source)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|rule| rule.kind != RuleKind::Function)
+                .map(move |rule| format!("{}.{}", policy.package, rule.name))
+        })
+        .collect()
 }
 
 #[rustler::nif]
-fn native_get_coverage_report<'a>(
+fn native_clear_query_cache(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        *resource
+            .queries
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))? = QueryCache::default();
+        Ok(())
+    })
+}
+
+/// Return the first result's first expression value, or undefined
+fn first_value(results: regorus::QueryResults) -> regorus::Value {
+    results
+        .result
+        .into_iter()
+        .next()
+        .and_then(|result| result.expressions.into_iter().next())
+        .map(|expr| expr.value)
+        .unwrap_or(regorus::Value::Undefined)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_with_input<'a>(
     env: Env<'a>,
     resource: ResourceArc<EngineResource>,
-) -> Result<Term<'a>, (Atom, String)> {
-    let engine = resource
-        .engine
+    query: String,
+    json_input: Term<'a>,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .parse_json_term(json_input)?;
+
+        eval_with_input(env, &resource, query, value)
+    })
+}
+
+/// Evaluate `query` with `input`, leaving the engine's own input alone
+fn eval_with_input<'a>(
+    env: Env<'a>,
+    resource: &EngineResource,
+    query: String,
+    input: regorus::Value,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    let decode = *resource
+        .decode
         .read()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+    let key = resource.decision_key(&query, Some(&input));
+    let value = match key.as_ref().and_then(|key| resource.cached_decision(key)) {
+        Some(value) => value,
+        None => {
+            let value = eval_detached(resource, &query, Some(&input))?;
+            if let Some(key) = key {
+                resource.cache_decision(key, &value);
+            }
+            value
+        }
+    };
+    resource.record_decision(&query, Some(&input), &value);
 
-    let report = engine
-        .get_coverage_report()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+    result_to_term(env, value, &decode)
+}
 
-    // Convert to Elixir map: %{filename => %{covered: [...], not_covered: [...]}}
-    let mut file_reports: Vec<(Term<'a>, Term<'a>)> = Vec::new();
+/// Evaluate `query` against a private copy of the engine, with `input` in
+/// place of its own if given, so concurrent callers never see each other's
+/// input
+fn eval_detached(
+    resource: &EngineResource,
+    query: &str,
+    input: Option<&regorus::Value>,
+) -> Result<regorus::Value, (Atom, ErrorDetail)> {
+    let mut engine = Engine::clone(&resource.snapshot());
+    if let Some(input) = input {
+        engine.set_input(input.clone());
+    }
 
-    for file_coverage in report.files.iter() {
-        let covered: Vec<i64> = file_coverage.covered.iter().map(|&n| n as i64).collect();
-        let not_covered: Vec<i64> = file_coverage.not_covered.iter().map(|&n| n as i64).collect();
+    let started = Instant::now();
+    let results = engine
+        .eval_query(query.to_string(), false)
+        .map_err(|e| located_error(atoms::eval_error(), e));
+    resource.record_eval(started, &results);
 
-        let covered_atom = rustler::Atom::from_str(env, "covered").unwrap();
-        let not_covered_atom = rustler::Atom::from_str(env, "not_covered").unwrap();
+    Ok(first_value(results?))
+}
 
-        let inner_map = Term::map_from_pairs(
-            env,
-            &[
-                (covered_atom.encode(env), covered.encode(env)),
-                (not_covered_atom.encode(env), not_covered.encode(env)),
-            ],
-        )
-        .unwrap();
+/// Evaluate one query against many inputs, returning `{:ok, value}` or
+/// `{:error, {type, detail}}` per input so one bad input doesn't fail the batch
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_batch<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    json_inputs: Vec<String>,
+) -> Result<Vec<Term<'a>>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let prepared = prepare_query(&resource, &query)?;
 
-        file_reports.push((file_coverage.path.encode(env), inner_map));
-    }
+        // One copy for the whole batch; the engine's own input is left alone
+        let mut engine = Engine::clone(&resource.snapshot());
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let results = json_inputs
+            .iter()
+            .map(|json_input| {
+                let result = limits
+                    .parse_json(json_input)
+                    .and_then(|input| {
+                        engine.set_input(input);
+                        let started = Instant::now();
+                        let value = eval_prepared(&mut engine, prepared, &query);
+                        resource.record_eval(started, &value);
+                        value
+                    });
+
+                match result.and_then(|value| result_to_term(env, value, &decode)) {
+                    Ok(term) => (atoms::ok(), term).encode(env),
+                    Err(error) => (atoms::error(), error).encode(env),
+                }
+            })
+            .collect();
 
-    Ok(Term::map_from_pairs(env, &file_reports).unwrap())
+        Ok(results)
+    })
 }
 
-#[rustler::nif]
-fn native_clear_coverage(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
-    let mut engine = resource
-        .engine
-        .write()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+/// Result of an evaluation along with the output of its `print` calls
+#[derive(NifMap)]
+struct TracedResult<'a> {
+    result: Term<'a>,
+    prints: Vec<String>,
+}
 
-    engine.clear_coverage_data();
-    Ok(())
+/// Evaluate a query, collecting `print(...)` output instead of writing it to stderr.
+///
+/// regorus doesn't emit OPA-style explain events (enter/eval/fail/redo), so
+/// `print` statements are the available trace: each line is prefixed with the
+/// policy file and line it came from.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_traced<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+) -> Result<TracedResult<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        // A private copy keeps print gathering from leaking into other evaluations
+        let mut engine = Engine::clone(&resource.snapshot());
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        engine.set_gather_prints(true);
+
+        let started = Instant::now();
+        let results = engine
+            .eval_query(query, false)
+            .map_err(|e| located_error(atoms::eval_error(), e));
+        resource.record_eval(started, &results);
+        let results = results?;
+
+        let prints = engine
+            .take_prints()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        Ok(TracedResult {
+            result: result_to_term(env, first_value(results), &decode)?,
+            prints,
+        })
+    })
 }
 
-/// Represents a parsed Rego rule with metadata
-#[derive(Debug)]
-struct RuleInfo {
-    name: String,
-    description: String,
-    start_line: usize,
-    end_line: usize,
-}
-
-/// Parse Rego source to extract rule definitions with their metadata
-fn parse_rules(source: &str) -> Vec<RuleInfo> {
-    let mut rules = Vec::new();
-    let lines: Vec<&str> = source.lines().collect();
-    let mut pending_comments: Vec<String> = Vec::new();
-    let mut i = 0;
-
-    while i < lines.len() {
-        let line = lines[i].trim();
-        let line_num = i + 1; // 1-indexed
-
-        // Collect comments
-        if line.starts_with('#') {
-            // Skip section dividers (lines of === or ---)
-            let comment_text = line.trim_start_matches('#').trim();
-            if !comment_text.chars().all(|c| c == '=' || c == '-' || c.is_whitespace()) {
-                pending_comments.push(comment_text.to_string());
-            }
-            i += 1;
-            continue;
-        }
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_full<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let started = Instant::now();
+        let results = engine
+            .eval_query(query, false)
+            .map_err(|e| located_error(atoms::eval_error(), e));
+        resource.record_eval(started, &results);
+        let results = results?;
+
+        // Convert to Elixir list: [%{expressions: [%{value: ..., text: ...}], bindings: %{...}}]
+        let expressions_atom = atoms::expressions();
+        let bindings_atom = atoms::bindings();
+        let value_atom = atoms::value();
+        let text_atom = atoms::text();
+
+        let decode_value = |value: regorus::Value| -> Result<Term<'a>, (Atom, ErrorDetail)> {
+            value_to_term(env, value, &decode).map_err(|(kind, message)| (kind, message.into()))
+        };
+
+        let result_terms = results
+            .result
+            .into_iter()
+            .map(|result| -> Result<Term<'a>, (Atom, ErrorDetail)> {
+                let expressions = result
+                    .expressions
+                    .into_iter()
+                    .map(|expr| -> Result<Term<'a>, (Atom, ErrorDetail)> {
+                        map_from_pairs(
+                            env,
+                            &[
+                                (value_atom.encode(env), decode_value(expr.value)?),
+                                (text_atom.encode(env), expr.text.as_ref().encode(env)),
+                            ],
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                map_from_pairs(
+                    env,
+                    &[
+                        (expressions_atom.encode(env), expressions.encode(env)),
+                        (bindings_atom.encode(env), decode_value(result.bindings)?),
+                    ],
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        // Skip empty lines but preserve comments
-        if line.is_empty() {
-            pending_comments.clear();
-            i += 1;
-            continue;
-        }
+        Ok(result_terms.encode(env))
+    })
+}
 
-        // Skip imports and package declarations
-        if line.starts_with("import ") || line.starts_with("package ") {
-            pending_comments.clear();
-            i += 1;
-            continue;
-        }
+/// Evaluate a query and return just the variable bindings of each result,
+/// e.g. `[%{"x" => "admin"}, %{"x" => "viewer"}]` for `x := data.roles[_]`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_bindings<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+) -> Result<Vec<Term<'a>>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let started = Instant::now();
+        let results = engine
+            .eval_query(query.clone(), false)
+            .map_err(|e| located_error(atoms::eval_error(), e));
+        resource.record_eval(started, &results);
+        let results = results?;
+        profile::record(&resource, &engine, &query);
+
+        results
+            .result
+            .into_iter()
+            .map(|result| match result.bindings {
+                // A query without variables still binds nothing per result
+                regorus::Value::Undefined => Ok(Term::map_new(env)),
+                bindings => value_to_term(env, bindings, &decode)
+                    .map_err(|(kind, message)| (kind, message.into())),
+            })
+            .collect()
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_rule<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    path: String,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let started = Instant::now();
+        let value = engine
+            .eval_rule(path.clone())
+            .map_err(|e| located_error(atoms::eval_error(), e));
+        resource.record_eval(started, &value);
+        let value = value?;
+        profile::record(&resource, &engine, &path);
+
+        result_to_term(env, value, &decode)
+    })
+}
 
-        // Try to parse a rule
-        if let Some(rule_name) = extract_rule_name(line) {
-            let description = pending_comments.last().cloned().unwrap_or_default();
-            pending_comments.clear();
-
-            // Find the end of the rule
-            let end_line = if line.contains('{') {
-                find_rule_end(&lines, i)
-            } else {
-                line_num // Single-line rule (like `default allow := false`)
-            };
-
-            rules.push(RuleInfo {
-                name: rule_name,
-                description,
-                start_line: line_num,
-                end_line,
-            });
-
-            // Skip to end of rule
-            i = end_line;
+/// Whether a rule name marks it as not part of a package's decision: a
+/// leading underscore, or a test rule as `native_run_tests` finds them
+fn is_internal_rule(name: &str) -> bool {
+    name.starts_with('_') || name.starts_with("test_") || name.starts_with("todo_test_")
+}
+
+/// Evaluate a whole package, e.g. `authz` or `data.authz`, returning its
+/// document of rule values. With `exclude_internal`, internal and test rules
+/// are left out of the document.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_package<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    package: String,
+    exclude_internal: bool,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let query = if package.starts_with("data.") {
+            package
         } else {
-            pending_comments.clear();
-            i += 1;
+            format!("data.{}", package)
+        };
+
+        let mut engine = resource.eval_engine();
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let prepared = prepare_query(&resource, &query)?;
+        let started = Instant::now();
+        let value = eval_prepared(&mut engine, prepared, &query);
+        resource.record_eval(started, &value);
+        let mut value = value?;
+        profile::record(&resource, &engine, &query);
+
+        if exclude_internal {
+            if let Ok(fields) = value.as_object_mut() {
+                fields.retain(|key, _| !key.as_string().is_ok_and(|name| is_internal_rule(name)));
+            }
         }
-    }
 
-    rules
+        result_to_term(env, value, &decode)
+    })
 }
 
-/// Extract the rule name from a line, if it's a rule definition
-fn extract_rule_name(line: &str) -> Option<String> {
-    let line = line.trim();
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_bool_query(
+    resource: ResourceArc<EngineResource>,
+    query: String,
+) -> Result<bool, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        let started = Instant::now();
+        let decision = engine
+            .eval_bool_query(query, false)
+            .map_err(|e| located_error(atoms::eval_error(), e));
+        resource.record_eval(started, &decision);
+        decision
+    })
+}
 
-    // default name := value
-    if line.starts_with("default ") {
-        let rest = line.strip_prefix("default ")?.trim();
-        let name = rest.split(|c: char| !c.is_alphanumeric() && c != '_')
-            .next()?;
-        return Some(name.to_string());
-    }
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_allow_query(
+    resource: ResourceArc<EngineResource>,
+    query: String,
+) -> Result<bool, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        let started = Instant::now();
+        let decision = eval_decision(&mut engine, query, false);
+        resource.record_eval(started, &decision);
+        decision
+    })
+}
 
-    // name := value if { or name if { or name contains value if {
-    // Look for pattern: identifier followed by := or contains or if
-    let mut chars = line.chars().peekable();
-    let mut name = String::new();
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_deny_query(
+    resource: ResourceArc<EngineResource>,
+    query: String,
+) -> Result<bool, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        // `deny contains msg if ...` rules produce sets of reasons
+        let started = Instant::now();
+        let decision = eval_decision(&mut engine, query, true);
+        resource.record_eval(started, &decision);
+        decision
+    })
+}
 
-    // Extract identifier
-    while let Some(&c) = chars.peek() {
-        if c.is_alphanumeric() || c == '_' {
-            name.push(c);
-            chars.next();
-        } else {
-            break;
-        }
+/// Evaluate a decision query where an undefined result means `false`.
+///
+/// When `collections` is set, a set or array result is `true` if non-empty.
+/// Any other non-boolean result is an error rather than a silent default.
+fn eval_decision(
+    engine: &mut Engine,
+    query: String,
+    collections: bool,
+) -> Result<bool, (Atom, ErrorDetail)> {
+    let results = engine
+        .eval_query(query, false)
+        .map_err(|e| located_error(atoms::eval_error(), e))?;
+
+    match first_value(results) {
+        regorus::Value::Bool(b) => Ok(b),
+        regorus::Value::Undefined => Ok(false),
+        regorus::Value::Set(set) if collections => Ok(!set.is_empty()),
+        regorus::Value::Array(arr) if collections => Ok(!arr.is_empty()),
+        other => Err((
+            atoms::eval_error(),
+            format!(
+                "expected a boolean decision, got {}",
+                other.to_json_str().unwrap_or_default()
+            )
+            .into(),
+        )),
     }
+}
 
-    if name.is_empty() {
-        return None;
-    }
+#[rustler::nif]
+fn native_set_limits(
+    resource: ResourceArc<EngineResource>,
+    limits: Limits,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut current = resource
+            .limits
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        *current = limits;
+        resource.store_changed();
+        Ok(())
+    })
+}
 
-    // Skip whitespace
-    while let Some(&c) = chars.peek() {
-        if c.is_whitespace() {
-            chars.next();
-        } else {
-            break;
-        }
-    }
+#[rustler::nif]
+fn native_set_sets_mode(
+    resource: ResourceArc<EngineResource>,
+    mode: SetEncoding,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut decode = resource
+            .decode
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        decode.sets = mode;
+        resource.store_changed();
+        Ok(())
+    })
+}
 
-    // Check what follows
-    let rest: String = chars.collect();
+#[rustler::nif]
+fn native_set_numbers_mode(
+    resource: ResourceArc<EngineResource>,
+    mode: NumberEncoding,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut decode = resource
+            .decode
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        decode.numbers = mode;
+        resource.store_changed();
+        Ok(())
+    })
+}
 
-    if rest.starts_with(":=") || rest.starts_with("=") || rest.starts_with("if ") || rest.starts_with("if{") ||
-       rest.starts_with("contains ") {
-        Some(name)
-    } else {
-        None
-    }
+#[rustler::nif]
+fn native_set_keys_mode(
+    resource: ResourceArc<EngineResource>,
+    mode: KeyEncoding,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut decode = resource
+            .decode
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        decode.keys = mode;
+        resource.store_changed();
+        Ok(())
+    })
 }
 
-/// Find the end line of a rule by counting braces
-fn find_rule_end(lines: &[&str], start_idx: usize) -> usize {
-    let mut brace_depth = 0;
-    let mut found_open = false;
+#[rustler::nif]
+fn native_set_undefined_mode(
+    resource: ResourceArc<EngineResource>,
+    mode: UndefinedEncoding,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut decode = resource
+            .decode
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        decode.undefined = mode;
+        resource.store_changed();
+        Ok(())
+    })
+}
 
-    for (i, line) in lines.iter().enumerate().skip(start_idx) {
-        for c in line.chars() {
-            if c == '{' {
-                brace_depth += 1;
-                found_open = true;
-            } else if c == '}' {
-                brace_depth -= 1;
-            }
+/// Cap how deeply nested a returned result may be, or lift the cap with `None`
+#[rustler::nif]
+fn native_set_max_result_depth(
+    resource: ResourceArc<EngineResource>,
+    max_depth: Option<usize>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut decode = resource
+            .decode
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        decode.max_depth = max_depth;
+        resource.store_changed();
+        Ok(())
+    })
+}
+
+/// Replace all of the engine's decode options at once
+#[rustler::nif]
+fn native_set_decode_opts(
+    resource: ResourceArc<EngineResource>,
+    opts: DecodeOptions,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        *resource
+            .decode
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))? = opts;
+        resource.store_changed();
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn native_get_packages(
+    resource: ResourceArc<EngineResource>,
+) -> Result<Vec<String>, (Atom, String)> {
+    catch_panic(|| {
+        let engine = resource.snapshot();
+
+        engine
+            .get_packages()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_remove_data_path(
+    resource: ResourceArc<EngineResource>,
+    path: String,
+) -> Result<bool, (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let segments = path_segments(&path);
+        if segments.is_empty() {
+            return Err((
+                atoms::engine_error(),
+                "path must not be empty, use clear_data to remove all data".to_string(),
+            ));
         }
 
-        if found_open && brace_depth == 0 {
-            return i + 1; // 1-indexed
+        let mut data = engine.get_data();
+        if !remove_at_path(&mut data, &segments) {
+            return Ok(false);
         }
-    }
 
-    // If we never find closing brace, return last line
-    lines.len()
+        // regorus only merges data, so swap in the pruned document
+        engine.clear_data();
+        engine
+            .add_data(data)
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        engine.commit();
+        Ok(true)
+    })
 }
 
 #[rustler::nif]
-fn native_get_rules<'a>(
+fn native_get_policies<'a>(
     env: Env<'a>,
     resource: ResourceArc<EngineResource>,
 ) -> Result<Term<'a>, (Atom, String)> {
-    let policies = resource
-        .policies
-        .read()
-        .map_err(|e| (atoms::engine_error(), e.to_string()))?;
-
-    // Build a map of policy_name => [rules]
-    let mut policy_rules: Vec<(Term<'a>, Term<'a>)> = Vec::new();
-
-    for (policy_name, source) in policies.iter() {
-        let rules = parse_rules(source);
-
-        let rule_terms: Vec<Term<'a>> = rules
-            .iter()
-            .map(|rule| {
-                let name_atom = rustler::Atom::from_str(env, "name").unwrap();
-                let desc_atom = rustler::Atom::from_str(env, "description").unwrap();
-                let start_atom = rustler::Atom::from_str(env, "start_line").unwrap();
-                let end_atom = rustler::Atom::from_str(env, "end_line").unwrap();
-
-                Term::map_from_pairs(
+    catch_panic(|| {
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let mut names: Vec<&String> = policies.keys().collect();
+        names.sort();
+
+        // Convert to Elixir list: [%{name: ..., package: ..., source_length: ...}]
+        let name_atom = atoms::name();
+        let package_atom = atoms::package();
+        let length_atom = atoms::source_length();
+
+        let policy_terms = names
+            .into_iter()
+            .map(|name| {
+                let policy = &policies[name];
+                map_from_pairs(
                     env,
                     &[
-                        (name_atom.encode(env), rule.name.encode(env)),
-                        (desc_atom.encode(env), rule.description.encode(env)),
-                        (start_atom.encode(env), (rule.start_line as i64).encode(env)),
-                        (end_atom.encode(env), (rule.end_line as i64).encode(env)),
+                        (name_atom.encode(env), name.encode(env)),
+                        (package_atom.encode(env), policy.package.encode(env)),
+                        (length_atom.encode(env), (policy.source.len() as i64).encode(env)),
                     ],
                 )
-                .unwrap()
             })
-            .collect();
+            .collect::<Result<Vec<_>, (Atom, String)>>()?;
 
-        policy_rules.push((policy_name.encode(env), rule_terms.encode(env)));
-    }
+        Ok(policy_terms.encode(env))
+    })
+}
+
+#[rustler::nif]
+fn native_clear_data(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        engine.clear_data();
+        engine.commit();
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn native_enable_coverage(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        settings.coverage_enabled = enable;
+
+        engine.set_enable_coverage(enable);
+        engine.commit();
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn native_set_strict_builtin_errors(
+    resource: ResourceArc<EngineResource>,
+    enable: bool,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        settings.strict_builtin_errors = enable;
+
+        engine.set_strict_builtin_errors(enable);
+        engine.commit();
+        Ok(())
+    })
+}
 
-    Ok(Term::map_from_pairs(env, &policy_rules).unwrap())
+#[rustler::nif]
+fn native_set_rego_version(
+    resource: ResourceArc<EngineResource>,
+    version: RegoVersion,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        settings.rego_version = version;
+
+        // Only affects policies added from now on
+        engine.set_rego_v0(version == RegoVersion::V0);
+        engine.commit();
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn native_add_extension(
+    resource: ResourceArc<EngineResource>,
+    path: String,
+    nargs: u8,
+    handler: LocalPid,
+    timeout_ms: u64,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let extension = ElixirExtension {
+            path,
+            nargs,
+            handler,
+            timeout: Duration::from_millis(timeout_ms),
+        };
+
+        extension
+            .register(&mut engine)
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        engine.commit();
+        settings.extensions.push(extension);
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn native_get_coverage_report<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+) -> Result<Term<'a>, (Atom, String)> {
+    catch_panic(|| {
+        let engine = resource.snapshot();
+
+        let report = engine
+            .get_coverage_report()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        // Convert to Elixir map: %{filename => %{covered: [...], not_covered: [...]}}
+        let mut file_reports: Vec<(Term<'a>, Term<'a>)> = Vec::new();
+
+        for file_coverage in report.files.iter() {
+            let covered: Vec<i64> = file_coverage.covered.iter().map(|&n| n as i64).collect();
+            let not_covered: Vec<i64> =
+                file_coverage.not_covered.iter().map(|&n| n as i64).collect();
+
+            let covered_atom = atoms::covered();
+            let not_covered_atom = atoms::not_covered();
+
+            let inner_map = map_from_pairs::<String>(
+                env,
+                &[
+                    (covered_atom.encode(env), covered.encode(env)),
+                    (not_covered_atom.encode(env), not_covered.encode(env)),
+                ],
+            )?;
+
+            file_reports.push((file_coverage.path.encode(env), inner_map));
+        }
+
+        map_from_pairs(env, &file_reports)
+    })
+}
+
+/// Render the coverage report the way the regorus CLI prints it, with covered
+/// and uncovered lines highlighted using ANSI colors
+#[rustler::nif]
+fn native_get_coverage_pretty(resource: ResourceArc<EngineResource>) -> Result<String, (Atom, String)> {
+    catch_panic(|| {
+        let engine = resource.snapshot();
+
+        let report = engine
+            .get_coverage_report()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        report
+            .to_string_pretty()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))
+    })
+}
+
+#[rustler::nif]
+fn native_clear_coverage(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        engine.clear_coverage_data();
+        engine.commit();
+        Ok(())
+    })
 }
 
 rustler::init!("Elixir.Regolix.Native");
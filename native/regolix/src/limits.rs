@@ -0,0 +1,191 @@
+use crate::atoms;
+use crate::error::{ErrorDetail, ErrorReason};
+use rustler::{Atom, Binary, NifMap, Term};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+/// Deepest JSON nesting `parse_json` accepts, whatever the engine's limits.
+///
+/// The JSON parser recurses once per level, so this keeps a hostile document
+/// from overflowing the scheduler's stack. It's below serde_json's own limit
+/// so that callers always get the same error.
+pub(crate) const MAX_JSON_DEPTH: usize = 100;
+
+/// Most bytes a bundle's files may add up to once decompressed, whatever the
+/// engine's limits, so a small gzip bomb can't exhaust memory
+pub(crate) const MAX_BUNDLE_BYTES: usize = 1 << 30;
+
+/// Per-engine caps on documents passed in as input or data.
+///
+/// Each limit is optional; `None` means unlimited.
+#[derive(Clone, Copy, Debug, Default, NifMap, Serialize, Deserialize)]
+pub(crate) struct Limits {
+    /// Size of the encoded document in bytes
+    pub max_bytes: Option<usize>,
+    /// Nesting depth of arrays and objects (a scalar has depth 0)
+    pub max_depth: Option<usize>,
+    /// Number of entries in any single array, object or set
+    pub max_entries: Option<usize>,
+    /// Total size of a bundle's files once decompressed
+    #[serde(default)]
+    pub max_bundle_bytes: Option<usize>,
+}
+
+impl Limits {
+    /// Parse a JSON document, rejecting it if it breaks any limit
+    pub fn parse_json(&self, text: &str) -> Result<regorus::Value, (Atom, ErrorDetail)> {
+        self.check_bytes(text)?;
+        check_json_depth(text)?;
+        let value = regorus::Value::from_json_str(text)
+            .map_err(|e| (atoms::json_error(), e.to_string().into()))?;
+        self.check_value(&value)?;
+        Ok(value)
+    }
+
+    /// Parse a YAML document, rejecting it if it breaks any limit
+    pub fn parse_yaml(&self, text: &str) -> Result<regorus::Value, (Atom, ErrorDetail)> {
+        self.check_bytes(text)?;
+        let value = regorus::Value::from_yaml_str(text)
+            .map_err(|e| (atoms::json_error(), e.to_string().into()))?;
+        self.check_value(&value)?;
+        Ok(value)
+    }
+
+    /// Parse a JSON document given as a binary or iodata.
+    ///
+    /// Binaries are read in place rather than copied into a `String`; iodata
+    /// is flattened once.
+    pub fn parse_json_term(&self, term: Term) -> Result<regorus::Value, (Atom, ErrorDetail)> {
+        let binary = Binary::from_iolist(term).map_err(|_| {
+            let message = "expected a binary or iodata".to_string();
+            (atoms::json_error(), message.into())
+        })?;
+        let text = std::str::from_utf8(binary.as_slice())
+            .map_err(|e| (atoms::json_error(), e.to_string().into()))?;
+        self.parse_json(text)
+    }
+
+    pub fn check_bytes(&self, document: impl AsRef<[u8]>) -> Result<(), (Atom, ErrorDetail)> {
+        let len = document.as_ref().len();
+        match self.max_bytes {
+            Some(max) if len > max => Err(exceeded(format!(
+                "document is {} bytes, limit is {}",
+                len, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Most bytes a bundle's files may add up to once decompressed
+    pub fn bundle_bytes(&self) -> usize {
+        self.max_bundle_bytes
+            .map_or(MAX_BUNDLE_BYTES, |max| max.min(MAX_BUNDLE_BYTES))
+    }
+
+    /// Read a bundle file, counting its size against `budget`, the bytes
+    /// left of `bundle_bytes`
+    pub fn read_bundle_file(
+        &self,
+        name: &str,
+        reader: impl Read,
+        budget: &mut usize,
+    ) -> Result<Vec<u8>, (Atom, ErrorDetail)> {
+        let mut contents = Vec::new();
+        reader
+            .take(*budget as u64 + 1)
+            .read_to_end(&mut contents)
+            .map_err(|e| (atoms::io_error(), format!("{}: {}", name, e).into()))?;
+
+        match budget.checked_sub(contents.len()) {
+            Some(left) => {
+                *budget = left;
+                Ok(contents)
+            }
+            None => Err(exceeded(format!(
+                "bundle is over {} bytes once decompressed",
+                self.bundle_bytes()
+            ))),
+        }
+    }
+
+    /// Walk a parsed document checking depth and entry counts.
+    ///
+    /// Documents nested deeper than `MAX_JSON_DEPTH` are rejected whatever
+    /// the limits, since later stages walk them recursively. Uses an explicit
+    /// stack so a deeply nested document can't overflow ours.
+    pub fn check_value(&self, value: &regorus::Value) -> Result<(), (Atom, ErrorDetail)> {
+        let mut stack = vec![(value, 0usize)];
+
+        while let Some((value, depth)) = stack.pop() {
+            let children: Vec<&regorus::Value> = match value {
+                regorus::Value::Array(items) => items.iter().collect(),
+                regorus::Value::Set(items) => items.iter().collect(),
+                regorus::Value::Object(fields) => fields.values().collect(),
+                _ => continue,
+            };
+
+            let depth = depth + 1;
+            if depth > MAX_JSON_DEPTH {
+                return Err((atoms::json_error(), ErrorReason::TooDeep.into()));
+            }
+
+            if let Some(max) = self.max_depth {
+                if depth > max {
+                    return Err(exceeded(format!("document nesting exceeds {} levels", max)));
+                }
+            }
+
+            if let Some(max) = self.max_entries {
+                if children.len() > max {
+                    return Err(exceeded(format!(
+                        "collection has {} entries, limit is {}",
+                        children.len(),
+                        max
+                    )));
+                }
+            }
+
+            stack.extend(children.into_iter().map(|child| (child, depth)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Reject JSON nested deeper than `MAX_JSON_DEPTH` without parsing it, as
+/// `{:json_error, :too_deep}`. Brackets inside strings don't count.
+fn check_json_depth(text: &str) -> Result<(), (Atom, ErrorDetail)> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for byte in text.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > MAX_JSON_DEPTH {
+                    return Err((atoms::json_error(), ErrorReason::TooDeep.into()));
+                }
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn exceeded(message: String) -> (Atom, ErrorDetail) {
+    (atoms::limit_exceeded(), message.into())
+}
@@ -0,0 +1,430 @@
+use crate::atoms;
+use crate::deps::literal_type;
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::rules::{parse_module, rule_infos, RuleInfo};
+use regorus::unstable::{Expr, Literal, Module, Query, Rule};
+use rustler::{Atom, NifMap, NifUnitEnum};
+use std::borrow::Cow;
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single lint result, pointing at the offending token
+#[derive(NifMap)]
+pub(crate) struct Finding {
+    pub check: String,
+    severity: Severity,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(NifMap)]
+struct LintOptions {
+    /// Check names to skip, e.g. `"unused-variable"`
+    disabled: Vec<String>,
+}
+
+/// Builtins replaced by newer equivalents
+const DEPRECATED_BUILTINS: &[(&str, &str)] = &[
+    ("any", "use `some x in xs` or a comprehension"),
+    ("all", "use `every`"),
+    ("re_match", "use `regex.match`"),
+    ("set_diff", "use the `-` operator"),
+    ("cast_array", "use an array comprehension"),
+    ("cast_set", "use a set comprehension"),
+    ("cast_string", "use `sprintf` or `format_int`"),
+    ("cast_boolean", "compare against `true` instead"),
+    ("cast_null", "compare against `null` instead"),
+    ("cast_object", "use an object comprehension"),
+    ("net.cidr_overlap", "use `net.cidr_contains`"),
+];
+
+/// Builtins a rule can accidentally hide by reusing the name
+const SHADOWABLE_BUILTINS: &[&str] = &[
+    "abs", "array", "concat", "contains", "count", "endswith", "format_int", "indexof", "is_array",
+    "is_boolean", "is_null", "is_number", "is_object", "is_set", "is_string", "lower", "max",
+    "min", "object", "print", "product", "replace", "round", "sort", "split", "sprintf",
+    "startswith", "substring", "sum", "to_number", "trim", "union", "upper",
+];
+
+pub(crate) const KEYWORDS: &[&str] = &[
+    "as", "contains", "default", "else", "every", "false", "if", "import", "in", "not", "null",
+    "package", "some", "true", "with",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TokenKind {
+    Ident,
+    Literal,
+    Punct,
+}
+
+#[derive(Debug)]
+pub(crate) struct Token<'s> {
+    pub kind: TokenKind,
+    pub text: &'s str,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Maps a token's line and column back to a byte offset in the source it
+/// was read from
+pub(crate) struct Offsets(Vec<usize>);
+
+impl Offsets {
+    pub fn new(source: &str) -> Self {
+        Offsets(
+            std::iter::once(0)
+                .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+        )
+    }
+
+    pub fn start(&self, token: &Token) -> usize {
+        self.0[token.line - 1] + token.column - 1
+    }
+
+    pub fn end(&self, token: &Token) -> usize {
+        self.start(token) + token.text.len()
+    }
+}
+
+/// Split Rego source into identifiers, literals and punctuation, dropping
+/// whitespace and comments. Good enough for lint heuristics, not a parser.
+///
+/// A raw string spanning several lines is a single literal token, reported
+/// at the line and column it starts on.
+pub(crate) fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+
+        let kind = match bytes[i] {
+            b'\n' => {
+                i += 1;
+                line += 1;
+                line_start = i;
+                continue;
+            }
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b' ' | b'\t' | b'\r' => {
+                i += 1;
+                continue;
+            }
+            b'"' => {
+                // Stopping only at ASCII bytes keeps `i` on a char boundary
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i = i.min(bytes.len());
+                if i < bytes.len() && bytes[i] == b'"' {
+                    i += 1;
+                }
+                TokenKind::Literal
+            }
+            b'`' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'`' {
+                    i += 1;
+                }
+                i = (i + 1).min(bytes.len());
+                TokenKind::Literal
+            }
+            b'0'..=b'9' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                TokenKind::Literal
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                match &source[start..i] {
+                    "true" | "false" | "null" => TokenKind::Literal,
+                    _ => TokenKind::Ident,
+                }
+            }
+            _ => {
+                let two = source.get(i..i + 2).unwrap_or("");
+                i += if [":=", "==", "!=", "<=", ">="].contains(&two) {
+                    2
+                } else {
+                    source[i..].chars().next().map_or(1, char::len_utf8)
+                };
+                TokenKind::Punct
+            }
+        };
+
+        let text = &source[start..i];
+        tokens.push(Token {
+            kind,
+            text,
+            line,
+            column: start - line_start + 1,
+        });
+
+        // Raw strings can span lines
+        if let Some(last) = text.rfind('\n') {
+            line += text.matches('\n').count();
+            line_start = start + last + 1;
+        }
+    }
+
+    tokens
+}
+
+fn finding(check: &str, severity: Severity, message: String, token: &Token) -> Finding {
+    Finding {
+        check: check.to_string(),
+        severity,
+        message,
+        line: token.line,
+        column: token.column,
+    }
+}
+
+/// `import future.keywords...` is a no-op in Rego v1
+fn check_deprecated_imports(tokens: &[Token], findings: &mut Vec<Finding>) {
+    for window in tokens.windows(3) {
+        if window[0].text == "import" && window[1].text == "future" && window[2].text == "." {
+            findings.push(finding(
+                "deprecated-import",
+                Severity::Warning,
+                "`future.keywords` imports are unnecessary in Rego v1".to_string(),
+                &window[0],
+            ));
+        }
+    }
+}
+
+/// A function call found in the token stream
+pub(crate) struct Call<'t, 's> {
+    /// Dotted function name, e.g. `net.cidr_overlap`
+    pub name: String,
+    /// First and last token of the name
+    pub first: &'t Token<'s>,
+    pub last: &'t Token<'s>,
+}
+
+/// Every function call in the token stream
+pub(crate) fn calls<'t, 's>(tokens: &'t [Token<'s>]) -> Vec<Call<'t, 's>> {
+    let mut calls = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Ident || (i > 0 && tokens[i - 1].text == ".") {
+            continue;
+        }
+
+        // Join a dotted call name such as `net.cidr_overlap`
+        let mut name = token.text.to_string();
+        let mut j = i + 1;
+        while j + 1 < tokens.len() && tokens[j].text == "." && tokens[j + 1].kind == TokenKind::Ident {
+            name.push('.');
+            name.push_str(tokens[j + 1].text);
+            j += 2;
+        }
+
+        if tokens.get(j).map(|t| t.text) == Some("(") {
+            calls.push(Call {
+                name,
+                first: token,
+                last: &tokens[j - 1],
+            });
+        }
+    }
+
+    calls
+}
+
+pub(crate) fn check_deprecated_builtins(tokens: &[Token], findings: &mut Vec<Finding>) {
+    for call in calls(tokens) {
+        if let Some((_, advice)) = DEPRECATED_BUILTINS.iter().find(|(builtin, _)| *builtin == call.name) {
+            findings.push(finding(
+                "deprecated-builtin",
+                Severity::Warning,
+                format!("`{}` is deprecated; {}", call.name, advice),
+                call.first,
+            ));
+        }
+    }
+}
+
+/// Rename calls to the given functions, e.g. to point a builtin at a
+/// stand-in registered as an extension. Line numbers are unchanged.
+pub(crate) fn rename_calls<'s>(source: &'s str, renames: &[(&str, &str)]) -> Cow<'s, str> {
+    if renames.is_empty() {
+        return Cow::Borrowed(source);
+    }
+
+    let offsets = Offsets::new(source);
+    let tokens = tokenize(source);
+    let mut renamed = String::with_capacity(source.len());
+    let mut copied = 0;
+
+    for call in calls(&tokens) {
+        if let Some((_, to)) = renames.iter().find(|(from, _)| *from == call.name) {
+            renamed.push_str(&source[copied..offsets.start(call.first)]);
+            renamed.push_str(to);
+            copied = offsets.end(call.last);
+        }
+    }
+
+    if copied == 0 {
+        return Cow::Borrowed(source);
+    }
+    renamed.push_str(&source[copied..]);
+    Cow::Owned(renamed)
+}
+
+/// Rules named `input`, `data` or after a builtin
+fn check_shadowing(rules: &[RuleInfo], tokens: &[Token], findings: &mut Vec<Finding>) {
+    for rule in rules {
+        let head = rule.name.split('.').next().unwrap_or_default();
+        let Some(token) = tokens
+            .iter()
+            .find(|t| t.line == rule.start_line && t.text == head)
+        else {
+            continue;
+        };
+
+        if head == "input" || head == "data" {
+            findings.push(finding(
+                "shadowed-document",
+                Severity::Error,
+                format!("rule `{}` shadows the `{}` document", rule.name, head),
+                token,
+            ));
+        } else if SHADOWABLE_BUILTINS.contains(&head) {
+            findings.push(finding(
+                "shadowed-builtin",
+                Severity::Warning,
+                format!("rule `{}` shadows the `{}` builtin", rule.name, head),
+                token,
+            ));
+        }
+    }
+}
+
+/// Body expressions that are always true or always false, like `1 == 1`
+fn check_constant_conditions(module: &Module, findings: &mut Vec<Finding>) {
+    for rule in &module.policy {
+        if let Rule::Spec { bodies, .. } = rule.as_ref() {
+            for body in bodies {
+                constant_conditions(body.query.as_ref(), findings);
+            }
+        }
+    }
+}
+
+fn constant_conditions(query: &Query, findings: &mut Vec<Finding>) {
+    for stmt in &query.stmts {
+        let expr = match &stmt.literal {
+            Literal::Expr { expr, .. } | Literal::NotExpr { expr, .. } => expr.as_ref(),
+            Literal::Every { query, .. } => {
+                constant_conditions(query.as_ref(), findings);
+                continue;
+            }
+            Literal::SomeIn { .. } | Literal::SomeVars { .. } => continue,
+        };
+
+        let constant = match expr {
+            Expr::BoolExpr { lhs, rhs, .. } => {
+                literal_type(lhs.as_ref()).is_some() && literal_type(rhs.as_ref()).is_some()
+            }
+            _ => literal_type(expr).is_some(),
+        };
+
+        if constant {
+            let span = expr.span();
+            findings.push(Finding {
+                check: "constant-condition".to_string(),
+                severity: Severity::Warning,
+                message: format!("condition `{}` is constant", span.text()),
+                line: span.line as usize,
+                column: span.col as usize,
+            });
+        }
+    }
+}
+
+/// Local variables assigned with `:=` and never referenced again in the rule
+fn check_unused_variables(rules: &[RuleInfo], tokens: &[Token], findings: &mut Vec<Finding>) {
+    for rule in rules {
+        let body: Vec<&Token> = tokens
+            .iter()
+            .filter(|t| t.line >= rule.start_line && t.line <= rule.end_line)
+            .collect();
+
+        for (i, token) in body.iter().enumerate() {
+            // Skip the rule's own head, `default name :=` and ref-head fields
+            let is_head = i == 0 || body[i - 1].text == "default" || body[i - 1].text == ".";
+            let assigned = body.get(i + 1).is_some_and(|next| next.text == ":=");
+
+            if token.kind != TokenKind::Ident
+                || is_head
+                || !assigned
+                || token.text.starts_with('_')
+                || KEYWORDS.contains(&token.text)
+            {
+                continue;
+            }
+
+            let uses = body
+                .iter()
+                .filter(|t| t.kind == TokenKind::Ident && t.text == token.text)
+                .count();
+
+            if uses == 1 {
+                findings.push(finding(
+                    "unused-variable",
+                    Severity::Warning,
+                    format!("`{}` is assigned but never used", token.text),
+                    token,
+                ));
+            }
+        }
+    }
+}
+
+/// Lint a single policy without loading it into an engine
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_lint_policy(
+    name: String,
+    source: String,
+    opts: LintOptions,
+) -> Result<Vec<Finding>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let module = parse_module(&name, &source)
+            .map_err(|e| located_error(atoms::parse_error(), e))?;
+        let rules = rule_infos(&module, &source);
+        let tokens = tokenize(&source);
+
+        let mut findings = Vec::new();
+        check_deprecated_imports(&tokens, &mut findings);
+        check_deprecated_builtins(&tokens, &mut findings);
+        check_shadowing(&rules, &tokens, &mut findings);
+        check_constant_conditions(&module, &mut findings);
+        check_unused_variables(&rules, &tokens, &mut findings);
+
+        findings.retain(|f| !opts.disabled.contains(&f.check));
+        findings.sort_by_key(|f| (f.line, f.column));
+
+        Ok(findings)
+    })
+}
@@ -0,0 +1,174 @@
+use crate::atoms;
+use crate::error::{catch_panic, located_error, parse_location, ErrorDetail};
+use crate::lint::{check_deprecated_builtins, tokenize, Token, TokenKind};
+use crate::rules::{parse_rules, RuleKind};
+use regorus::Engine;
+use rustler::{Atom, NifMap};
+
+/// Words that are keywords in Rego v1 and can no longer name rules or variables
+const V1_KEYWORDS: &[&str] = &["contains", "every", "if", "in"];
+
+/// Something that stops a policy parsing, or behaving the same, under Rego v1
+#[derive(NifMap)]
+struct MigrationIssue {
+    check: String,
+    message: String,
+    line: usize,
+    column: usize,
+    /// How to rewrite the construct for v1
+    suggestion: String,
+}
+
+fn issue(check: &str, message: String, token: &Token, suggestion: String) -> MigrationIssue {
+    MigrationIssue {
+        check: check.to_string(),
+        message,
+        line: token.line,
+        column: token.column,
+        suggestion,
+    }
+}
+
+/// Index of the `{` opening a rule body, if the rule has one. A `{` right
+/// after an operator or delimiter starts a value (an object or set) instead.
+fn body_start(head: &[&Token]) -> Option<usize> {
+    head.iter().enumerate().skip(1).find_map(|(i, token)| {
+        let after_value = matches!(head[i - 1].kind, TokenKind::Ident | TokenKind::Literal)
+            || [")", "]", "}"].contains(&head[i - 1].text);
+        (token.text == "{" && after_value).then_some(i)
+    })
+}
+
+/// `name[var] {` or `name[var] if`, a v0 partial set rule
+fn partial_set_key<'t>(head: &[&'t Token]) -> Option<&'t str> {
+    match head {
+        [name, open, key, close, next, ..]
+            if name.kind == TokenKind::Ident
+                && open.text == "["
+                && close.text == "]"
+                && (next.text == "{" || next.text == "if") =>
+        {
+            Some(key.text)
+        }
+        _ => None,
+    }
+}
+
+/// List the constructs in a v0 policy that need rewriting for Rego v1
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_check_rego_v1(
+    name: String,
+    source: String,
+) -> Result<Vec<MigrationIssue>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let rules = parse_rules(&name, &source)
+            .map_err(|e| located_error(atoms::parse_error(), e))?;
+        let tokens = tokenize(&source);
+        let mut issues = Vec::new();
+
+        for window in tokens.windows(3) {
+            if window[0].text == "import" && window[1].text == "future" && window[2].text == "." {
+                issues.push(issue(
+                    "future-keywords-import",
+                    "`future.keywords` imports are unnecessary in Rego v1".to_string(),
+                    &window[0],
+                    "remove the import".to_string(),
+                ));
+            }
+        }
+
+        for rule in &rules {
+            let head: Vec<&Token> = tokens
+                .iter()
+                .filter(|t| t.line >= rule.start_line && t.line <= rule.end_line)
+                .collect();
+            let Some(first) = head.first() else {
+                continue;
+            };
+            // v0 refs like `deny[msg]` include the key; only the name matters here
+            let rule_name = rule.name.split(['.', '[']).next().unwrap_or_default();
+
+            if V1_KEYWORDS.contains(&rule_name) {
+                issues.push(issue(
+                    "reserved-keyword",
+                    format!("`{}` is a keyword in Rego v1 and can't name a rule", rule_name),
+                    first,
+                    "rename the rule".to_string(),
+                ));
+            }
+
+            if rule.kind == RuleKind::Default {
+                continue;
+            }
+
+            if let Some(key) = partial_set_key(&head) {
+                issues.push(issue(
+                    "missing-contains",
+                    format!("partial set rule `{}` must use `contains` in Rego v1", rule_name),
+                    first,
+                    format!("{} contains {} if {{ ... }}", rule_name, key),
+                ));
+            } else if let Some(body) = body_start(&head) {
+                if !head[..body].iter().any(|t| t.text == "if") {
+                    issues.push(issue(
+                        "missing-if",
+                        format!("rule `{}` must use `if` before its body in Rego v1", rule_name),
+                        head[body],
+                        "insert `if` before `{`".to_string(),
+                    ));
+                }
+            }
+
+            for (i, token) in head.iter().enumerate().skip(1) {
+                let assigned = head.get(i + 1).is_some_and(|next| next.text == ":=");
+                if assigned && V1_KEYWORDS.contains(&token.text) {
+                    issues.push(issue(
+                        "reserved-keyword",
+                        format!(
+                            "`{}` is a keyword in Rego v1 and can't name a variable",
+                            token.text
+                        ),
+                        token,
+                        "rename the variable".to_string(),
+                    ));
+                }
+            }
+        }
+
+        let mut deprecated = Vec::new();
+        check_deprecated_builtins(&tokens, &mut deprecated);
+        for finding in deprecated {
+            issues.push(MigrationIssue {
+                check: finding.check,
+                message: format!("{} and unavailable in Rego v1", finding.message),
+                line: finding.line,
+                column: finding.column,
+                suggestion: finding
+                    .message
+                    .split_once("; ")
+                    .map(|(_, advice)| advice.to_string())
+                    .unwrap_or_default(),
+            });
+        }
+
+        // Anything the checks above didn't explain still has to be reported
+        if issues.is_empty() {
+            let mut engine = Engine::new();
+            engine.set_rego_v0(false);
+            if let Err(e) = engine.add_policy(name, source) {
+                let text = e.to_string();
+                let location = parse_location(atoms::parse_error(), &text);
+                issues.push(MigrationIssue {
+                    check: "parse-error".to_string(),
+                    message: location.as_ref().map_or(text.clone(), |l| l.message.clone()),
+                    line: location.as_ref().map_or(1, |l| l.line as usize),
+                    column: location.as_ref().map_or(1, |l| l.column as usize),
+                    suggestion: String::new(),
+                });
+            }
+        }
+
+        issues.sort_by_key(|i| (i.line, i.column));
+        Ok(issues)
+    })
+}
@@ -0,0 +1,115 @@
+use crate::error::catch_panic;
+use crate::{atoms, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, Env, LocalPid, Monitor, ResourceArc};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, TryLockError};
+
+impl EngineResource {
+    /// Drop the engine's policies, data and input once its owner has exited.
+    ///
+    /// Other processes may still hold a reference to the resource, so its
+    /// memory can't be freed outright; emptying it releases the large parts
+    /// now and leaves an empty engine behind.
+    ///
+    /// Runs from the monitor callback, so it never waits for the writer lock:
+    /// if a writer holds it, the release happens when that writer lets go.
+    pub(crate) fn release(&self) {
+        self.discard_store();
+        self.release_pending.store(true, Ordering::SeqCst);
+        self.finish_release();
+    }
+
+    /// Carry out a pending release, unless a writer holds the lock
+    pub(crate) fn finish_release(&self) {
+        // Checked again after unlocking, in case `release` ran in between
+        while self.release_pending.load(Ordering::SeqCst) {
+            let _writer = match self.writer.try_lock() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                Err(TryLockError::WouldBlock) => return,
+            };
+            if !self.release_pending.swap(false, Ordering::SeqCst) {
+                return;
+            }
+
+            self.engine.store(Arc::new(Engine::new()));
+
+            if let Ok(mut policies) = self.policies.write() {
+                policies.clear();
+            }
+            if let Ok(mut input) = self.input.write() {
+                *input = None;
+            }
+            if let Ok(mut bundles) = self.bundles.write() {
+                bundles.clear();
+            }
+            self.invalidate_queries();
+            self.invalidate_decisions();
+        }
+    }
+}
+
+/// Release the engine's policies and data as soon as `pid` exits, rather
+/// than when the last reference to it is garbage collected.
+///
+/// Replaces any previous owner. If `pid` is already dead the engine is
+/// released right away.
+#[rustler::nif]
+fn native_set_owner(
+    env: Env,
+    resource: ResourceArc<EngineResource>,
+    pid: LocalPid,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut owner = resource
+            .owner
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        if let Some(previous) = owner.take() {
+            env.demonitor(&resource, &previous);
+        }
+
+        match env.monitor(&resource, &pid) {
+            Some(monitor) => *owner = Some(monitor),
+            None => resource.release(),
+        }
+
+        Ok(())
+    })
+}
+
+/// Stop releasing the engine when its owner exits
+#[rustler::nif]
+fn native_clear_owner(
+    env: Env,
+    resource: ResourceArc<EngineResource>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut owner = resource
+            .owner
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        if let Some(previous) = owner.take() {
+            env.demonitor(&resource, &previous);
+        }
+
+        Ok(())
+    })
+}
+
+/// Called by the runtime when the monitored owner exits
+pub(crate) fn owner_down(resource: &EngineResource, monitor: Monitor) {
+    let Ok(mut owner) = resource.owner.lock() else {
+        return;
+    };
+
+    // Ignore a stale monitor whose owner was since replaced
+    if owner.as_ref() == Some(&monitor) {
+        *owner = None;
+        drop(owner);
+        resource.release();
+    }
+}
@@ -0,0 +1,222 @@
+use crate::decode::result_to_term;
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::lint::{tokenize, Offsets, TokenKind};
+use crate::{atoms, first_value, nest_value, value_at_path, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, Env, ResourceArc, Term};
+use std::time::Instant;
+
+/// Where parameter values are mounted in the private copy's data
+const PARAMS_ROOT: &str = "__regolix_params";
+
+/// Where `with` override values are mounted, as an array in path order
+const OVERRIDES_ROOT: &str = "__regolix_overrides";
+
+/// Replace each `$name` placeholder outside string literals with a reference
+/// to the parameter's value under `data.__regolix_params`
+fn bind_placeholders(
+    query: &str,
+    params: &regorus::Value,
+) -> Result<String, (Atom, ErrorDetail)> {
+    let offsets = Offsets::new(query);
+    let tokens = tokenize(query);
+    let mut bound = String::with_capacity(query.len());
+    let mut copied = 0;
+
+    for pair in tokens.windows(2) {
+        let (dollar, name) = (&pair[0], &pair[1]);
+        let start = offsets.start(dollar);
+        let name_start = offsets.start(name);
+
+        if dollar.text != "$" || name.kind != TokenKind::Ident || name_start != start + 1 {
+            continue;
+        }
+
+        if value_at_path(params, &[name.text]) == regorus::Value::Undefined {
+            return Err((
+                atoms::eval_error(),
+                format!("missing query parameter ${}", name.text).into(),
+            ));
+        }
+
+        bound.push_str(&query[copied..start]);
+        bound.push_str(&format!("data.{}.{}", PARAMS_ROOT, name.text));
+        copied = offsets.end(name);
+    }
+
+    bound.push_str(&query[copied..]);
+    Ok(bound)
+}
+
+/// Evaluate a query containing `$name` placeholders, bound to the values in
+/// `json_params`.
+///
+/// Values are added as data to a private copy of the engine and the
+/// placeholders become references to them, so a value can never change the
+/// shape of the query the way string interpolation can. Error locations refer
+/// to the query after placeholders are replaced.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_params<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    json_params: Term<'a>,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let params = parse_object(&resource, json_params, "query parameters")?;
+        let bound = bind_placeholders(&query, &params)?;
+
+        eval_mounted(env, &resource, bound, PARAMS_ROOT, params)
+    })
+}
+
+/// Check that `path` is a plain dotted reference under `input` or `data`,
+/// since it is spliced into the query as text
+fn check_override_path(path: &str) -> Result<(), (Atom, ErrorDetail)> {
+    let mut segments = path.split('.');
+    let root_ok = matches!(segments.next(), Some("input" | "data"));
+    let segments_ok = segments.all(|segment| {
+        let mut chars = segment.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+
+    if root_ok && segments_ok {
+        Ok(())
+    } else {
+        Err((
+            atoms::eval_error(),
+            format!("invalid override path {:?}, expected input.a.b or data.a.b", path).into(),
+        ))
+    }
+}
+
+/// Append `with <path> as <value>` for every override to each top-level
+/// expression of the query, since `with` only applies to the expression it
+/// modifies
+fn apply_overrides(query: &str, paths: &[String]) -> String {
+    let modifiers: String = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| format!(" with {} as data.{}[{}]", path, OVERRIDES_ROOT, i))
+        .collect();
+
+    let offsets = Offsets::new(query);
+    let tokens = tokenize(query);
+    let mut ends = Vec::new();
+    let mut depth = 0usize;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        // An expression ends at `;` or at a line break outside brackets
+        let next = tokens.get(i + 1);
+        if token.text == ";" {
+            continue;
+        }
+        let ends_here = match next {
+            None => true,
+            Some(next) => depth == 0 && (next.text == ";" || next.line > token.line),
+        };
+        if ends_here {
+            ends.push(offsets.end(token));
+        }
+    }
+
+    let mut modified = String::with_capacity(query.len() + ends.len() * modifiers.len());
+    let mut copied = 0;
+    for end in ends {
+        modified.push_str(&query[copied..end]);
+        modified.push_str(&modifiers);
+        copied = end;
+    }
+    modified.push_str(&query[copied..]);
+    modified
+}
+
+/// Evaluate a query with `input` and `data` paths replaced for this call
+/// only, as if each expression carried `with <path> as <value>`.
+///
+/// Keys of `json_overrides` are paths such as `"data.ldap.groups"` or
+/// `"input.time"`. Overriding a rule's path mocks the rule. Error locations
+/// refer to the query after the `with` modifiers are added.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_with_overrides<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    json_overrides: Term<'a>,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let overrides = parse_object(&resource, json_overrides, "overrides")?;
+
+        let mut paths = Vec::new();
+        let mut values = Vec::new();
+        if let regorus::Value::Object(fields) = overrides {
+            for (path, value) in fields.iter() {
+                let regorus::Value::String(path) = path else {
+                    continue;
+                };
+                check_override_path(path)?;
+                paths.push(path.to_string());
+                values.push(value.clone());
+            }
+        }
+
+        let modified = apply_overrides(&query, &paths);
+
+        eval_mounted(env, &resource, modified, OVERRIDES_ROOT, regorus::Value::from(values))
+    })
+}
+
+/// Parse a JSON object argument, within the engine's limits
+fn parse_object(
+    resource: &EngineResource,
+    json: Term,
+    what: &str,
+) -> Result<regorus::Value, (Atom, ErrorDetail)> {
+    let value = resource
+        .limits
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+        .parse_json_term(json)?;
+
+    match value {
+        regorus::Value::Object(_) => Ok(value),
+        _ => Err((atoms::json_error(), format!("{} must be a JSON object", what).into())),
+    }
+}
+
+/// Evaluate `query` against a private copy of the engine with `value`
+/// mounted at `data.<root>`
+fn eval_mounted<'a>(
+    env: Env<'a>,
+    resource: &EngineResource,
+    query: String,
+    root: &str,
+    value: regorus::Value,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    let decode = *resource
+        .decode
+        .read()
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+    let mut engine = Engine::clone(&resource.snapshot());
+
+    engine
+        .add_data(nest_value(&[root], value))
+        .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+    let started = Instant::now();
+    let results = engine
+        .eval_query(query, false)
+        .map_err(|e| located_error(atoms::eval_error(), e));
+    resource.record_eval(started, &results);
+    let results = results?;
+
+    result_to_term(env, first_value(results), &decode)
+}
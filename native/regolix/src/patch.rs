@@ -0,0 +1,260 @@
+use crate::error::catch_panic;
+use crate::{atoms, EngineResource};
+use rustler::{Atom, ResourceArc, Term};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// One operation of an RFC 6902 JSON Patch, plus OPA's delta bundle `upsert`
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub(crate) enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+    /// Add or replace, creating missing parent objects (delta bundles only)
+    Upsert { path: String, value: Value },
+}
+
+impl Operation {
+    /// The path this operation writes to
+    pub fn path(&self) -> &str {
+        match self {
+            Operation::Add { path, .. }
+            | Operation::Remove { path }
+            | Operation::Replace { path, .. }
+            | Operation::Move { path, .. }
+            | Operation::Copy { path, .. }
+            | Operation::Test { path, .. }
+            | Operation::Upsert { path, .. } => path,
+        }
+    }
+}
+
+/// A JSON Patch array, or the `patch.json` of an OPA delta bundle
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum PatchDocument {
+    JsonPatch(Vec<Operation>),
+    Delta { data: Vec<Operation> },
+}
+
+impl PatchDocument {
+    pub fn into_operations(self) -> Result<Vec<Operation>, String> {
+        match self {
+            PatchDocument::JsonPatch(ops) => {
+                if ops.iter().any(|op| matches!(op, Operation::Upsert { .. })) {
+                    return Err("`upsert` is only allowed in delta bundle patches".to_string());
+                }
+                Ok(ops)
+            }
+            PatchDocument::Delta { data } => Ok(data),
+        }
+    }
+}
+
+/// Split a JSON Pointer (RFC 6901) such as `"/users/a~1b"` into segments
+pub(crate) fn pointer_segments(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(format!("`{}` is not a JSON pointer", pointer));
+    };
+
+    Ok(rest
+        .split('/')
+        .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn array_index(segment: &str, len: usize, pointer: &str) -> Result<usize, String> {
+    match segment.parse::<usize>() {
+        Ok(index) if index < len && (segment == "0" || !segment.starts_with('0')) => Ok(index),
+        _ => Err(format!("`{}` does not exist", pointer)),
+    }
+}
+
+fn get<'v>(doc: &'v Value, path: &[String], pointer: &str) -> Result<&'v Value, String> {
+    let mut current = doc;
+    for segment in path {
+        current = match current {
+            Value::Object(fields) => fields.get(segment),
+            Value::Array(items) => items.get(array_index(segment, items.len(), pointer)?),
+            _ => None,
+        }
+        .ok_or_else(|| format!("`{}` does not exist", pointer))?;
+    }
+    Ok(current)
+}
+
+fn get_mut<'v>(
+    doc: &'v mut Value,
+    path: &[String],
+    pointer: &str,
+) -> Result<&'v mut Value, String> {
+    let mut current = doc;
+    for segment in path {
+        current = match current {
+            Value::Object(fields) => fields.get_mut(segment),
+            Value::Array(items) => {
+                let index = array_index(segment, items.len(), pointer)?;
+                items.get_mut(index)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| format!("`{}` does not exist", pointer))?;
+    }
+    Ok(current)
+}
+
+fn add(doc: &mut Value, path: &[String], value: Value, pointer: &str) -> Result<(), String> {
+    let Some((last, parents)) = path.split_last() else {
+        *doc = value;
+        return Ok(());
+    };
+
+    match get_mut(doc, parents, pointer)? {
+        Value::Object(fields) => {
+            fields.insert(last.clone(), value);
+        }
+        Value::Array(items) if last == "-" => items.push(value),
+        Value::Array(items) => {
+            // Inserting may also append, so the end index is allowed here
+            let index = array_index(last, items.len() + 1, pointer)?;
+            items.insert(index, value);
+        }
+        _ => return Err(format!("the parent of `{}` is not an object or array", pointer)),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, path: &[String], pointer: &str) -> Result<Value, String> {
+    let Some((last, parents)) = path.split_last() else {
+        return Err("cannot remove the whole document".to_string());
+    };
+
+    match get_mut(doc, parents, pointer)? {
+        Value::Object(fields) => fields
+            .remove(last)
+            .ok_or_else(|| format!("`{}` does not exist", pointer)),
+        Value::Array(items) => {
+            let index = array_index(last, items.len(), pointer)?;
+            Ok(items.remove(index))
+        }
+        _ => Err(format!("`{}` does not exist", pointer)),
+    }
+}
+
+fn upsert(doc: &mut Value, path: &[String], value: Value, pointer: &str) -> Result<(), String> {
+    let Some((last, parents)) = path.split_last() else {
+        *doc = value;
+        return Ok(());
+    };
+
+    let mut current = doc;
+    for segment in parents {
+        let Value::Object(fields) = current else {
+            return Err(format!("the parent of `{}` is not an object", pointer));
+        };
+        current = fields
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    }
+
+    match current {
+        Value::Object(fields) => {
+            fields.insert(last.clone(), value);
+            Ok(())
+        }
+        _ => Err(format!("the parent of `{}` is not an object", pointer)),
+    }
+}
+
+/// Apply `operations` in order; on error `doc` may be partly patched, so
+/// callers work on a copy
+pub(crate) fn apply(doc: &mut Value, operations: Vec<Operation>) -> Result<(), String> {
+    for operation in operations {
+        let pointer = operation.path().to_string();
+        let path = pointer_segments(&pointer)?;
+
+        match operation {
+            Operation::Add { value, .. } => add(doc, &path, value, &pointer)?,
+            Operation::Remove { .. } => {
+                remove(doc, &path, &pointer)?;
+            }
+            Operation::Replace { value, .. } => *get_mut(doc, &path, &pointer)? = value,
+            Operation::Move { from, .. } => {
+                let from_path = pointer_segments(&from)?;
+                if path.len() > from_path.len() && path.starts_with(&from_path) {
+                    return Err(format!("cannot move `{}` into itself", from));
+                }
+                let value = remove(doc, &from_path, &from)?;
+                add(doc, &path, value, &pointer)?;
+            }
+            Operation::Copy { from, .. } => {
+                let value = get(doc, &pointer_segments(&from)?, &from)?.clone();
+                add(doc, &path, value, &pointer)?;
+            }
+            Operation::Test { value, .. } => {
+                if *get(doc, &path, &pointer)? != value {
+                    return Err(format!("test failed at `{}`", pointer));
+                }
+            }
+            Operation::Upsert { value, .. } => upsert(doc, &path, value, &pointer)?,
+        }
+    }
+
+    if !doc.is_object() {
+        return Err("data at the root path must be an object".to_string());
+    }
+    Ok(())
+}
+
+/// Apply a JSON Patch (RFC 6902) or an OPA delta bundle patch
+/// (`{"data": [...]}`) to the data document.
+///
+/// All operations apply or none do. regorus only merges data, so the patched
+/// document replaces the whole data tree.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_patch_data(
+    resource: ResourceArc<EngineResource>,
+    patch_json: Term,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let patch = resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .parse_json_term(patch_json)
+            .map_err(|(kind, message)| (kind, message.into()))?;
+        let operations = serde_json::to_value(&patch)
+            .and_then(serde_json::from_value::<PatchDocument>)
+            .map_err(|_| {
+                (
+                    atoms::json_error(),
+                    "expected a JSON Patch array or a delta bundle patch".to_string(),
+                )
+            })?
+            .into_operations()
+            .map_err(|e| (atoms::json_error(), e))?;
+
+        let mut engine = resource.begin_write();
+
+        let mut data = serde_json::to_value(engine.get_data())
+            .map_err(|e| (atoms::json_error(), e.to_string()))?;
+        apply(&mut data, operations).map_err(|e| (atoms::engine_error(), e))?;
+        let data: regorus::Value =
+            serde_json::from_value(data).map_err(|e| (atoms::json_error(), e.to_string()))?;
+
+        engine.clear_data();
+        engine
+            .add_data(data)
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        engine.commit();
+
+        Ok(())
+    })
+}
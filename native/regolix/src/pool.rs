@@ -0,0 +1,97 @@
+use crate::decode::{result_to_term, DecodeOptions};
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::limits::Limits;
+use crate::{atoms, first_value, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, Env, ResourceArc, Term};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// Independent copies of one engine, so concurrent evaluations don't queue
+/// behind a single lock
+pub struct PoolResource {
+    engines: Vec<Mutex<Engine>>,
+    decode: DecodeOptions,
+    limits: Limits,
+    next: AtomicUsize,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for PoolResource {}
+
+impl PoolResource {
+    /// Take the first idle replica, starting from a rotating offset; if all
+    /// are busy, wait for the one at the offset
+    fn checkout(&self) -> Result<MutexGuard<'_, Engine>, (Atom, ErrorDetail)> {
+        let size = self.engines.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % size;
+
+        for offset in 0..size {
+            if let Ok(engine) = self.engines[(start + offset) % size].try_lock() {
+                return Ok(engine);
+            }
+        }
+
+        self.engines[start]
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))
+    }
+}
+
+/// Snapshot an engine's policies and data into `size` replicas
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_new_pool(
+    resource: ResourceArc<EngineResource>,
+    size: usize,
+) -> Result<ResourceArc<PoolResource>, (Atom, String)> {
+    catch_panic(|| {
+        if size == 0 {
+            return Err((atoms::engine_error(), "pool size must be at least 1".to_string()));
+        }
+
+        let engine = resource.snapshot();
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let engines = (0..size).map(|_| Mutex::new(Engine::clone(&engine))).collect();
+
+        Ok(ResourceArc::new(PoolResource {
+            engines,
+            decode,
+            limits,
+            next: AtomicUsize::new(0),
+        }))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_pool_eval<'a>(
+    env: Env<'a>,
+    pool: ResourceArc<PoolResource>,
+    query: String,
+    json_input: String,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let input = pool.limits.parse_json(&json_input)?;
+
+        let mut engine = pool.checkout()?;
+        engine.set_input(input);
+
+        let results = engine
+            .eval_query(query, false)
+            .map_err(|e| located_error(atoms::eval_error(), e))?;
+
+        result_to_term(env, first_value(results), &pool.decode)
+    })
+}
+
+#[rustler::nif]
+fn native_pool_size(pool: ResourceArc<PoolResource>) -> usize {
+    pool.engines.len()
+}
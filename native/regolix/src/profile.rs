@@ -0,0 +1,225 @@
+use crate::deps::{bases, scan_rule};
+use crate::error::{catch_panic, ErrorDetail};
+use crate::graph::{dependencies, resolve, Dependencies, NodeKind};
+use crate::rules::parse_module;
+use crate::{atoms, eval_prepared, prepare_query, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, NifMap, ResourceArc, Term};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// Timing totals for one rule
+#[derive(Clone, Copy, Default, NifMap)]
+pub(crate) struct RuleProfile {
+    count: u64,
+    total_ns: u64,
+    max_ns: u64,
+}
+
+/// Per-rule timings, present only while profiling is enabled
+pub(crate) type Profile = Option<HashMap<String, RuleProfile>>;
+
+/// Rules `query` reads, directly or through other rules
+fn reached_rules(query: &str, deps: &Dependencies) -> BTreeSet<String> {
+    // Parse the query as the body of a rule to find its references
+    let source = format!("package profile\n\nquery if {{\n{}\n}}\n", query);
+    let Ok(module) = parse_module("query", &source) else {
+        return BTreeSet::new();
+    };
+    let bases = bases(&module);
+
+    let mut pending: Vec<String> = module
+        .policy
+        .iter()
+        .flat_map(|rule| scan_rule(rule.as_ref(), &bases).refs)
+        .flat_map(|found| resolve(&found.path, &deps.rule_ids))
+        .filter(|(_, kind)| *kind == NodeKind::Rule)
+        .map(|(id, _)| id)
+        .collect();
+
+    let mut reached = BTreeSet::new();
+    while let Some(id) = pending.pop() {
+        if let Some(to) = deps.edges.get(&id) {
+            pending.extend(to.iter().filter(|to| deps.rule_ids.contains(*to)).cloned());
+        }
+        reached.insert(id);
+    }
+    reached
+}
+
+/// Time each rule `query` reads if profiling is enabled.
+///
+/// regorus can't time the rules inside an evaluation, so each rule is
+/// evaluated once more on its own, against a copy of `engine` as the query
+/// left it. A rule's time includes the rules it depends on.
+pub(crate) fn record(resource: &EngineResource, engine: &Engine, query: &str) {
+    if !resource
+        .profile
+        .lock()
+        .is_ok_and(|profile| profile.is_some())
+    {
+        return;
+    }
+
+    let rules = match resource.policies.read() {
+        Ok(policies) => match dependencies(&policies) {
+            Ok(deps) => reached_rules(query, &deps),
+            Err(_) => return,
+        },
+        Err(_) => return,
+    };
+
+    let mut engine = Engine::clone(engine);
+    let timings: Vec<(String, Duration)> = rules
+        .into_iter()
+        .filter_map(|rule| {
+            let started = Instant::now();
+            // Functions and partial rules with variables in their head can't
+            // be evaluated on their own
+            engine.eval_rule(rule.clone()).ok()?;
+            Some((rule, started.elapsed()))
+        })
+        .collect();
+
+    let Ok(mut profile) = resource.profile.lock() else {
+        return;
+    };
+
+    if let Some(entries) = profile.as_mut() {
+        for (rule, elapsed) in timings {
+            let ns = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+            let entry = entries.entry(rule).or_default();
+            entry.count += 1;
+            entry.total_ns = entry.total_ns.saturating_add(ns);
+            entry.max_ns = entry.max_ns.max(ns);
+        }
+    }
+}
+
+#[rustler::nif]
+fn native_enable_profiling(
+    resource: ResourceArc<EngineResource>,
+    enabled: bool,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut profile = resource
+            .profile
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        match (enabled, profile.is_some()) {
+            (true, false) => *profile = Some(HashMap::new()),
+            (false, _) => *profile = None,
+            _ => {}
+        }
+
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn native_get_profile(
+    resource: ResourceArc<EngineResource>,
+) -> Result<HashMap<String, RuleProfile>, (Atom, String)> {
+    catch_panic(|| {
+        let profile = resource
+            .profile
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        Ok(profile.clone().unwrap_or_default())
+    })
+}
+
+#[rustler::nif]
+fn native_clear_profile(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut profile = resource
+            .profile
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        if let Some(entries) = profile.as_mut() {
+            entries.clear();
+        }
+
+        Ok(())
+    })
+}
+
+/// Latency distribution of repeated evaluations, in nanoseconds
+#[derive(NifMap)]
+struct BenchmarkReport {
+    iterations: usize,
+    min_ns: u64,
+    p50_ns: u64,
+    p95_ns: u64,
+    p99_ns: u64,
+    max_ns: u64,
+    mean_ns: u64,
+}
+
+/// Most iterations one benchmark runs, which bounds the timings it keeps
+const MAX_ITERATIONS: usize = 1_000_000;
+
+/// Nearest-rank percentile of sorted timings
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Evaluate `query` `iterations` times in a loop and report the latencies,
+/// leaving out the cost of crossing into the NIF and decoding results.
+///
+/// Runs on a private copy of the engine with `json_input` as input, or the
+/// engine's own input when it's `None`. One untimed evaluation first warms up
+/// the copy and reports any evaluation error. The iterations aren't counted
+/// in `stats/1` or the profile.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_benchmark(
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    json_input: Option<Term>,
+    iterations: usize,
+) -> Result<BenchmarkReport, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        if iterations == 0 || iterations > MAX_ITERATIONS {
+            return Err((
+                atoms::invalid_argument(),
+                format!("iterations must be between 1 and {}", MAX_ITERATIONS).into(),
+            ));
+        }
+
+        let mut engine = Engine::clone(&resource.snapshot());
+        if let Some(json_input) = json_input {
+            let input = resource
+                .limits
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+                .parse_json_term(json_input)?;
+            engine.set_input(input);
+        }
+
+        let prepared = prepare_query(&resource, &query)?;
+        eval_prepared(&mut engine, prepared, &query)?;
+
+        let mut timings = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let started = Instant::now();
+            let _ = eval_prepared(&mut engine, prepared, &query);
+            timings.push(u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX));
+        }
+        timings.sort_unstable();
+
+        let total: u128 = timings.iter().map(|&ns| u128::from(ns)).sum();
+        Ok(BenchmarkReport {
+            iterations,
+            min_ns: timings[0],
+            p50_ns: percentile(&timings, 50),
+            p95_ns: percentile(&timings, 95),
+            p99_ns: percentile(&timings, 99),
+            max_ns: timings[iterations - 1],
+            mean_ns: u64::try_from(total / iterations as u128).unwrap_or(u64::MAX),
+        })
+    })
+}
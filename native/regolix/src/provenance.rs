@@ -0,0 +1,143 @@
+use crate::decode::result_to_term;
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::{atoms, first_value, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, Env, NifMap, ResourceArc, Term};
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[derive(NifMap)]
+struct Provenance {
+    /// See `native_set_label`
+    label: Option<String>,
+    /// Number of changes made to the engine before the decision
+    revision: u64,
+    /// Revision of each loaded bundle owning a contributing policy, by name
+    bundles: HashMap<String, String>,
+    /// Policy files with lines evaluated for the decision, sorted
+    policies: Vec<String>,
+}
+
+#[derive(NifMap)]
+struct ProvenancedResult<'a> {
+    result: Term<'a>,
+    provenance: Provenance,
+}
+
+/// Set the name this engine reports in decision provenance, or clear it
+#[rustler::nif]
+fn native_set_label(
+    resource: ResourceArc<EngineResource>,
+    label: Option<String>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .label = label;
+        resource.store_changed();
+        Ok(())
+    })
+}
+
+/// Evaluate `query`, with `json_input` in place of the engine's input if
+/// given, and return the result along with where it came from, for audit
+/// logs.
+///
+/// Contributing policies are found by evaluating a private copy of the engine
+/// with coverage enabled, so this costs more than a plain evaluation and never
+/// uses the decision cache. The engine's own coverage data is left alone.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_with_provenance<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    query: String,
+    json_input: Option<Term<'a>>,
+) -> Result<ProvenancedResult<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let input = match json_input {
+            Some(json_input) => Some(
+                resource
+                    .limits
+                    .read()
+                    .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+                    .parse_json_term(json_input)?,
+            ),
+            None => None,
+        };
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let label = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .label
+            .clone();
+        let revision = resource
+            .decisions
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+            .revision();
+
+        let mut engine = Engine::clone(&resource.snapshot());
+        engine.set_enable_coverage(true);
+        engine.clear_coverage_data();
+        if let Some(input) = &input {
+            engine.set_input(input.clone());
+        }
+
+        let started = Instant::now();
+        let results = engine
+            .eval_query(query.clone(), false)
+            .map_err(|e| located_error(atoms::eval_error(), e));
+        resource.record_eval(started, &results);
+        let value = first_value(results?);
+        resource.record_decision(&query, input.as_ref(), &value);
+
+        let report = engine
+            .get_coverage_report()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let mut policies: Vec<String> = report
+            .files
+            .into_iter()
+            .filter(|file| !file.covered.is_empty())
+            .map(|file| file.path)
+            .collect();
+        policies.sort();
+
+        let bundles = {
+            let sources = resource
+                .policies
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+            let packages: Vec<&str> = policies
+                .iter()
+                .filter_map(|name| sources.get(name))
+                .map(|policy| policy.package.as_str())
+                .collect();
+
+            resource
+                .bundles
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+                .iter()
+                .filter(|(_, manifest)| packages.iter().any(|package| manifest.owns(package)))
+                .map(|(name, manifest)| (name.clone(), manifest.revision().to_string()))
+                .collect()
+        };
+
+        Ok(ProvenancedResult {
+            result: result_to_term(env, value, &decode)?,
+            provenance: Provenance {
+                label,
+                revision,
+                bundles,
+                policies,
+            },
+        })
+    })
+}
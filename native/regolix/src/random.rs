@@ -0,0 +1,107 @@
+use crate::error::catch_panic;
+use crate::{atoms, rebuild_engine, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, ResourceArc};
+
+/// Stand-ins for the random builtins while a seed is set
+const RAND_INTN: &str = "regolix.rand.intn";
+const UUID_RFC4122: &str = "regolix.uuid.rfc4122";
+
+/// Builtins to point at seeded stand-ins
+pub(crate) const RENAMES: &[(&str, &str)] = &[("rand.intn", RAND_INTN), ("uuid.rfc4122", UUID_RFC4122)];
+
+/// FNV-1a, stable across platforms and Rust versions unlike `DefaultHasher`
+fn fnv1a(state: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(state, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// splitmix64 step, to spread the hash over all 64 bits
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Random bits determined only by the seed, the builtin and its arguments.
+///
+/// As in OPA, calling a builtin twice with the same arguments gives the same
+/// result; with a seed, so does every later run.
+fn draw(seed: u64, builtin: &str, args: &[regorus::Value], index: u64) -> anyhow::Result<u64> {
+    let mut hash = fnv1a(0xcbf2_9ce4_8422_2325, &seed.to_le_bytes());
+    hash = fnv1a(hash, builtin.as_bytes());
+    for arg in args {
+        hash = fnv1a(hash, arg.to_json_str()?.as_bytes());
+    }
+    Ok(mix(hash.wrapping_add(index)))
+}
+
+/// Register seeded `rand.intn` and `uuid.rfc4122` stand-ins
+pub(crate) fn register(seed: u64, engine: &mut Engine) -> anyhow::Result<()> {
+    let intn = move |args: Vec<regorus::Value>| -> anyhow::Result<regorus::Value> {
+        let n = args[1].as_i64()?.unsigned_abs();
+        if n == 0 {
+            return Ok(regorus::Value::from(0i64));
+        }
+        Ok(regorus::Value::from((draw(seed, "rand.intn", &args, 0)? % n) as i64))
+    };
+    engine.add_extension(RAND_INTN.to_string(), 2, Box::new(intn))?;
+
+    let uuid = move |args: Vec<regorus::Value>| -> anyhow::Result<regorus::Value> {
+        let high = draw(seed, "uuid.rfc4122", &args, 0)?;
+        let low = draw(seed, "uuid.rfc4122", &args, 1)?;
+
+        // Version 4, RFC 4122 variant
+        let high = (high & 0xffff_ffff_ffff_0fff) | 0x0000_0000_0000_4000;
+        let low = (low & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+
+        Ok(regorus::Value::from(format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            high >> 32,
+            (high >> 16) & 0xffff,
+            high & 0xffff,
+            low >> 48,
+            low & 0xffff_ffff_ffff
+        )))
+    };
+    engine.add_extension(UUID_RFC4122.to_string(), 1, Box::new(uuid))
+}
+
+/// Make `rand.intn` and `uuid.rfc4122` in policies reproducible from `seed`,
+/// or random again with `None`.
+///
+/// Like `native_set_time`, this rebuilds the engine so policies already
+/// loaded are affected, and queries passed to the eval functions are not.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_set_random_seed(
+    resource: ResourceArc<EngineResource>,
+    seed: Option<u64>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let mut engine = resource.begin_write();
+
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let input = resource
+            .input
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clone();
+
+        let mut next = settings.clone();
+        next.random_seed = seed;
+
+        *engine = rebuild_engine(&policies, engine.get_data(), input, &next)?;
+        engine.commit();
+        *settings = next;
+
+        Ok(())
+    })
+}
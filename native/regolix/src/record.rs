@@ -0,0 +1,215 @@
+use crate::decode::value_to_term;
+use crate::error::{catch_panic, ErrorDetail};
+use crate::{atoms, eval_detached, EngineResource};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rustler::{Atom, Env, NifMap, ResourceArc, Term};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+/// One line of a recording. Undefined inputs and results are left out.
+#[derive(Serialize, Deserialize)]
+struct Decision {
+    query: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    input: Option<serde_json::Value>,
+    /// Number of changes made to the engine before the decision
+    revision: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+}
+
+/// JSON, or `None` for undefined
+fn to_json(value: &regorus::Value) -> Option<serde_json::Value> {
+    match value {
+        regorus::Value::Undefined => None,
+        value => serde_json::to_value(value).ok(),
+    }
+}
+
+impl EngineResource {
+    /// Append a decision to the recording, if one is running. `input` is the
+    /// input evaluated, or `None` for the engine's own.
+    ///
+    /// A failed write stops the recording rather than failing the evaluation.
+    pub(crate) fn record_decision(
+        &self,
+        query: &str,
+        input: Option<&regorus::Value>,
+        result: &regorus::Value,
+    ) {
+        let Ok(mut recorder) = self.recorder.lock() else {
+            return;
+        };
+        let Some(out) = recorder.as_mut() else {
+            return;
+        };
+
+        let input = match input {
+            Some(input) => to_json(input),
+            None => self
+                .input
+                .read()
+                .ok()
+                .and_then(|input| input.as_ref().and_then(to_json)),
+        };
+        let revision = self
+            .decisions
+            .lock()
+            .map(|cache| cache.revision())
+            .unwrap_or(0);
+
+        let decision = Decision {
+            query: query.to_string(),
+            input,
+            revision,
+            result: to_json(result),
+        };
+
+        let written = serde_json::to_string(&decision)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(out, "{}", line));
+        if written.is_err() {
+            *recorder = None;
+        }
+    }
+}
+
+/// Record every successful evaluation of the engine to a gzipped JSON Lines
+/// file at `path`, replacing the file and any recording already running
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_start_recording(
+    resource: ResourceArc<EngineResource>,
+    path: String,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let file =
+            File::create(&path).map_err(|e| (atoms::io_error(), format!("{}: {}", path, e)))?;
+
+        let mut recorder = resource
+            .recorder
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        if let Some(previous) = recorder.take() {
+            previous
+                .finish()
+                .map_err(|e| (atoms::io_error(), e.to_string()))?;
+        }
+        *recorder = Some(GzEncoder::new(file, Compression::default()));
+
+        Ok(())
+    })
+}
+
+/// Stop recording and finish writing the file
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_stop_recording(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let previous = resource
+            .recorder
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .take();
+
+        if let Some(previous) = previous {
+            previous
+                .finish()
+                .map_err(|e| (atoms::io_error(), e.to_string()))?;
+        }
+
+        Ok(())
+    })
+}
+
+#[derive(NifMap)]
+struct Divergence<'a> {
+    /// Line of the decision in the recording, from 1
+    line: usize,
+    query: String,
+    revision: u64,
+    recorded: Term<'a>,
+    /// The current result, or `nil` if evaluating failed
+    replayed: Option<Term<'a>>,
+    error: Option<(Atom, ErrorDetail)>,
+}
+
+#[derive(NifMap)]
+struct ReplayReport<'a> {
+    total: usize,
+    diverged: Vec<Divergence<'a>>,
+}
+
+/// A recorded decision with its input and result converted back to values
+fn parse_decision(
+    line: &str,
+) -> Result<(Decision, Option<regorus::Value>, regorus::Value), serde_json::Error> {
+    let mut decision: Decision = serde_json::from_str(line)?;
+    let input = decision.input.take().map(serde_json::from_value).transpose()?;
+    let result = match &decision.result {
+        Some(result) => serde_json::from_value(result.clone())?,
+        None => regorus::Value::Undefined,
+    };
+    Ok((decision, input, result))
+}
+
+/// Re-evaluate each decision in a recording against the current engine,
+/// reporting those whose result changed
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_replay<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+    path: String,
+) -> Result<ReplayReport<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let file = File::open(&path)
+            .map_err(|e| (atoms::io_error(), format!("{}: {}", path, e).into()))?;
+        let lines = BufReader::new(MultiGzDecoder::new(file)).lines();
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let mut total = 0;
+        let mut diverged = Vec::new();
+
+        for (index, line) in lines.enumerate() {
+            let line = line.map_err(|e| (atoms::io_error(), format!("{}: {}", path, e).into()))?;
+            if line.is_empty() {
+                continue;
+            }
+            let (decision, input, recorded) = parse_decision(&line)
+                .map_err(|e| (atoms::json_error(), format!("line {}: {}", index + 1, e).into()))?;
+            total += 1;
+
+            let replayed = eval_detached(&resource, &decision.query, input.as_ref());
+            if let Ok(value) = &replayed {
+                if to_json(value) == decision.result {
+                    continue;
+                }
+            }
+
+            let (replayed, error) = match replayed {
+                Ok(value) => (Some(value_to_term(env, value, &decode)), None),
+                Err(error) => (None, Some(error)),
+            };
+
+            diverged.push(Divergence {
+                line: index + 1,
+                query: decision.query,
+                revision: decision.revision,
+                recorded: value_to_term(env, recorded, &decode)
+                    .map_err(|(kind, message)| (kind, message.into()))?,
+                replayed: replayed
+                    .transpose()
+                    .map_err(|(kind, message)| (kind, message.into()))?,
+                error,
+            });
+        }
+
+        Ok(ReplayReport { total, diverged })
+    })
+}
@@ -0,0 +1,75 @@
+use crate::error::catch_panic;
+use crate::{atoms, EngineResource};
+use rustler::{Atom, Env, ResourceArc};
+use std::collections::HashMap;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+type Registry = RwLock<HashMap<String, ResourceArc<EngineResource>>>;
+
+/// Engines registered under a name, reachable from any process on the node
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+fn atom_name(env: Env, name: Atom) -> Result<String, (Atom, String)> {
+    name.to_term(env)
+        .atom_to_string()
+        .map_err(|_| (atoms::engine_error(), "engine names must be atoms".to_string()))
+}
+
+/// Register an engine under `name`, replacing any engine already there
+#[rustler::nif]
+fn native_register(
+    env: Env,
+    name: Atom,
+    resource: ResourceArc<EngineResource>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let name = atom_name(env, name)?;
+        registry()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name, resource);
+        Ok(())
+    })
+}
+
+#[rustler::nif]
+fn native_unregister(env: Env, name: Atom) -> Result<bool, (Atom, String)> {
+    catch_panic(|| {
+        let name = atom_name(env, name)?;
+        Ok(registry()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&name)
+            .is_some())
+    })
+}
+
+#[rustler::nif]
+fn native_whereis(
+    env: Env,
+    name: Atom,
+) -> Result<Option<ResourceArc<EngineResource>>, (Atom, String)> {
+    catch_panic(|| {
+        let name = atom_name(env, name)?;
+        Ok(registry()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&name)
+            .cloned())
+    })
+}
+
+#[rustler::nif]
+fn native_registered() -> Vec<String> {
+    let mut names: Vec<String> = registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .keys()
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
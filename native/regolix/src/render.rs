@@ -0,0 +1,90 @@
+use crate::atoms;
+use crate::encode::{term_to_value, EncodeOptions};
+use crate::error::catch_panic;
+use rustler::{Atom, NifUnitEnum, Term};
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+enum ValueFormat {
+    /// Rego literal syntax: sets as `{1, 2}`, `set()` and `undefined`
+    Rego,
+    Json,
+    PrettyJson,
+}
+
+/// Write `value` as a Rego literal, e.g. `{"roles": {"admin", "dev"}}`
+fn write_rego(value: &regorus::Value, out: &mut String) -> Result<(), String> {
+    match value {
+        regorus::Value::Undefined => out.push_str("undefined"),
+        regorus::Value::Array(items) => {
+            out.push('[');
+            write_items(items.iter(), out)?;
+            out.push(']');
+        }
+        regorus::Value::Set(items) if items.is_empty() => out.push_str("set()"),
+        regorus::Value::Set(items) => {
+            out.push('{');
+            write_items(items.iter(), out)?;
+            out.push('}');
+        }
+        regorus::Value::Object(fields) => {
+            out.push('{');
+            for (i, (key, field)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_rego(key, out)?;
+                out.push_str(": ");
+                write_rego(field, out)?;
+            }
+            out.push('}');
+        }
+        // Scalars are written the same way in JSON and Rego
+        scalar => out.push_str(&serde_json::to_string(scalar).map_err(|e| e.to_string())?),
+    }
+    Ok(())
+}
+
+fn write_items<'v>(
+    items: impl Iterator<Item = &'v regorus::Value>,
+    out: &mut String,
+) -> Result<(), String> {
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_rego(item, out)?;
+    }
+    Ok(())
+}
+
+/// Render an Elixir value, as returned by the eval functions, as Rego or JSON
+/// text. JSON has no sets or undefined: sets become arrays, and undefined is
+/// an error.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_value_to_string(value: Term, format: ValueFormat) -> Result<String, (Atom, String)> {
+    catch_panic(|| {
+        let value = term_to_value(value, &EncodeOptions::default())
+            .map_err(|e| (atoms::json_error(), e.describe("value")))?;
+
+        if format != ValueFormat::Rego && value == regorus::Value::Undefined {
+            return Err((
+                atoms::json_error(),
+                "undefined has no JSON representation".to_string(),
+            ));
+        }
+
+        let text = match format {
+            ValueFormat::Rego => {
+                let mut out = String::new();
+                write_rego(&value, &mut out).map_err(|e| (atoms::json_error(), e))?;
+                out
+            }
+            ValueFormat::Json => serde_json::to_string(&value)
+                .map_err(|e| (atoms::json_error(), e.to_string()))?,
+            ValueFormat::PrettyJson => serde_json::to_string_pretty(&value)
+                .map_err(|e| (atoms::json_error(), e.to_string()))?,
+        };
+
+        Ok(text)
+    })
+}
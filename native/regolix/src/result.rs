@@ -0,0 +1,56 @@
+use crate::decode::{result_to_term, DecodeOptions};
+use crate::error::{catch_panic, ErrorDetail};
+use crate::{atoms, eval_prepared, extract_path, prepare_query, profile, EngineResource};
+use rustler::{Atom, Env, ResourceArc, Term};
+use std::time::Instant;
+
+/// An evaluation result kept on the Rust side, converted to terms piecemeal
+pub struct ResultResource {
+    value: regorus::Value,
+    /// The engine's decode options when the query was evaluated
+    decode: DecodeOptions,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for ResultResource {}
+
+/// Evaluate a query like `native_eval_query`, but return a handle to the
+/// result instead of converting all of it to terms
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_eval_query_lazy(
+    resource: ResourceArc<EngineResource>,
+    query: String,
+) -> Result<ResourceArc<ResultResource>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let mut engine = resource.eval_engine();
+
+        let decode = *resource
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let prepared = prepare_query(&resource, &query)?;
+        let started = Instant::now();
+        let value = eval_prepared(&mut engine, prepared, &query);
+        resource.record_eval(started, &value);
+        let value = value?;
+        profile::record(&resource, &query, started.elapsed());
+
+        Ok(ResourceArc::new(ResultResource { value, decode }))
+    })
+}
+
+/// Convert just the part of a result at a dotted path such as
+/// `"violations.0.msg"` or a JSON Pointer, indexing arrays by number. An
+/// empty path returns the whole result, and a missing one is undefined.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_result_get<'a>(
+    env: Env<'a>,
+    result: ResourceArc<ResultResource>,
+    path: String,
+) -> Result<Term<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = extract_path(&result.value, &path);
+        result_to_term(env, value, &result.decode)
+    })
+}
@@ -0,0 +1,255 @@
+use crate::decode::{map_from_pairs, value_to_term, DecodeOptions};
+use crate::error::catch_panic;
+use crate::{atoms, first_value, EngineResource};
+use regorus::unstable::{Expr, Module, Parser, Rule, RuleHead, Source};
+use regorus::Engine;
+use rustler::{Atom, Encoder, Env, NifUnitEnum, ResourceArc, Term};
+
+/// What a rule definition produces
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+pub(crate) enum RuleKind {
+    /// `name := value`, `name if ...`, `a.b.c := value`
+    Complete,
+    /// `name contains value if ...`
+    PartialSet,
+    /// `name[key] := value if ...`
+    PartialObject,
+    /// `name(args) := value`
+    Function,
+    /// `default name := value`
+    Default,
+}
+
+/// Represents a parsed Rego rule with metadata
+#[derive(Debug)]
+pub(crate) struct RuleInfo {
+    /// The rule's ref as written, e.g. `allow` or `a.b.c` for ref heads
+    pub name: String,
+    pub kind: RuleKind,
+    pub description: String,
+    /// Parsed `# METADATA` block, if the rule has one
+    pub annotations: Option<regorus::Value>,
+    /// The comment block directly above the rule, one line per comment line
+    /// with its `#`, as written
+    pub comments: Vec<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Bodies of the definition, none for `x := 1` and one more per `else`
+    pub bodies: usize,
+    /// Source of the value of a `default` rule
+    pub default: Option<String>,
+}
+
+/// Parse a policy into its module AST.
+///
+/// Policies are parsed as Rego v1 first and, failing that, as v0, since the
+/// engine's version may have changed since a policy was added.
+pub(crate) fn parse_module(name: &str, source: &str) -> anyhow::Result<Module> {
+    let parsed_source = Source::from_contents(name.to_string(), source.to_string())?;
+
+    let mut parser = Parser::new(&parsed_source)?;
+    parser.enable_rego_v1()?;
+    match parser.parse() {
+        Ok(module) => Ok(module),
+        Err(_) => Parser::new(&parsed_source)?.parse(),
+    }
+}
+
+/// Parse Rego source to extract rule definitions with their metadata
+pub(crate) fn parse_rules(name: &str, source: &str) -> anyhow::Result<Vec<RuleInfo>> {
+    Ok(rule_infos(&parse_module(name, source)?, source))
+}
+
+/// Metadata of each rule definition of `module`, in source order
+pub(crate) fn rule_infos(module: &Module, source: &str) -> Vec<RuleInfo> {
+    let lines: Vec<&str> = source.lines().collect();
+
+    module
+        .policy
+        .iter()
+        .map(|rule| {
+            let (span, refr, kind, bodies) = match rule.as_ref() {
+                Rule::Spec { span, head, bodies, .. } => match head {
+                    RuleHead::Compr { refr, .. } if is_partial_object(refr.as_ref()) => {
+                        (span, refr, RuleKind::PartialObject, bodies.len())
+                    }
+                    RuleHead::Compr { refr, .. } => (span, refr, RuleKind::Complete, bodies.len()),
+                    RuleHead::Set { refr, .. } => (span, refr, RuleKind::PartialSet, bodies.len()),
+                    RuleHead::Func { refr, .. } => (span, refr, RuleKind::Function, bodies.len()),
+                },
+                Rule::Default { span, refr, .. } => (span, refr, RuleKind::Default, 0),
+            };
+            let default = match rule.as_ref() {
+                Rule::Default { value, .. } => Some(value.span().text().to_string()),
+                Rule::Spec { .. } => None,
+            };
+
+            let start_line = span.line as usize;
+            let end_line = start_line + span.text().matches('\n').count();
+            let (description, annotations, comments) = describe(&lines, start_line);
+
+            RuleInfo {
+                name: refr.span().text().to_string(),
+                kind,
+                description,
+                annotations,
+                comments,
+                start_line,
+                end_line,
+                bodies,
+                default,
+            }
+        })
+        .collect()
+}
+
+/// Whether a rule head ends in a variable key, as in `p[k] := v`, so the rule
+/// builds an object a key at a time
+fn is_partial_object(refr: &Expr) -> bool {
+    let Expr::RefBrack { index, .. } = refr else {
+        return false;
+    };
+    let key = index.span().text();
+    let is_constant = key.starts_with(['"', '`', '-'])
+        || key.starts_with(|c: char| c.is_ascii_digit())
+        || matches!(key, "true" | "false" | "null");
+    !is_constant
+}
+
+/// The value of a `default` rule from its source, which Rego requires to be
+/// a constant
+fn default_value(source: &str) -> Result<regorus::Value, (Atom, String)> {
+    let results = Engine::new()
+        .eval_query(source.to_string(), false)
+        .map_err(|e| (atoms::eval_error(), e.to_string()))?;
+    Ok(first_value(results))
+}
+
+/// Whether a comment's text is empty or a `# ====` style divider
+fn is_divider(text: &str) -> bool {
+    text.chars()
+        .all(|c| c == '=' || c == '-' || c.is_whitespace())
+}
+
+/// Description, annotations and comment lines from the comment block directly
+/// above a rule.
+///
+/// Only the comment block touching the rule counts; a blank line ends it. If
+/// the block holds an OPA `# METADATA` annotation, its YAML is parsed and the
+/// description comes from its `description` (or `title`). Otherwise the
+/// description is the whole block joined into one line, leaving out empty
+/// comments and `# ====` style dividers.
+fn describe(lines: &[&str], start_line: usize) -> (String, Option<regorus::Value>, Vec<String>) {
+    let comments: Vec<&str> = {
+        let mut comments: Vec<&str> = lines[..start_line.saturating_sub(1)]
+            .iter()
+            .rev()
+            .map(|line| line.trim())
+            .take_while(|line| line.starts_with('#'))
+            .collect();
+        comments.reverse();
+        comments
+    };
+    let block: Vec<&str> = comments
+        .iter()
+        .map(|line| line.trim_start_matches('#'))
+        .collect();
+    let comments = comments.into_iter().map(String::from).collect();
+
+    if let Some(marker) = block.iter().position(|line| line.trim() == "METADATA") {
+        let yaml: Vec<&str> = block[marker + 1..]
+            .iter()
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect();
+
+        if let Ok(annotations) = regorus::Value::from_yaml_str(&yaml.join("\n")) {
+            let description = ["description", "title"]
+                .iter()
+                .find_map(|key| match &annotations[*key] {
+                    regorus::Value::String(text) => Some(text.trim().to_string()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+
+            return (description, Some(annotations), comments);
+        }
+    }
+
+    let description = block
+        .iter()
+        .map(|line| line.trim())
+        .filter(|text| !is_divider(text))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (description, None, comments)
+}
+
+#[rustler::nif]
+fn native_get_rules<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+) -> Result<Term<'a>, (Atom, String)> {
+    catch_panic(|| {
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        // Build a map of policy_name => [rules]
+        let mut policy_rules: Vec<(Term<'a>, Term<'a>)> = Vec::new();
+
+        for (policy_name, policy) in policies.iter() {
+            let rules = parse_rules(policy_name, &policy.source)
+                .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+
+            let rule_terms = rules
+                .iter()
+                .map(|rule| -> Result<Term<'a>, (Atom, String)> {
+                    let name_atom = atoms::name();
+                    let desc_atom = atoms::description();
+                    let start_atom = atoms::start_line();
+                    let end_atom = atoms::end_line();
+                    let annotations_atom = atoms::annotations();
+                    let comments_atom = atoms::comments();
+                    let kind_atom = atoms::kind();
+                    let default_atom = atoms::default();
+                    let ref_atom = atoms::rule_ref();
+
+                    let annotations = match &rule.annotations {
+                        Some(value) => {
+                            value_to_term(env, value.clone(), &DecodeOptions::default())?
+                        }
+                        None => rustler::types::atom::nil().encode(env),
+                    };
+                    let default = match &rule.default {
+                        Some(source) => {
+                            value_to_term(env, default_value(source)?, &DecodeOptions::default())?
+                        }
+                        None => rustler::types::atom::nil().encode(env),
+                    };
+                    let full_ref = format!("{}.{}", policy.package, rule.name);
+
+                    map_from_pairs(
+                        env,
+                        &[
+                            (name_atom.encode(env), rule.name.encode(env)),
+                            (desc_atom.encode(env), rule.description.encode(env)),
+                            (start_atom.encode(env), (rule.start_line as i64).encode(env)),
+                            (end_atom.encode(env), (rule.end_line as i64).encode(env)),
+                            (annotations_atom.encode(env), annotations),
+                            (comments_atom.encode(env), rule.comments.encode(env)),
+                            (kind_atom.encode(env), rule.kind.encode(env)),
+                            (default_atom.encode(env), default),
+                            (ref_atom.encode(env), full_ref.encode(env)),
+                        ],
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            policy_rules.push((policy_name.encode(env), rule_terms.encode(env)));
+        }
+
+        map_from_pairs(env, &policy_rules)
+    })
+}
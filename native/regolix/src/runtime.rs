@@ -0,0 +1,76 @@
+use crate::error::catch_panic;
+use crate::{atoms, rebuild_engine, EngineResource};
+use regorus::Engine;
+use rustler::{Atom, ResourceArc, Term};
+
+/// Stand-in for `opa.runtime` while a runtime document is set
+const OPA_RUNTIME: &str = "regolix.opa.runtime";
+
+pub(crate) const RENAMES: &[(&str, &str)] = &[("opa.runtime", OPA_RUNTIME)];
+
+/// Register an `opa.runtime` stand-in that returns `runtime`
+pub(crate) fn register(runtime: &regorus::Value, engine: &mut Engine) -> anyhow::Result<()> {
+    let runtime = runtime.clone();
+    let opa_runtime = move |_args: Vec<regorus::Value>| -> anyhow::Result<regorus::Value> {
+        Ok(runtime.clone())
+    };
+    engine.add_extension(OPA_RUNTIME.to_string(), 0, Box::new(opa_runtime))
+}
+
+/// Make `opa.runtime()` in policies return `json_runtime`, e.g.
+/// `{"env": {...}, "version": "0.60.0", "config": {...}}`, or the regorus
+/// default again when it is `nil`.
+///
+/// Like `native_set_time`, this rebuilds the engine so policies already
+/// loaded are affected, and queries passed to the eval functions are not.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_set_runtime(
+    resource: ResourceArc<EngineResource>,
+    json_runtime: Option<Term>,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let runtime = match json_runtime {
+            Some(json) => {
+                let value = resource
+                    .limits
+                    .read()
+                    .map_err(|e| (atoms::engine_error(), e.to_string()))?
+                    .parse_json_term(json)
+                    .map_err(|(kind, message)| (kind, message.into()))?;
+                if !matches!(value, regorus::Value::Object(_)) {
+                    return Err((
+                        atoms::json_error(),
+                        "runtime must be a JSON object".to_string(),
+                    ));
+                }
+                Some(value)
+            }
+            None => None,
+        };
+
+        let mut engine = resource.begin_write();
+
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+        let input = resource
+            .input
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clone();
+
+        let mut next = settings.clone();
+        next.runtime = runtime;
+
+        *engine = rebuild_engine(&policies, engine.get_data(), input, &next)?;
+        engine.commit();
+        *settings = next;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,135 @@
+use crate::deps::bases;
+use crate::error::{catch_panic, located_error, ErrorDetail};
+use crate::lint::{calls, tokenize, KEYWORDS};
+use crate::rules::{parse_module, parse_rules, RuleKind};
+use crate::{atoms, EngineResource};
+use rustler::{Atom, ResourceArc};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Reject a policy that calls any of the `disabled` builtins.
+///
+/// Rego has no way to call a function indirectly, so every call is visible in
+/// the source and checking at load time is enough.
+pub(crate) fn check_disabled_builtins(
+    disabled: &[String],
+    name: &str,
+    source: &str,
+) -> Result<(), (Atom, ErrorDetail)> {
+    if disabled.is_empty() {
+        return Ok(());
+    }
+
+    let tokens = tokenize(source);
+    match calls(&tokens)
+        .into_iter()
+        .find(|call| disabled.contains(&call.name))
+    {
+        Some(call) => Err(located_error(
+            atoms::builtin_disabled(),
+            format!(
+                "{}:{}:{}: builtin `{}` is disabled for this engine",
+                name, call.first.line, call.first.column, call.name
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Refuse to load policies that call any of `builtins`, e.g. `"http.send"`.
+///
+/// Fails without changing anything if a policy already loaded calls one of
+/// them. Builtins stay disabled for the life of the engine, and carry over to
+/// clones and dumps. Queries are written by the application and are not
+/// checked.
+///
+/// Takes the writer lock, so a policy being loaded is checked either before
+/// the change and then rejected here, or after it against the new list.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_disable_builtins(
+    resource: ResourceArc<EngineResource>,
+    builtins: Vec<String>,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let _writer = resource.lock_writer();
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let mut settings = resource
+            .settings
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let mut names: Vec<&String> = policies.keys().collect();
+        names.sort();
+        for name in names {
+            check_disabled_builtins(&builtins, name, &policies[name].source)?;
+        }
+
+        for builtin in builtins {
+            if !settings.disabled_builtins.contains(&builtin) {
+                settings.disabled_builtins.push(builtin);
+            }
+        }
+
+        resource.store_changed();
+        Ok(())
+    })
+}
+
+/// The builtins each loaded policy calls, for reviewing what policies can
+/// reach (the network, the environment, crypto keys) before activating them.
+///
+/// Calls to functions defined in loaded policies are left out; anything
+/// else, including extensions, is reported.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_builtin_usage(
+    resource: ResourceArc<EngineResource>,
+) -> Result<HashMap<String, Vec<String>>, (Atom, String)> {
+    catch_panic(|| {
+        let policies = resource
+            .policies
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        // Functions by full path, e.g. `data.lib.is_admin`
+        let mut functions = HashSet::new();
+        for (name, policy) in policies.iter() {
+            let rules = parse_rules(name, &policy.source)
+                .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+            for rule in rules.iter().filter(|rule| rule.kind == RuleKind::Function) {
+                functions.insert(format!("{}.{}", policy.package, rule.name));
+            }
+        }
+
+        let mut usage = HashMap::new();
+        for (name, policy) in policies.iter() {
+            let tokens = tokenize(&policy.source);
+            let module = parse_module(name, &policy.source)
+                .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+            let imports = bases(&module);
+
+            let builtins: BTreeSet<String> = calls(&tokens)
+                .into_iter()
+                .filter(|call| call.name == "contains" || !KEYWORDS.contains(&call.name.as_str()))
+                .filter(|call| {
+                    let (head, rest) = match call.name.split_once('.') {
+                        Some((head, rest)) => (head, Some(rest)),
+                        None => (call.name.as_str(), None),
+                    };
+                    let path = match (imports.get(head), rest) {
+                        (Some(base), Some(rest)) => format!("{}.{}", base, rest),
+                        (Some(base), None) => base.clone(),
+                        (None, _) => format!("{}.{}", policy.package, call.name),
+                    };
+                    !functions.contains(&path) && !functions.contains(&call.name)
+                })
+                .map(|call| call.name)
+                .collect();
+
+            usage.insert(name.clone(), builtins.into_iter().collect());
+        }
+
+        Ok(usage)
+    })
+}
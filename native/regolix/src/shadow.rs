@@ -0,0 +1,141 @@
+use crate::decode::{result_to_term, value_to_term};
+use crate::error::{catch_panic, ErrorDetail};
+use crate::{atoms, eval_detached, EngineResource};
+use rustler::{Atom, Env, NifMap, ResourceArc, Term};
+
+#[derive(NifMap)]
+struct Difference<'a> {
+    /// JSON Pointer to the differing part, `""` for the whole result
+    path: String,
+    primary: Term<'a>,
+    candidate: Term<'a>,
+}
+
+#[derive(NifMap)]
+struct ShadowReport<'a> {
+    /// The primary engine's result
+    result: Term<'a>,
+    agreed: bool,
+    differences: Vec<Difference<'a>>,
+    /// Why the candidate failed, when the primary didn't
+    candidate_error: Option<(Atom, ErrorDetail)>,
+}
+
+/// Escape a key for use as a JSON Pointer segment (RFC 6901)
+fn pointer_segment(key: &regorus::Value) -> String {
+    let text = match key {
+        regorus::Value::String(text) => text.to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    };
+    text.replace('~', "~0").replace('/', "~1")
+}
+
+/// The innermost places where two results differ, in document order.
+///
+/// Objects are compared key by key and arrays index by index; anything else,
+/// sets included, is reported whole. A side missing a key or index is
+/// undefined there.
+fn diff_values(
+    primary: &regorus::Value,
+    candidate: &regorus::Value,
+) -> Vec<(String, regorus::Value, regorus::Value)> {
+    let mut found = Vec::new();
+    let mut stack = vec![(String::new(), Some(primary), Some(candidate))];
+
+    while let Some((path, primary, candidate)) = stack.pop() {
+        if primary == candidate {
+            continue;
+        }
+
+        match (primary, candidate) {
+            (Some(regorus::Value::Object(a)), Some(regorus::Value::Object(b))) => {
+                let keys = a.keys().chain(b.keys().filter(|key| !a.contains_key(*key)));
+                let children: Vec<_> = keys
+                    .map(|key| {
+                        (
+                            format!("{}/{}", path, pointer_segment(key)),
+                            a.get(key),
+                            b.get(key),
+                        )
+                    })
+                    .collect();
+                // Reversed so they come off the stack in order
+                stack.extend(children.into_iter().rev());
+            }
+            (Some(regorus::Value::Array(a)), Some(regorus::Value::Array(b))) => {
+                let children: Vec<_> = (0..a.len().max(b.len()))
+                    .map(|i| (format!("{}/{}", path, i), a.get(i), b.get(i)))
+                    .collect();
+                stack.extend(children.into_iter().rev());
+            }
+            _ => found.push((
+                path,
+                primary.cloned().unwrap_or(regorus::Value::Undefined),
+                candidate.cloned().unwrap_or(regorus::Value::Undefined),
+            )),
+        }
+    }
+
+    found
+}
+
+/// Evaluate `query` on both engines and return the primary's result, along
+/// with whether the candidate agreed and where it didn't.
+///
+/// With `json_input` both engines evaluate that input instead of their own.
+/// An error from the primary is returned as usual; an error from the
+/// candidate is only reported.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_shadow_eval<'a>(
+    env: Env<'a>,
+    primary: ResourceArc<EngineResource>,
+    candidate: ResourceArc<EngineResource>,
+    query: String,
+    json_input: Option<Term<'a>>,
+) -> Result<ShadowReport<'a>, (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let input = match json_input {
+            Some(json_input) => Some(
+                primary
+                    .limits
+                    .read()
+                    .map_err(|e| (atoms::engine_error(), e.to_string().into()))?
+                    .parse_json_term(json_input)?,
+            ),
+            None => None,
+        };
+
+        let decode = *primary
+            .decode
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        let primary_value = eval_detached(&primary, &query, input.as_ref())?;
+
+        let (agreed, differences, candidate_error) =
+            match eval_detached(&candidate, &query, input.as_ref()) {
+                Ok(candidate_value) => {
+                    let differences = diff_values(&primary_value, &candidate_value)
+                        .into_iter()
+                        .map(|(path, primary, candidate)| {
+                            Ok(Difference {
+                                path,
+                                primary: value_to_term(env, primary, &decode)?,
+                                candidate: value_to_term(env, candidate, &decode)?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>, (Atom, String)>>()
+                        .map_err(|(kind, message)| (kind, message.into()))?;
+                    (differences.is_empty(), differences, None)
+                }
+                Err(error) => (false, Vec::new(), Some(error)),
+            };
+
+        Ok(ShadowReport {
+            result: result_to_term(env, primary_value, &decode)?,
+            agreed,
+            differences,
+            candidate_error,
+        })
+    })
+}
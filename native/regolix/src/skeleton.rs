@@ -0,0 +1,86 @@
+use crate::decode::{value_to_term, DecodeOptions};
+use crate::deps::{bases, scan_rule};
+use crate::error::catch_panic;
+use crate::rules::parse_module;
+use crate::{atoms, EngineResource};
+use rustler::{Atom, Env, ResourceArc, Term};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Insert `segments` into `tree`, ending in `leaf` unless something already
+/// lives below that path
+fn insert(tree: &mut serde_json::Map<String, serde_json::Value>, segments: &[&str], leaf: &str) {
+    let Some((first, rest)) = segments.split_first() else {
+        return;
+    };
+
+    if rest.is_empty() {
+        tree.entry(first.to_string())
+            .or_insert_with(|| serde_json::Value::from(leaf));
+        return;
+    }
+
+    let child = tree
+        .entry(first.to_string())
+        .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+    // A path with children is an object, whatever it was compared with
+    if !child.is_object() {
+        *child = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let serde_json::Value::Object(fields) = child {
+        insert(fields, rest, leaf);
+    }
+}
+
+/// An input document with every `input.*` path the loaded policies read,
+/// for showing integrators what shape of input is expected.
+///
+/// Leaves are placeholders naming the type the policies seem to expect,
+/// judged from what each reference is compared with and whether it's indexed
+/// or iterated, as found by `scan_rule`: `"string"`, `"number"`,
+/// `"boolean"`, `"null"`, `"array"`, `"object"`, or `"any"` when there's no
+/// clue or the clues disagree.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_input_skeleton<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<EngineResource>,
+) -> Result<Term<'a>, (Atom, String)> {
+    catch_panic(|| {
+        let mut hints: BTreeMap<String, BTreeSet<&'static str>> = BTreeMap::new();
+
+        {
+            let policies = resource
+                .policies
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+            for (name, policy) in policies.iter() {
+                let module = parse_module(name, &policy.source)
+                    .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+                let bases = bases(&module);
+
+                for rule in &module.policy {
+                    for found in scan_rule(rule.as_ref(), &bases).refs {
+                        if found.path == "input" || found.path.starts_with("input.") {
+                            let types = hints.entry(found.path).or_default();
+                            types.extend(found.hint);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut skeleton = serde_json::Map::new();
+        for (path, types) in &hints {
+            let segments: Vec<&str> = path.split('.').skip(1).collect();
+            let leaf = match types.len() {
+                1 => types.first().copied().unwrap_or("any"),
+                _ => "any",
+            };
+            insert(&mut skeleton, &segments, leaf);
+        }
+
+        let skeleton: regorus::Value = serde_json::from_value(serde_json::Value::Object(skeleton))
+            .map_err(|e| (atoms::json_error(), e.to_string()))?;
+        value_to_term(env, skeleton, &DecodeOptions::default())
+    })
+}
@@ -0,0 +1,142 @@
+use crate::EngineResource;
+use regorus::Engine;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, MutexGuard, PoisonError};
+
+/// The writer lock, held until dropped.
+///
+/// Letting go of it finishes a release that was put off because the lock was
+/// held when the engine's owner exited.
+pub(crate) struct WriterLock<'a> {
+    resource: &'a EngineResource,
+    guard: Option<MutexGuard<'a, ()>>,
+}
+
+impl Drop for WriterLock<'_> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        self.resource.finish_release();
+    }
+}
+
+/// A private copy of the engine being modified by a writer.
+///
+/// Nothing is visible to evaluations until `commit`; dropping the writer (for
+/// example when `?` returns an error) discards every change.
+pub(crate) struct EngineWriter<'a> {
+    resource: &'a EngineResource,
+    engine: Engine,
+    _guard: WriterLock<'a>,
+}
+
+impl EngineWriter<'_> {
+    /// Atomically replace the published engine with this one.
+    ///
+    /// The writer lock stays held until the writer is dropped, so bookkeeping
+    /// updated after the commit lands before the next writer starts.
+    pub fn commit(&mut self) {
+        // Evaluating once prepares the engine, so the copies each evaluation
+        // takes of this snapshot don't all redo that work
+        let _ = self.engine.eval_query("true".to_string(), false);
+        let engine = std::mem::replace(&mut self.engine, Engine::new());
+        self.resource.engine.store(Arc::new(engine));
+        self.resource.invalidate_decisions();
+        self.resource.store_changed();
+    }
+}
+
+impl Deref for EngineWriter<'_> {
+    type Target = Engine;
+
+    fn deref(&self) -> &Engine {
+        &self.engine
+    }
+}
+
+impl DerefMut for EngineWriter<'_> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+}
+
+/// A copy of the current snapshot for a single evaluation.
+///
+/// With coverage enabled the copy holds the writer lock and is published when
+/// dropped, so coverage accumulates across evaluations like it used to.
+pub(crate) struct EvalEngine<'a> {
+    engine: Engine,
+    publish: Option<(&'a EngineResource, WriterLock<'a>)>,
+}
+
+impl Drop for EvalEngine<'_> {
+    fn drop(&mut self) {
+        if let Some((resource, _guard)) = self.publish.take() {
+            let engine = std::mem::replace(&mut self.engine, Engine::new());
+            resource.engine.store(Arc::new(engine));
+        }
+    }
+}
+
+impl Deref for EvalEngine<'_> {
+    type Target = Engine;
+
+    fn deref(&self) -> &Engine {
+        &self.engine
+    }
+}
+
+impl DerefMut for EvalEngine<'_> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+}
+
+impl EngineResource {
+    /// The current engine state. Never blocks, even while a writer is
+    /// rebuilding the engine.
+    pub(crate) fn snapshot(&self) -> Arc<Engine> {
+        self.engine.load_full()
+    }
+
+    /// Take the writer lock, for work that must not interleave with writers
+    pub(crate) fn lock_writer(&self) -> WriterLock<'_> {
+        WriterLock {
+            resource: self,
+            guard: Some(self.writer.lock().unwrap_or_else(PoisonError::into_inner)),
+        }
+    }
+
+    /// Start modifying the engine. Writers are serialized with each other but
+    /// never block evaluations, which keep using the previous snapshot.
+    pub(crate) fn begin_write(&self) -> EngineWriter<'_> {
+        let guard = self.lock_writer();
+
+        EngineWriter {
+            resource: self,
+            engine: Engine::clone(&self.snapshot()),
+            _guard: guard,
+        }
+    }
+
+    /// A copy of the current snapshot to evaluate against
+    pub(crate) fn eval_engine(&self) -> EvalEngine<'_> {
+        let coverage = self
+            .settings
+            .read()
+            .map(|settings| settings.coverage_enabled)
+            .unwrap_or(false);
+
+        if coverage {
+            let guard = self.lock_writer();
+            EvalEngine {
+                engine: Engine::clone(&self.snapshot()),
+                publish: Some((self, guard)),
+            }
+        } else {
+            EvalEngine {
+                engine: Engine::clone(&self.snapshot()),
+                publish: None,
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::{catch_panic, ErrorDetail};
+use crate::{atoms, EngineResource};
+use rustler::{Atom, NifMap, ResourceArc};
+use std::time::Instant;
+
+/// Error type and message of a failed evaluation
+#[derive(Clone, NifMap)]
+pub(crate) struct LastError {
+    kind: Atom,
+    message: String,
+}
+
+/// Evaluation counters since the engine was created
+#[derive(Default)]
+pub(crate) struct EvalStats {
+    count: u64,
+    total_ns: u64,
+    last_error: Option<LastError>,
+}
+
+impl EngineResource {
+    /// Count an evaluation that began at `started`, remembering its error if
+    /// it failed
+    pub(crate) fn record_eval<T>(
+        &self,
+        started: Instant,
+        result: &Result<T, (Atom, ErrorDetail)>,
+    ) {
+        let Ok(mut stats) = self.stats.lock() else {
+            return;
+        };
+
+        let ns = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        stats.count += 1;
+        stats.total_ns = stats.total_ns.saturating_add(ns);
+
+        if let Err((kind, detail)) = result {
+            let message = match detail {
+                ErrorDetail::Located(location) => location.message.clone(),
+                ErrorDetail::AtPath(at_path) => at_path.message.clone(),
+                ErrorDetail::Reason(reason) => reason.to_string(),
+                ErrorDetail::Message(message) => message.clone(),
+            };
+            stats.last_error = Some(LastError { kind: *kind, message });
+        }
+    }
+}
+
+#[derive(NifMap)]
+struct EngineStats {
+    policy_count: usize,
+    /// Total length of the policy sources
+    policy_bytes: usize,
+    /// Length of the data document serialized as JSON
+    data_bytes: usize,
+    eval_count: u64,
+    /// Time spent evaluating, summed over all evaluations
+    eval_ns: u64,
+    last_error: Option<LastError>,
+    /// Decision cache lookups that found a result, and those that didn't
+    cache_hits: u64,
+    cache_misses: u64,
+}
+
+/// Counters for health checks and metrics.
+///
+/// Evaluations through a pool aren't counted, since pools run on copies
+/// detached from the engine.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_stats(resource: ResourceArc<EngineResource>) -> Result<EngineStats, (Atom, String)> {
+    catch_panic(|| {
+        let (policy_count, policy_bytes) = {
+            let policies = resource
+                .policies
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+            (
+                policies.len(),
+                policies.values().map(|policy| policy.source.len()).sum(),
+            )
+        };
+
+        let data_bytes = serde_json::to_string(&resource.snapshot().get_data())
+            .map_err(|e| (atoms::json_error(), e.to_string()))?
+            .len();
+
+        let (cache_hits, cache_misses) = resource
+            .decisions
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .counts();
+
+        let stats = resource
+            .stats
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        Ok(EngineStats {
+            policy_count,
+            policy_bytes,
+            data_bytes,
+            eval_count: stats.count,
+            eval_ns: stats.total_ns,
+            last_error: stats.last_error.clone(),
+            cache_hits,
+            cache_misses,
+        })
+    })
+}
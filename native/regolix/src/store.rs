@@ -0,0 +1,166 @@
+use crate::error::catch_panic;
+use crate::{atoms, EngineResource};
+use rustler::{Atom, ResourceArc};
+use std::sync::mpsc::Sender;
+use std::thread::JoinHandle;
+
+#[cfg(feature = "store")]
+use crate::dump::EngineDump;
+#[cfg(feature = "store")]
+use std::sync::mpsc::Receiver;
+
+/// Key the engine state is kept under in the store
+#[cfg(feature = "store")]
+const ENGINE_KEY: &str = "engine";
+
+/// Messages to the thread persisting an engine, in increasing precedence
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Signal {
+    /// The engine changed and should be saved
+    Changed,
+    /// Save once more and stop
+    Close,
+    /// Stop without saving
+    Discard,
+}
+
+/// The thread persisting an engine opened with `native_open_store`
+pub(crate) struct StoreHandle {
+    signals: Sender<Signal>,
+    /// Returns the error of the last save, if it failed
+    thread: JoinHandle<Result<(), String>>,
+}
+
+impl EngineResource {
+    /// Have the store, if any, save the engine's new state
+    pub(crate) fn store_changed(&self) {
+        if let Ok(store) = self.store.lock() {
+            if let Some(store) = store.as_ref() {
+                let _ = store.signals.send(Signal::Changed);
+            }
+        }
+    }
+
+    /// Stop persisting the engine without saving changes not yet saved, for
+    /// when the engine is about to be emptied
+    pub(crate) fn discard_store(&self) {
+        if let Ok(mut store) = self.store.lock() {
+            if let Some(store) = store.take() {
+                let _ = store.signals.send(Signal::Discard);
+            }
+        }
+    }
+}
+
+/// Save everything `native_dump` would to `db`, durably
+#[cfg(feature = "store")]
+fn save(resource: &EngineResource, db: &sled::Db) -> Result<(), String> {
+    let dump = {
+        // Hold off writers so the data matches the policies
+        let _writer = resource.lock_writer();
+        EngineDump::capture(resource).map_err(|(_, message)| message)?
+    };
+
+    let json = serde_json::to_vec(&dump).map_err(|e| e.to_string())?;
+    db.insert(ENGINE_KEY, json).map_err(|e| e.to_string())?;
+    db.flush().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Save the engine each time it changes, once per burst of changes, until
+/// told to stop
+#[cfg(feature = "store")]
+fn persist(
+    resource: ResourceArc<EngineResource>,
+    db: sled::Db,
+    signals: Receiver<Signal>,
+) -> Result<(), String> {
+    let mut last = Ok(());
+
+    while let Ok(signal) = signals.recv() {
+        let signal = signals.try_iter().fold(signal, Signal::max);
+        if signal == Signal::Discard {
+            break;
+        }
+
+        last = save(&resource, &db);
+        if signal == Signal::Close {
+            break;
+        }
+    }
+
+    last
+}
+
+/// Open the store at `path`, creating it if needed, and return an engine
+/// with the state saved there. From then on every change to the engine is
+/// saved to the store in the background until `native_close_store`.
+///
+/// The background thread holds a reference to the engine, so it stays
+/// alive until the store is closed.
+#[cfg(feature = "store")]
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_open_store(path: String) -> Result<ResourceArc<EngineResource>, (Atom, String)> {
+    catch_panic(|| {
+        let db = sled::open(&path).map_err(|e| (atoms::io_error(), format!("{}: {}", path, e)))?;
+
+        let saved = db
+            .get(ENGINE_KEY)
+            .map_err(|e| (atoms::io_error(), format!("{}: {}", path, e)))?;
+        let resource = match saved {
+            Some(json) => serde_json::from_slice::<EngineDump>(&json)
+                .map_err(|e| (atoms::json_error(), format!("{}: {}", path, e)))?
+                .restore()?,
+            None => EngineResource::new(),
+        };
+        let resource = ResourceArc::new(resource);
+
+        let (signals, receiver) = std::sync::mpsc::channel();
+        let thread = {
+            let resource = resource.clone();
+            std::thread::spawn(move || persist(resource, db, receiver))
+        };
+        *resource
+            .store
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))? =
+            Some(StoreHandle { signals, thread });
+
+        Ok(resource)
+    })
+}
+
+#[cfg(not(feature = "store"))]
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_open_store(path: String) -> Result<ResourceArc<EngineResource>, (Atom, String)> {
+    catch_panic(|| {
+        Err((
+            atoms::engine_error(),
+            format!("{}: regolix was built without the `store` feature", path),
+        ))
+    })
+}
+
+/// Save the engine a last time and stop persisting it. Does nothing for an
+/// engine that wasn't opened from a store.
+#[rustler::nif(schedule = "DirtyIo")]
+fn native_close_store(resource: ResourceArc<EngineResource>) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let store = resource
+            .store
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .take();
+
+        if let Some(store) = store {
+            let _ = store.signals.send(Signal::Close);
+            store
+                .thread
+                .join()
+                .map_err(|_| (atoms::engine_error(), "store thread panicked".to_string()))?
+                .map_err(|e| (atoms::io_error(), e))?;
+        }
+
+        Ok(())
+    })
+}
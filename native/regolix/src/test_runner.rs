@@ -0,0 +1,126 @@
+use crate::error::catch_panic;
+use crate::rules::{parse_rules, RuleKind};
+use crate::{atoms, EngineResource};
+use rustler::{Atom, NifMap, NifUnitEnum, ResourceArc};
+use std::collections::HashSet;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, NifUnitEnum)]
+enum TestStatus {
+    Pass,
+    Fail,
+    Error,
+    /// `todo_test_` rules, which OPA reports without running
+    Skip,
+}
+
+#[derive(NifMap)]
+struct TestResult {
+    /// Full rule path, e.g. `data.authz_test.test_admin_allowed`
+    name: String,
+    package: String,
+    file: String,
+    line: usize,
+    status: TestStatus,
+    duration_ns: u64,
+    /// Why the test failed or errored
+    message: Option<String>,
+}
+
+#[derive(NifMap)]
+struct TestOptions {
+    /// Only run tests whose full name contains this string
+    filter: Option<String>,
+}
+
+/// Run every `test_*` rule in the loaded policies, like `opa test`.
+///
+/// A test passes when its rule evaluates to `true`; `false` or undefined is a
+/// failure and an evaluation error is an error. All tests share one copy of
+/// the engine, so coverage accumulates on the engine when it is enabled.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_run_tests(
+    resource: ResourceArc<EngineResource>,
+    opts: TestOptions,
+) -> Result<Vec<TestResult>, (Atom, String)> {
+    catch_panic(|| {
+        let mut tests = Vec::new();
+        {
+            let policies = resource
+                .policies
+                .read()
+                .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+            let mut names: Vec<&String> = policies.keys().collect();
+            names.sort();
+
+            for file in names {
+                let policy = &policies[file];
+                let rules = parse_rules(file, &policy.source)
+                    .map_err(|e| (atoms::parse_error(), e.to_string()))?;
+
+                for rule in rules {
+                    let is_test =
+                        rule.name.starts_with("test_") || rule.name.starts_with("todo_test_");
+                    if is_test && rule.kind != RuleKind::Function {
+                        tests.push((
+                            format!("{}.{}", policy.package, rule.name),
+                            policy.package.clone(),
+                            file.clone(),
+                            rule.start_line,
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut engine = resource.eval_engine();
+        let mut seen = HashSet::new();
+        let mut results = Vec::new();
+
+        for (name, package, file, line) in tests {
+            // A test split across several definitions runs once, as in OPA
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if let Some(filter) = &opts.filter {
+                if !name.contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            let started = Instant::now();
+            let skipped = name.rsplit('.').next().unwrap_or_default().starts_with("todo_");
+            let (status, message) = if skipped {
+                (TestStatus::Skip, None)
+            } else {
+                match engine.eval_rule(name.clone()) {
+                    Ok(regorus::Value::Bool(true)) => (TestStatus::Pass, None),
+                    Ok(regorus::Value::Undefined) => {
+                        (TestStatus::Fail, Some("test is undefined".to_string()))
+                    }
+                    Ok(value) => (
+                        TestStatus::Fail,
+                        Some(format!(
+                            "test evaluated to {}",
+                            value.to_json_str().unwrap_or_default()
+                        )),
+                    ),
+                    Err(e) => (TestStatus::Error, Some(e.to_string())),
+                }
+            };
+
+            results.push(TestResult {
+                name,
+                package,
+                file,
+                line,
+                status,
+                duration_ns: started.elapsed().as_nanos() as u64,
+                message,
+            });
+        }
+
+        Ok(results)
+    })
+}
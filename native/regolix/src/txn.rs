@@ -0,0 +1,153 @@
+use crate::error::{catch_panic, ErrorDetail};
+use crate::limits::Limits;
+use crate::{atoms, nest_value, path_segments, remove_at_path, EngineResource};
+use rustler::{Atom, ResourceArc, Term};
+use std::sync::Mutex;
+
+/// A data change queued in a transaction
+enum TxnOp {
+    AddData(regorus::Value),
+    RemoveDataPath(Vec<String>),
+}
+
+/// A batch of data changes that becomes visible to evaluations all at once.
+///
+/// Changes are validated as they're queued and replayed onto the latest
+/// engine on commit, so a transaction never overwrites policies or data
+/// changed by others in the meantime.
+pub struct Txn {
+    engine: ResourceArc<EngineResource>,
+    limits: Limits,
+    /// `None` once committed or aborted
+    ops: Mutex<Option<Vec<TxnOp>>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Txn {}
+
+impl Txn {
+    fn push(&self, op: TxnOp) -> Result<(), (Atom, String)> {
+        self.ops
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .as_mut()
+            .ok_or_else(|| (atoms::engine_error(), "transaction already finished".to_string()))?
+            .push(op);
+        Ok(())
+    }
+}
+
+#[rustler::nif]
+fn native_txn_begin(
+    resource: ResourceArc<EngineResource>,
+) -> Result<ResourceArc<Txn>, (Atom, String)> {
+    catch_panic(|| {
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        Ok(ResourceArc::new(Txn {
+            engine: resource,
+            limits,
+            ops: Mutex::new(Some(Vec::new())),
+        }))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_txn_add_data(txn: ResourceArc<Txn>, json_data: Term) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = txn.limits.parse_json_term(json_data)?;
+        txn.push(TxnOp::AddData(value))
+            .map_err(|(kind, message)| (kind, message.into()))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_txn_add_data_at_path(
+    txn: ResourceArc<Txn>,
+    path: String,
+    json_data: Term,
+) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = txn.limits.parse_json_term(json_data)?;
+
+        let segments = path_segments(&path);
+        if segments.is_empty() && !matches!(value, regorus::Value::Object(_)) {
+            return Err((
+                atoms::engine_error(),
+                "data at the root path must be an object".to_string().into(),
+            ));
+        }
+
+        txn.push(TxnOp::AddData(nest_value(&segments, value)))
+            .map_err(|(kind, message)| (kind, message.into()))
+    })
+}
+
+#[rustler::nif]
+fn native_txn_remove_data_path(txn: ResourceArc<Txn>, path: String) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let segments: Vec<String> = path_segments(&path).into_iter().map(String::from).collect();
+        if segments.is_empty() {
+            return Err((
+                atoms::engine_error(),
+                "path must not be empty, use clear_data to remove all data".to_string(),
+            ));
+        }
+
+        txn.push(TxnOp::RemoveDataPath(segments))
+    })
+}
+
+/// Apply every queued change to the latest engine and publish the result in
+/// one step. If any change fails, nothing is published.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_txn_commit(
+    txn: ResourceArc<Txn>,
+) -> Result<ResourceArc<EngineResource>, (Atom, String)> {
+    catch_panic(|| {
+        let ops = txn
+            .ops
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .take()
+            .ok_or_else(|| (atoms::engine_error(), "transaction already finished".to_string()))?;
+
+        let mut engine = txn.engine.begin_write();
+
+        for op in ops {
+            match op {
+                TxnOp::AddData(value) => engine
+                    .add_data(value)
+                    .map_err(|e| (atoms::engine_error(), e.to_string()))?,
+                TxnOp::RemoveDataPath(segments) => {
+                    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+                    let mut data = engine.get_data();
+                    if remove_at_path(&mut data, &segments) {
+                        engine.clear_data();
+                        engine
+                            .add_data(data)
+                            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+                    }
+                }
+            }
+        }
+
+        engine.commit();
+        Ok(txn.engine.clone())
+    })
+}
+
+/// Drop the queued changes without applying them
+#[rustler::nif]
+fn native_txn_abort(txn: ResourceArc<Txn>) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        txn.ops
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .take();
+        Ok(())
+    })
+}
@@ -0,0 +1,159 @@
+use crate::error::{catch_panic, ErrorDetail};
+use crate::limits::Limits;
+use crate::{atoms, EngineResource};
+use rustler::{Atom, Binary, ResourceArc};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+/// Chunks queued ahead of the parser before `native_add_data_chunk` blocks
+const QUEUE_DEPTH: usize = 4;
+
+type ParseResult = Result<regorus::Value, String>;
+
+/// A data document being streamed in chunk by chunk.
+///
+/// A parser thread reads the chunks as they arrive, so only the chunks still
+/// queued and the parsed value are held in memory, never the whole encoded
+/// document.
+pub struct DataUpload {
+    engine: ResourceArc<EngineResource>,
+    limits: Limits,
+    received: AtomicUsize,
+    sender: Mutex<Option<SyncSender<Vec<u8>>>>,
+    parser: Mutex<Option<JoinHandle<ParseResult>>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for DataUpload {}
+
+/// Presents the queued chunks to serde as one continuous stream; the stream
+/// ends when the sender is dropped
+struct ChunkReader {
+    chunks: Receiver<Vec<u8>>,
+    current: Vec<u8>,
+    offset: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.current.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.current = chunk;
+                    self.offset = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.current.len() - self.offset);
+        buf[..n].copy_from_slice(&self.current[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+impl DataUpload {
+    /// Stop accepting chunks and wait for the parser's verdict
+    fn finish(&self) -> Result<regorus::Value, (Atom, String)> {
+        self.sender
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .take();
+
+        let parser = self
+            .parser
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .take()
+            .ok_or_else(|| (atoms::engine_error(), "upload already finished".to_string()))?;
+
+        parser
+            .join()
+            .map_err(|_| (atoms::engine_error(), "data parser panicked".to_string()))?
+            .map_err(|message| (atoms::json_error(), message))
+    }
+}
+
+#[rustler::nif]
+fn native_add_data_begin(
+    resource: ResourceArc<EngineResource>,
+) -> Result<ResourceArc<DataUpload>, (Atom, String)> {
+    catch_panic(|| {
+        let limits = *resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?;
+
+        let (sender, chunks) = mpsc::sync_channel(QUEUE_DEPTH);
+        let parser = thread::spawn(move || {
+            let reader = ChunkReader {
+                chunks,
+                current: Vec::new(),
+                offset: 0,
+            };
+            serde_json::from_reader(reader).map_err(|e| e.to_string())
+        });
+
+        Ok(ResourceArc::new(DataUpload {
+            engine: resource,
+            limits,
+            received: AtomicUsize::new(0),
+            sender: Mutex::new(Some(sender)),
+            parser: Mutex::new(Some(parser)),
+        }))
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_add_data_chunk(
+    upload: ResourceArc<DataUpload>,
+    chunk: Binary,
+) -> Result<(), (Atom, String)> {
+    catch_panic(|| {
+        let received = upload.received.fetch_add(chunk.len(), Ordering::SeqCst) + chunk.len();
+        if let Some(max) = upload.limits.max_bytes {
+            if received > max {
+                return Err((
+                    atoms::limit_exceeded(),
+                    format!("document is over {} bytes, limit is {}", received, max),
+                ));
+            }
+        }
+
+        let sender = upload
+            .sender
+            .lock()
+            .map_err(|e| (atoms::engine_error(), e.to_string()))?
+            .clone()
+            .ok_or_else(|| (atoms::engine_error(), "upload already finished".to_string()))?;
+
+        // The parser hangs up as soon as it hits a syntax error
+        if sender.send(chunk.as_slice().to_vec()).is_err() {
+            upload.finish()?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Finish parsing and merge the document into the engine's data
+#[rustler::nif(schedule = "DirtyCpu")]
+fn native_add_data_commit(upload: ResourceArc<DataUpload>) -> Result<(), (Atom, ErrorDetail)> {
+    catch_panic(|| {
+        let value = upload
+            .finish()
+            .map_err(|(kind, message)| (kind, message.into()))?;
+        upload.limits.check_value(&value)?;
+
+        let mut engine = upload.engine.begin_write();
+        engine
+            .add_data(value)
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        engine.commit();
+        Ok(())
+    })
+}
@@ -0,0 +1,305 @@
+use crate::error::{located_error, ErrorDetail};
+use crate::sandbox::check_disabled_builtins;
+use crate::{atoms, EngineResource, PolicySource};
+use regorus::Engine;
+use rustler::{Atom, Encoder, LocalPid, NifMap, OwnedEnv, ResourceArc};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Polls a set of files and directories, reloading the engine when any
+/// `.rego`, `.json`, `.yaml` or `.yml` file under them changes
+pub struct Watcher {
+    stopped: Arc<AtomicBool>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for Watcher {}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Sent to the subscriber after every reload attempt
+#[derive(NifMap)]
+struct ReloadReport {
+    /// Files added or modified since the last reload
+    changed: Vec<String>,
+    /// Files deleted since the last reload
+    removed: Vec<String>,
+    /// Why the reload was rejected; empty when it was applied
+    errors: Vec<ErrorDetail>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FileKind {
+    Policy,
+    Json,
+    Yaml,
+}
+
+fn file_kind(path: &Path) -> Option<FileKind> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("rego") => Some(FileKind::Policy),
+        Some("json") => Some(FileKind::Json),
+        Some("yaml" | "yml") => Some(FileKind::Yaml),
+        _ => None,
+    }
+}
+
+/// Modification times of every watched file currently on disk
+fn scan(roots: &[PathBuf]) -> HashMap<PathBuf, SystemTime> {
+    let mut files = HashMap::new();
+    let mut pending: Vec<PathBuf> = roots.to_vec();
+
+    while let Some(path) = pending.pop() {
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            if let Ok(entries) = std::fs::read_dir(&path) {
+                pending.extend(entries.flatten().map(|entry| entry.path()));
+            }
+        } else if file_kind(&path).is_some() {
+            if let Ok(modified) = metadata.modified() {
+                files.insert(path, modified);
+            }
+        }
+    }
+
+    files
+}
+
+/// State the watcher keeps between reloads
+struct WatchState {
+    resource: ResourceArc<EngineResource>,
+    /// Data documents loaded from each data file, so what they added can be
+    /// taken out again when the file changes or disappears
+    data: HashMap<String, regorus::Value>,
+}
+
+impl WatchState {
+    /// Apply a set of file changes to the engine in one step; on any error
+    /// the engine is left as it was
+    fn reload(
+        &mut self,
+        changed: &[String],
+        removed: &[String],
+    ) -> Result<(), (Atom, ErrorDetail)> {
+        let mut sources = Vec::new();
+        let mut documents = Vec::new();
+        let limits = *self
+            .resource
+            .limits
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        for name in changed {
+            let path = Path::new(name);
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| (atoms::io_error(), format!("{}: {}", name, e).into()))?;
+
+            match file_kind(path) {
+                Some(FileKind::Policy) => sources.push((name.clone(), contents)),
+                Some(kind) => {
+                    let value = if kind == FileKind::Yaml {
+                        limits.parse_yaml(&contents)
+                    } else {
+                        limits.parse_json(&contents)
+                    }
+                    .map_err(|(kind, detail)| (kind, detail.in_file(name)))?;
+                    documents.push((name.clone(), value));
+                }
+                None => {}
+            }
+        }
+
+        let resource = &self.resource;
+        let mut engine = resource.begin_write();
+        let mut policies = resource
+            .policies
+            .write()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let settings = resource
+            .settings
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        let input = resource
+            .input
+            .read()
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+
+        // Drop what changed or disappeared, then build the engine from scratch
+        // since regorus can't unload a single policy
+        let mut next_policies = policies.clone();
+        let mut data = engine.get_data();
+        for name in changed.iter().chain(removed) {
+            next_policies.remove(name);
+            if let Some(document) = self.data.get(name) {
+                remove_document(&mut data, document);
+            }
+        }
+
+        let mut next = Engine::new();
+        settings.apply(&mut next);
+
+        for (name, policy) in &next_policies {
+            settings
+                .add_policy(&mut next, name, &policy.source)
+                .map_err(|e| located_error(atoms::parse_error(), e))?;
+        }
+        for (name, source) in sources {
+            check_disabled_builtins(&settings.disabled_builtins, &name, &source)?;
+            let package = settings
+                .add_policy(&mut next, &name, &source)
+                .map_err(|e| located_error(atoms::parse_error(), e))?;
+            next_policies.insert(name, PolicySource { source, package });
+        }
+
+        next
+            .add_data(data)
+            .map_err(|e| (atoms::engine_error(), e.to_string().into()))?;
+        for (name, value) in &documents {
+            next
+                .add_data(value.clone())
+                .map_err(|e| (atoms::engine_error(), format!("{}: {}", name, e).into()))?;
+        }
+        if let Some(input) = input.clone() {
+            next.set_input(input);
+        }
+
+        *engine = next;
+        engine.commit();
+        *policies = next_policies;
+        resource.invalidate_queries();
+
+        for name in removed {
+            self.data.remove(name);
+        }
+        self.data.extend(documents);
+
+        Ok(())
+    }
+}
+
+/// Take what `document` added to `data` back out.
+///
+/// Other files and `add_data` calls may have merged into the same objects, so
+/// only the document's own leaves are removed, followed by any object that
+/// is left empty.
+fn remove_document(data: &mut regorus::Value, document: &regorus::Value) {
+    let (Ok(data_fields), regorus::Value::Object(fields)) = (data.as_object_mut(), document) else {
+        return;
+    };
+
+    for (key, field) in fields.iter() {
+        let emptied = match (data_fields.get_mut(key), field) {
+            (Some(existing @ regorus::Value::Object(_)), regorus::Value::Object(_)) => {
+                remove_document(existing, field);
+                matches!(existing, regorus::Value::Object(rest) if rest.is_empty())
+            }
+            (Some(_), _) => true,
+            (None, _) => false,
+        };
+        if emptied {
+            data_fields.remove(key);
+        }
+    }
+}
+
+/// Start watching `paths`, polling every `interval_ms`.
+///
+/// Every watched file is loaded straight away, then reloaded whenever its
+/// modification time changes. Each reload sends
+/// `{:regolix_reloaded, %{changed, removed, errors}}` to `subscriber`.
+/// Watching stops when the watcher is garbage collected or stopped, or when
+/// the subscriber exits.
+#[rustler::nif]
+fn native_watch(
+    resource: ResourceArc<EngineResource>,
+    paths: Vec<String>,
+    subscriber: LocalPid,
+    interval_ms: u64,
+) -> ResourceArc<Watcher> {
+    let stopped = Arc::new(AtomicBool::new(false));
+    let roots: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+    let interval = Duration::from_millis(interval_ms.max(1));
+
+    let flag = stopped.clone();
+    thread::spawn(move || {
+        let mut state = WatchState {
+            resource,
+            data: HashMap::new(),
+        };
+        let mut seen: HashMap<PathBuf, SystemTime> = HashMap::new();
+        // Changes not yet applied because the last reload failed
+        let mut changed: BTreeSet<String> = BTreeSet::new();
+        let mut removed: BTreeSet<String> = BTreeSet::new();
+        let mut msg_env = OwnedEnv::new();
+
+        while !flag.load(Ordering::SeqCst) {
+            let current = scan(&roots);
+            let mut dirty = false;
+
+            for (path, modified) in &current {
+                if seen.get(path) != Some(modified) {
+                    let name = path.to_string_lossy().into_owned();
+                    removed.remove(&name);
+                    changed.insert(name);
+                    dirty = true;
+                }
+            }
+            for path in seen.keys().filter(|path| !current.contains_key(*path)) {
+                let name = path.to_string_lossy().into_owned();
+                changed.remove(&name);
+                removed.insert(name);
+                dirty = true;
+            }
+            seen = current;
+
+            // A failed reload is retried on the next change to any file
+            if dirty {
+                let changed_list: Vec<String> = changed.iter().cloned().collect();
+                let removed_list: Vec<String> = removed.iter().cloned().collect();
+
+                let errors = match state.reload(&changed_list, &removed_list) {
+                    Ok(()) => {
+                        changed.clear();
+                        removed.clear();
+                        Vec::new()
+                    }
+                    Err((_, detail)) => vec![detail],
+                };
+
+                let report = ReloadReport {
+                    changed: changed_list,
+                    removed: removed_list,
+                    errors,
+                };
+
+                let sent = msg_env.send_and_clear(&subscriber, |env| {
+                    (atoms::regolix_reloaded(), report).encode(env)
+                });
+                if sent.is_err() {
+                    break;
+                }
+            }
+
+            thread::sleep(interval);
+        }
+    });
+
+    ResourceArc::new(Watcher { stopped })
+}
+
+#[rustler::nif]
+fn native_unwatch(watcher: ResourceArc<Watcher>) -> Atom {
+    watcher.stopped.store(true, Ordering::SeqCst);
+    atoms::ok()
+}
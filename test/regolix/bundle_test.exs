@@ -0,0 +1,304 @@
+defmodule Regolix.BundleTest do
+  use ExUnit.Case
+
+  @moduletag :tmp_dir
+
+  defp build_bundle(tmp_dir, files) do
+    path = Path.join(tmp_dir, "bundle.tar.gz")
+    entries = Enum.map(files, fn {name, contents} -> {String.to_charlist(name), contents} end)
+    :ok = :erl_tar.create(String.to_charlist(path), entries, [:compressed])
+    path
+  end
+
+  # Prepend a `.signatures.json` signing every file with an HS256 secret
+  defp sign(files, keyid, secret) do
+    signed =
+      for {name, contents} <- files do
+        hash = :crypto.hash(:sha256, contents) |> Base.encode16(case: :lower)
+        %{"name" => name, "hash" => hash, "algorithm" => "SHA-256"}
+      end
+
+    encode = &Base.url_encode64(Jason.encode!(&1), padding: false)
+    message = encode.(%{"alg" => "HS256", "kid" => keyid}) <> "." <> encode.(%{"files" => signed})
+    signature = :crypto.mac(:hmac, :sha256, secret, message) |> Base.url_encode64(padding: false)
+
+    signatures = Jason.encode!(%{"signatures" => [message <> "." <> signature]})
+    [{".signatures.json", signatures} | files]
+  end
+
+  describe "load_bundle/2" do
+    test "installs policies, data and manifest", %{tmp_dir: tmp_dir} do
+      path =
+        build_bundle(tmp_dir, [
+          {".manifest", ~s({"revision": "v42", "roots": ["authz", "users"]})},
+          {"authz/policy.rego", """
+           package authz
+           allow if data.users.admins[_] == input.user
+           """},
+          {"users/data.json", ~s({"admins": ["alice"]})}
+        ])
+
+      assert {:ok, engine, info} = Regolix.load_bundle(Regolix.new!(), path)
+      assert info.revision == "v42"
+      assert info.roots == ["authz", "users"]
+      assert info.policies == ["authz/policy.rego"]
+
+      engine = Regolix.set_input!(engine, %{"user" => "alice"})
+      assert {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+    end
+
+    test "reads YAML data documents", %{tmp_dir: tmp_dir} do
+      path =
+        build_bundle(tmp_dir, [
+          {"config/data.yaml", "limits:\n  max: 10\n"}
+        ])
+
+      engine = Regolix.load_bundle!(Regolix.new!(), path)
+      assert {:ok, 10} = Regolix.eval_query(engine, "data.config.limits.max")
+    end
+
+    test "leaves the engine untouched when a policy fails to parse", %{tmp_dir: tmp_dir} do
+      path =
+        build_bundle(tmp_dir, [
+          {"good.rego", "package good"},
+          {"bad.rego", "invalid {{{"},
+          {"data.json", ~s({"loaded": true})}
+        ])
+
+      engine = Regolix.new!()
+
+      assert {:error, %Regolix.Error{type: :parse_error}} = Regolix.load_bundle(engine, path)
+      assert Regolix.get_packages(engine) == []
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.loaded")
+    end
+
+    test "ignores files it does not read, such as wasm modules", %{tmp_dir: tmp_dir} do
+      path =
+        build_bundle(tmp_dir, [
+          {"authz/policy.rego", "package authz\nallow := true\n"},
+          {"policy.wasm", <<0, 97, 115, 109, 0xFF, 0xFE>>}
+        ])
+
+      engine = Regolix.load_bundle!(Regolix.new!(), path)
+      assert {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+    end
+
+    test "caps the decompressed size of the bundle", %{tmp_dir: tmp_dir} do
+      path =
+        build_bundle(tmp_dir, [
+          {"authz/policy.rego", "package authz\nallow := true\n"},
+          {"data.json", Jason.encode!(%{"padding" => String.duplicate("a", 10_000)})}
+        ])
+
+      engine = Regolix.set_limits!(Regolix.new!(), max_bundle_bytes: 1_000)
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} = Regolix.load_bundle(engine, path)
+      assert Regolix.get_packages(engine) == []
+    end
+
+    test "returns io_error for missing bundle", %{tmp_dir: tmp_dir} do
+      assert {:error, %Regolix.Error{type: :io_error}} =
+               Regolix.load_bundle(Regolix.new!(), Path.join(tmp_dir, "missing.tar.gz"))
+    end
+  end
+
+  describe "load_bundle/2 with verification keys" do
+    setup do
+      files = [
+        {".manifest", ~s({"revision": "v1"})},
+        {"authz/policy.rego", "package authz\nallow := true\n"},
+        {"users/data.json", ~s({"admins":["alice"]})}
+      ]
+
+      engine =
+        Regolix.add_verification_key!(Regolix.new!(), "release", "s3cret", algorithm: "HS256")
+
+      %{engine: engine, files: files}
+    end
+
+    test "loads bundles signed by a trusted key",
+         %{engine: engine, files: files, tmp_dir: tmp_dir} do
+      path = build_bundle(tmp_dir, sign(files, "release", "s3cret"))
+
+      assert {:ok, engine, %{revision: "v1"}} = Regolix.load_bundle(engine, path)
+      assert {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+    end
+
+    test "rejects unsigned, tampered and untrusted bundles",
+         %{engine: engine, files: files, tmp_dir: tmp_dir} do
+      assert {:error, %Regolix.Error{type: :bundle_error, message: message}} =
+               Regolix.load_bundle(engine, build_bundle(tmp_dir, files))
+
+      assert message =~ ".signatures.json"
+
+      tampered =
+        files
+        |> sign("release", "s3cret")
+        |> List.keyreplace("authz/policy.rego", 0, {"authz/policy.rego", "package authz\n"})
+
+      assert {:error, %Regolix.Error{type: :bundle_error, message: message}} =
+               Regolix.load_bundle(engine, build_bundle(tmp_dir, tampered))
+
+      assert message =~ "authz/policy.rego does not match"
+
+      assert {:error, %Regolix.Error{type: :bundle_error, message: message}} =
+               Regolix.load_bundle(engine, build_bundle(tmp_dir, sign(files, "other", "s3cret")))
+
+      assert message =~ "`other` is not trusted"
+
+      assert {:error, %Regolix.Error{type: :bundle_error}} =
+               Regolix.load_bundle(engine, build_bundle(tmp_dir, sign(files, "release", "wrong")))
+
+      assert Regolix.get_packages(engine) == []
+    end
+
+    test "rejects a signed bundle with a second copy of a signed file",
+         %{engine: engine, files: files, tmp_dir: tmp_dir} do
+      files = sign(files, "release", "s3cret") ++ [{"./authz/policy.rego", "package evil\n"}]
+
+      assert {:error, %Regolix.Error{type: :bundle_error, message: message}} =
+               Regolix.load_bundle(engine, build_bundle(tmp_dir, files))
+
+      assert message =~ "authz/policy.rego appears more than once"
+      assert Regolix.get_packages(engine) == []
+    end
+  end
+
+  describe "load_bundle_binary/2" do
+    test "loads a bundle from memory", %{tmp_dir: tmp_dir} do
+      contents =
+        tmp_dir
+        |> build_bundle([{"policy.rego", "package example\nvalue := 1\n"}])
+        |> File.read!()
+
+      assert {:ok, engine, _info} = Regolix.load_bundle_binary(Regolix.new!(), contents)
+      assert {:ok, 1} = Regolix.eval_query(engine, "data.example.value")
+    end
+  end
+
+  describe "build_bundle/2" do
+    test "packages policies and data that load back into a new engine" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz/allow.rego", """
+        package authz
+        allow if data.users[input.user].admin
+        """)
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{"admin" => true}}})
+
+      assert {:ok, tarball} = Regolix.build_bundle(engine, revision: "v7", roots: ["authz", "users"])
+
+      assert {:ok, loaded, %{revision: "v7", roots: ["authz", "users"], policies: ["authz/allow.rego"]}} =
+               Regolix.load_bundle_binary(Regolix.new!(), tarball)
+
+      loaded = Regolix.set_input!(loaded, %{"user" => "alice"})
+      assert {:ok, true} = Regolix.eval_query(loaded, "data.authz.allow")
+    end
+
+    test "writes a manifest and gives policies a .rego extension" do
+      engine = Regolix.add_policy!(Regolix.new!(), "/policies/authz", "package authz\nallow := true")
+
+      {:ok, files} = :erl_tar.extract({:binary, Regolix.build_bundle!(engine)}, [:memory, :compressed])
+      files = Map.new(files, fn {name, contents} -> {to_string(name), contents} end)
+
+      assert Map.keys(files) |> Enum.sort() == [".manifest", "data.json", "policies/authz.rego"]
+      assert %{"revision" => ""} = Jason.decode!(files[".manifest"])
+      refute Map.has_key?(Jason.decode!(files[".manifest"]), "roots")
+    end
+  end
+
+  describe "load_bundle_binary/3 manifest roots" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", "package authz\nallow := true")
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{}}})
+
+      %{engine: engine}
+    end
+
+    test "rejects policies and data outside the roots", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :bundle_error, message: message}} =
+               Regolix.load_bundle_binary(Regolix.new!(), Regolix.build_bundle!(engine, roots: ["users"]))
+
+      assert message =~ "data.authz"
+
+      assert {:error, %Regolix.Error{type: :bundle_error, message: message}} =
+               Regolix.load_bundle_binary(Regolix.new!(), Regolix.build_bundle!(engine, roots: ["authz"]))
+
+      assert message =~ "users"
+    end
+
+    test "rejects roots overlapping another bundle and records revisions", %{engine: engine} do
+      target = Regolix.new!()
+      tarball = Regolix.build_bundle!(engine, revision: "v1", roots: ["authz", "users"])
+      assert {:ok, _, _} = Regolix.load_bundle_binary(target, tarball, name: "main")
+
+      other = Regolix.add_data!(Regolix.new!(), %{"users" => %{"bob" => %{}}})
+      other_tarball = Regolix.build_bundle!(other, roots: ["users/bob"])
+
+      assert {:error, %Regolix.Error{type: :bundle_error}} =
+               Regolix.load_bundle_binary(target, other_tarball, name: "extra")
+
+      tarball = Regolix.build_bundle!(engine, revision: "v2", roots: ["authz", "users"])
+      assert {:ok, _, %{revision: "v2"}} = Regolix.load_bundle_binary(target, tarball, name: "main")
+
+      assert Regolix.bundle_status(target) ==
+               {:ok, %{"main" => %{revision: "v2", roots: ["authz", "users"]}}}
+    end
+
+    test "a new revision drops the policies and data of the old one" do
+      v1 =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", "package authz\nallow := true")
+        |> Regolix.add_policy!("old.rego", "package authz.old\nlegacy := true")
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{}, "bob" => %{}}})
+        |> Regolix.build_bundle!(revision: "v1", roots: ["authz", "users"])
+
+      v2 =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", "package authz\nallow := false")
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{}}})
+        |> Regolix.build_bundle!(revision: "v2", roots: ["authz", "users"])
+
+      engine = Regolix.add_data!(Regolix.new!(), %{"other" => 1})
+      {:ok, engine, _} = Regolix.load_bundle_binary(engine, v1, name: "main")
+      {:ok, engine, _} = Regolix.load_bundle_binary(engine, v2, name: "main")
+
+      assert {:ok, false} = Regolix.eval_query(engine, "data.authz.allow")
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.authz.old.legacy")
+      assert Regolix.eval_query(engine, "data.users") == {:ok, %{"alice" => %{}}}
+      assert {:ok, 1} = Regolix.eval_query(engine, "data.other")
+      assert Regolix.get_packages(engine) == ["data.authz"]
+    end
+
+    test "rejects a policy file name already loaded by another bundle" do
+      bundle = fn package, value, revision ->
+        Regolix.new!()
+        |> Regolix.add_policy!("policy.rego", "package #{package}\nvalue := #{value}")
+        |> Regolix.build_bundle!(revision: revision, roots: [package])
+      end
+
+      {:ok, engine, _} =
+        Regolix.load_bundle_binary(Regolix.new!(), bundle.("a", 1, "v1"), name: "a")
+
+      assert {:error, %Regolix.Error{type: :bundle_error, message: message}} =
+               Regolix.load_bundle_binary(engine, bundle.("b", 2, "v1"), name: "b")
+
+      assert message =~ "policy.rego: a policy of the same name is already loaded"
+
+      {:ok, engine, _} = Regolix.load_bundle_binary(engine, bundle.("a", 3, "v2"), name: "a")
+
+      assert {:ok, 3} = Regolix.eval_query(engine, "data.a.value")
+      assert Regolix.get_packages(engine) == ["data.a"]
+      assert {:ok, %{"a" => %{revision: "v2"}}} = Regolix.bundle_status(engine)
+
+      engine = Regolix.add_policy!(Regolix.new!(), "policy.rego", "package c\nvalue := 4")
+
+      assert {:error, %Regolix.Error{type: :bundle_error}} =
+               Regolix.load_bundle_binary(engine, bundle.("b", 2, "v1"), name: "b")
+
+      assert {:ok, 4} = Regolix.eval_query(engine, "data.c.value")
+    end
+  end
+end
@@ -18,4 +18,16 @@ defmodule Regolix.ErrorTest do
     error = %Error{type: :parse_error, message: "unexpected token"}
     assert Exception.message(error) == "parse_error: unexpected token"
   end
+
+  test "error message/1 includes location when present" do
+    error = %Error{
+      type: :parse_error,
+      message: "unexpected token",
+      file: "authz.rego",
+      line: 3,
+      column: 9
+    }
+
+    assert Exception.message(error) == "parse_error: unexpected token (authz.rego:3:9)"
+  end
 end
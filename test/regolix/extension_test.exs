@@ -0,0 +1,91 @@
+defmodule Regolix.ExtensionTest do
+  use ExUnit.Case
+
+  describe "add_extension/5" do
+    test "calls an Elixir function from a policy" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_extension!("myorg.double", 1, fn x -> x * 2 end)
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        doubled := myorg.double(input.value)
+        """)
+        |> Regolix.set_input!(%{"value" => 21})
+
+      assert {:ok, 42} = Regolix.eval_query(engine, "data.test.doubled")
+    end
+
+    test "passes structured arguments" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_extension!("myorg.role", 2, fn users, name -> users[name]["role"] end)
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        role := myorg.role({"alice": {"role": "admin"}}, "alice")
+        """)
+
+      assert {:ok, "admin"} = Regolix.eval_query(engine, "data.test.role")
+    end
+
+    test "supports a custom handler process" do
+      handler =
+        spawn(fn ->
+          receive do
+            {:regolix_extension, call_id, "myorg.answer", "[]"} ->
+              Regolix.Extension.reply(call_id, {:ok, "42"})
+          end
+        end)
+
+      engine =
+        Regolix.new!()
+        |> Regolix.add_extension!("myorg.answer", 0, handler)
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        answer := myorg.answer()
+        """)
+
+      assert {:ok, 42} = Regolix.eval_query(engine, "data.test.answer")
+    end
+
+    test "turns raised exceptions into evaluation errors" do
+      engine =
+        Regolix.new!()
+        |> Regolix.set_strict_builtin_errors!(true)
+        |> Regolix.add_extension!("myorg.fail", 1, fn _ -> raise "boom" end)
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        value := myorg.fail(1)
+        """)
+
+      assert {:error, %Regolix.Error{type: :eval_error, message: message}} =
+               Regolix.eval_query(engine, "data.test.value")
+
+      assert message =~ "boom"
+    end
+
+    test "fails the call when the handler doesn't reply in time" do
+      silent = spawn(fn -> Process.sleep(:infinity) end)
+
+      engine =
+        Regolix.new!()
+        |> Regolix.set_strict_builtin_errors!(true)
+        |> Regolix.add_extension!("myorg.slow", 0, silent, timeout: 50)
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        value := myorg.slow()
+        """)
+
+      assert {:error, %Regolix.Error{message: message}} =
+               Regolix.eval_query(engine, "data.test.value")
+
+      assert message =~ "did not reply"
+    end
+
+    test "rejects duplicate registrations" do
+      engine = Regolix.add_extension!(Regolix.new!(), "myorg.f", 1, & &1)
+
+      assert {:error, %Regolix.Error{type: :engine_error}} =
+               Regolix.add_extension(engine, "myorg.f", 1, & &1)
+    end
+  end
+end
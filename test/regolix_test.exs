@@ -15,6 +15,535 @@ defmodule RegolixTest do
     end
   end
 
+  describe "clone/1" do
+    test "copies policies, data and input" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        allowed := data.users[input.user].allowed
+        """)
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{"allowed" => true}}})
+        |> Regolix.set_input!(%{"user" => "alice"})
+
+      assert {:ok, copy} = Regolix.clone(engine)
+      assert copy != engine
+      assert {:ok, true} = Regolix.eval_query(copy, "data.test.allowed")
+      assert Regolix.get_policies!(copy) == Regolix.get_policies!(engine)
+    end
+
+    test "changes to the copy don't affect the original" do
+      engine = Regolix.add_data!(Regolix.new!(), %{"flags" => %{"beta" => false}})
+
+      copy =
+        engine
+        |> Regolix.clone!()
+        |> Regolix.add_policy!("extra.rego", "package extra")
+        |> Regolix.add_data!(%{"scratch" => true})
+
+      assert {:ok, true} = Regolix.eval_query(copy, "data.scratch")
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.scratch")
+      assert Regolix.get_packages(engine) == []
+    end
+  end
+
+  describe "set_owner/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", "package test\nx := 1\n")
+        |> Regolix.add_data!(%{"users" => ["alice"]})
+
+      %{engine: engine}
+    end
+
+    test "releases the engine when the owner exits", %{engine: engine} do
+      owner = spawn(fn -> Process.sleep(:infinity) end)
+      assert {:ok, ^engine} = Regolix.set_owner(engine, owner)
+      assert {:ok, 1} = Regolix.eval_query(engine, "data.test.x")
+
+      ref = Process.monitor(owner)
+      Process.exit(owner, :kill)
+      assert_receive {:DOWN, ^ref, :process, ^owner, :killed}
+      # The monitor message is handled asynchronously by the runtime
+      Process.sleep(50)
+
+      assert Regolix.get_packages(engine) == []
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.users")
+    end
+
+    test "releases the engine right away if the owner is already dead", %{engine: engine} do
+      owner = spawn(fn -> Process.sleep(:infinity) end)
+      ref = Process.monitor(owner)
+      Process.exit(owner, :kill)
+      assert_receive {:DOWN, ^ref, :process, ^owner, :killed}
+
+      assert {:ok, _} = Regolix.set_owner(engine, owner)
+      assert Regolix.get_packages(engine) == []
+    end
+
+    test "keeps the engine once the owner is cleared", %{engine: engine} do
+      owner = spawn(fn -> Process.sleep(:infinity) end)
+      {:ok, engine} = Regolix.set_owner(engine, owner)
+      assert {:ok, ^engine} = Regolix.clear_owner(engine)
+
+      ref = Process.monitor(owner)
+      Process.exit(owner, :kill)
+      assert_receive {:DOWN, ^ref, :process, ^owner, :killed}
+      Process.sleep(50)
+
+      assert Regolix.get_packages(engine) == ["data.test"]
+    end
+  end
+
+  describe "dump/1 and restore/1" do
+    test "round-trips policies, data, input and settings" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        allowed := data.users[input.user].allowed
+        """)
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{"allowed" => true}}})
+        |> Regolix.set_input!(%{"user" => "alice"})
+        |> Regolix.set_undefined_mode!(nil)
+
+      assert {:ok, blob} = Regolix.dump(engine)
+      assert is_binary(blob)
+
+      assert {:ok, restored} = Regolix.restore(blob)
+      assert {:ok, true} = Regolix.eval_query(restored, "data.test.allowed")
+      assert {:ok, nil} = Regolix.eval_query(restored, "data.test.missing")
+      assert Regolix.get_policies!(restored) == Regolix.get_policies!(engine)
+    end
+
+    test "rejects binaries that aren't dumps" do
+      assert {:error, %Regolix.Error{}} = Regolix.restore("not a dump")
+    end
+  end
+
+  describe "open_store/1" do
+    @describetag :tmp_dir
+
+    @tag :store
+    test "brings back policies and data saved before closing", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "store")
+
+      engine =
+        Regolix.open_store!(path)
+        |> Regolix.add_policy!("authz.rego", "package authz\nallow if data.users[input.user].admin")
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{"admin" => true}}})
+
+      assert :ok = Regolix.close_store(engine)
+
+      reopened = Regolix.open_store!(path)
+      assert {:ok, true} = Regolix.eval_query_with_input(reopened, "data.authz.allow", %{"user" => "alice"})
+      assert :ok = Regolix.close_store(reopened)
+    end
+
+    test "closing an engine without a store does nothing" do
+      assert :ok = Regolix.close_store(Regolix.new!())
+    end
+  end
+
+  describe "check_rego_v1/2" do
+    test "flags v0-only constructs with locations" do
+      source = """
+      package authz
+      import future.keywords.in
+
+      allow {
+        input.user == "admin"
+      }
+
+      deny[msg] {
+        msg := "denied"
+      }
+      """
+
+      assert {:ok, issues} = Regolix.check_rego_v1(source)
+
+      assert [
+               %{check: "future-keywords-import", line: 2},
+               %{check: "missing-if", line: 4, column: 7},
+               %{check: "missing-contains", line: 8, suggestion: "deny contains msg if { ... }"}
+             ] = issues
+    end
+
+    test "flags deprecated builtins" do
+      source = """
+      package test
+      matched if re_match("^a", input.name)
+      """
+
+      assert [%{check: "deprecated-builtin", line: 2, suggestion: "use `regex.match`"}] =
+               Regolix.check_rego_v1!(source)
+    end
+
+    test "returns nothing for v1 policies" do
+      assert {:ok, []} = Regolix.check_rego_v1("package test\nallow if input.admin\n")
+    end
+  end
+
+  describe "diff_policies/2" do
+    @old """
+    package authz
+
+    default allow := false
+
+    # Admins can do anything
+    allow if input.user.role == "admin"
+
+    deny contains "no user" if not input.user
+    """
+
+    test "reports added, removed and changed rules" do
+      new = """
+      package authz
+
+      default allow := false
+
+      allow if input.user.role in {"admin", "owner"}
+
+      audit := true
+      """
+
+      assert {:ok, %{added: ["audit"], removed: ["deny"], changed: ["allow"]}} =
+               Regolix.diff_policies(@old, new)
+    end
+
+    test "ignores formatting and comments" do
+      new = String.replace(@old, "# Admins can do anything", "# reworded") <> "\n\n"
+      assert %{added: [], removed: [], changed: []} = Regolix.diff_policies!(@old, new)
+    end
+
+    test "returns parse errors" do
+      assert {:error, %Regolix.Error{type: :parse_error}} = Regolix.diff_policies(@old, "package")
+    end
+  end
+
+  describe "shadow_eval/4" do
+    setup do
+      primary =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        decision := {"allow": input.user == "admin", "reasons": ["role"]}
+        """)
+
+      candidate =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        decision := {"allow": input.user in {"admin", "owner"}, "reasons": ["role", "owner"]}
+        """)
+
+      %{primary: primary, candidate: candidate}
+    end
+
+    test "returns the primary's result and where the candidate differs", ctx do
+      assert {:ok, report} =
+               Regolix.shadow_eval(ctx.primary, ctx.candidate, "data.authz.decision",
+                 input: %{"user" => "owner"}
+               )
+
+      assert report.result == %{"allow" => false, "reasons" => ["role"]}
+      refute report.agreed
+      assert report.candidate_error == nil
+
+      assert [
+               %{path: "/allow", primary: false, candidate: true},
+               %{path: "/reasons/1", primary: :undefined, candidate: "owner"}
+             ] = report.differences
+    end
+
+    test "reports agreement", ctx do
+      assert %{result: true, agreed: true, differences: []} =
+               Regolix.shadow_eval!(ctx.primary, ctx.candidate, "data.authz.decision.allow",
+                 input: %{"user" => "admin"}
+               )
+    end
+
+    test "reports candidate errors without failing", ctx do
+      candidate =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        decision := 1 if true
+        decision := 2 if true
+        """)
+
+      assert {:ok, %{result: %{"allow" => true}, agreed: false, differences: []} = report} =
+               Regolix.shadow_eval(ctx.primary, candidate, "data.authz.decision",
+                 input: %{"user" => "admin"}
+               )
+
+      assert %Regolix.Error{type: :eval_error} = report.candidate_error
+    end
+
+    test "returns the primary's errors", ctx do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.shadow_eval(ctx.candidate, ctx.primary, "data.authz.decision +")
+    end
+  end
+
+  describe "eval_with_provenance/3" do
+    test "reports the label, bundle revision and contributing policies" do
+      bundle =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        allow if data.lib.admin(input.user)
+        """)
+        |> Regolix.build_bundle!(revision: "v7", roots: ["authz"])
+
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("lib.rego", """
+        package lib
+        admin(user) if user == "alice"
+        """)
+        |> Regolix.add_policy!("unused.rego", "package unused\nanswer := 42")
+        |> Regolix.set_label!("authz-eu")
+
+      {:ok, engine, _info} = Regolix.load_bundle_binary(engine, bundle, name: "authz")
+
+      assert {:ok, %{result: true, provenance: provenance}} =
+               Regolix.eval_with_provenance(engine, "data.authz.allow", input: %{"user" => "alice"})
+
+      assert %{
+               label: "authz-eu",
+               bundles: %{"authz" => "v7"},
+               policies: ["authz.rego", "lib.rego"]
+             } = provenance
+
+      assert provenance.revision > 0
+    end
+
+    test "works without a label or bundles" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", "package authz\nallow := true")
+
+      assert %{result: true, provenance: %{label: nil, bundles: %{}}} =
+               Regolix.eval_with_provenance!(engine, "data.authz.allow")
+
+      assert {:error, %Regolix.Error{}} = Regolix.eval_with_provenance(engine, "data.authz[")
+    end
+  end
+
+  describe "verification keys" do
+    @pem """
+    -----BEGIN PUBLIC KEY-----
+    MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEEVs/o5+uQbTjL3chynL4wXgUg2R9
+    q9UU8I5mEovUf86QZ7kOBIjJwqnzD1omageEHWwHdBO6B+dFabmdT9POxg==
+    -----END PUBLIC KEY-----
+    """
+
+    test "registers, rotates and removes keys by keyid" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_verification_key!("old", @pem, algorithm: "ES256")
+        |> Regolix.add_verification_key!("shared", "s3cret", algorithm: "HS256")
+
+      {:ok, engine, ["new"]} =
+        Regolix.add_jwks(engine, %{
+          "keys" => [%{"kid" => "new", "kty" => "EC", "crv" => "P-384", "x" => "AA", "y" => "AA"}]
+        })
+
+      {:ok, engine} = Regolix.remove_verification_key(engine, "old")
+
+      assert {:ok,
+              [
+                %{keyid: "new", algorithm: "ES384", format: :jwk},
+                %{keyid: "shared", algorithm: "HS256", format: :secret}
+              ]} = Regolix.verification_keys(Regolix.clone!(engine))
+    end
+
+    test "refuses private and malformed keys" do
+      engine = Regolix.new!()
+      private = String.replace(@pem, "PUBLIC KEY", "PRIVATE KEY")
+
+      assert {:error, %Regolix.Error{type: :key_error}} =
+               Regolix.add_verification_key(engine, "a", private)
+
+      assert {:error, %Regolix.Error{type: :key_error}} =
+               Regolix.add_verification_key(engine, "a", "not a key")
+
+      assert {:error, %Regolix.Error{type: :key_error}} =
+               Regolix.add_verification_key(engine, "a", @pem, algorithm: "none")
+
+      assert {:error, %Regolix.Error{type: :key_error}} =
+               Regolix.add_jwks(engine, %{
+                 "keys" => [%{"kid" => "a", "kty" => "RSA", "n" => "AQ", "e" => "AQAB", "d" => "AQ"}]
+               })
+
+      assert {:ok, []} = Regolix.verification_keys(engine)
+    end
+  end
+
+  describe "start_recording/2 and replay/2" do
+    @describetag :tmp_dir
+
+    test "replays recorded decisions against a changed policy", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "decisions.jsonl.gz")
+
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        allow if input.user == "admin"
+        """)
+
+      assert {:ok, engine} = Regolix.start_recording(engine, path)
+
+      for user <- ["admin", "owner", "guest"] do
+        Regolix.eval_query_with_input!(engine, "data.authz.allow", %{"user" => user})
+      end
+
+      engine = Regolix.set_input!(engine, %{"user" => "admin"})
+      assert {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+      assert {:ok, engine} = Regolix.stop_recording(engine)
+
+      assert {:ok, %{total: 4, diverged: []}} = Regolix.replay(engine, path)
+
+      candidate =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        allow if input.user in {"admin", "owner"}
+        """)
+
+      assert %{total: 4, diverged: [divergence]} = Regolix.replay!(candidate, path)
+
+      assert %{line: 2, query: "data.authz.allow", recorded: :undefined, replayed: true} =
+               divergence
+    end
+
+    test "reports decisions that now fail", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "decisions.jsonl.gz")
+
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        allow := true
+        """)
+
+      {:ok, engine} = Regolix.start_recording(engine, path)
+      Regolix.eval_query!(engine, "data.authz.allow")
+      {:ok, _} = Regolix.stop_recording(engine)
+
+      candidate =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        allow := 1 if true
+        allow := 2 if true
+        """)
+
+      assert {:ok, %{diverged: [%{replayed: nil, error: %Regolix.Error{}}]}} =
+               Regolix.replay(candidate, path)
+    end
+
+    test "returns io_error for a missing recording", %{tmp_dir: tmp_dir} do
+      assert {:error, %Regolix.Error{type: :io_error}} =
+               Regolix.replay(Regolix.new!(), Path.join(tmp_dir, "missing.jsonl.gz"))
+    end
+  end
+
+  describe "run_tests/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        allow if input.user == "admin"
+        """)
+        |> Regolix.add_policy!("authz_test.rego", """
+        package authz_test
+        import data.authz
+
+        test_admin_allowed if authz.allow with input as {"user": "admin"}
+        test_guest_allowed if authz.allow with input as {"user": "guest"}
+        test_broken if conflict == 1
+        todo_test_later if false
+
+        conflict := 1 if true
+        conflict := 2 if true
+        """)
+
+      %{engine: engine}
+    end
+
+    test "reports pass, fail, error and skip", %{engine: engine} do
+      assert {:ok, results} = Regolix.run_tests(engine)
+      statuses = Map.new(results, &{&1.name, &1.status})
+
+      assert statuses == %{
+               "data.authz_test.test_admin_allowed" => :pass,
+               "data.authz_test.test_guest_allowed" => :fail,
+               "data.authz_test.test_broken" => :error,
+               "data.authz_test.todo_test_later" => :skip
+             }
+
+      assert %{file: "authz_test.rego", line: 4} =
+               Enum.find(results, &(&1.name == "data.authz_test.test_admin_allowed"))
+    end
+
+    test "filters by name", %{engine: engine} do
+      assert [%{name: "data.authz_test.test_admin_allowed"}] =
+               Regolix.run_tests!(engine, filter: "admin")
+    end
+  end
+
+  describe "register/2" do
+    test "makes an engine reachable by name from other processes" do
+      engine = Regolix.add_data!(Regolix.new!(), %{"shared" => true})
+      assert :ok = Regolix.register(:regolix_test_registry, engine)
+
+      task = Task.async(fn -> Regolix.whereis(:regolix_test_registry) end)
+      found = Task.await(task)
+
+      assert {:ok, true} = Regolix.eval_query(found, "data.shared")
+      assert :regolix_test_registry in Regolix.registered()
+
+      assert Regolix.unregister(:regolix_test_registry)
+      assert Regolix.whereis(:regolix_test_registry) == nil
+      refute Regolix.unregister(:regolix_test_registry)
+    end
+  end
+
+  describe "reloading" do
+    test "evaluations keep working while policies are added" do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "flags.rego", """
+        package flags
+        version := 0
+        """)
+
+      readers =
+        for _ <- 1..4 do
+          Task.async(fn ->
+            for _ <- 1..50, do: Regolix.eval_query!(engine, "data.flags.version")
+          end)
+        end
+
+      for version <- 1..20 do
+        Regolix.add_policy!(engine, "v#{version}.rego", "package flags.v#{version}\nversion := #{version}\n")
+      end
+
+      for results <- Task.await_many(readers), result <- results do
+        assert result == 0
+      end
+
+      assert Regolix.eval_query!(engine, "data.flags.v20.version") == 20
+    end
+
+    test "a failed load leaves the previous policies in place" do
+      engine = Regolix.add_policy!(Regolix.new!(), "a.rego", "package a\nx := 1\n")
+
+      assert {:error, %Regolix.Error{type: :parse_error}} =
+               Regolix.add_policy(engine, "a.rego", "package a\nx := \n")
+
+      assert Regolix.eval_query!(engine, "data.a.x") == 1
+    end
+  end
+
   describe "add_policy/3" do
     test "adds a valid policy" do
       {:ok, engine} = Regolix.new()
@@ -50,6 +579,28 @@ defmodule RegolixTest do
     end
   end
 
+  describe "add_policy/3 error locations" do
+    test "reports file, line and column for parse errors" do
+      engine = Regolix.new!()
+
+      {:error, error} =
+        Regolix.add_policy(engine, "bad.rego", """
+        package test
+
+        allow if {
+          input.user ==
+        }
+        """)
+
+      assert error.type == :parse_error
+      assert error.file == "bad.rego"
+      assert is_integer(error.line) and error.line > 1
+      assert is_integer(error.column)
+      assert is_binary(error.snippet)
+      assert Exception.message(error) =~ "bad.rego:"
+    end
+  end
+
   describe "add_policy!/3" do
     test "returns engine for valid policy" do
       engine = Regolix.new!()
@@ -71,35 +622,262 @@ defmodule RegolixTest do
     end
   end
 
-  describe "get_packages/1" do
-    test "returns empty list for new engine" do
-      engine = Regolix.new!()
-      assert Regolix.get_packages(engine) == []
-    end
+  describe "add_policy_from_file/2" do
+    @describetag :tmp_dir
 
-    test "returns loaded package names" do
-      engine =
-        Regolix.new!()
-        |> Regolix.add_policy!("test.rego", "package mypackage")
+    test "loads a policy from disk", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "authz.rego")
+      File.write!(path, "package authz\nallow := true\n")
 
-      assert "data.mypackage" in Regolix.get_packages(engine)
+      assert {:ok, engine} = Regolix.add_policy_from_file(Regolix.new!(), path)
+      assert {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+      assert [%{name: ^path}] = Regolix.get_policies!(engine)
     end
-  end
 
-  describe "set_input/2" do
-    test "sets input from Elixir map" do
-      {:ok, engine} = Regolix.new()
-      {:ok, engine} = Regolix.set_input(engine, %{"user" => "alice", "roles" => ["admin"]})
-      assert is_reference(engine)
+    test "returns io_error for missing file", %{tmp_dir: tmp_dir} do
+      assert {:error, %Regolix.Error{type: :io_error}} =
+               Regolix.add_policy_from_file(Regolix.new!(), Path.join(tmp_dir, "missing.rego"))
     end
+  end
 
-    test "handles nested data structures" do
-      {:ok, engine} = Regolix.new()
+  describe "watch/3" do
+    @describetag :tmp_dir
 
-      {:ok, engine} =
-        Regolix.set_input(engine, %{
-          "request" => %{
-            "method" => "GET",
+    test "loads files and reloads them on change", %{tmp_dir: tmp_dir} do
+      policy = Path.join(tmp_dir, "flags.rego")
+      File.write!(policy, "package flags\nlimit := data.config.limit\n")
+      File.write!(Path.join(tmp_dir, "config.json"), ~s({"config": {"limit": 1}}))
+
+      engine = Regolix.new!()
+      assert {:ok, watcher} = Regolix.watch(engine, [tmp_dir], interval: 10)
+
+      assert_receive {:regolix_reloaded, %{changed: [_, _], errors: []}}, 1000
+      assert {:ok, 1} = Regolix.eval_query(engine, "data.flags.limit")
+
+      File.write!(policy, "package flags\nlimit := data.config.limit * 10\n")
+      # Make sure the modification time moves even on coarse-grained filesystems
+      File.touch!(policy, System.os_time(:second) + 5)
+
+      assert_receive {:regolix_reloaded, %{changed: [^policy], errors: []}}, 1000
+      assert {:ok, 10} = Regolix.eval_query(engine, "data.flags.limit")
+
+      assert :ok = Regolix.unwatch(watcher)
+    end
+
+    test "keeps the previous state when a file fails to parse", %{tmp_dir: tmp_dir} do
+      policy = Path.join(tmp_dir, "flags.rego")
+      File.write!(policy, "package flags\nenabled := true\n")
+
+      engine = Regolix.new!()
+      {:ok, watcher} = Regolix.watch(engine, [tmp_dir], interval: 10)
+      assert_receive {:regolix_reloaded, %{errors: []}}, 1000
+
+      File.write!(policy, "package flags\nenabled := \n")
+      File.touch!(policy, System.os_time(:second) + 5)
+
+      assert_receive {:regolix_reloaded, %{errors: [_]}}, 1000
+      assert {:ok, true} = Regolix.eval_query(engine, "data.flags.enabled")
+
+      Regolix.unwatch(watcher)
+    end
+
+    test "reloading a data file keeps data other sources put under the same keys",
+         %{tmp_dir: tmp_dir} do
+      users = Path.join(tmp_dir, "users.json")
+      File.write!(users, ~s({"org": {"users": ["alice"]}}))
+      File.write!(Path.join(tmp_dir, "teams.json"), ~s({"org": {"teams": ["ops"]}}))
+
+      engine = Regolix.add_data!(Regolix.new!(), %{"org" => %{"name" => "acme"}})
+      {:ok, watcher} = Regolix.watch(engine, [tmp_dir], interval: 10)
+      assert_receive {:regolix_reloaded, %{errors: []}}, 1000
+
+      File.write!(users, ~s({"org": {"users": ["bob"]}}))
+      File.touch!(users, System.os_time(:second) + 5)
+      assert_receive {:regolix_reloaded, %{changed: [^users], errors: []}}, 1000
+
+      assert Regolix.eval_query(engine, "data.org") ==
+               {:ok, %{"name" => "acme", "teams" => ["ops"], "users" => ["bob"]}}
+
+      File.rm!(users)
+      assert_receive {:regolix_reloaded, %{removed: [^users], errors: []}}, 1000
+      assert Regolix.eval_query(engine, "data.org") == {:ok, %{"name" => "acme", "teams" => ["ops"]}}
+
+      Regolix.unwatch(watcher)
+    end
+  end
+
+  describe "add_policies_from_dir/3" do
+    @describetag :tmp_dir
+
+    test "loads all matching policies recursively", %{tmp_dir: tmp_dir} do
+      File.mkdir_p!(Path.join(tmp_dir, "nested"))
+      File.write!(Path.join(tmp_dir, "authz.rego"), "package authz\nallow := true\n")
+      File.write!(Path.join(tmp_dir, "nested/rbac.rego"), "package rbac\ncheck := true\n")
+      File.write!(Path.join(tmp_dir, "notes.txt"), "not a policy")
+
+      assert {:ok, engine, paths} = Regolix.add_policies_from_dir(Regolix.new!(), tmp_dir)
+      assert length(paths) == 2
+
+      packages = Regolix.get_packages(engine)
+      assert "data.authz" in packages
+      assert "data.rbac" in packages
+    end
+
+    test "honours the glob pattern", %{tmp_dir: tmp_dir} do
+      File.mkdir_p!(Path.join(tmp_dir, "nested"))
+      File.write!(Path.join(tmp_dir, "authz.rego"), "package authz")
+      File.write!(Path.join(tmp_dir, "nested/rbac.rego"), "package rbac")
+
+      assert {:ok, engine, [_]} = Regolix.add_policies_from_dir(Regolix.new!(), tmp_dir, "*.rego")
+      assert Regolix.get_packages(engine) == ["data.authz"]
+    end
+
+    test "adds nothing when one policy fails to parse", %{tmp_dir: tmp_dir} do
+      File.write!(Path.join(tmp_dir, "good.rego"), "package good")
+      File.write!(Path.join(tmp_dir, "bad.rego"), "invalid {{{")
+
+      engine = Regolix.new!()
+
+      assert {:error, %Regolix.Error{type: :parse_error}} =
+               Regolix.add_policies_from_dir(engine, tmp_dir)
+
+      assert Regolix.get_packages(engine) == []
+      assert Regolix.get_policies!(engine) == []
+    end
+  end
+
+  describe "remove_policy/2" do
+    test "removes the policy and its package" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", "package authz")
+        |> Regolix.add_policy!("rbac.rego", "package rbac")
+
+      assert {:ok, engine} = Regolix.remove_policy(engine, "authz.rego")
+
+      packages = Regolix.get_packages(engine)
+      refute "data.authz" in packages
+      assert "data.rbac" in packages
+
+      rules = Regolix.get_rules!(engine)
+      refute Map.has_key?(rules, "authz.rego")
+    end
+
+    test "preserves data and input" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        allowed := data.users[input.user].allowed
+        """)
+        |> Regolix.add_policy!("other.rego", "package other")
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{"allowed" => true}}})
+        |> Regolix.set_input!(%{"user" => "alice"})
+        |> Regolix.remove_policy!("other.rego")
+
+      assert {:ok, true} = Regolix.eval_query(engine, "data.test.allowed")
+    end
+
+    test "returns error for unknown policy" do
+      engine = Regolix.new!()
+
+      assert {:error, %Regolix.Error{type: :engine_error}} =
+               Regolix.remove_policy(engine, "missing.rego")
+    end
+  end
+
+  describe "remove_policy!/2" do
+    test "raises for unknown policy" do
+      engine = Regolix.new!()
+
+      assert_raise Regolix.Error, ~r/policy not found/, fn ->
+        Regolix.remove_policy!(engine, "missing.rego")
+      end
+    end
+  end
+
+  describe "get_packages/1" do
+    test "returns empty list for new engine" do
+      engine = Regolix.new!()
+      assert Regolix.get_packages(engine) == []
+    end
+
+    test "returns loaded package names" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", "package mypackage")
+
+      assert "data.mypackage" in Regolix.get_packages(engine)
+    end
+  end
+
+  describe "get_policies/1" do
+    test "returns empty list for new engine" do
+      engine = Regolix.new!()
+      assert {:ok, []} = Regolix.get_policies(engine)
+    end
+
+    test "returns name, package and source length per policy" do
+      rbac = "package rbac"
+
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("rbac.rego", rbac)
+        |> Regolix.add_policy!("authz.rego", "package authz.v1")
+
+      assert {:ok, [authz, rbac_info]} = Regolix.get_policies(engine)
+
+      assert authz.name == "authz.rego"
+      assert authz.package == "data.authz.v1"
+      assert rbac_info == %{name: "rbac.rego", package: "data.rbac", source_length: byte_size(rbac)}
+    end
+
+    test "does not list policies that failed to parse" do
+      engine = Regolix.new!()
+      {:error, _} = Regolix.add_policy(engine, "bad.rego", "invalid {{{")
+
+      assert Regolix.get_policies!(engine) == []
+    end
+  end
+
+  describe "set_input/3" do
+    test "names binaries that aren't valid UTF-8, or replaces them" do
+      input = %{"user" => %{"tags" => ["ok", <<0xC3, 0x28>>]}}
+
+      assert {:error, %Regolix.Error{type: :json_error, path: "input.user.tags[1]"} = error} =
+               Regolix.set_input(Regolix.new!(), input)
+
+      assert error.message == "input.user.tags[1] is not valid UTF-8"
+
+      engine = Regolix.set_input!(Regolix.new!(), input, invalid_utf8: :lossy)
+      assert {:ok, "\uFFFD("} = Regolix.eval_query(engine, "input.user.tags[1]")
+    end
+
+    test "replaces invalid UTF-8 the same way as set_input_etf/3" do
+      input = %{"name" => <<"a", 0xE2, 0x82, "b", 0xFF, 0xED, 0xA0, 0x80>>}
+      etf = :erlang.term_to_binary(input)
+
+      engine = Regolix.set_input!(Regolix.new!(), input, invalid_utf8: :lossy)
+      etf_engine = Regolix.set_input_etf!(Regolix.new!(), etf, invalid_utf8: :lossy)
+
+      expected = "a\uFFFDb\uFFFD\uFFFD\uFFFD\uFFFD"
+      assert {:ok, ^expected} = Regolix.eval_query(engine, "input.name")
+      assert {:ok, ^expected} = Regolix.eval_query(etf_engine, "input.name")
+    end
+
+    test "sets input from Elixir map" do
+      {:ok, engine} = Regolix.new()
+      {:ok, engine} = Regolix.set_input(engine, %{"user" => "alice", "roles" => ["admin"]})
+      assert is_reference(engine)
+    end
+
+    test "handles nested data structures" do
+      {:ok, engine} = Regolix.new()
+
+      {:ok, engine} =
+        Regolix.set_input(engine, %{
+          "request" => %{
+            "method" => "GET",
             "path" => "/api/users"
           }
         })
@@ -113,6 +891,217 @@ defmodule RegolixTest do
       assert {:error, %Regolix.Error{type: :json_error}} =
                Regolix.set_input(engine, %{"pid" => self()})
     end
+
+    test "rejects documents nested too deeply" do
+      nest = fn levels -> Enum.reduce(1..levels, "leaf", fn _, acc -> [acc] end) end
+      engine = Regolix.new!()
+
+      assert {:ok, _} = Regolix.set_input(engine, nest.(100))
+
+      assert {:error, %Regolix.Error{type: :json_error, reason: :too_deep, message: message}} =
+               Regolix.set_input(engine, nest.(101))
+
+      assert message =~ "100 levels deep"
+
+      assert {:error, %Regolix.Error{type: :json_error, reason: :too_deep}} =
+               Regolix.add_data(engine, %{"deep" => nest.(100)})
+
+      # Brackets inside strings don't count towards the depth
+      assert {:ok, _} = Regolix.set_input(engine, String.duplicate("[", 200))
+    end
+  end
+
+  describe "set_input_json/2" do
+    setup do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "test.rego", """
+        package test
+        user := input.user
+        """)
+
+      %{engine: engine}
+    end
+
+    test "sets input from a JSON binary", %{engine: engine} do
+      assert {:ok, engine} = Regolix.set_input_json(engine, ~s({"user": "alice"}))
+      assert {:ok, "alice"} = Regolix.eval_query(engine, "data.test.user")
+    end
+
+    test "sets input from iodata", %{engine: engine} do
+      engine = Regolix.set_input_json!(engine, Jason.encode_to_iodata!(%{"user" => "bob"}))
+      assert {:ok, "bob"} = Regolix.eval_query(engine, "data.test.user")
+    end
+
+    test "returns error for invalid JSON", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.set_input_json(engine, ~s({"user": ))
+    end
+  end
+
+  describe "set_input_yaml/2" do
+    test "sets the input from YAML" do
+      engine =
+        Regolix.new!()
+        |> Regolix.set_input_yaml!("""
+        kind: Deployment
+        spec:
+          replicas: 3
+        """)
+
+      assert {:ok, 3} = Regolix.eval_query(engine, "input.spec.replicas")
+      assert {:ok, "Deployment"} = Regolix.eval_query(engine, "input.kind")
+    end
+
+    test "returns error for invalid YAML and keeps the previous input" do
+      engine = Regolix.set_input!(Regolix.new!(), %{"user" => "alice"})
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.set_input_yaml(engine, "user: [unclosed")
+
+      assert {:ok, "alice"} = Regolix.eval_query(engine, "input.user")
+    end
+
+    test "applies size limits" do
+      engine = Regolix.set_limits!(Regolix.new!(), max_bytes: 8)
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.set_input_yaml(engine, "user: alice-with-a-long-name")
+    end
+  end
+
+  describe "set_input_etf/3" do
+    test "sets the input from external term format" do
+      input = %{"user" => %{"roles" => MapSet.new(["admin"]), "age" => 30}}
+
+      engine =
+        Regolix.new!()
+        |> Regolix.set_input_etf!(:erlang.term_to_binary(input))
+
+      assert {:ok, 30} = Regolix.eval_query(engine, "input.user.age")
+      assert {:ok, "admin"} = Regolix.eval_query(engine, ~s(input.user.roles["admin"]))
+    end
+
+    test "returns error for an invalid binary and keeps the previous input" do
+      engine = Regolix.set_input!(Regolix.new!(), %{"user" => "alice"})
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.set_input_etf(engine, "not a term")
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.set_input_etf(engine, :erlang.term_to_binary(%{"f" => self()}))
+
+      assert {:ok, "alice"} = Regolix.eval_query(engine, "input.user")
+    end
+
+    test "applies size limits" do
+      engine = Regolix.set_limits!(Regolix.new!(), max_bytes: 8)
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.set_input_etf(engine, :erlang.term_to_binary(%{"user" => "alice"}))
+    end
+
+    test "converts dates, decimals and other structs" do
+      input = %{
+        "at" => ~U[2024-05-01 12:30:00.120Z],
+        "local" => ~N[2024-05-01 12:30:00],
+        "price" => %{__struct__: Decimal, sign: -1, coef: 1005, exp: -2},
+        "range" => 1..3//1
+      }
+
+      engine = Regolix.set_input_etf!(Regolix.new!(), :erlang.term_to_binary(input))
+
+      assert {:ok, "2024-05-01T12:30:00.120Z"} = Regolix.eval_query(engine, "input.at")
+      assert {:ok, "2024-05-01T12:30:00"} = Regolix.eval_query(engine, "input.local")
+      assert {:ok, true} = Regolix.eval_query(engine, "input.price == -10.05")
+      assert {:ok, %{"first" => 1, "last" => 3, "step" => 1}} =
+               Regolix.eval_query(engine, "input.range")
+    end
+
+    test "converts keyword lists to objects" do
+      input = %{
+        "opts" => [role: "admin", level: 3, role: "ignored"],
+        "sets" => [{:set, [1]}],
+        "empty" => []
+      }
+
+      engine = Regolix.set_input_etf!(Regolix.new!(), :erlang.term_to_binary(input))
+
+      assert {:ok, %{"role" => "admin", "level" => 3}} = Regolix.eval_query(engine, "input.opts")
+      assert {:ok, [[1]]} = Regolix.eval_query(engine, "input.sets")
+      assert {:ok, []} = Regolix.eval_query(engine, "input.empty")
+    end
+
+    test "converts tuples according to the tuple policy" do
+      etf = :erlang.term_to_binary(%{"point" => {1, 2}})
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.set_input_etf(Regolix.new!(), etf)
+
+      engine = Regolix.set_input_etf!(Regolix.new!(), etf, tuples: :list)
+      assert {:ok, [1, 2]} = Regolix.eval_query(engine, "input.point")
+
+      etf = :erlang.term_to_binary(%{"limits" => [{"cpu", 2}, {"memory", 512}]})
+      engine = Regolix.set_input_etf!(Regolix.new!(), etf, tuples: :pairs)
+      assert {:ok, %{"cpu" => 2, "memory" => 512}} = Regolix.eval_query(engine, "input.limits")
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.set_input_etf(Regolix.new!(), etf)
+    end
+
+    test "converts or rejects charlists and improper lists" do
+      etf = :erlang.term_to_binary(%{"role" => ~c"admin", "ids" => [1, 2]})
+
+      engine = Regolix.set_input_etf!(Regolix.new!(), etf)
+      assert {:ok, ~c"admin"} = Regolix.eval_query(engine, "input.role")
+
+      engine = Regolix.set_input_etf!(Regolix.new!(), etf, charlists: :string)
+      assert {:ok, "admin"} = Regolix.eval_query(engine, "input.role")
+      assert {:ok, [1, 2]} = Regolix.eval_query(engine, "input.ids")
+
+      assert {:error, %Regolix.Error{type: :json_error, message: message}} =
+               Regolix.set_input_etf(Regolix.new!(), etf, charlists: :error)
+
+      assert message =~ "input.role is a charlist"
+
+      etf = :erlang.term_to_binary(%{"users" => [%{}, %{}, %{}, ["ab" | "c"]]})
+
+      assert {:error, %Regolix.Error{message: "input.users[3] is an improper list"}} =
+               Regolix.set_input_etf(Regolix.new!(), etf)
+
+      engine = Regolix.set_input_etf!(Regolix.new!(), etf, charlists: :string)
+      assert {:ok, "abc"} = Regolix.eval_query(engine, "input.users[3]")
+    end
+
+    test "names binaries that aren't valid UTF-8, or replaces them" do
+      etf = :erlang.term_to_binary(%{"users" => [%{"name" => <<"al", 0xFF, "ice">>}]})
+
+      assert {:error, %Regolix.Error{type: :json_error, path: "input.users[0].name"}} =
+               Regolix.set_input_etf(Regolix.new!(), etf)
+
+      engine = Regolix.set_input_etf!(Regolix.new!(), etf, invalid_utf8: :lossy)
+      assert {:ok, "al\uFFFDice"} = Regolix.eval_query(engine, "input.users[0].name")
+    end
+
+    test "rejects deeply nested terms" do
+      deep = Enum.reduce(1..10_000, "leaf", fn _, acc -> [acc] end)
+
+      assert {:error, %Regolix.Error{type: :json_error, message: message}} =
+               Regolix.set_input_etf(Regolix.new!(), :erlang.term_to_binary(%{"deep" => deep}))
+
+      assert message =~ "nested more than 100 levels deep"
+    end
+
+    test "rejects unknown structs in strict mode" do
+      etf = :erlang.term_to_binary(%{"range" => 1..3//1, "at" => ~U[2024-05-01 12:30:00Z]})
+
+      assert {:error, %Regolix.Error{type: :json_error, message: message}} =
+               Regolix.set_input_etf(Regolix.new!(), etf, structs: :strict)
+
+      assert message =~ "Range"
+
+      etf = :erlang.term_to_binary(%{"at" => ~U[2024-05-01 12:30:00Z]})
+      assert {:ok, _engine} = Regolix.set_input_etf(Regolix.new!(), etf, structs: :strict)
+    end
   end
 
   describe "set_input!/2" do
@@ -146,162 +1135,1777 @@ defmodule RegolixTest do
       assert is_reference(engine)
     end
 
-    test "returns error for non-encodable data" do
-      {:ok, engine} = Regolix.new()
+    test "returns error for non-encodable data" do
+      {:ok, engine} = Regolix.new()
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.add_data(engine, %{"pid" => self()})
+    end
+  end
+
+  describe "add_data_stream/2" do
+    test "parses a document split across chunks" do
+      json = Jason.encode!(%{"users" => Map.new(1..500, &{"user#{&1}", %{"id" => &1}})})
+      chunks = for <<chunk::binary-size(64) <- json>>, do: chunk
+      rest = binary_part(json, length(chunks) * 64, rem(byte_size(json), 64))
+
+      assert {:ok, engine} = Regolix.add_data_stream(Regolix.new!(), chunks ++ [rest])
+      assert {:ok, 250} = Regolix.eval_query(engine, "data.users.user250.id")
+    end
+
+    test "leaves data untouched on invalid JSON" do
+      engine = Regolix.add_data!(Regolix.new!(), %{"kept" => true})
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.add_data_stream(engine, [~s({"a": ), ~s(})])
+
+      assert {:ok, true} = Regolix.eval_query(engine, "data.kept")
+    end
+
+    test "enforces max_bytes across chunks" do
+      engine = Regolix.set_limits!(Regolix.new!(), max_bytes: 10)
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.add_data_stream(engine, [~s({"a": "12), ~s(3456789"})])
+    end
+  end
+
+  describe "add_data!/2" do
+    test "returns engine directly" do
+      engine = Regolix.new!()
+      engine = Regolix.add_data!(engine, %{"config" => %{"enabled" => true}})
+      assert is_reference(engine)
+    end
+
+    test "raises for non-encodable data" do
+      engine = Regolix.new!()
+
+      assert_raise Regolix.Error, ~r/json_error/, fn ->
+        Regolix.add_data!(engine, %{"pid" => self()})
+      end
+    end
+  end
+
+  describe "add_data_at_path/3" do
+    test "nests the value under the path" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_data_at_path!("tenants.acme.roles", ["admin"])
+        |> Regolix.add_data_at_path!("tenants.globex.roles", ["viewer"])
+
+      assert {:ok, ["admin"]} = Regolix.eval_query(engine, "data.tenants.acme.roles")
+      assert {:ok, ["viewer"]} = Regolix.eval_query(engine, "data.tenants.globex.roles")
+    end
+
+    test "rejects non-object values at the root" do
+      assert {:error, %Regolix.Error{type: :engine_error}} =
+               Regolix.add_data_at_path(Regolix.new!(), "", ["admin"])
+    end
+  end
+
+  describe "add_data_yaml/2" do
+    test "adds YAML data" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_data_yaml!("""
+        users:
+          alice:
+            role: admin
+        """)
+
+      assert {:ok, "admin"} = Regolix.eval_query(engine, "data.users.alice.role")
+    end
+
+    test "returns error for invalid YAML" do
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.add_data_yaml(Regolix.new!(), "users: [unclosed")
+    end
+  end
+
+  describe "add_data_from_file/2" do
+    @describetag :tmp_dir
+
+    test "loads JSON and YAML files", %{tmp_dir: tmp_dir} do
+      json_path = Path.join(tmp_dir, "users.json")
+      yaml_path = Path.join(tmp_dir, "config.yml")
+      File.write!(json_path, ~s({"users": {"bob": {"role": "viewer"}}}))
+      File.write!(yaml_path, "config:\n  enabled: true\n")
+
+      engine =
+        Regolix.new!()
+        |> Regolix.add_data_from_file!(json_path)
+        |> Regolix.add_data_from_file!(yaml_path)
+
+      assert {:ok, "viewer"} = Regolix.eval_query(engine, "data.users.bob.role")
+      assert {:ok, true} = Regolix.eval_query(engine, "data.config.enabled")
+    end
+
+    test "returns io_error for missing file", %{tmp_dir: tmp_dir} do
+      assert {:error, %Regolix.Error{type: :io_error}} =
+               Regolix.add_data_from_file(Regolix.new!(), Path.join(tmp_dir, "missing.yaml"))
+    end
+
+    test "rejects files nested too deeply", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "deep.json")
+      File.write!(path, ~s({"deep": #{String.duplicate("[", 101)}#{String.duplicate("]", 101)}}))
+
+      assert {:error, %Regolix.Error{type: :json_error, reason: :too_deep}} =
+               Regolix.add_data_from_file(Regolix.new!(), path)
+    end
+  end
+
+  describe "get_data/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_data!(%{"tenants" => %{"acme" => %{"roles" => ["admin", "viewer"]}}})
+
+      %{engine: engine}
+    end
+
+    test "returns the whole data document", %{engine: engine} do
+      assert {:ok, %{"tenants" => %{"acme" => _}}} = Regolix.get_data(engine)
+    end
+
+    test "returns the subtree at a dotted path", %{engine: engine} do
+      assert {:ok, %{"roles" => ["admin", "viewer"]}} = Regolix.get_data(engine, "tenants.acme")
+      assert {:ok, "viewer"} = Regolix.get_data(engine, "tenants.acme.roles.1")
+    end
+
+    test "returns :undefined for missing paths", %{engine: engine} do
+      assert {:ok, :undefined} = Regolix.get_data(engine, "tenants.globex")
+    end
+
+    test "returns empty map for new engine" do
+      assert Regolix.get_data!(Regolix.new!()) == %{}
+    end
+  end
+
+  describe "eval_query/2" do
+    test "evaluates a simple boolean rule" do
+      {:ok, engine} = Regolix.new()
+
+      {:ok, engine} =
+        Regolix.add_policy(engine, "test.rego", """
+        package test
+        default allow = false
+        allow if input.user == "admin"
+        """)
+
+      {:ok, engine} = Regolix.set_input(engine, %{"user" => "admin"})
+      assert {:ok, true} = Regolix.eval_query(engine, "data.test.allow")
+    end
+
+    test "returns false when rule doesn't match" do
+      {:ok, engine} = Regolix.new()
+
+      {:ok, engine} =
+        Regolix.add_policy(engine, "test.rego", """
+        package test
+        default allow = false
+        allow if input.user == "admin"
+        """)
+
+      {:ok, engine} = Regolix.set_input(engine, %{"user" => "guest"})
+      assert {:ok, false} = Regolix.eval_query(engine, "data.test.allow")
+    end
+
+    test "returns :undefined for non-existent rule" do
+      {:ok, engine} = Regolix.new()
+
+      {:ok, engine} =
+        Regolix.add_policy(engine, "test.rego", """
+        package test
+        """)
+
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.nonexistent")
+    end
+
+    test "returns complex data structures" do
+      {:ok, engine} = Regolix.new()
+
+      {:ok, engine} =
+        Regolix.add_policy(engine, "test.rego", """
+        package test
+        user := {"name": "alice", "roles": ["admin", "user"]}
+        """)
+
+      {:ok, result} = Regolix.eval_query(engine, "data.test.user")
+      assert result["name"] == "alice"
+      assert result["roles"] == ["admin", "user"]
+    end
+
+    test "verifies set_input actually sets input" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        username := input.user
+        """)
+        |> Regolix.set_input!(%{"user" => "alice"})
+
+      assert {:ok, "alice"} = Regolix.eval_query(engine, "data.test.username")
+    end
+
+    test "verifies add_data actually adds data" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        admin_role := data.users.alice.role
+        """)
+        |> Regolix.add_data!(%{"users" => %{"alice" => %{"role" => "admin"}}})
+
+      assert {:ok, "admin"} = Regolix.eval_query(engine, "data.test.admin_role")
+    end
+
+    test "returns error for invalid query" do
+      {:ok, engine} = Regolix.new()
+      assert {:error, %Regolix.Error{type: :eval_error}} = Regolix.eval_query(engine, "invalid[[")
+    end
+  end
+
+  describe "eval_query!/2" do
+    test "returns result directly" do
+      {:ok, engine} = Regolix.new()
+
+      {:ok, engine} =
+        Regolix.add_policy(engine, "test.rego", """
+        package test
+        result := 42
+        """)
+
+      assert 42 = Regolix.eval_query!(engine, "data.test.result")
+    end
+
+    test "raises on error" do
+      {:ok, engine} = Regolix.new()
+
+      assert_raise Regolix.Error, ~r/eval_error/, fn ->
+        Regolix.eval_query!(engine, "invalid[[")
+      end
+    end
+  end
+
+  describe "eval_query_json/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        users := [{"name": "alice", "roles": ["admin"]}]
+        names contains "alice"
+        """)
+
+      {:ok, engine: engine}
+    end
+
+    test "returns the result as a JSON binary", %{engine: engine} do
+      assert {:ok, json} = Regolix.eval_query_json(engine, "data.test.users")
+      assert Jason.decode!(json) == [%{"name" => "alice", "roles" => ["admin"]}]
+    end
+
+    test "encodes sets as arrays", %{engine: engine} do
+      assert Regolix.eval_query_json!(engine, "data.test.names") == ~s(["alice"])
+    end
+
+    test "returns :undefined for missing results", %{engine: engine} do
+      assert {:ok, :undefined} = Regolix.eval_query_json(engine, "data.test.missing")
+    end
+
+    test "returns eval errors", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_query_json(engine, "data.test.users[")
+    end
+  end
+
+  describe "eval_query_lazy/2 and result_get/2" do
+    setup do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "report.rego", """
+        package report
+        result := {
+          "violations": [{"msg": "image must be signed"}, {"msg": "no root"}],
+          "total": 2
+        }
+        """)
+
+      %{engine: engine}
+    end
+
+    test "converts only the requested path", %{engine: engine} do
+      assert {:ok, result} = Regolix.eval_query_lazy(engine, "data.report.result")
+
+      assert {:ok, "image must be signed"} = Regolix.result_get(result, "violations.0.msg")
+      assert {:ok, 2} = Regolix.result_get(result, "total")
+      assert {:ok, %{"msg" => "no root"}} = Regolix.result_get(result, "violations.1")
+      assert {:ok, :undefined} = Regolix.result_get(result, "violations.5.msg")
+      assert {:ok, %{"total" => 2}} = Regolix.result_get(result, "")
+    end
+
+    test "returns evaluation errors", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_query_lazy(engine, "no_such_function(1)")
+    end
+  end
+
+  describe "eval_query_params/3" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        allow if input.user == "alice"
+        """)
+
+      %{engine: engine}
+    end
+
+    test "binds parameters as values", %{engine: engine} do
+      query = "data.authz.allow with input.user as $user"
+
+      assert {:ok, true} = Regolix.eval_query_params(engine, query, %{"user" => "alice"})
+      assert {:ok, :undefined} = Regolix.eval_query_params(engine, query, %{"user" => "bob"})
+    end
+
+    test "never splices parameter text into the query", %{engine: engine} do
+      injected = ~s("alice"; true) <> "\n" <> ~s(x := "y)
+
+      assert {:ok, :undefined} =
+               Regolix.eval_query_params(engine, "data.authz.allow with input.user as $user", %{
+                 "user" => injected
+               })
+    end
+
+    test "leaves placeholders inside strings alone", %{engine: engine} do
+      assert {:ok, "$user"} = Regolix.eval_query_params(engine, ~s("$user"), %{"user" => "alice"})
+    end
+
+    test "returns error for a missing parameter", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error, message: message}} =
+               Regolix.eval_query_params(engine, "data.authz.allow with input.user as $user", %{})
+
+      assert message =~ "$user"
+    end
+  end
+
+  describe "eval_query_with_overrides/3" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_data!(%{"ldap" => %{"groups" => ["staff"]}})
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        admin if "admins" in data.ldap.groups
+        allow if {
+          admin
+          input.user == "alice"
+        }
+        """)
+
+      %{engine: engine}
+    end
+
+    test "replaces data and input for the call", %{engine: engine} do
+      overrides = %{"data.ldap.groups" => ["admins"], "input.user" => "alice"}
+
+      assert {:ok, true} = Regolix.eval_query_with_overrides(engine, "data.authz.allow", overrides)
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.authz.allow")
+    end
+
+    test "mocks a rule", %{engine: engine} do
+      overrides = %{"data.authz.admin" => true, "input.user" => "alice"}
+
+      assert {:ok, true} = Regolix.eval_query_with_overrides(engine, "data.authz.allow", overrides)
+    end
+
+    test "applies to every expression of the query", %{engine: engine} do
+      overrides = %{"data.ldap.groups" => ["admins", "ops"]}
+
+      query = "data.authz.admin\ncount(data.ldap.groups) == 2"
+
+      assert {:ok, true} = Regolix.eval_query_with_overrides(engine, query, overrides)
+    end
+
+    test "rejects paths outside input and data", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_query_with_overrides(engine, "data.authz.allow", %{"time.now_ns" => 0})
+
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_query_with_overrides(engine, "data.authz.allow", %{"input.a as 1; x" => 0})
+    end
+  end
+
+  describe "eval_query_with_input/3" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        default allow = false
+        allow if input.user == "admin"
+        """)
+
+      %{engine: engine}
+    end
+
+    test "evaluates against the given input", %{engine: engine} do
+      assert {:ok, true} =
+               Regolix.eval_query_with_input(engine, "data.test.allow", %{"user" => "admin"})
+
+      assert {:ok, false} =
+               Regolix.eval_query_with_input(engine, "data.test.allow", %{"user" => "guest"})
+    end
+
+    test "does not change the engine's input", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"user" => "guest"})
+      Regolix.eval_query_with_input!(engine, "data.test.allow", %{"user" => "admin"})
+
+      assert {:ok, false} = Regolix.eval_query(engine, "data.test.allow")
+    end
+
+    test "supports concurrent evaluation", %{engine: engine} do
+      results =
+        1..50
+        |> Task.async_stream(fn i ->
+          user = if rem(i, 2) == 0, do: "admin", else: "guest"
+          {user, Regolix.eval_query_with_input!(engine, "data.test.allow", %{"user" => user})}
+        end)
+        |> Enum.map(fn {:ok, result} -> result end)
+
+      assert Enum.all?(results, fn {user, allowed} -> allowed == (user == "admin") end)
+    end
+
+    test "returns error for non-encodable input", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.eval_query_with_input(engine, "data.test.allow", %{"pid" => self()})
+    end
+  end
+
+  describe "parse_input/1" do
+    test "reuses a parsed input across evaluations and engines" do
+      authz =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        allow if input.user == "admin"
+        """)
+
+      audit =
+        Regolix.add_policy!(Regolix.new!(), "audit.rego", """
+        package audit
+        path := input.path
+        """)
+
+      assert {:ok, input} = Regolix.parse_input(%{"user" => "admin", "path" => "/admin"})
+
+      assert {:ok, true} = Regolix.eval_query_with_input(authz, "data.authz.allow", input)
+      assert {:ok, true} = Regolix.eval_query_with_input(authz, "data.authz.allow", input)
+      assert Regolix.eval_query_with_input!(audit, "data.audit.path", input) == "/admin"
+      assert {:ok, :undefined} = Regolix.eval_query(authz, "input")
+    end
+
+    test "returns error for non-encodable input" do
+      assert {:error, %Regolix.Error{type: :json_error}} = Regolix.parse_input(%{"pid" => self()})
+    end
+  end
+
+  describe "eval_rule/2" do
+    test "evaluates a rule by path" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        default allow = false
+        allow if input.user == "admin"
+        """)
+        |> Regolix.set_input!(%{"user" => "admin"})
+
+      assert {:ok, true} = Regolix.eval_rule(engine, "data.test.allow")
+    end
+
+    test "returns :undefined for rule without value" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        allow if input.user == "admin"
+        """)
+        |> Regolix.set_input!(%{"user" => "guest"})
+
+      assert {:ok, :undefined} = Regolix.eval_rule(engine, "data.test.allow")
+    end
+
+    test "returns error for invalid rule path" do
+      engine = Regolix.new!()
+      assert {:error, %Regolix.Error{type: :eval_error}} = Regolix.eval_rule(engine, "not a path")
+    end
+  end
+
+  describe "eval_rule!/2" do
+    test "returns result directly" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        answer := 42
+        """)
+
+      assert Regolix.eval_rule!(engine, "data.test.answer") == 42
+    end
+  end
+
+  describe "eval_package/3" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        _admins := {"alice"}
+        allow if input.user in _admins
+        reason := "admin" if allow
+        test_allow if allow with input as {"user": "alice"}
+        """)
+        |> Regolix.set_input!(%{"user" => "alice"})
+
+      {:ok, engine: engine}
+    end
+
+    test "evaluates every rule of the package", %{engine: engine} do
+      assert {:ok, %{"allow" => true, "reason" => "admin", "test_allow" => true}} =
+               Regolix.eval_package(engine, "authz")
+
+      assert Regolix.eval_package!(engine, "data.authz") == Regolix.eval_package!(engine, "authz")
+    end
+
+    test "leaves out internal and test rules on request", %{engine: engine} do
+      assert Regolix.eval_package!(engine, "authz", exclude_internal: true) ==
+               %{"allow" => true, "reason" => "admin"}
+    end
+
+    test "returns :undefined for an unknown package", %{engine: engine} do
+      assert {:ok, :undefined} = Regolix.eval_package(engine, "missing")
+    end
+  end
+
+  describe "boolean decision queries" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        default allow = false
+        allow if input.user == "admin"
+        maybe if input.user == "admin"
+        deny contains "blocked" if input.blocked
+        name := "test"
+        """)
+
+      %{engine: engine}
+    end
+
+    test "eval_bool_query/2 returns the boolean", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"user" => "admin"})
+      assert {:ok, true} = Regolix.eval_bool_query(engine, "data.test.allow")
+    end
+
+    test "eval_bool_query/2 rejects non-boolean results", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_bool_query(engine, "data.test.name")
+    end
+
+    test "eval_allow_query/2 treats undefined as false", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"user" => "guest"})
+      assert {:ok, false} = Regolix.eval_allow_query(engine, "data.test.maybe")
+    end
+
+    test "eval_allow_query/2 rejects non-boolean results", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_allow_query(engine, "data.test.name")
+    end
+
+    test "eval_deny_query/2 is true for non-empty deny sets", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"blocked" => true})
+      assert Regolix.eval_deny_query!(engine, "data.test.deny") == true
+
+      engine = Regolix.set_input!(engine, %{"blocked" => false})
+      assert Regolix.eval_deny_query!(engine, "data.test.deny") == false
+    end
+  end
+
+  describe "eval_query_full/2" do
+    test "returns every result with bindings" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        roles := ["admin", "viewer"]
+        """)
+
+      {:ok, results} = Regolix.eval_query_full(engine, "x = data.test.roles[_]")
+
+      assert length(results) == 2
+      assert Enum.map(results, & &1.bindings["x"]) |> Enum.sort() == ["admin", "viewer"]
+
+      [%{expressions: [expression]} | _] = results
+      assert expression.value == true
+      assert expression.text == "x = data.test.roles[_]"
+    end
+
+    test "returns empty list when query has no results" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        roles := []
+        """)
+
+      assert {:ok, []} = Regolix.eval_query_full(engine, "x = data.test.roles[_]")
+    end
+
+    test "returns error for invalid query" do
+      engine = Regolix.new!()
+
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_query_full(engine, "invalid[[")
+    end
+  end
+
+  describe "eval_bindings/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        roles := ["admin", "viewer"]
+        """)
+
+      %{engine: engine}
+    end
+
+    test "returns the bindings of each result", %{engine: engine} do
+      {:ok, bindings} = Regolix.eval_bindings(engine, "x := data.test.roles[_]")
+
+      assert Enum.sort_by(bindings, & &1["x"]) == [%{"x" => "admin"}, %{"x" => "viewer"}]
+    end
+
+    test "returns empty list when query has no results", %{engine: engine} do
+      assert {:ok, []} = Regolix.eval_bindings(engine, "x := data.test.roles[_]; x == \"owner\"")
+    end
+
+    test "returns error for invalid query", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} = Regolix.eval_bindings(engine, "invalid[[")
+    end
+  end
+
+  describe "eval_query_full!/2" do
+    test "raises on error" do
+      engine = Regolix.new!()
+
+      assert_raise Regolix.Error, ~r/eval_error/, fn ->
+        Regolix.eval_query_full!(engine, "invalid[[")
+      end
+    end
+  end
+
+  describe "set_sets_mode/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        roles contains "admin"
+        roles contains "viewer"
+        list := ["admin", "viewer"]
+        """)
+
+      %{engine: engine}
+    end
+
+    test "returns sets as plain lists by default", %{engine: engine} do
+      assert {:ok, roles} = Regolix.eval_query(engine, "data.test.roles")
+      assert Enum.sort(roles) == ["admin", "viewer"]
+    end
+
+    test "returns tagged sets when enabled", %{engine: engine} do
+      assert {:ok, engine} = Regolix.set_sets_mode(engine, :tagged)
+
+      assert {:ok, {:set, roles}} = Regolix.eval_query(engine, "data.test.roles")
+      assert MapSet.new(roles) == MapSet.new(["admin", "viewer"])
+
+      # Arrays are unaffected
+      assert {:ok, ["admin", "viewer"]} = Regolix.eval_query(engine, "data.test.list")
+    end
+
+    test "can switch back to lists", %{engine: engine} do
+      engine =
+        engine
+        |> Regolix.set_sets_mode!(:tagged)
+        |> Regolix.set_sets_mode!(:list)
+
+      assert {:ok, roles} = Regolix.eval_query(engine, "data.test.roles")
+      assert is_list(roles)
+    end
+  end
+
+  describe "set_numbers_mode/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        big := 123456789012345678901234567890
+        rate := 0.5
+        """)
+
+      %{engine: engine}
+    end
+
+    test "returns integers beyond 64 bits as bignums", %{engine: engine} do
+      assert {:ok, 123_456_789_012_345_678_901_234_567_890} =
+               Regolix.eval_query(engine, "data.test.big")
+    end
+
+    test "returns computed integers beyond 64 bits as bignums" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        product := 1099511627776 * 1099511627776
+        negative := 0 - product
+        """)
+
+      assert {:ok, 1_208_925_819_614_629_174_706_176} =
+               Regolix.eval_query(engine, "data.test.product")
+
+      assert {:ok, -1_208_925_819_614_629_174_706_176} =
+               Regolix.eval_query(engine, "data.test.negative")
+    end
+
+    test "returns floats by default", %{engine: engine} do
+      assert {:ok, 0.5} = Regolix.eval_query(engine, "data.test.rate")
+    end
+
+    test "returns exact strings in :string mode", %{engine: engine} do
+      engine = Regolix.set_numbers_mode!(engine, :string)
+      assert {:ok, "0.5"} = Regolix.eval_query(engine, "data.test.rate")
+    end
+
+    test "returns decimal tuples in :decimal mode", %{engine: engine} do
+      engine = Regolix.set_numbers_mode!(engine, :decimal)
+      assert {:ok, {:decimal, "0.5"}} = Regolix.eval_query(engine, "data.test.rate")
+      # Integers are unaffected
+      assert {:ok, 123_456_789_012_345_678_901_234_567_890} =
+               Regolix.eval_query(engine, "data.test.big")
+    end
+  end
+
+  describe "set_keys_mode/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        decision := {"allowed": true, "regolix_unknown_key_7f3a": 1}
+        """)
+
+      %{engine: engine}
+    end
+
+    test "returns binary keys by default", %{engine: engine} do
+      assert {:ok, %{"allowed" => true}} = Regolix.eval_query(engine, "data.test.decision")
+    end
+
+    test "returns existing atoms in :existing_atoms mode", %{engine: engine} do
+      engine = Regolix.set_keys_mode!(engine, :existing_atoms)
+      _ = :allowed
+
+      assert {:ok, decision} = Regolix.eval_query(engine, "data.test.decision")
+      assert decision[:allowed] == true
+      # Unknown names stay binaries rather than creating atoms
+      assert decision["regolix_unknown_key_7f3a"] == 1
+    end
+  end
+
+  describe "set_undefined_mode/2" do
+    setup do
+      engine = Regolix.add_policy!(Regolix.new!(), "test.rego", "package test\nx := 1\n")
+      %{engine: engine}
+    end
+
+    test "returns :undefined by default", %{engine: engine} do
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.missing")
+    end
+
+    test "returns nil in nil mode", %{engine: engine} do
+      engine = Regolix.set_undefined_mode!(engine, nil)
+      assert {:ok, nil} = Regolix.eval_query(engine, "data.test.missing")
+      assert {:ok, nil} = Regolix.eval_rule(engine, "data.test.missing")
+    end
+
+    test "returns an error in :error mode", %{engine: engine} do
+      engine = Regolix.set_undefined_mode!(engine, :error)
+
+      assert {:error, %Regolix.Error{type: :undefined}} =
+               Regolix.eval_query(engine, "data.test.missing")
+
+      assert {:ok, [{:ok, 1}, {:error, %Regolix.Error{type: :undefined}}]} =
+               Regolix.eval_batch(engine, "input.x", [%{"x" => 1}, %{}])
+
+      assert Regolix.eval_query!(engine, "data.test.x") == 1
+    end
+  end
+
+  describe "set_max_result_depth/2" do
+    setup do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "test.rego", """
+        package test
+        nested := {"a": [{"b": {1, 2}}]}
+        """)
+
+      %{engine: engine}
+    end
+
+    test "returns results within the limit", %{engine: engine} do
+      engine = Regolix.set_max_result_depth!(engine, 4)
+      assert {:ok, %{"a" => [%{"b" => [1, 2]}]}} = Regolix.eval_query(engine, "data.test.nested")
+    end
+
+    test "returns an error for deeper results", %{engine: engine} do
+      engine = Regolix.set_max_result_depth!(engine, 3)
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.eval_query(engine, "data.test.nested")
+
+      assert {:ok, 1} = Regolix.eval_query(engine, "count(data.test.nested)")
+      engine = Regolix.set_max_result_depth!(engine, :infinity)
+      assert {:ok, %{"a" => _}} = Regolix.eval_query(engine, "data.test.nested")
+    end
+  end
+
+  describe "set_decode_opts/2" do
+    setup do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "test.rego", """
+        package test
+        roles := {"admin"}
+        rate := 0.1
+        """)
+
+      %{engine: engine}
+    end
+
+    test "sets several options at once", %{engine: engine} do
+      engine = Regolix.set_decode_opts!(engine, sets: :tagged, numbers: :string, undefined: nil)
+
+      assert {:ok, {:set, ["admin"]}} = Regolix.eval_query(engine, "data.test.roles")
+      assert {:ok, "0.1"} = Regolix.eval_query(engine, "data.test.rate")
+      assert {:ok, nil} = Regolix.eval_query(engine, "data.test.missing")
+    end
+
+    test "resets omitted options to their defaults", %{engine: engine} do
+      engine =
+        engine
+        |> Regolix.set_sets_mode!(:tagged)
+        |> Regolix.set_decode_opts!(undefined: :error)
+
+      assert {:ok, ["admin"]} = Regolix.eval_query(engine, "data.test.roles")
+
+      assert {:error, %Regolix.Error{type: :undefined}} =
+               Regolix.eval_query(engine, "data.test.missing")
+    end
+  end
+
+  describe "eval_query/3 with :decode" do
+    test "overrides the engine's decode options for one call" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        roles := {"admin"}
+        rate := 0.1
+        """)
+        |> Regolix.set_decode_opts!(sets: :tagged, undefined: :error)
+
+      assert {:ok, ["admin"]} =
+               Regolix.eval_query(engine, "data.test.roles", decode: [sets: :list])
+
+      assert {:ok, {:set, ["admin"]}} = Regolix.eval_query(engine, "data.test.roles")
+
+      assert {:ok, nil} =
+               Regolix.eval_query(engine, "data.test.missing", decode: [undefined: nil])
+
+      assert {:ok, "0.1"} =
+               Regolix.eval_query(engine, "data.test.rate",
+                 decode: [numbers: :string],
+                 timeout: 5_000
+               )
+
+      assert {:error, %Regolix.Error{type: :undefined}} =
+               Regolix.eval_query(engine, "data.test.missing")
+    end
+  end
+
+  describe "set_decision_cache/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        allow if data.admins[input.user]
+        """)
+        |> Regolix.add_data!(%{"admins" => %{"alice" => true}})
+        |> Regolix.set_decision_cache!(100)
+
+      %{engine: engine}
+    end
+
+    test "returns cached results for repeated inputs", %{engine: engine} do
+      input = %{"user" => "alice"}
+
+      assert {:ok, true} = Regolix.eval_query_with_input(engine, "data.authz.allow", input)
+      assert {:ok, true} = Regolix.eval_query_with_input(engine, "data.authz.allow", input)
+
+      assert {:ok, :undefined} =
+               Regolix.eval_query_with_input(engine, "data.authz.allow", %{"user" => "bob"})
+
+      assert {:ok, %{cache_hits: 1, cache_misses: 2, eval_count: 2}} = Regolix.stats(engine)
+    end
+
+    test "is invalidated when data or policies change", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"user" => "bob"})
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.authz.allow")
+
+      engine = Regolix.add_data!(engine, %{"admins" => %{"bob" => true}})
+      assert {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+
+      engine =
+        Regolix.add_policy!(engine, "authz.rego", """
+        package authz
+        allow := false
+        """)
+
+      assert {:ok, false} = Regolix.eval_query(engine, "data.authz.allow")
+    end
+
+    test "can be cleared and turned off", %{engine: engine} do
+      Regolix.eval_query!(engine, "data.authz.allow")
+      assert {:ok, engine} = Regolix.clear_decision_cache(engine)
+      Regolix.eval_query!(engine, "data.authz.allow")
+      assert {:ok, %{cache_hits: 0}} = Regolix.stats(engine)
+
+      engine = Regolix.set_decision_cache!(engine, 0)
+      Regolix.eval_query!(engine, "data.authz.allow")
+      assert {:ok, %{cache_hits: 0, eval_count: 3}} = Regolix.stats(engine)
+    end
+  end
+
+  describe "eval_query/3" do
+    @slow_query "count([1 | r := numbers.range(1, 300); r[_]; r[_]; r[_]])"
+
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        allow if input.user == "admin"
+        """)
+        |> Regolix.set_input!(%{"user" => "admin"})
+
+      %{engine: engine}
+    end
+
+    test "returns the result within the budget", %{engine: engine} do
+      assert {:ok, true} = Regolix.eval_query(engine, "data.test.allow", timeout: 5_000)
+    end
+
+    test "returns a timeout error when the budget runs out", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :timeout}} =
+               Regolix.eval_query(engine, @slow_query, timeout: 20)
+    end
+
+    test "returns a cancelled error for a tripped token", %{engine: engine} do
+      token = Regolix.cancel_token()
+      refute Regolix.cancelled?(token)
+
+      assert :ok = Regolix.cancel(token)
+      assert Regolix.cancelled?(token)
+
+      assert {:error, %Regolix.Error{type: :cancelled}} =
+               Regolix.eval_query(engine, "data.test.allow", cancel: token)
+    end
+
+    test "can be cancelled from another process", %{engine: engine} do
+      token = Regolix.cancel_token()
+      task = Task.async(fn -> Regolix.eval_query(engine, @slow_query, cancel: token) end)
+
+      Process.sleep(20)
+      Regolix.cancel(token)
+
+      assert {:error, %Regolix.Error{type: :cancelled}} = Task.await(task)
+    end
+
+    test "extracts part of the result by dotted path or JSON Pointer" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("report.rego", """
+        package report
+        violations := [{"msg": "a"}, {"msg": "b"}]
+        total := 2
+        """)
+
+      assert {:ok, [%{"msg" => "a"}, %{"msg" => "b"}]} =
+               Regolix.eval_query(engine, "data.report", extract: "violations")
+
+      assert {:ok, "b"} = Regolix.eval_query(engine, "data.report", extract: "/violations/1/msg")
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.report", extract: "missing")
+
+      assert {:ok, 2} =
+               Regolix.eval_query(engine, "data.report", extract: "total", timeout: 5_000)
+    end
+  end
+
+  describe "eval_query_traced/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+
+        allow if {
+          print("checking role", input.role)
+          input.role == "admin"
+        }
+        """)
+
+      %{engine: engine}
+    end
+
+    test "returns print output with the result", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"role" => "viewer"})
+
+      assert {:ok, %{result: :undefined, prints: [line]}} =
+               Regolix.eval_query_traced(engine, "data.authz.allow")
+
+      assert line =~ "checking role viewer"
+      assert line =~ "authz.rego"
+    end
+
+    test "returns no prints when none run", %{engine: engine} do
+      assert %{result: 3, prints: []} = Regolix.eval_query_traced!(engine, "1 + 2")
+    end
+
+    test "returns error for invalid query", %{engine: engine} do
+      assert {:error, %Regolix.Error{}} = Regolix.eval_query_traced(engine, "data.authz.allow ==")
+    end
+  end
+
+  describe "eval_query_with_metrics/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        allow if is_admin
+        is_admin if input.user == "admin"
+        """)
+        |> Regolix.set_input!(%{"user" => "admin"})
+
+      %{engine: engine}
+    end
+
+    test "returns the result with timings", %{engine: engine} do
+      assert {:ok, true, metrics} = Regolix.eval_query_with_metrics(engine, "data.authz.allow")
+
+      assert %{
+               lock_wait_ns: lock_wait,
+               eval_ns: eval,
+               decode_ns: decode,
+               result_count: 1,
+               expression_count: 1,
+               rule_lookup: true,
+               strict_builtin_errors: false
+             } = metrics
+
+      assert Enum.all?([lock_wait, eval, decode], &(is_integer(&1) and &1 >= 0))
+    end
+
+    test "counts every result of a general query", %{engine: engine} do
+      assert {:ok, _, %{result_count: 3, rule_lookup: false}} =
+               Regolix.eval_query_with_metrics(engine, "x := [1, 2, 3][_]")
+    end
+
+    test "counts no results for undefined", %{engine: engine} do
+      assert {:ok, :undefined, %{result_count: 0}} =
+               Regolix.eval_query_with_metrics(engine, "data.authz.missing")
+    end
+  end
+
+  describe "new_pool/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        default allow := false
+        allow if input.user == data.admin
+        """)
+        |> Regolix.add_data!(%{"admin" => "alice"})
+
+      %{engine: engine}
+    end
+
+    test "evaluates with per-call input", %{engine: engine} do
+      pool = Regolix.new_pool!(engine, 2)
+      assert Regolix.pool_size(pool) == 2
+
+      assert {:ok, true} = Regolix.pool_eval(pool, "data.authz.allow", %{"user" => "alice"})
+      assert {:ok, false} = Regolix.pool_eval(pool, "data.authz.allow", %{"user" => "bob"})
+    end
+
+    test "serves concurrent callers", %{engine: engine} do
+      pool = Regolix.new_pool!(engine, 4)
+
+      results =
+        1..50
+        |> Task.async_stream(fn i ->
+          user = if rem(i, 2) == 0, do: "alice", else: "bob"
+          {rem(i, 2) == 0, Regolix.pool_eval!(pool, "data.authz.allow", %{"user" => user})}
+        end)
+        |> Enum.map(fn {:ok, result} -> result end)
+
+      assert Enum.all?(results, fn {expected, actual} -> expected == actual end)
+    end
+
+    test "is a snapshot of the engine", %{engine: engine} do
+      pool = Regolix.new_pool!(engine, 1)
+      Regolix.clear_data!(engine)
+
+      assert {:ok, true} = Regolix.pool_eval(pool, "data.authz.allow", %{"user" => "alice"})
+    end
+  end
+
+  describe "eval_async/3" do
+    setup do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "test.rego", """
+        package test
+        allow if input.user == "admin"
+        """)
+
+      %{engine: engine}
+    end
+
+    test "delivers the result as a message", %{engine: engine} do
+      assert {:ok, ref} = Regolix.eval_async(engine, "data.test.allow", %{"user" => "admin"})
+      assert_receive {^ref, {:ok, true}}
+    end
+
+    test "await/2 converts errors", %{engine: engine} do
+      {:ok, ref} = Regolix.eval_async(engine, "invalid[[", %{})
+      assert {:error, %Regolix.Error{type: :eval_error}} = Regolix.await(ref)
+    end
+
+    test "leaves the engine's input alone", %{engine: engine} do
+      {:ok, ref} = Regolix.eval_async(engine, "data.test.allow", %{"user" => "admin"})
+      assert {:ok, true} = Regolix.await(ref)
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.allow")
+    end
+  end
+
+  describe "eval_batch/3" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        default allow := false
+        allow if input.user == "admin"
+        """)
+
+      %{engine: engine}
+    end
+
+    test "evaluates each input in order", %{engine: engine} do
+      inputs = [%{"user" => "admin"}, %{"user" => "guest"}, %{"user" => "admin"}]
+
+      assert {:ok, [{:ok, true}, {:ok, false}, {:ok, true}]} =
+               Regolix.eval_batch(engine, "data.authz.allow", inputs)
+    end
+
+    test "works with arbitrary queries", %{engine: engine} do
+      assert {:ok, [{:ok, 2}, {:ok, 3}]} =
+               Regolix.eval_batch(engine, "input.n + 1", [%{"n" => 1}, %{"n" => 2}])
+    end
+
+    test "returns an empty list for no inputs", %{engine: engine} do
+      assert {:ok, []} = Regolix.eval_batch(engine, "data.authz.allow", [])
+    end
+
+    test "does not change the engine's input", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"user" => "admin"})
+
+      assert [{:ok, false}] = Regolix.eval_batch!(engine, "data.authz.allow", [%{"user" => "guest"}])
+      assert {:ok, true} = Regolix.eval_query(engine, "data.authz.allow")
+    end
+
+    test "reports per-input errors", %{engine: engine} do
+      assert {:ok, [{:error, %Regolix.Error{}}]} =
+               Regolix.eval_batch(engine, "data.authz.allow ==", [%{"user" => "admin"}])
+    end
+
+    test "returns error for unencodable input", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.eval_batch(engine, "data.authz.allow", [%{"pid" => self()}])
+    end
+  end
+
+  describe "clear_query_cache/1" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        allow if input.user == "admin"
+        """)
+
+      %{engine: engine}
+    end
+
+    test "cached rule queries follow input changes", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"user" => "admin"})
+      assert {:ok, true} = Regolix.eval_query(engine, "data.test.allow")
+
+      engine = Regolix.set_input!(engine, %{"user" => "guest"})
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.allow")
+    end
+
+    test "cached rule queries are dropped when the policy is removed", %{engine: engine} do
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.allow")
+
+      {:ok, engine} = Regolix.remove_policy(engine, "test.rego")
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.allow")
+    end
+
+    test "clears the cache", %{engine: engine} do
+      engine = Regolix.set_input!(engine, %{"user" => "admin"})
+      assert {:ok, true} = Regolix.eval_query(engine, "data.test.allow")
+
+      assert {:ok, ^engine} = Regolix.clear_query_cache(engine)
+      assert {:ok, true} = Regolix.eval_query(engine, "data.test.allow")
+    end
+  end
+
+  describe "clear_data/1" do
+    test "clears data but keeps policies" do
+      {:ok, engine} = Regolix.new()
+
+      {:ok, engine} =
+        Regolix.add_policy(engine, "test.rego", """
+        package test
+        result := data.config.value
+        """)
+
+      {:ok, engine} = Regolix.add_data(engine, %{"config" => %{"value" => 42}})
+      assert {:ok, 42} = Regolix.eval_query(engine, "data.test.result")
+
+      {:ok, engine} = Regolix.clear_data(engine)
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.result")
+
+      # Verify policy still works after clear
+      assert "data.test" in Regolix.get_packages(engine)
+    end
+  end
+
+  describe "transaction/2" do
+    setup do
+      engine = Regolix.add_data!(Regolix.new!(), %{"users" => %{"alice" => "admin"}, "version" => 1})
+      %{engine: engine}
+    end
+
+    test "applies every change together", %{engine: engine} do
+      assert {:ok, ^engine} =
+               Regolix.transaction(engine, fn txn ->
+                 {:ok, txn} = Regolix.txn_remove_data_path(txn, "users")
+                 {:ok, txn} = Regolix.txn_add_data_at_path(txn, "users.bob", "viewer")
+
+                 # Nothing is visible before the commit
+                 assert {:ok, "admin"} = Regolix.eval_query(engine, "data.users.alice")
+
+                 Regolix.txn_add_data(txn, %{"refreshed" => true})
+               end)
+
+      assert {:ok, %{"bob" => "viewer"}} = Regolix.eval_query(engine, "data.users")
+      assert {:ok, true} = Regolix.eval_query(engine, "data.refreshed")
+    end
+
+    test "drops the changes when the function returns an error", %{engine: engine} do
+      assert {:error, :stale} =
+               Regolix.transaction(engine, fn txn ->
+                 {:ok, _txn} = Regolix.txn_remove_data_path(txn, "users")
+                 {:error, :stale}
+               end)
+
+      assert {:ok, "admin"} = Regolix.eval_query(engine, "data.users.alice")
+    end
+
+    test "applies nothing when a change conflicts", %{engine: engine} do
+      assert {:error, %Regolix.Error{}} =
+               Regolix.transaction(engine, fn txn ->
+                 {:ok, txn} = Regolix.txn_add_data(txn, %{"added" => true})
+                 Regolix.txn_add_data(txn, %{"version" => 2})
+               end)
+
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.added")
+      assert {:ok, 1} = Regolix.eval_query(engine, "data.version")
+    end
+
+    test "can't be reused once finished", %{engine: engine} do
+      {:ok, txn} = Regolix.txn_begin(engine)
+      assert {:ok, ^engine} = Regolix.txn_commit(txn)
+      assert {:error, %Regolix.Error{type: :engine_error}} = Regolix.txn_add_data(txn, %{})
+    end
+  end
+
+  describe "remove_data_path/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_data!(%{
+          "tenants" => %{
+            "acme" => %{"plan" => "pro"},
+            "globex" => %{"plan" => "free"}
+          }
+        })
+
+      %{engine: engine}
+    end
+
+    test "removes only the subtree", %{engine: engine} do
+      assert {:ok, engine} = Regolix.remove_data_path(engine, "tenants.acme")
+
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.tenants.acme")
+      assert {:ok, "free"} = Regolix.eval_query(engine, "data.tenants.globex.plan")
+    end
+
+    test "is a no-op for missing paths", %{engine: engine} do
+      assert {:ok, engine} = Regolix.remove_data_path(engine, "tenants.initech")
+      assert {:ok, "pro"} = Regolix.eval_query(engine, "data.tenants.acme.plan")
+    end
+
+    test "rejects the empty path", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :engine_error}} = Regolix.remove_data_path(engine, "")
+    end
+  end
+
+  describe "patch_data/2" do
+    setup do
+      engine =
+        Regolix.add_data!(Regolix.new!(), %{
+          "tenants" => %{"acme" => %{"plan" => "pro", "tags" => ["a"]}}
+        })
+
+      %{engine: engine}
+    end
+
+    test "applies JSON Patch operations in order", %{engine: engine} do
+      assert {:ok, engine} =
+               Regolix.patch_data(engine, [
+                 %{"op" => "test", "path" => "/tenants/acme/plan", "value" => "pro"},
+                 %{"op" => "replace", "path" => "/tenants/acme/plan", "value" => "enterprise"},
+                 %{"op" => "add", "path" => "/tenants/acme/tags/-", "value" => "b"},
+                 %{"op" => "copy", "from" => "/tenants/acme", "path" => "/tenants/globex"},
+                 %{"op" => "remove", "path" => "/tenants/globex/tags"}
+               ])
+
+      assert Regolix.get_data(engine, "tenants") ==
+               {:ok,
+                %{
+                  "acme" => %{"plan" => "enterprise", "tags" => ["a", "b"]},
+                  "globex" => %{"plan" => "enterprise"}
+                }}
+    end
+
+    test "applies nothing when an operation fails", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :engine_error}} =
+               Regolix.patch_data(engine, [
+                 %{"op" => "replace", "path" => "/tenants/acme/plan", "value" => "free"},
+                 %{"op" => "remove", "path" => "/tenants/initech"}
+               ])
+
+      assert {:ok, "pro"} = Regolix.eval_query(engine, "data.tenants.acme.plan")
+    end
+
+    test "accepts delta bundle patches with upsert", %{engine: engine} do
+      patch = %{"data" => [%{"op" => "upsert", "path" => "/tenants/initech/plan", "value" => "free"}]}
+
+      assert {:ok, engine} = Regolix.patch_data(engine, patch)
+      assert {:ok, "free"} = Regolix.eval_query(engine, "data.tenants.initech.plan")
+
+      assert {:error, %Regolix.Error{type: :json_error}} =
+               Regolix.patch_data(engine, patch["data"])
+    end
+  end
+
+  describe "clear_data!/1" do
+    test "returns engine directly" do
+      engine = Regolix.new!()
+      engine = Regolix.clear_data!(engine)
+      assert is_reference(engine)
+    end
+  end
+
+  describe "set_limits/2" do
+    test "rejects input over the byte limit" do
+      engine = Regolix.new!() |> Regolix.set_limits!(max_bytes: 20)
+
+      assert {:ok, _} = Regolix.set_input(engine, %{"a" => 1})
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.set_input(engine, %{"a" => String.duplicate("x", 50)})
+    end
+
+    test "rejects data nested too deeply" do
+      engine = Regolix.new!() |> Regolix.set_limits!(max_depth: 2)
+
+      assert {:ok, _} = Regolix.add_data(engine, %{"a" => %{"b" => 1}})
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.add_data(engine, %{"a" => %{"b" => %{"c" => 1}}})
+    end
+
+    test "rejects collections with too many entries" do
+      engine = Regolix.new!() |> Regolix.set_limits!(max_entries: 3)
+
+      assert {:ok, _} = Regolix.add_data(engine, %{"items" => [1, 2, 3]})
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.add_data(engine, %{"items" => [1, 2, 3, 4]})
+    end
+
+    test "applies to per-call input" do
+      engine = Regolix.new!() |> Regolix.set_limits!(max_entries: 1)
+
+      assert {:error, %Regolix.Error{type: :limit_exceeded}} =
+               Regolix.eval_query_with_input(engine, "input", %{"a" => 1, "b" => 2})
+    end
+
+    test "later calls replace earlier limits" do
+      engine =
+        Regolix.new!()
+        |> Regolix.set_limits!(max_entries: 1)
+        |> Regolix.set_limits!([])
+
+      assert {:ok, _} = Regolix.add_data(engine, %{"items" => [1, 2, 3]})
+    end
+  end
+
+  describe "Regolix.HTTP.builtin/1" do
+    test "converts the handler's response to OPA's response object" do
+      send = Regolix.HTTP.builtin(fn %{"url" => "https://example.com/users"} ->
+        {:ok, %{status: 200, headers: %{"content-type" => "application/json"}, body: ~s({"id": 1})}}
+      end)
+
+      assert send.(%{"method" => "GET", "url" => "https://example.com/users"}) == %{
+               "status_code" => 200,
+               "status" => "200",
+               "headers" => %{"content-type" => "application/json"},
+               "body" => %{"id" => 1},
+               "raw_body" => ~s({"id": 1})
+             }
+    end
+
+    test "reports failures in the response when raise_error is false" do
+      send = Regolix.HTTP.builtin(fn _request -> {:error, :econnrefused} end)
+
+      assert %{"status_code" => 0, "error" => %{"message" => ":econnrefused"}} =
+               send.(%{"url" => "https://example.com", "raise_error" => false})
+
+      assert_raise RuntimeError, ~r/econnrefused/, fn -> send.(%{"url" => "https://example.com"}) end
+    end
+  end
+
+  describe "set_time/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("hours.rego", """
+        package hours
+        now := time.now_ns()
+        business_hours if {
+          [hour, _, _] := time.clock(time.now_ns())
+          hour >= 9
+          hour < 17
+        }
+        """)
+
+      %{engine: engine}
+    end
+
+    # Monday 2024-01-15 10:00 UTC and 20:00 UTC
+    @morning 1_705_312_800_000_000_000
+    @evening 1_705_348_800_000_000_000
+
+    test "fixes the time seen by policies", %{engine: engine} do
+      engine = Regolix.set_time!(engine, @morning)
+      assert {:ok, @morning} = Regolix.eval_query(engine, "data.hours.now")
+      assert {:ok, true} = Regolix.eval_query(engine, "data.hours.business_hours")
+
+      engine = Regolix.set_time!(engine, @evening)
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.hours.business_hours")
+    end
+
+    test "applies to policies added later", %{engine: engine} do
+      engine = Regolix.set_time!(engine, @morning)
+
+      Regolix.add_policy!(engine, "later.rego", "package later\nnow := time.now_ns()")
+      assert {:ok, @morning} = Regolix.eval_query(engine, "data.later.now")
+    end
+
+    test "shifts the system time by an offset", %{engine: engine} do
+      day = 86_400 * 1_000_000_000
+      engine = Regolix.set_time!(engine, {:offset, day})
+
+      {:ok, now} = Regolix.eval_query(engine, "data.hours.now")
+      assert now > System.os_time(:nanosecond) + day - 60 * 1_000_000_000
+    end
+
+    test "goes back to the system clock", %{engine: engine} do
+      engine = engine |> Regolix.set_time!(@morning) |> Regolix.set_time!(:system)
+
+      {:ok, now} = Regolix.eval_query(engine, "data.hours.now")
+      assert now > @morning
+    end
+  end
+
+  describe "set_random_seed/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("sample.rego", """
+        package sample
+        bucket := rand.intn("bucket", 100)
+        id := uuid.rfc4122("request")
+        """)
+
+      %{engine: engine}
+    end
+
+    test "gives the same results for the same seed", %{engine: engine} do
+      {:ok, other} = Regolix.clone(engine)
+
+      engine = Regolix.set_random_seed!(engine, 42)
+      other = Regolix.set_random_seed!(other, 42)
+
+      {:ok, bucket} = Regolix.eval_query(engine, "data.sample.bucket")
+      assert bucket in 0..99
+      assert {:ok, ^bucket} = Regolix.eval_query(other, "data.sample.bucket")
+
+      {:ok, id} = Regolix.eval_query(engine, "data.sample.id")
+      assert id =~ ~r/^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$/
+      assert {:ok, ^id} = Regolix.eval_query(other, "data.sample.id")
+    end
+
+    test "gives different results for different seeds", %{engine: engine} do
+      {:ok, other} = Regolix.clone(engine)
+
+      {:ok, a} = engine |> Regolix.set_random_seed!(1) |> Regolix.eval_query("data.sample.id")
+      {:ok, b} = other |> Regolix.set_random_seed!(2) |> Regolix.eval_query("data.sample.id")
 
-      assert {:error, %Regolix.Error{type: :json_error}} =
-               Regolix.add_data(engine, %{"pid" => self()})
+      assert a != b
     end
   end
 
-  describe "add_data!/2" do
-    test "returns engine directly" do
-      engine = Regolix.new!()
-      engine = Regolix.add_data!(engine, %{"config" => %{"enabled" => true}})
-      assert is_reference(engine)
+  describe "set_runtime/2" do
+    test "makes opa.runtime() return the given document" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("deploy.rego", """
+        package deploy
+        production if opa.runtime().env.DEPLOY_ENV == "production"
+        region := opa.runtime().config.labels.region
+        """)
+        |> Regolix.set_runtime!(%{
+          "env" => %{"DEPLOY_ENV" => "production"},
+          "config" => %{"labels" => %{"region" => "eu-west-1"}}
+        })
+
+      assert {:ok, true} = Regolix.eval_query(engine, "data.deploy.production")
+      assert {:ok, "eu-west-1"} = Regolix.eval_query(engine, "data.deploy.region")
     end
 
-    test "raises for non-encodable data" do
-      engine = Regolix.new!()
+    test "goes back to the default with nil" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("deploy.rego", """
+        package deploy
+        env := opa.runtime().env.DEPLOY_ENV
+        """)
+        |> Regolix.set_runtime!(%{"env" => %{"DEPLOY_ENV" => "staging"}})
+        |> Regolix.set_runtime!(nil)
 
-      assert_raise Regolix.Error, ~r/json_error/, fn ->
-        Regolix.add_data!(engine, %{"pid" => self()})
-      end
+      refute match?({:ok, "staging"}, Regolix.eval_query(engine, "data.deploy.env"))
     end
   end
 
-  describe "eval_query/2" do
-    test "evaluates a simple boolean rule" do
-      {:ok, engine} = Regolix.new()
+  describe "disable_builtins/2" do
+    test "rejects policies that call a disabled builtin" do
+      engine = Regolix.disable_builtins!(Regolix.new!(), ["http.send", "opa.runtime"])
 
-      {:ok, engine} =
-        Regolix.add_policy(engine, "test.rego", """
-        package test
-        default allow = false
-        allow if input.user == "admin"
-        """)
+      assert {:error, %Regolix.Error{type: :builtin_disabled, file: "net.rego", line: 3}} =
+               Regolix.add_policy(engine, "net.rego", """
+               package net
+               response := r if {
+                 r := http.send({"method": "GET", "url": "https://example.com"})
+               }
+               """)
 
-      {:ok, engine} = Regolix.set_input(engine, %{"user" => "admin"})
-      assert {:ok, true} = Regolix.eval_query(engine, "data.test.allow")
+      assert Regolix.get_packages(engine) == []
     end
 
-    test "returns false when rule doesn't match" do
-      {:ok, engine} = Regolix.new()
-
-      {:ok, engine} =
-        Regolix.add_policy(engine, "test.rego", """
-        package test
-        default allow = false
-        allow if input.user == "admin"
-        """)
+    test "finds calls after a multi-line raw string" do
+      engine = Regolix.disable_builtins!(Regolix.new!(), ["http.send"])
 
-      {:ok, engine} = Regolix.set_input(engine, %{"user" => "guest"})
-      assert {:ok, false} = Regolix.eval_query(engine, "data.test.allow")
+      assert {:error, %Regolix.Error{type: :builtin_disabled, line: 4}} =
+               Regolix.add_policy(engine, "raw.rego", """
+               package raw
+               x := `a
+               `
+               r := http.send({"method": "GET", "url": "https://example.com"})
+               """)
     end
 
-    test "returns :undefined for non-existent rule" do
-      {:ok, engine} = Regolix.new()
+    test "allows policies that only mention the name" do
+      engine = Regolix.disable_builtins!(Regolix.new!(), ["http.send"])
 
-      {:ok, engine} =
-        Regolix.add_policy(engine, "test.rego", """
-        package test
+      assert {:ok, _} =
+               Regolix.add_policy(engine, "ok.rego", """
+               package ok
+               note := "never calls http.send"
+               """)
+    end
+
+    test "fails without disabling anything when a loaded policy calls one" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("env.rego", """
+        package env
+        env := opa.runtime().env
         """)
 
-      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.nonexistent")
-    end
+      assert {:error, %Regolix.Error{type: :builtin_disabled, line: 2}} =
+               Regolix.disable_builtins(engine, ["opa.runtime"])
 
-    test "returns complex data structures" do
-      {:ok, engine} = Regolix.new()
+      assert {:ok, _} =
+               Regolix.add_policy(engine, "more.rego", """
+               package more
+               env := opa.runtime().env
+               """)
+    end
 
-      {:ok, engine} =
-        Regolix.add_policy(engine, "test.rego", """
-        package test
-        user := {"name": "alice", "roles": ["admin", "user"]}
-        """)
+    test "carries over to clones" do
+      engine = Regolix.disable_builtins!(Regolix.new!(), ["time.now_ns"])
+      {:ok, clone} = Regolix.clone(engine)
 
-      {:ok, result} = Regolix.eval_query(engine, "data.test.user")
-      assert result["name"] == "alice"
-      assert result["roles"] == ["admin", "user"]
+      assert {:error, %Regolix.Error{type: :builtin_disabled}} =
+               Regolix.add_policy(clone, "t.rego", "package t\nnow := time.now_ns()")
     end
+  end
 
-    test "verifies set_input actually sets input" do
+  describe "builtin_usage/1" do
+    test "lists builtins called by each policy, leaving out policy functions" do
       engine =
         Regolix.new!()
-        |> Regolix.add_policy!("test.rego", """
-        package test
-        username := input.user
+        |> Regolix.add_policy!("lib.rego", """
+        package lib
+        is_admin(user) if startswith(user, "admin-")
         """)
-        |> Regolix.set_input!(%{"user" => "alice"})
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        import data.lib
 
-      assert {:ok, "alice"} = Regolix.eval_query(engine, "data.test.username")
+        allow if {
+          lib.is_admin(input.user)
+          count(input.roles) > 0
+        }
+
+        token := io.jwt.decode(input.token)
+        remote := http.send({"method": "GET", "url": "https://example.com"})
+        """)
+
+      assert {:ok, usage} = Regolix.builtin_usage(engine)
+      assert usage["lib.rego"] == ["startswith"]
+      assert usage["authz.rego"] == ["count", "http.send", "io.jwt.decode"]
     end
 
-    test "verifies add_data actually adds data" do
+    test "reports an empty list for policies without calls" do
+      engine = Regolix.add_policy!(Regolix.new!(), "p.rego", "package p\nx := 1")
+      assert Regolix.builtin_usage(engine) == {:ok, %{"p.rego" => []}}
+    end
+  end
+
+  describe "set_strict_builtin_errors/2" do
+    setup do
       engine =
         Regolix.new!()
         |> Regolix.add_policy!("test.rego", """
         package test
-        admin_role := data.users.alice.role
+        value := to_number("not a number")
         """)
-        |> Regolix.add_data!(%{"users" => %{"alice" => %{"role" => "admin"}}})
 
-      assert {:ok, "admin"} = Regolix.eval_query(engine, "data.test.admin_role")
+      %{engine: engine}
     end
 
-    test "returns error for invalid query" do
-      {:ok, engine} = Regolix.new()
-      assert {:error, %Regolix.Error{type: :eval_error}} = Regolix.eval_query(engine, "invalid[[")
+    test "builtin errors are undefined by default", %{engine: engine} do
+      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.value")
     end
-  end
-
-  describe "eval_query!/2" do
-    test "returns result directly" do
-      {:ok, engine} = Regolix.new()
 
-      {:ok, engine} =
-        Regolix.add_policy(engine, "test.rego", """
-        package test
-        result := 42
-        """)
+    test "builtin errors fail evaluation in strict mode", %{engine: engine} do
+      assert {:ok, engine} = Regolix.set_strict_builtin_errors(engine, true)
 
-      assert 42 = Regolix.eval_query!(engine, "data.test.result")
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_query(engine, "data.test.value")
     end
 
-    test "raises on error" do
-      {:ok, engine} = Regolix.new()
+    test "strict mode survives removing a policy", %{engine: engine} do
+      engine =
+        engine
+        |> Regolix.set_strict_builtin_errors!(true)
+        |> Regolix.add_policy!("other.rego", "package other")
+        |> Regolix.remove_policy!("other.rego")
 
-      assert_raise Regolix.Error, ~r/eval_error/, fn ->
-        Regolix.eval_query!(engine, "invalid[[")
-      end
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_query(engine, "data.test.value")
     end
   end
 
-  describe "clear_data/1" do
-    test "clears data but keeps policies" do
-      {:ok, engine} = Regolix.new()
+  describe "set_rego_version/2" do
+    @v0_policy """
+    package legacy
 
-      {:ok, engine} =
-        Regolix.add_policy(engine, "test.rego", """
-        package test
-        result := data.config.value
-        """)
+    default allow = false
 
-      {:ok, engine} = Regolix.add_data(engine, %{"config" => %{"value" => 42}})
-      assert {:ok, 42} = Regolix.eval_query(engine, "data.test.result")
+    allow {
+      input.user == "admin"
+    }
+    """
 
-      {:ok, engine} = Regolix.clear_data(engine)
-      assert {:ok, :undefined} = Regolix.eval_query(engine, "data.test.result")
+    test "rejects v0 syntax by default" do
+      engine = Regolix.new!()
 
-      # Verify policy still works after clear
-      assert "data.test" in Regolix.get_packages(engine)
+      assert {:error, %Regolix.Error{type: :parse_error}} =
+               Regolix.add_policy(engine, "legacy.rego", @v0_policy)
     end
-  end
 
-  describe "clear_data!/1" do
-    test "returns engine directly" do
-      engine = Regolix.new!()
-      engine = Regolix.clear_data!(engine)
-      assert is_reference(engine)
+    test "accepts v0 syntax when enabled" do
+      assert {:ok, engine} = Regolix.set_rego_version(Regolix.new!(), :v0)
+
+      engine =
+        engine
+        |> Regolix.add_policy!("legacy.rego", @v0_policy)
+        |> Regolix.set_input!(%{"user" => "admin"})
+
+      assert {:ok, true} = Regolix.eval_query(engine, "data.legacy.allow")
     end
   end
 
@@ -360,6 +2964,130 @@ defmodule RegolixTest do
     end
   end
 
+  describe "get_coverage_pretty/1" do
+    test "renders covered policy source as text" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        allow := true
+        """)
+        |> Regolix.enable_coverage!()
+
+      Regolix.eval_query!(engine, "data.test.allow")
+
+      assert {:ok, text} = Regolix.get_coverage_pretty(engine)
+      assert text =~ "test.rego"
+      assert text =~ "allow := true"
+    end
+  end
+
+  describe "merge_coverage/1" do
+    test "a line is covered if any report covered it" do
+      a = %{"a.rego" => %{covered: [3], not_covered: [5, 7]}}
+      b = %{"a.rego" => %{covered: [5], not_covered: [3, 7]}, "b.rego" => %{covered: [2], not_covered: []}}
+
+      assert {:ok, merged} = Regolix.merge_coverage([a, b])
+
+      assert merged == %{
+               "a.rego" => %{covered: [3, 5], not_covered: [7]},
+               "b.rego" => %{covered: [2], not_covered: []}
+             }
+    end
+
+    test "merges coverage straight from engines" do
+      policy = """
+      package test
+
+      allow if input.admin
+
+      deny if input.banned
+      """
+
+      engine_a =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", policy)
+        |> Regolix.enable_coverage!()
+        |> Regolix.set_input!(%{"admin" => true})
+
+      engine_b =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", policy)
+        |> Regolix.enable_coverage!()
+        |> Regolix.set_input!(%{"banned" => true})
+
+      Regolix.eval_query!(engine_a, "data.test.allow")
+      Regolix.eval_query!(engine_b, "data.test.deny")
+
+      merged = Regolix.merge_coverage!([engine_a, engine_b])
+      covered_a = Regolix.get_coverage_report!(engine_a)["test.rego"].covered
+      covered_b = Regolix.get_coverage_report!(engine_b)["test.rego"].covered
+
+      assert merged["test.rego"].covered == Enum.sort(Enum.uniq(covered_a ++ covered_b))
+    end
+
+    test "returns an empty report for no sources" do
+      assert {:ok, %{}} = Regolix.merge_coverage([])
+    end
+  end
+
+  describe "coverage exports" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+
+        allow if input.admin
+
+        deny if input.banned
+        """)
+        |> Regolix.enable_coverage!()
+        |> Regolix.set_input!(%{"admin" => true})
+
+      Regolix.eval_query!(engine, "data.test.allow")
+
+      %{engine: engine, report: Regolix.get_coverage_report!(engine)}
+    end
+
+    test "coverage_to_lcov/1 writes one record per file", %{engine: engine, report: report} do
+      assert {:ok, lcov} = Regolix.coverage_to_lcov(engine)
+
+      %{covered: covered, not_covered: not_covered} = report["test.rego"]
+
+      assert lcov =~ "SF:test.rego\n"
+      assert lcov =~ "LF:#{length(covered) + length(not_covered)}\n"
+      assert lcov =~ "LH:#{length(covered)}\n"
+      assert lcov =~ "end_of_record"
+
+      for line <- covered, do: assert(lcov =~ "DA:#{line},1\n")
+      for line <- not_covered, do: assert(lcov =~ "DA:#{line},0\n")
+    end
+
+    test "coverage_to_cobertura/1 writes a class per file", %{engine: engine, report: report} do
+      assert {:ok, xml} = Regolix.coverage_to_cobertura(engine)
+
+      %{covered: covered} = report["test.rego"]
+
+      assert xml =~ ~s(<?xml version="1.0" ?>)
+      assert xml =~ ~s(<class name="test.rego" filename="test.rego")
+      assert xml =~ ~s(lines-covered="#{length(covered)}")
+
+      for line <- covered, do: assert(xml =~ ~s(<line number="#{line}" hits="1"))
+    end
+
+    test "get_rule_coverage/1 reports rules and unexercised rules", %{engine: engine} do
+      assert {:ok, %{rules: rules, unexercised: unexercised}} = Regolix.get_rule_coverage(engine)
+
+      allow = Enum.find(rules, &(&1.rule == "data.test.allow"))
+      assert %{file: "test.rego", start_line: 3, end_line: 3} = allow
+      assert allow.covered > 0
+      assert allow.percent == allow.covered / allow.total * 100
+
+      assert [%{rule: "data.test.deny", start_line: 5, covered: 0, percent: 0.0}] = unexercised
+    end
+  end
+
   describe "get_coverage_report!/1" do
     test "returns coverage directly" do
       engine =
@@ -370,10 +3098,134 @@ defmodule RegolixTest do
         """)
         |> Regolix.enable_coverage!()
 
-      Regolix.eval_query!(engine, "data.test.value")
+      Regolix.eval_query!(engine, "data.test.value")
+
+      coverage = Regolix.get_coverage_report!(engine)
+      assert is_map(coverage)
+    end
+  end
+
+  describe "profiling" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        allow if input.user == "admin"
+        """)
+        |> Regolix.set_input!(%{"user" => "admin"})
+
+      %{engine: engine}
+    end
+
+    test "records nothing while disabled", %{engine: engine} do
+      Regolix.eval_query!(engine, "data.authz.allow")
+      assert {:ok, %{}} = Regolix.get_profile(engine)
+    end
+
+    test "records count and timings per rule", %{engine: engine} do
+      engine = Regolix.enable_profiling!(engine)
+
+      Regolix.eval_query!(engine, "data.authz.allow")
+      Regolix.eval_query!(engine, "data.authz.allow")
+      Regolix.eval_rule!(engine, "data.authz.allow")
+      Regolix.eval_query!(engine, "x := data.authz.is_admin")
+      Regolix.eval_query!(engine, "1 + 1")
+
+      profile = Regolix.get_profile!(engine)
+
+      assert %{count: 3, total_ns: total, max_ns: max} = profile["data.authz.allow"]
+      assert max <= total
+      assert %{count: 4} = profile["data.authz.is_admin"]
+      assert Map.keys(profile) == ["data.authz.allow", "data.authz.is_admin"]
+    end
+
+    test "clear_profile!/1 keeps profiling enabled", %{engine: engine} do
+      engine = Regolix.enable_profiling!(engine)
+      Regolix.eval_query!(engine, "data.authz.allow")
+
+      engine = Regolix.clear_profile!(engine)
+      assert Regolix.get_profile!(engine) == %{}
+
+      Regolix.eval_query!(engine, "data.authz.allow")
+      assert %{count: 1} = Regolix.get_profile!(engine)["data.authz.allow"]
+    end
+
+    test "disable_profiling!/1 discards timings", %{engine: engine} do
+      engine = Regolix.enable_profiling!(engine)
+      Regolix.eval_query!(engine, "data.authz.allow")
+
+      engine = Regolix.disable_profiling!(engine)
+      assert Regolix.get_profile!(engine) == %{}
+    end
+  end
+
+  describe "benchmark/3" do
+    setup do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+        allow if input.user == "admin"
+        """)
+
+      %{engine: engine}
+    end
+
+    test "reports ordered latency percentiles", %{engine: engine} do
+      assert {:ok, report} =
+               Regolix.benchmark(engine, "data.authz.allow",
+                 input: %{"user" => "admin"},
+                 iterations: 50
+               )
+
+      assert %{iterations: 50, min_ns: min, p50_ns: p50, p95_ns: p95, p99_ns: p99, max_ns: max} =
+               report
+
+      assert min <= p50 and p50 <= p95 and p95 <= p99 and p99 <= max
+      assert report.mean_ns >= min and report.mean_ns <= max
+    end
+
+    test "doesn't count towards stats", %{engine: engine} do
+      assert {:ok, _} = Regolix.benchmark(engine, "data.authz.allow", iterations: 10)
+      assert {:ok, %{eval_count: 0}} = Regolix.stats(engine)
+    end
+
+    test "returns evaluation errors", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.benchmark(engine, "no_such_function(1)", iterations: 10)
+    end
+
+    test "rejects iteration counts out of range", %{engine: engine} do
+      for iterations <- [0, 1_000_001] do
+        assert {:error, %Regolix.Error{type: :invalid_argument}} =
+                 Regolix.benchmark(engine, "data.authz.allow", iterations: iterations)
+      end
+    end
+  end
+
+  describe "stats/1" do
+    test "reports sizes, evaluation counters and the last error" do
+      source = "package authz\nallow if input.user == \"admin\""
+
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", source)
+        |> Regolix.add_data!(%{"a" => 1})
+
+      assert {:ok, %{eval_count: 0, eval_ns: 0, last_error: nil}} = Regolix.stats(engine)
 
-      coverage = Regolix.get_coverage_report!(engine)
-      assert is_map(coverage)
+      Regolix.eval_query!(engine, "data.authz.allow")
+      Regolix.eval_rule!(engine, "data.authz.allow")
+      assert {:error, _} = Regolix.eval_query(engine, "data.authz.allow +")
+
+      assert {:ok, stats} = Regolix.stats(engine)
+      assert stats.policy_count == 1
+      assert stats.policy_bytes == byte_size(source)
+      assert stats.data_bytes == byte_size(~s({"a":1}))
+      assert stats.eval_count == 3
+      assert stats.eval_ns > 0
+      assert %{kind: :eval_error, message: message} = stats.last_error
+      assert is_binary(message)
     end
   end
 
@@ -529,6 +3381,198 @@ defmodule RegolixTest do
     end
   end
 
+  describe "validate_query/2" do
+    test "accepts a valid query" do
+      assert :ok = Regolix.validate_query("x := data.roles[_]; x != \"guest\"")
+      assert :ok = Regolix.validate_query("data.authz.allow with input.user as \"alice\"")
+    end
+
+    test "reports the position of a syntax error within the query" do
+      assert {:error, %Regolix.Error{type: :parse_error, line: 2}} =
+               Regolix.validate_query("x := 1\ny := := 2")
+    end
+
+    test "rejects a query that closes its own body" do
+      assert {:error, %Regolix.Error{type: :parse_error, line: 1, column: 6}} =
+               Regolix.validate_query("true } evil := 1 { true")
+    end
+  end
+
+  describe "parse_policy/2" do
+    @policy """
+    package authz
+
+    allow if input.user == "admin"
+    """
+
+    test "returns the module AST as maps" do
+      assert {:ok, ast} = Regolix.parse_policy(@policy)
+      assert is_map(ast)
+      assert Jason.encode!(ast) =~ "allow"
+    end
+
+    test "returns JSON when asked" do
+      assert {:ok, json} = Regolix.parse_policy(@policy, format: :json)
+      assert {:ok, _} = Jason.decode(json)
+    end
+
+    test "returns a located parse error" do
+      assert {:error, %Regolix.Error{type: :parse_error, file: "bad.rego"}} =
+               Regolix.parse_policy("package authz\nallow if {", name: "bad.rego")
+    end
+
+    test "parses legacy syntax with rego_version: :v0" do
+      source = """
+      package authz
+      allow { input.user == "admin" }
+      """
+
+      assert {:error, _} = Regolix.parse_policy(source)
+      assert {:ok, _} = Regolix.parse_policy(source, rego_version: :v0)
+    end
+  end
+
+  describe "value_to_string/2" do
+    test "renders Rego literals, including sets and undefined" do
+      assert Regolix.value_to_string(%{"roles" => {:set, ["dev", "admin"]}, "n" => nil}) ==
+               {:ok, ~s({"n": null, "roles": {"admin", "dev"}})}
+
+      assert Regolix.value_to_string!(MapSet.new([2, 1])) == "{1, 2}"
+      assert Regolix.value_to_string!({:set, []}) == "set()"
+      assert Regolix.value_to_string!([1, 2.5, "a\"b"]) == ~s([1, 2.5, "a\\"b"])
+      assert Regolix.value_to_string!(:undefined) == "undefined"
+    end
+
+    test "renders JSON, with sets as arrays" do
+      assert Regolix.value_to_string(%{"a" => {:set, [1]}, "b" => true}, :json) ==
+               {:ok, ~s({"a":[1],"b":true})}
+
+      assert Regolix.value_to_string!(%{"a" => 1}, :pretty_json) == ~s({\n  "a": 1\n})
+    end
+
+    test "rejects undefined as JSON and unsupported terms" do
+      assert {:error, %Regolix.Error{type: :json_error}} = Regolix.value_to_string(:undefined, :json)
+      assert {:error, %Regolix.Error{type: :json_error}} = Regolix.value_to_string({:a, :b, :c})
+      assert {:error, %Regolix.Error{type: :json_error}} = Regolix.value_to_string(self())
+    end
+  end
+
+  describe "lint_policy/2" do
+    test "returns no findings for a clean policy" do
+      assert {:ok, []} =
+               Regolix.lint_policy("""
+               package authz
+
+               default allow := false
+
+               allow if {
+                 role := input.user.role
+                 role == "admin"
+               }
+               """)
+    end
+
+    test "reports unused variables" do
+      assert {:ok, [%{check: "unused-variable", severity: :warning, line: 4, column: 3}]} =
+               Regolix.lint_policy("""
+               package authz
+
+               allow if {
+                 role := input.user.role
+                 input.user.active
+               }
+               """)
+    end
+
+    test "reads multi-line raw strings as literals" do
+      assert {:ok, []} =
+               Regolix.lint_policy("""
+               package authz
+
+               msg := `hi
+               any(café) {"a": 1}`
+               """)
+    end
+
+    test "reports shadowed documents and builtins" do
+      {:ok, findings} =
+        Regolix.lint_policy("""
+        package authz
+
+        input := {"user": "x"}
+
+        count := 3
+        """)
+
+      assert [
+               %{check: "shadowed-document", severity: :error, line: 3},
+               %{check: "shadowed-builtin", severity: :warning, line: 5}
+             ] = findings
+    end
+
+    test "reports deprecated imports and builtins" do
+      {:ok, findings} =
+        Regolix.lint_policy("""
+        package authz
+
+        import future.keywords.if
+
+        allow if re_match("^a", input.name)
+        """)
+
+      assert [%{check: "deprecated-import", line: 3}, %{check: "deprecated-builtin", line: 5}] =
+               findings
+    end
+
+    test "reports constant conditions" do
+      {:ok, findings} =
+        Regolix.lint_policy("""
+        package authz
+
+        allow if {
+          1 == 1
+          input.admin
+        }
+        """)
+
+      assert [%{check: "constant-condition", line: 4, column: 3}] = findings
+    end
+
+    test "does not report the last element of a multi-line collection" do
+      assert {:ok, []} =
+               Regolix.lint_policy("""
+               package authz
+
+               allow if {
+                 input.role in [
+                   "admin",
+                   "owner"
+                 ]
+                 input.user in {
+                   "alice",
+                   "bob",
+                 }
+               }
+               """)
+    end
+
+    test "skips disabled checks" do
+      source = """
+      package authz
+
+      allow if {
+        1 == 1
+      }
+      """
+
+      assert {:ok, []} = Regolix.lint_policy(source, disable: ["constant-condition"])
+    end
+
+    test "returns a parse error for invalid policies" do
+      assert {:error, %Regolix.Error{type: :parse_error}} = Regolix.lint_policy("package authz\nallow if {")
+    end
+  end
+
   describe "get_rules/1" do
     test "returns empty map for engine with no policies" do
       engine = Regolix.new!()
@@ -617,6 +3661,60 @@ defmodule RegolixTest do
       assert allow_rule[:description] == "Allow admin users full access"
     end
 
+    test "reports each definition's kind, default value and full ref" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        default allow := false
+        allow if input.admin
+        users contains input.user
+        roles[name] := role if some name, role in input.roles
+        limits["max"] := 10
+        double(x) := x * 2
+        """)
+
+      {:ok, %{"test.rego" => rules}} = Regolix.get_rules(engine)
+
+      assert Enum.map(rules, &{&1.name, &1.kind, &1.default, &1.ref}) == [
+               {"allow", :default, false, "data.test.allow"},
+               {"allow", :complete, nil, "data.test.allow"},
+               {"users", :partial_set, nil, "data.test.users"},
+               {"roles[name]", :partial_object, nil, "data.test.roles[name]"},
+               {"limits[\"max\"]", :complete, nil, "data.test.limits[\"max\"]"},
+               {"double", :function, nil, "data.test.double"}
+             ]
+    end
+
+    test "joins a multi-line comment block into the description" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+
+        # Unrelated comment
+
+        # ==========
+        # Allow admin users
+        # full access.
+        #
+        # Everyone else is denied.
+        allow if input.role == "admin"
+        """)
+
+      {:ok, %{"test.rego" => [allow]}} = Regolix.get_rules(engine)
+
+      assert allow.description == "Allow admin users full access. Everyone else is denied."
+
+      assert allow.comments == [
+               "# ==========",
+               "# Allow admin users",
+               "# full access.",
+               "#",
+               "# Everyone else is denied."
+             ]
+    end
+
     test "handles multiple policies" do
       engine =
         Regolix.new!()
@@ -641,6 +3739,79 @@ defmodule RegolixTest do
       assert "check" in rbac_rule_names
     end
 
+    test "extracts ref-head rules" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+        limits.max_users := 10
+        """)
+
+      {:ok, rules} = Regolix.get_rules(engine)
+      rule_names = Enum.map(rules["test.rego"], & &1[:name])
+      assert "limits.max_users" in rule_names
+    end
+
+    test "covers else chains and multi-line heads in one line range" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+
+        # Pick a tier
+        tier := "gold" if {
+          input.spend > 1000
+        } else := "silver" if {
+          input.spend > 100
+        } else := "bronze"
+
+        greeting := concat(" ", [
+          "hello",
+          input.name,
+        ])
+        """)
+
+      {:ok, rules} = Regolix.get_rules(engine)
+      tier = Enum.find(rules["test.rego"], &(&1[:name] == "tier"))
+      greeting = Enum.find(rules["test.rego"], &(&1[:name] == "greeting"))
+
+      assert %{start_line: 4, end_line: 8, description: "Pick a tier"} = tier
+      assert %{start_line: 10, end_line: 13, description: ""} = greeting
+    end
+
+    test "parses METADATA annotations" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("test.rego", """
+        package test
+
+        # METADATA
+        # title: Admin access
+        # description: Admins can do anything
+        # custom:
+        #   severity: high
+        allow if input.role == "admin"
+
+        # Plain comment
+        deny if input.banned
+        """)
+
+      {:ok, rules} = Regolix.get_rules(engine)
+      allow = Enum.find(rules["test.rego"], &(&1[:name] == "allow"))
+      deny = Enum.find(rules["test.rego"], &(&1[:name] == "deny"))
+
+      assert allow.description == "Admins can do anything"
+
+      assert allow.annotations == %{
+               "title" => "Admin access",
+               "description" => "Admins can do anything",
+               "custom" => %{"severity" => "high"}
+             }
+
+      assert deny.description == "Plain comment"
+      assert deny.annotations == nil
+    end
+
     test "handles contains rules" do
       engine =
         Regolix.new!()
@@ -657,6 +3828,211 @@ defmodule RegolixTest do
     end
   end
 
+  describe "data_deps/1" do
+    test "lists the data paths each rule reads" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("lib.rego", """
+        package lib
+        admins := data.directory["admins"]
+        """)
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        import data.roles as role_table
+
+        allow if {
+          data.users[input.user].active
+          role_table.editor[_] == input.action
+          input.user in data.lib.admins
+        }
+
+        audit := data.audit.enabled
+        """)
+
+      assert {:ok, deps} = Regolix.data_deps(engine)
+
+      assert deps["authz.rego"] == %{
+               "allow" => ["data.roles.editor", "data.users"],
+               "audit" => ["data.audit.enabled"]
+             }
+
+      assert deps["lib.rego"] == %{"admins" => ["data.directory.admins"]}
+    end
+
+    test "follows references through local variables" do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+
+        allow if {
+          users := data.users
+          users.alice.active
+          role := users[input.user].role
+          role in data.roles.admin
+        }
+        """)
+
+      assert {:ok, %{"authz.rego" => deps}} = Regolix.data_deps(engine)
+
+      assert deps == %{
+               "allow" => ["data.roles.admin", "data.users", "data.users.alice.active"]
+             }
+    end
+
+    test "keeps data stored under a loaded package" do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+
+        allow if {
+          input.level >= data.authz.config.min_level
+          data.authz.admin
+        }
+
+        admin if input.role == "admin"
+        """)
+
+      assert {:ok, %{"authz.rego" => deps}} = Regolix.data_deps(engine)
+      assert deps == %{"allow" => ["data.authz.config.min_level"]}
+    end
+  end
+
+  describe "input_deps/1" do
+    test "lists the input paths each rule reads" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        import input.request as req
+
+        allow if {
+          input.user == "alice"
+          req.method == "GET"
+          input.resources[_].owner == input.user
+        }
+
+        deny := true if data.blocked with input.user as "nobody"
+        """)
+
+      assert {:ok, %{"authz.rego" => deps}} = Regolix.input_deps(engine)
+
+      assert deps == %{
+               "allow" => ["input.request.method", "input.resources", "input.user"]
+             }
+    end
+  end
+
+  describe "input_skeleton/1" do
+    test "builds a template of the input with placeholder types" do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        import input.request as req
+
+        allow if {
+          input.user.role == "admin"
+          req.method == "GET"
+          input.amount < 100
+          some group in input.user.groups
+          input.resources[_].owner == input.user.name
+          input.flags[name]
+          input.debug == true
+        }
+        """)
+
+      assert {:ok, skeleton} = Regolix.input_skeleton(engine)
+
+      assert skeleton == %{
+               "user" => %{"role" => "string", "groups" => "array", "name" => "any"},
+               "request" => %{"method" => "string"},
+               "amount" => "number",
+               "resources" => "array",
+               "flags" => "object",
+               "debug" => "boolean"
+             }
+    end
+
+    test "is empty without policies" do
+      assert {:ok, %{}} = Regolix.input_skeleton(Regolix.new!())
+    end
+  end
+
+  describe "rule_graph/2" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+        import data.lib
+
+        allow if is_admin
+        allow if lib.owns(input.user, input.resource)
+
+        is_admin if input.user in data.admins
+        """)
+        |> Regolix.add_policy!("lib.rego", """
+        package lib
+        owns(user, resource) if data.owners[resource] == user
+        """)
+
+      %{engine: engine}
+    end
+
+    test "returns rule and data dependencies", %{engine: engine} do
+      assert {:ok, %{nodes: nodes, edges: edges}} = Regolix.rule_graph(engine)
+
+      assert Enum.sort(edges) == [
+               %{from: "data.authz.allow", to: "data.authz.is_admin"},
+               %{from: "data.authz.allow", to: "data.lib.owns"},
+               %{from: "data.authz.is_admin", to: "data.admins"},
+               %{from: "data.lib.owns", to: "data.owners"}
+             ]
+
+      assert %{kind: :rule, depth: 1} = Enum.find(nodes, &(&1.id == "data.authz.allow"))
+      assert %{kind: :rule, depth: 0} = Enum.find(nodes, &(&1.id == "data.lib.owns"))
+      assert %{kind: :data} = Enum.find(nodes, &(&1.id == "data.admins"))
+    end
+
+    test "renders Graphviz DOT", %{engine: engine} do
+      assert {:ok, "digraph rules {" <> _ = dot} = Regolix.rule_graph(engine, :dot)
+      assert dot =~ ~s("data.authz.allow" -> "data.authz.is_admin";)
+      assert dot =~ ~s("data.admins" [shape=cylinder];)
+    end
+  end
+
+  describe "rule_complexity/1" do
+    test "reports metrics for each rule definition" do
+      engine =
+        Regolix.add_policy!(Regolix.new!(), "authz.rego", """
+        package authz
+
+        default decision := "deny"
+
+        decision := "allow" if {
+          admins := {u | some u in data.users; data.roles[u] == "admin"}
+          input.user in admins
+        } else := "root" if {
+          input.user == "root"
+        }
+        """)
+
+      assert {:ok, [default, decision]} = Regolix.rule_complexity(engine)
+
+      assert %{file: "authz.rego", name: "decision", line: 3, bodies: 0, depth: 0} = default
+
+      assert %{
+               name: "decision",
+               line: 5,
+               bodies: 2,
+               comprehensions: 1,
+               depth: 2,
+               data_refs: 2,
+               input_refs: 2
+             } = decision
+    end
+  end
+
   describe "get_rules!/1" do
     test "returns rules directly" do
       engine =
@@ -672,6 +4048,69 @@ defmodule RegolixTest do
     end
   end
 
+  describe "entrypoints" do
+    setup do
+      engine =
+        Regolix.new!()
+        |> Regolix.add_policy!("authz.rego", """
+        package authz
+
+        # METADATA
+        # description: Whether the request is allowed
+        # entrypoint: true
+        allow if input.user == "admin"
+
+        # METADATA
+        # description: Not a decision point
+        helper := true
+        """)
+
+      {:ok, engine: engine}
+    end
+
+    test "lists rules annotated as entrypoints", %{engine: engine} do
+      assert {:ok,
+              [
+                %{
+                  name: "authz/allow",
+                  path: "data.authz.allow",
+                  file: "authz.rego",
+                  line: 6,
+                  description: "Whether the request is allowed"
+                }
+              ]} = Regolix.get_entrypoints(engine)
+    end
+
+    test "evaluates an entrypoint by name or path", %{engine: engine} do
+      assert {:ok, true} = Regolix.eval_entrypoint(engine, "authz/allow", %{"user" => "admin"})
+      assert Regolix.eval_entrypoint!(engine, "data.authz.allow", %{"user" => "bob"}) == :undefined
+    end
+
+    test "returns an error for an unknown entrypoint", %{engine: engine} do
+      assert {:error, %Regolix.Error{type: :eval_error}} =
+               Regolix.eval_entrypoint(engine, "authz/helper", %{})
+    end
+
+    test "follows policies added and removed after a lookup", %{engine: engine} do
+      assert {:ok, [%{name: "authz/allow"}]} = Regolix.get_entrypoints(engine)
+
+      {:ok, engine} =
+        Regolix.add_policy(engine, "audit.rego", """
+        package audit
+
+        # METADATA
+        # entrypoint: true
+        log := true
+        """)
+
+      assert {:ok, [%{name: "audit/log"}, %{name: "authz/allow"}]} =
+               Regolix.get_entrypoints(engine)
+
+      {:ok, engine} = Regolix.remove_policy(engine, "authz.rego")
+      assert {:ok, [%{name: "audit/log"}]} = Regolix.get_entrypoints(engine)
+    end
+  end
+
   describe "integration" do
     test "complete authorization workflow" do
       # Create engine and add policy
@@ -1 +1,2 @@
-ExUnit.start()
+# Tests tagged :store need the `store` feature, see Regolix.open_store/1
+ExUnit.start(exclude: [:store])